@@ -0,0 +1,31 @@
+use crate::user::User;
+use chrono::{
+	DateTime,
+	Utc,
+};
+use database_table::Id;
+use serde::{
+	Deserialize,
+	Serialize,
+};
+use std::net::IpAddr;
+
+/// A login into an existing account, shown to its user on their profile
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LoginAttempt {
+	pub user: Id<User>,
+	pub time: DateTime<Utc>,
+	/// address of the client, if the server knows it
+	pub ip: Option<IpAddr>,
+	pub success: bool,
+}
+impl LoginAttempt {
+	pub fn new(user: Id<User>, ip: Option<IpAddr>, success: bool) -> Self {
+		Self {
+			user,
+			time: Utc::now(),
+			ip,
+			success,
+		}
+	}
+}
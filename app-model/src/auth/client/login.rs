@@ -2,6 +2,8 @@ use crate::auth::{
 	credentials::Credentials,
 	session::Session,
 	Auth,
+	LoginResponse,
+	SecondFactorRequest,
 	UserSession,
 };
 use components::{
@@ -19,29 +21,66 @@ use tracing::debug;
 #[derive(Debug, Clone)]
 pub struct Login {
 	pub credentials: Credentials,
+	/// challenge of a correct password, waiting for the second factor
+	pub challenge: Option<String>,
+	pub code: String,
 }
 #[derive(Debug, Clone)]
 pub enum Msg {
 	ChangeUsername(String),
 	ChangePassword(String),
-	LoginResponse(Result<UserSession, String>),
+	ChangeCode(String),
+	LoginResponse(Result<LoginResponse, String>),
+	SecondFactorResponse(Result<UserSession, String>),
 	Submit,
 }
 impl Login {
-	async fn login_request(self) -> Result<UserSession, FetchError> {
-		let req = seed::fetch::Request::new(format!("{}/api/auth/login", crate::get_base_url().unwrap())).method(Method::Post);
+	async fn login_request(self) -> Result<LoginResponse, FetchError> {
+		let req = crate::post_request(format!("{}/api/auth/login", crate::get_base_url().unwrap()));
 		seed::fetch::fetch(req.json(&self.credentials)?)
 			.await?
 			.check_status()?
 			.json()
 			.await
-			.map(|session: UserSession| session)
+	}
+	async fn second_factor_request(request: SecondFactorRequest) -> Result<UserSession, FetchError> {
+		let req = crate::post_request(format!("{}/api/auth/second_factor", crate::get_base_url().unwrap()));
+		seed::fetch::fetch(req.json(&request)?)
+			.await?
+			.check_status()?
+			.json()
+			.await
+	}
+	fn view_second_factor(&self) -> Node<Msg> {
+		form![
+			label!["Code of your authenticator app or a recovery code"],
+			input![
+				attrs! {
+					At::Placeholder => "Code",
+					At::Value => self.code,
+					At::AutoComplete => "one-time-code",
+				},
+				input_ev(Ev::Input, Msg::ChangeCode)
+			],
+			button![
+				attrs! {
+					At::Type => "submit",
+				},
+				"Verify"
+			],
+			ev(Ev::Submit, |ev| {
+				ev.prevent_default();
+				Msg::Submit
+			}),
+		]
 	}
 }
 impl Default for Login {
 	fn default() -> Self {
 		Self {
 			credentials: Default::default(),
+			challenge: None,
+			code: String::new(),
 		}
 	}
 }
@@ -51,29 +90,62 @@ impl Component for Login {
 		match msg {
 			Msg::ChangeUsername(u) => self.credentials.username = u,
 			Msg::ChangePassword(p) => self.credentials.password = p,
+			Msg::ChangeCode(c) => self.code = c,
 			Msg::Submit => {
-				debug!("Logging in...");
-				orders.perform_cmd(self.clone().login_request().map(
-					|result: Result<UserSession, FetchError>| {
-						Msg::LoginResponse(result.map_err(|e| format!("{:?}", e)))
-					},
-				));
+				if let Some(challenge) = self.challenge.clone() {
+					debug!("Sending second factor...");
+					let request = SecondFactorRequest {
+						challenge,
+						code: self.code.clone(),
+					};
+					orders.perform_cmd(Self::second_factor_request(request).map(
+						|result: Result<UserSession, FetchError>| {
+							Msg::SecondFactorResponse(result.map_err(|e| format!("{:?}", e)))
+						},
+					));
+				} else {
+					debug!("Logging in...");
+					orders.perform_cmd(self.clone().login_request().map(
+						|result: Result<LoginResponse, FetchError>| {
+							Msg::LoginResponse(result.map_err(|e| format!("{:?}", e)))
+						},
+					));
+				}
 			}
 			Msg::LoginResponse(result) => {
 				debug!("Login Response");
 				match result {
-					Ok(session) => {
+					Ok(LoginResponse::Session(session)) => {
 						orders.notify(Auth::Session(Session::from(session)));
 					}
+					Ok(LoginResponse::SecondFactorRequired { challenge }) => {
+						self.challenge = Some(challenge);
+					}
 					Err(e) => seed::log!(e),
 				}
 			}
+			Msg::SecondFactorResponse(result) => {
+				self.code.clear();
+				match result {
+					Ok(session) => {
+						orders.notify(Auth::Session(Session::from(session)));
+					}
+					Err(e) => {
+						// the challenge was used up, log in with the password again
+						self.challenge = None;
+						seed::log!(e)
+					}
+				}
+			}
 		}
 	}
 }
 impl Viewable for Login {
 	fn view(&self) -> Node<Msg> {
 		//debug!("Login redraw");
+		if self.challenge.is_some() {
+			return self.view_second_factor();
+		}
 		form![
 			label!["Username"],
 			input![
@@ -4,6 +4,8 @@ pub mod register;
 pub use register::*;
 pub mod session;
 pub use session::Session;
+pub mod second_factor;
+pub use second_factor::TwoFactorSettings;
 
 use crate::{
 	auth::Route,
@@ -29,6 +31,14 @@ pub fn get_base_url() -> Result<String, JsValue> {
 		loc.host()?,
 	))
 }
+/// POST request to `url` with the CSRF token of the page
+pub fn post_request<'a>(url: impl Into<std::borrow::Cow<'a, str>>) -> seed::fetch::Request<'a> {
+	let req = seed::fetch::Request::new(url).method(seed::fetch::Method::Post);
+	match database_table::csrf_token() {
+		Some(token) => req.header(seed::fetch::Header::custom(database_table::CSRF_HEADER, token)),
+		None => req,
+	}
+}
 
 #[derive(Debug, Clone)]
 pub enum Auth {
@@ -1,5 +1,6 @@
 use crate::{
 	auth::{
+		credentials::Credentials,
 		session::Session,
 		Auth,
 		UserSession,
@@ -27,10 +28,10 @@ pub enum Msg {
 	Submit,
 	RegistrationResponse(Result<UserSession, String>),
 }
-pub async fn registration_request(user: User) -> Result<UserSession, FetchError> {
+pub async fn registration_request(credentials: Credentials) -> Result<UserSession, FetchError> {
 	let url = "/api/auth/register";
-	let req = seed::fetch::Request::new(url).method(Method::Post);
-	seed::fetch::fetch(req.json(&user)?)
+	let req = crate::post_request(url);
+	seed::fetch::fetch(req.json(&credentials)?)
 		.await?
 		.check_status()?
 		.json()
@@ -49,7 +50,7 @@ impl Component for Register {
 			}
 			Msg::Submit => {
 				debug!("Registration...");
-				orders.perform_cmd(registration_request(self.user.clone()).map(
+				orders.perform_cmd(registration_request(self.user.credentials().clone()).map(
 					|result: Result<UserSession, FetchError>| {
 						Msg::RegistrationResponse(result.map_err(|e| format!("{:?}", e)))
 					},
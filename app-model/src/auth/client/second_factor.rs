@@ -0,0 +1,127 @@
+use crate::auth::{
+	session,
+	CodeRequest,
+	Enrolment,
+};
+use components::{
+	Component,
+	Viewable,
+};
+use seed::{
+	browser::fetch::FetchError,
+	prelude::*,
+	*,
+};
+
+/// Enrolment and removal of the second factor of the current user
+#[derive(Debug, Clone, Default)]
+pub struct TwoFactorSettings {
+	/// shown until confirmed, as the secret and recovery codes are not sent again
+	pub enrolment: Option<Enrolment>,
+	pub code: String,
+	pub status: Option<String>,
+}
+#[derive(Debug, Clone)]
+pub enum Msg {
+	Enrol,
+	Enrolled(Result<Enrolment, String>),
+	ChangeCode(String),
+	Confirm,
+	Disable,
+	Done(Result<String, String>),
+}
+async fn enrol_request() -> Result<Enrolment, FetchError> {
+	let req = session::authorized(crate::post_request(format!("{}/api/auth/two_factor/enrol", crate::get_base_url().unwrap())));
+	seed::fetch::fetch(req)
+		.await?
+		.check_status()?
+		.json()
+		.await
+}
+/// Post `code` to `/api/auth/two_factor/<action>`
+async fn code_request(action: &'static str, code: String) -> Result<(), FetchError> {
+	let req = session::authorized(crate::post_request(format!("{}/api/auth/two_factor/{}", crate::get_base_url().unwrap(), action)))
+		.json(&CodeRequest { code })?;
+	seed::fetch::fetch(req)
+		.await?
+		.check_status()?;
+	Ok(())
+}
+impl Component for TwoFactorSettings {
+	type Msg = Msg;
+	fn update(&mut self, msg: Self::Msg, orders: &mut impl Orders<Self::Msg>) {
+		match msg {
+			Msg::Enrol => {
+				orders.perform_cmd(async {
+					Msg::Enrolled(enrol_request().await.map_err(|e| format!("{:?}", e)))
+				});
+			}
+			Msg::Enrolled(result) => {
+				match result {
+					Ok(enrolment) => self.enrolment = Some(enrolment),
+					Err(e) => self.status = Some(e),
+				}
+			}
+			Msg::ChangeCode(code) => self.code = code,
+			Msg::Confirm => {
+				let code = std::mem::take(&mut self.code);
+				orders.perform_cmd(async {
+					Msg::Done(code_request("confirm", code).await
+						.map(|_| "Two-factor authentication is enabled.".to_string())
+						.map_err(|e| format!("{:?}", e)))
+				});
+			}
+			Msg::Disable => {
+				let code = std::mem::take(&mut self.code);
+				orders.perform_cmd(async {
+					Msg::Done(code_request("disable", code).await
+						.map(|_| "Two-factor authentication is disabled.".to_string())
+						.map_err(|e| format!("{:?}", e)))
+				});
+			}
+			Msg::Done(result) => {
+				if result.is_ok() {
+					self.enrolment = None;
+				}
+				self.status = Some(result.unwrap_or_else(|e| e));
+			}
+		}
+	}
+}
+impl TwoFactorSettings {
+	fn view_code_input(&self) -> Node<Msg> {
+		input![
+			attrs! {
+				At::Placeholder => "Code",
+				At::Value => self.code,
+				At::AutoComplete => "one-time-code",
+			},
+			input_ev(Ev::Input, Msg::ChangeCode)
+		]
+	}
+}
+impl Viewable for TwoFactorSettings {
+	fn view(&self) -> Node<Msg> {
+		div![
+			h3!["Two-factor authentication"],
+			if let Some(enrolment) = &self.enrolment {
+				div![
+					p!["Add this account to your authenticator app, e.g. by turning the link into a QR code:"],
+					a![enrolment.uri.as_str(), attrs! { At::Href => enrolment.uri }],
+					p![format!("Secret: {}", enrolment.secret)],
+					p!["Keep these recovery codes, each can be used once instead of a code:"],
+					ul![enrolment.recovery_codes.iter().map(|code| li![code.as_str()])],
+					self.view_code_input(),
+					button![ev(Ev::Click, |_| Msg::Confirm), "Confirm"],
+				]
+			} else {
+				div![
+					button![ev(Ev::Click, |_| Msg::Enrol), "Set up"],
+					self.view_code_input(),
+					button![ev(Ev::Click, |_| Msg::Disable), "Turn off"],
+				]
+			},
+			self.status.as_ref().map(|status| p![status.as_str()]),
+		]
+	}
+}
@@ -1,4 +1,8 @@
-use crate::auth::UserSession;
+use crate::auth::{
+	LoginAttempt,
+	RefreshRequest,
+	UserSession,
+};
 use components::{
 	Component,
 	Viewable,
@@ -30,25 +34,81 @@ pub fn set(session: UserSession) {
 	seed::log!("Setting UserSession");
 	*SESSION.lock().unwrap() = Some(session.clone());
 	store(&session.clone());
+	schedule_refresh(&session);
+}
+/// seconds before the access token expires when it is refreshed
+const REFRESH_MARGIN: i64 = 60;
+/// Refresh the session shortly before its access token expires
+fn schedule_refresh(session: &UserSession) {
+	if session.refresh_token.is_empty() {
+		return;
+	}
+	let delay = (session.expires - REFRESH_MARGIN - chrono::Utc::now().timestamp()).max(0);
+	let refresh_token = session.refresh_token.clone();
+	spawn_local(async move {
+		cmds::timeout((delay * 1000) as u32, || ()).await;
+		// the session may have ended or been refreshed in the meantime
+		let current = get().map(|session| session.refresh_token == refresh_token).unwrap_or(false);
+		if current {
+			match refresh(refresh_token).await {
+				Ok(session) => set(session),
+				Err(e) => {
+					seed::log!("Failed to refresh session", e);
+					end();
+				}
+			}
+		}
+	});
+}
+/// Exchange a refresh token for a new session
+pub async fn refresh(refresh_token: String) -> Result<UserSession, FetchError> {
+	let req = crate::post_request(format!("{}/api/auth/refresh", crate::get_base_url().unwrap()))
+		.json(&RefreshRequest { refresh_token })?;
+	seed::fetch::fetch(req)
+		.await?
+		.check_status()?
+		.json()
+		.await
 }
 pub fn get() -> Option<UserSession> {
 	SESSION.lock().unwrap().clone()
 }
+/// End the session and revoke its refresh token
 pub fn end() {
-	*SESSION.lock().unwrap() = None;
+	let session = SESSION.lock().unwrap().take();
 	clear();
+	if let Some(session) = session.filter(|session| !session.refresh_token.is_empty()) {
+		spawn_local(async move {
+			if let Err(e) = logout(session.refresh_token).await {
+				seed::log!("Failed to revoke session", e);
+			}
+		});
+	}
+}
+async fn logout(refresh_token: String) -> Result<(), FetchError> {
+	let req = crate::post_request(format!("{}/api/auth/logout", crate::get_base_url().unwrap()))
+		.json(&RefreshRequest { refresh_token })?;
+	seed::fetch::fetch(req)
+		.await?
+		.check_status()?;
+	Ok(())
+}
+/// Add the access token of the current session to a request
+pub fn authorized(req: seed::fetch::Request<'_>) -> seed::fetch::Request<'_> {
+	let token = get().map(|session| session.token).unwrap_or_default();
+	req.header(Header::custom("Authorization", token))
+}
+/// Login attempts into the account of the current session, latest first
+pub async fn login_attempts() -> Result<Vec<LoginAttempt>, FetchError> {
+	let req = authorized(seed::fetch::Request::new(format!("{}/api/auth/attempts", crate::get_base_url().unwrap())));
+	seed::fetch::fetch(req)
+		.await?
+		.check_status()?
+		.json()
+		.await
 }
 #[derive(Debug, Clone, Default)]
 pub struct Session;
-impl Session {
-	async fn logout_request(self) -> Result<(), FetchError> {
-		let req = seed::fetch::Request::new(format!("{}/api/auth/logout", crate::get_base_url().unwrap())).method(Method::Post);
-		seed::fetch::fetch(req)
-			.await?
-			.check_status()?;
-		Ok(())
-	}
-}
 impl From<UserSession> for Session {
 	fn from(session: UserSession) -> Self {
 		set(session);
@@ -61,11 +121,9 @@ pub enum Msg {
 }
 impl Component for Session {
 	type Msg = Msg;
-	fn update(&mut self, msg: Self::Msg, orders: &mut impl Orders<Self::Msg>) {
+	fn update(&mut self, msg: Self::Msg, _orders: &mut impl Orders<Self::Msg>) {
 		match msg {
-			Msg::Logout => {
-				orders.perform_cmd(self.clone().logout_request());
-			},
+			Msg::Logout => end(),
 		}
 	}
 }
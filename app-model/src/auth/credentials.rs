@@ -1,10 +1,11 @@
 use crate::user::*;
+use database_table::JsonSchema;
 use serde::{
 	Deserialize,
 	Serialize,
 };
 
-#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct Credentials {
 	pub username: String,
 	pub password: String,
@@ -1,6 +1,12 @@
+pub mod attempt;
+pub use attempt::*;
 pub mod credentials;
+pub mod role;
+pub use role::*;
+pub mod two_factor;
+pub use two_factor::*;
 use enum_paths::AsPath;
-use rql::Id;
+use database_table::Id;
 use serde::{
 	Deserialize,
 	Serialize,
@@ -30,5 +36,34 @@ impl database_table::Route for Route {}
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct UserSession {
 	pub user_id: Id<User>,
+	/// access token
 	pub token: String,
+	/// unix time in seconds when `token` expires
+	#[serde(default)]
+	pub expires: i64,
+	/// exchanged for a new session at `/api/auth/refresh`
+	#[serde(default)]
+	pub refresh_token: String,
+}
+/// Body of `/api/auth/refresh` and `/api/auth/logout`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RefreshRequest {
+	pub refresh_token: String,
+}
+
+/// The user calling an api function, from the claims of their token
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuthUser {
+	pub id: Id<User>,
+	pub name: String,
+	pub roles: Vec<String>,
+}
+impl AuthUser {
+	pub fn has_role(&self, role: &str) -> bool {
+		self.roles.iter().any(|r| r == role)
+	}
+}
+/// Rows which belong to users, checked by `#[owner(Type, id)]` on api functions
+pub trait Owned: Sized {
+	fn is_owner(&self, id: Id<Self>, user: Id<User>) -> bool;
 }
@@ -0,0 +1,204 @@
+//! Roles granting privileges on tables to the members of groups
+//!
+//! A `Privilege` allows a `TableAction` on all rows of a table, or only on the
+//! rows the caller owns (see `Owned`). Roles bundle privileges and are given
+//! to groups, whose members have the privileges of all roles of their groups.
+use crate::{
+	auth::Owned,
+	user::*,
+};
+use database_table::{
+	Id,
+	JsonSchema,
+	Updatable,
+};
+use serde::{
+	Deserialize,
+	Serialize,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum TableAction {
+	Read,
+	Create,
+	Update,
+	Delete,
+}
+/// Rows a privilege applies to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum Scope {
+	All,
+	/// only rows owned by the caller
+	Owned,
+}
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Privilege {
+	/// name of the table as given by `table_name`, or `*` for all tables
+	pub table: String,
+	pub action: TableAction,
+	pub scope: Scope,
+}
+impl Privilege {
+	/// `action` on all rows of the table of `T`
+	pub fn all<T>(action: TableAction) -> Self {
+		Self {
+			table: table_name::<T>(),
+			action,
+			scope: Scope::All,
+		}
+	}
+	/// `action` on the rows of the table of `T` owned by the caller
+	pub fn owned<T>(action: TableAction) -> Self {
+		Self {
+			table: table_name::<T>(),
+			action,
+			scope: Scope::Owned,
+		}
+	}
+	/// `action` on all rows of all tables
+	pub fn any_table(action: TableAction) -> Self {
+		Self {
+			table: "*".to_string(),
+			action,
+			scope: Scope::All,
+		}
+	}
+	pub fn allows(&self, action: TableAction, resource: &Resource) -> bool {
+		self.action == action
+			&& (self.table == "*" || self.table == resource.table)
+			&& (self.scope == Scope::All || resource.owned)
+	}
+}
+/// Rows an action is taken on
+#[derive(Clone, Debug, PartialEq)]
+pub struct Resource {
+	pub table: String,
+	/// whether the caller owns the rows
+	pub owned: bool,
+}
+impl Resource {
+	/// All rows of the table of `T`
+	pub fn table<T>() -> Self {
+		Self {
+			table: table_name::<T>(),
+			owned: false,
+		}
+	}
+	/// A row of the table of `T`
+	pub fn row<T>(owned: bool) -> Self {
+		Self {
+			table: table_name::<T>(),
+			owned,
+		}
+	}
+}
+/// Name of the table of `T` in privileges, the lowercase name of the type
+/// as in the paths of `rest_api!`
+pub fn table_name<T>() -> String {
+	let name = std::any::type_name::<T>();
+	let name = name.split('<').next().unwrap_or(name);
+	name.rsplit("::").next().unwrap_or(name).to_lowercase()
+}
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, Updatable, JsonSchema)]
+pub struct Role {
+	name: String,
+	privileges: Vec<Privilege>,
+}
+impl Role {
+	pub fn new<S: ToString>(name: S) -> Self {
+		Self {
+			name: name.to_string(),
+			privileges: Vec::new(),
+		}
+	}
+	pub fn with_privilege(mut self, privilege: Privilege) -> Self {
+		self.privileges.push(privilege);
+		self
+	}
+	pub fn name(&self) -> &String {
+		&self.name
+	}
+	pub fn privileges(&self) -> &Vec<Privilege> {
+		&self.privileges
+	}
+	pub fn allows(&self, action: TableAction, resource: &Resource) -> bool {
+		self.privileges.iter().any(|privilege| privilege.allows(action, resource))
+	}
+}
+/// Roles belong to no user, only privileges on all roles apply to them
+impl Owned for Role {
+	fn is_owner(&self, _id: Id<Self>, _user: Id<User>) -> bool {
+		false
+	}
+}
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, Updatable, JsonSchema)]
+pub struct Group {
+	name: String,
+	members: Vec<Id<User>>,
+	roles: Vec<Id<Role>>,
+}
+impl Group {
+	pub fn new<S: ToString>(name: S) -> Self {
+		Self {
+			name: name.to_string(),
+			members: Vec::new(),
+			roles: Vec::new(),
+		}
+	}
+	pub fn with_member(mut self, user: Id<User>) -> Self {
+		self.add_member(user);
+		self
+	}
+	pub fn with_role(mut self, role: Id<Role>) -> Self {
+		if !self.roles.contains(&role) {
+			self.roles.push(role);
+		}
+		self
+	}
+	pub fn name(&self) -> &String {
+		&self.name
+	}
+	pub fn members(&self) -> &Vec<Id<User>> {
+		&self.members
+	}
+	pub fn roles(&self) -> &Vec<Id<Role>> {
+		&self.roles
+	}
+	pub fn is_member(&self, user: Id<User>) -> bool {
+		self.members.contains(&user)
+	}
+	pub fn add_member(&mut self, user: Id<User>) {
+		if !self.is_member(user) {
+			self.members.push(user);
+		}
+	}
+	pub fn remove_member(&mut self, user: Id<User>) {
+		self.members.retain(|member| *member != user);
+	}
+}
+impl Owned for Group {
+	fn is_owner(&self, _id: Id<Self>, user: Id<User>) -> bool {
+		self.is_member(user)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::project::Project;
+	#[test]
+	fn privileges() {
+		assert_eq!(table_name::<Project>(), "project");
+		assert_eq!(table_name::<database_table::AuditRecord<Project>>(), "auditrecord");
+		let role = Role::new("member")
+			.with_privilege(Privilege::all::<Project>(TableAction::Read))
+			.with_privilege(Privilege::owned::<Project>(TableAction::Update));
+		assert!(role.allows(TableAction::Read, &Resource::table::<Project>()));
+		assert!(!role.allows(TableAction::Read, &Resource::table::<User>()));
+		assert!(role.allows(TableAction::Update, &Resource::row::<Project>(true)));
+		assert!(!role.allows(TableAction::Update, &Resource::row::<Project>(false)));
+		assert!(!role.allows(TableAction::Delete, &Resource::row::<Project>(true)));
+		let admin = Role::new("admin").with_privilege(Privilege::any_table(TableAction::Delete));
+		assert!(admin.allows(TableAction::Delete, &Resource::row::<User>(false)));
+	}
+}
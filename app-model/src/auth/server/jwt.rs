@@ -1,93 +1,155 @@
-use crate::user::*;
+use crate::{
+	auth::{
+		keys::KeyRing,
+		AuthUser,
+		UserSession,
+	},
+	user::*,
+};
+use database_table::{
+	Entry,
+	Id,
+};
 use chrono::{
 	Duration,
 	Utc,
 };
 use jsonwebtoken::{
 	errors::*,
-	DecodingKey,
-	EncodingKey,
 	Header,
 	*,
 };
 use lazy_static::lazy_static;
 use serde::*;
-use std::convert::TryFrom;
+use std::{
+	convert::TryFrom,
+	sync::{
+		Arc,
+		RwLock,
+	},
+};
 use std::fmt::{
 	self,
 	Display,
 	Formatter,
 };
 
+/// Signs and verifies access tokens with the keys of a `KeyRing`
 #[derive(Debug)]
 pub struct JWTProvider {
-	secret: String,
-	header: Header,
+	keys: KeyRing,
 	validation: Validation,
+	/// time until access tokens expire
+	lifetime: Duration,
 }
 lazy_static! {
-	static ref JWT_PROVIDER: JWTProvider = JWTProvider::new();
-	static ref TOKEN_LIFETIME: Duration = Duration::days(1);
+	static ref JWT_PROVIDER: RwLock<Arc<JWTProvider>> = RwLock::new(Arc::new(JWTProvider::new()));
 }
-
-fn get_token_lifetime() -> Duration {
-	*TOKEN_LIFETIME
+/// Replace the provider, which uses the keys of `KeyRing::from_env` by default
+pub fn set_jwt_provider(provider: JWTProvider) {
+	*JWT_PROVIDER.write().expect("JWTProvider poisoned") = Arc::new(provider);
+}
+pub fn jwt_provider() -> Arc<JWTProvider> {
+	JWT_PROVIDER.read().expect("JWTProvider poisoned").clone()
 }
 impl JWTProvider {
-	fn generate_secret() -> String {
-		//format!("{}", chrono::Utc::now().timestamp_nanos())
-		format!("{}", "dw8ed832ekjdkal29ewi92ie921e09iksdjwjwi")
-	}
 	pub fn new() -> Self {
-		let validation = Validation {
-			validate_exp: true,
-			validate_nbf: true,
-			leeway: 60,
-			..Default::default()
-		};
+		Self::with_keys(KeyRing::from_env())
+	}
+	pub fn with_keys(keys: KeyRing) -> Self {
+		let mut validation = Validation::default();
+		validation.validate_exp = true;
+		validation.validate_nbf = true;
+		validation.leeway = 60;
 		Self {
-			secret: Self::generate_secret(),
-			header: Header::default(),
+			keys,
 			validation,
+			lifetime: Duration::minutes(15),
 		}
 	}
+	/// Time until access tokens expire, refresh tokens renew them
+	pub fn with_lifetime(mut self, lifetime: Duration) -> Self {
+		self.lifetime = lifetime;
+		self
+	}
+	pub fn lifetime(&self) -> Duration {
+		self.lifetime
+	}
 	pub fn encode(&self, claims: &JWTClaims) -> Result<String> {
-		encode(
-			&self.header,
-			&claims,
-			&EncodingKey::from_secret(self.secret.as_bytes()),
-		)
+		let key = self.keys
+			.signing_key()
+			.ok_or_else(|| Error::from(ErrorKind::InvalidKeyFormat))?;
+		let header = Header {
+			kid: Some(key.kid.clone()),
+			..Header::new(key.algorithm)
+		};
+		encode(&header, &claims, key.encoding().expect("signing key without private key"))
 	}
+	/// Decode a token signed with the key of its `kid`, or the signing key
 	pub fn decode(&self, token: &str) -> Result<TokenData<JWTClaims>> {
-		decode(
-			token,
-			&DecodingKey::from_secret(self.secret.as_bytes()),
-			&self.validation,
-		)
+		let header = decode_header(token)?;
+		let key = match &header.kid {
+			Some(kid) => self.keys.get(kid),
+			None => self.keys.signing_key(),
+		}
+		.ok_or_else(|| Error::from(ErrorKind::InvalidToken))?;
+		let mut validation = self.validation.clone();
+		validation.algorithms = vec![key.algorithm];
+		decode(token, key.decoding(), &validation)
 	}
 }
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct JWTClaims {
 	sub: String,
+	/// id of the user
+	#[serde(default)]
+	uid: String,
+	#[serde(default)]
+	roles: Vec<String>,
 	exp: i64,
 	iat: i64,
 	nbf: i64,
 }
-impl From<&User> for JWTClaims {
-	fn from(user: &User) -> Self {
+impl JWTClaims {
+	pub fn new(id: Id<User>, user: &User) -> Self {
 		Self {
 			sub: user.name().clone(),
+			uid: id.to_string(),
+			roles: Vec::new(),
 			iat: Utc::now().timestamp(),
 			nbf: Utc::now().timestamp(),
-			exp: (Utc::now() + get_token_lifetime()).timestamp(),
+			exp: (Utc::now() + jwt_provider().lifetime()).timestamp(),
 		}
 	}
+	pub fn with_roles(mut self, roles: Vec<String>) -> Self {
+		self.roles = roles;
+		self
+	}
+	/// Unix time in seconds when the token expires
+	pub fn expires(&self) -> i64 {
+		self.exp
+	}
+	/// The authenticated user, `None` for tokens without a user id
+	pub fn user(&self) -> Option<AuthUser> {
+		self.uid.parse().ok().map(|id| AuthUser {
+			id,
+			name: self.sub.clone(),
+			roles: self.roles.clone(),
+		})
+	}
+}
+impl From<&Entry<User>> for JWTClaims {
+	fn from(entry: &Entry<User>) -> Self {
+		Self::new(entry.id, entry.data())
+	}
 }
 #[derive(Debug)]
 pub enum JWTError {
 	MissingToken,
 	Invalid(jsonwebtoken::errors::Error),
 	BadCount,
+	/// valid token without a user id
+	MissingUser,
 }
 #[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct JWT(String);
@@ -97,19 +159,19 @@ impl Display for JWT {
 		write!(f, "{}", self.0)
 	}
 }
-impl TryFrom<&User> for JWT {
+impl TryFrom<&Entry<User>> for JWT {
 	type Error = errors::Error;
-	fn try_from(user: &User) -> std::result::Result<Self, Self::Error> {
-		let claims = JWTClaims::from(user);
+	fn try_from(entry: &Entry<User>) -> std::result::Result<Self, Self::Error> {
+		let claims = JWTClaims::from(entry);
 		JWT::encode(&claims)
 	}
 }
 impl JWT {
 	pub fn encode(claims: &JWTClaims) -> Result<Self> {
-		JWT_PROVIDER.encode(&claims).map(JWT::from)
+		jwt_provider().encode(&claims).map(JWT::from)
 	}
 	pub fn decode(&self) -> Result<JWTClaims> {
-		JWT_PROVIDER.decode(&self.0).map(|td| td.claims)
+		jwt_provider().decode(&self.0).map(|td| td.claims)
 	}
 }
 impl From<String> for JWT {
@@ -130,30 +192,84 @@ impl<'a, 'r> FromRequest<'a, 'r> for JWT {
 	fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
 		let keys: Vec<_> = request.headers().get("authorization").collect();
 		match keys.len() {
-			0 => Outcome::Failure((Status::BadRequest, JWTError::MissingToken)),
+			0 => Outcome::Failure((Status::Unauthorized, JWTError::MissingToken)),
 			1 => {
 				let token = keys[0];
-				match JWT_PROVIDER.decode(token) {
+				match jwt_provider().decode(token) {
 					Ok(_claims) => Outcome::Success(JWT(token.to_string())),
-					Err(err) => Outcome::Failure((Status::BadRequest, JWTError::Invalid(err))),
+					Err(err) => Outcome::Failure((Status::Unauthorized, JWTError::Invalid(err))),
 				}
 			}
 			_ => Outcome::Failure((Status::BadRequest, JWTError::BadCount)),
 		}
 	}
 }
+impl<'a, 'r> FromRequest<'a, 'r> for AuthUser {
+	type Error = JWTError;
+	fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+		let token = match JWT::from_request(request) {
+			Outcome::Success(token) => token,
+			Outcome::Failure(failure) => return Outcome::Failure(failure),
+			Outcome::Forward(forward) => return Outcome::Forward(forward),
+		};
+		match token.decode().ok().and_then(|claims| claims.user()) {
+			Some(user) => Outcome::Success(user),
+			None => Outcome::Failure((Status::Unauthorized, JWTError::MissingUser)),
+		}
+	}
+}
+impl AuthUser {
+	/// The user of a token, `None` if it is invalid or expired
+	pub fn from_token(token: &str) -> Option<Self> {
+		JWT::from(token.to_string())
+			.decode()
+			.ok()
+			.and_then(|claims| claims.user())
+	}
+	/// The token of a tide request, from its authorization header or its session
+	pub fn tide_token<State>(request: &tide::Request<State>) -> Option<String> {
+		request
+			.header("authorization")
+			.map(|values| values.last().as_str().to_string())
+			.or_else(|| {
+				request
+					.ext::<tide::sessions::Session>()
+					.and_then(|session| session.get::<UserSession>("session"))
+					.map(|session| session.token)
+			})
+	}
+	/// The caller of a tide request, from its authorization header or the token in its session
+	pub fn from_tide<State>(request: &tide::Request<State>) -> std::result::Result<Self, tide::Error> {
+		let token = Self::tide_token(request)
+			.ok_or_else(|| tide::Error::from_str(401, "Missing token."))?;
+		Self::from_token(&token)
+			.ok_or_else(|| tide::Error::from_str(401, "Invalid token."))
+	}
+}
 #[cfg(test)]
 pub mod tests {
 	use super::*;
-	lazy_static! {
-		static ref JWT_PROVIDER: JWTProvider = JWTProvider::new();
-		static ref TOKEN_LIFETIME: Duration = Duration::minutes(5);
-	}
+	use crate::auth::keys::SigningKey;
 	#[test]
 	fn encode_decode() {
 		let user = User::new("Slim Shady", "my_name_is");
-		let claims = JWTClaims::from(&user);
+		let id = Id::new();
+		let claims = JWTClaims::new(id, &user).with_roles(vec!["admin".into()]);
 		let token = JWT::encode(&claims).unwrap();
-		assert_eq!(token.decode().unwrap(), claims)
+		assert_eq!(token.decode().unwrap(), claims);
+		let auth = claims.user().unwrap();
+		assert_eq!(auth.id, id);
+		assert!(auth.has_role("admin"));
+
+		// tokens of the previous key stay valid after rotating keys
+		let old = KeyRing::new().with_key(SigningKey::hs256("1", b"old secret"));
+		let new = old.clone().with_key(SigningKey::hs256("2", b"new secret"));
+		let token = JWTProvider::with_keys(old).encode(&claims).unwrap();
+		let provider = JWTProvider::with_keys(new);
+		assert_eq!(provider.decode(&token).unwrap().header.kid, Some("1".to_string()));
+		let token = provider.encode(&claims).unwrap();
+		assert_eq!(provider.decode(&token).unwrap().header.kid, Some("2".to_string()));
+		let removed = JWTProvider::with_keys(KeyRing::new().with_key(SigningKey::hs256("1", b"old secret")));
+		assert!(removed.decode(&token).is_err());
 	}
 }
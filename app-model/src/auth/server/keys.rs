@@ -0,0 +1,214 @@
+//! Keys signing and verifying JWTs
+//!
+//! `KeyRing::load` reads a directory with one file per key, named after its
+//! key id (`kid`) and algorithm:
+//! - `<kid>.hs256`: shared secret
+//! - `<kid>.rs256.pem`, `<kid>.eddsa.pem`: private key, with the public key
+//!   in `<kid>.rs256.pub.pem` or `<kid>.eddsa.pub.pem`
+//!
+//! Keys without a private key only verify tokens. New tokens are signed with
+//! the key chosen by `sign_with`, or else the last signing key in the order
+//! of their ids, so adding a key named by date rotates it in, while tokens
+//! signed with older keys stay valid until their key is removed.
+use argon2::password_hash::rand_core::{
+	OsRng,
+	RngCore,
+};
+use jsonwebtoken::{
+	errors::Result,
+	Algorithm,
+	DecodingKey,
+	EncodingKey,
+};
+use std::{
+	fmt::{
+		self,
+		Debug,
+		Formatter,
+	},
+	path::{
+		Path,
+		PathBuf,
+	},
+};
+use tracing::warn;
+
+/// Directory of the keys if `JWT_KEYS` is not set
+pub const DEFAULT_KEY_DIR: &str = "keys/jwt";
+
+#[derive(Clone)]
+pub struct SigningKey {
+	pub kid: String,
+	pub algorithm: Algorithm,
+	encoding: Option<EncodingKey>,
+	decoding: DecodingKey,
+}
+impl Debug for SigningKey {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		f.debug_struct("SigningKey")
+			.field("kid", &self.kid)
+			.field("algorithm", &self.algorithm)
+			.field("signs", &self.encoding.is_some())
+			.finish()
+	}
+}
+impl SigningKey {
+	pub fn hs256<S: ToString>(kid: S, secret: &[u8]) -> Self {
+		Self {
+			kid: kid.to_string(),
+			algorithm: Algorithm::HS256,
+			encoding: Some(EncodingKey::from_secret(secret)),
+			decoding: DecodingKey::from_secret(secret),
+		}
+	}
+	/// RSA key from PEM files, verifying only without the private key
+	pub fn rs256<S: ToString>(kid: S, private_pem: Option<&[u8]>, public_pem: &[u8]) -> Result<Self> {
+		Ok(Self {
+			kid: kid.to_string(),
+			algorithm: Algorithm::RS256,
+			encoding: private_pem.map(EncodingKey::from_rsa_pem).transpose()?,
+			decoding: DecodingKey::from_rsa_pem(public_pem)?,
+		})
+	}
+	/// Ed25519 key from PEM files, verifying only without the private key
+	pub fn eddsa<S: ToString>(kid: S, private_pem: Option<&[u8]>, public_pem: &[u8]) -> Result<Self> {
+		Ok(Self {
+			kid: kid.to_string(),
+			algorithm: Algorithm::EdDSA,
+			encoding: private_pem.map(EncodingKey::from_ed_pem).transpose()?,
+			decoding: DecodingKey::from_ed_pem(public_pem)?,
+		})
+	}
+	/// HS256 key with a random secret, tokens become invalid when it is dropped
+	pub fn random() -> Self {
+		let mut secret = [0u8; 32];
+		OsRng.fill_bytes(&mut secret);
+		Self::hs256("random", &secret)
+	}
+	pub fn signs(&self) -> bool {
+		self.encoding.is_some()
+	}
+	pub fn encoding(&self) -> Option<&EncodingKey> {
+		self.encoding.as_ref()
+	}
+	pub fn decoding(&self) -> &DecodingKey {
+		&self.decoding
+	}
+}
+#[derive(Clone, Debug, Default)]
+pub struct KeyRing {
+	keys: Vec<SigningKey>,
+	current: Option<String>,
+}
+impl KeyRing {
+	pub fn new() -> Self {
+		Self::default()
+	}
+	/// Add a key, replacing a key with the same id
+	pub fn with_key(mut self, key: SigningKey) -> Self {
+		self.keys.retain(|other| other.kid != key.kid);
+		self.keys.push(key);
+		self.keys.sort_by(|a, b| a.kid.cmp(&b.kid));
+		self
+	}
+	/// Sign new tokens with the key `kid`
+	pub fn sign_with<S: ToString>(mut self, kid: S) -> Self {
+		self.current = Some(kid.to_string());
+		self
+	}
+	/// Keys in the files of `dir`
+	pub fn load<P: AsRef<Path>>(dir: P) -> std::io::Result<Self> {
+		let dir = dir.as_ref();
+		let mut ring = Self::new();
+		for file in std::fs::read_dir(dir)? {
+			let name = file?.file_name().to_string_lossy().to_string();
+			let invalid = |e: jsonwebtoken::errors::Error| {
+				std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{}: {}", name, e))
+			};
+			let key = if let Some(kid) = name.strip_suffix(".hs256") {
+				let secret = std::fs::read_to_string(dir.join(&name))?;
+				SigningKey::hs256(kid, secret.trim_end().as_bytes())
+			} else if let Some((kid, algorithm)) = public_key_name(&name) {
+				let public = std::fs::read(dir.join(&name))?;
+				let private = std::fs::read(dir.join(format!("{}.{}.pem", kid, algorithm))).ok();
+				match algorithm {
+					"rs256" => SigningKey::rs256(kid, private.as_deref(), &public),
+					_ => SigningKey::eddsa(kid, private.as_deref(), &public),
+				}
+				.map_err(invalid)?
+			} else {
+				continue;
+			};
+			ring = ring.with_key(key);
+		}
+		Ok(ring)
+	}
+	/// Keys in the directory `JWT_KEYS` or `keys/jwt`, and an HS256 key from
+	/// `JWT_SECRET`. `JWT_KID` selects the signing key. Without any signing
+	/// key, tokens are signed with a random key valid until the server stops.
+	pub fn from_env() -> Self {
+		Self::from_env_in(DEFAULT_KEY_DIR)
+	}
+	/// Like `from_env`, with the key directory `dir` if `JWT_KEYS` is not set
+	pub fn from_env_in<P: AsRef<Path>>(dir: P) -> Self {
+		let dir = std::env::var_os("JWT_KEYS")
+			.map(PathBuf::from)
+			.unwrap_or_else(|| dir.as_ref().to_path_buf());
+		let mut ring = Self::load(&dir).unwrap_or_else(|e| {
+			warn!("No JWT keys loaded from {}: {}", dir.display(), e);
+			Self::new()
+		});
+		if let Ok(secret) = std::env::var("JWT_SECRET") {
+			ring = ring.with_key(SigningKey::hs256("env", secret.as_bytes()));
+		}
+		if let Ok(kid) = std::env::var("JWT_KID") {
+			ring = ring.sign_with(kid);
+		}
+		if ring.signing_key().is_none() {
+			warn!("No JWT signing key configured, tokens are invalid after restarting");
+			ring = ring.with_key(SigningKey::random()).sign_with("random");
+		}
+		ring
+	}
+	/// The key signing new tokens
+	pub fn signing_key(&self) -> Option<&SigningKey> {
+		match &self.current {
+			Some(kid) => self.get(kid).filter(|key| key.signs()),
+			None => self.keys.iter().rev().find(|key| key.signs()),
+		}
+	}
+	pub fn get(&self, kid: &str) -> Option<&SigningKey> {
+		self.keys.iter().find(|key| key.kid == kid)
+	}
+}
+/// `(kid, algorithm)` of `<kid>.rs256.pub.pem` and `<kid>.eddsa.pub.pem`
+fn public_key_name(name: &str) -> Option<(&str, &str)> {
+	let stem = name.strip_suffix(".pub.pem")?;
+	["rs256", "eddsa"]
+		.iter()
+		.find_map(|algorithm| {
+			stem.strip_suffix(algorithm)
+				.and_then(|kid| kid.strip_suffix('.'))
+				.map(|kid| (kid, *algorithm))
+		})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	#[test]
+	fn load_keys() {
+		let dir = std::env::temp_dir().join(format!("jwt_keys_{}", std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		std::fs::write(dir.join("2021-01.hs256"), "old secret\n").unwrap();
+		std::fs::write(dir.join("2021-02.hs256"), "new secret\n").unwrap();
+		std::fs::write(dir.join("README"), "not a key").unwrap();
+		let ring = KeyRing::load(&dir).unwrap();
+		std::fs::remove_dir_all(&dir).unwrap();
+		assert_eq!(ring.signing_key().unwrap().kid, "2021-02");
+		assert!(ring.get("2021-01").is_some());
+		assert_eq!(ring.sign_with("2021-01").signing_key().unwrap().kid, "2021-01");
+		assert_eq!(public_key_name("a.b.eddsa.pub.pem"), Some(("a.b", "eddsa")));
+		assert_eq!(public_key_name("a.rs256.pem"), None);
+	}
+}
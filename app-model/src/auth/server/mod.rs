@@ -1,59 +1,229 @@
 pub mod jwt;
+pub mod keys;
+pub mod password;
+pub mod privilege;
+pub mod refresh;
+pub mod throttle;
+pub mod totp;
+pub mod second_factor;
 use crate::{
+	auth::{
+		Group,
+		LoginAttempt,
+		LoginResponse,
+		Role,
+	},
 	user::*,
 };
+use argon2::password_hash::rand_core::{
+	OsRng,
+	RngCore,
+};
+use database_table::Id;
+use lazy_static::lazy_static;
+use std::net::IpAddr;
+use throttle::{
+	login_throttle,
+	ThrottleKey,
+};
 
 pub use {
 	crate::auth::{
+		RefreshRequest,
 		UserSession,
 		credentials::*,
 	},
 	database_table::{
 		Database,
 		DatabaseTable,
+		Entry,
 	},
 	jwt::*,
+	privilege::*,
+	refresh::RefreshToken,
+	second_factor::*,
 	std::convert::TryFrom,
 	tide::Error,
 };
-pub async fn login<'db, D: Database<'db, User>>(
-	credentials: Credentials,
-) -> Result<UserSession, Error> {
-	DatabaseTable::<'db, D>::find(|user| *user.name() == credentials.username)
-		.ok_or(Error::from_str(404, "User not found."))
-		.and_then(|entry| {
-			let user = entry.data();
-			if *user.password() == credentials.password {
-				Ok(entry)
-			} else {
-				Err(Error::from_str(401, "Unauthorized."))
+/// Random bytes from the random number generator of the operating system
+pub(crate) fn random_bytes(len: usize) -> Vec<u8> {
+	let mut bytes = vec![0u8; len];
+	OsRng.fill_bytes(&mut bytes);
+	bytes
+}
+/// Lowercase hex string of `bytes`
+pub fn to_hex(bytes: &[u8]) -> String {
+	bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+/// Random hex string of `len` bytes
+pub fn random_hex(len: usize) -> String {
+	to_hex(&random_bytes(len))
+}
+/// Verify the password of a user, replacing a legacy plain text password with its hash
+pub fn verify_password<'db, D: Database<'db, User>>(entry: &Entry<User>, password: &str) -> bool {
+	let verified = password::verify(password, entry.data().password());
+	if verified == password::Verified::Legacy {
+		match password::hash(password) {
+			Ok(hash) => {
+				let credentials = Credentials::new(entry.data().name(), hash);
+				DatabaseTable::<'db, D>::update(entry.id, UserUpdate::default().credentials(credentials));
+			},
+			Err(e) => tracing::error!("{}", e),
+		}
+	}
+	verified.is_valid()
+}
+/// Replace the plain text password of a new user with its hash
+pub fn hash_password(mut user: User) -> Result<User, password::PasswordError> {
+	let hash = password::hash(user.password())?;
+	user.credentials_mut().password = hash;
+	Ok(user)
+}
+/// Error of every failed login, so responses do not tell which usernames exist
+pub const LOGIN_FAILED: &str = "Invalid username or password.";
+lazy_static! {
+	/// verified against when the user does not exist, so that takes as long as a wrong password
+	static ref DUMMY_HASH: String = password::hash("").expect("Failed to hash dummy password");
+}
+/// Reserve an attempt for `keys`, refusing it while any of them has to wait after failed logins
+///
+/// The attempt has to be released or failed afterwards.
+fn reserve_attempt(keys: &[ThrottleKey]) -> Result<(), Error> {
+	login_throttle().reserve(keys).map_err(|wait| Error::from_str(
+		429,
+		format!("Too many failed logins, try again in {} seconds.", wait.num_seconds() + 1),
+	))
+}
+/// Check the password of a login from `ip`, subject to the `throttle`
+///
+/// Failures are recorded in the login attempts of the user, successes only
+/// once the session is issued, which may need a second factor first.
+pub fn authenticate<'db, D>(
+	credentials: &Credentials,
+	ip: Option<IpAddr>,
+) -> Result<Entry<User>, Error>
+	where D: Database<'db, User> + Database<'db, LoginAttempt>,
+{
+	let mut keys = vec![ThrottleKey::Account(credentials.username.clone())];
+	keys.extend(ip.map(ThrottleKey::Ip));
+	reserve_attempt(&keys)?;
+	let entry = <User as DatabaseTable<'db, D>>::find(|user| *user.name() == credentials.username);
+	let verified = match &entry {
+		Some(entry) => verify_password::<D>(entry, &credentials.password),
+		None => {
+			password::verify(&credentials.password, &DUMMY_HASH);
+			false
+		},
+	};
+	match entry {
+		Some(entry) if verified => {
+			login_throttle().release(&keys);
+			Ok(entry)
+		},
+		entry => {
+			tracing::warn!("Failed login of {} from {:?}", credentials.username, ip);
+			if let Some(entry) = entry {
+				<LoginAttempt as DatabaseTable<'db, D>>::insert(LoginAttempt::new(entry.id, ip, false));
 			}
+			let mut throttle = login_throttle();
+			keys.into_iter().for_each(|key| throttle.fail(key));
+			Err(Error::from_str(401, LOGIN_FAILED))
+		},
+	}
+}
+/// Record the successful login of an authenticated user and issue their session
+fn complete_login<'db, D>(entry: &Entry<User>, ip: Option<IpAddr>) -> Result<UserSession, Error>
+	where D: Database<'db, User> + Database<'db, Group> + Database<'db, Role> + Database<'db, LoginAttempt> + Database<'db, RefreshToken>,
+{
+	<LoginAttempt as DatabaseTable<'db, D>>::insert(LoginAttempt::new(entry.id, ip, true));
+	login_throttle().succeed(&ThrottleKey::Account(entry.data().name().clone()));
+	UserSession::issue::<D>(entry, role_names::<D>(entry.id))
+		.map_err(|e| Error::from_str(500, e.to_string()))
+}
+/// Check the password and issue a session, or a challenge for the second
+/// factor if the user enabled it
+pub fn begin_login<'db, D>(
+	credentials: &Credentials,
+	ip: Option<IpAddr>,
+) -> Result<LoginResponse, Error>
+	where D: Database<'db, User> + Database<'db, Group> + Database<'db, Role> + Database<'db, LoginAttempt> + Database<'db, RefreshToken>,
+{
+	let entry = authenticate::<D>(credentials, ip)?;
+	if entry.data().two_factor_enabled() {
+		Ok(LoginResponse::SecondFactorRequired {
+			challenge: challenges().issue(entry.id),
 		})
-		.and_then(|entry| {
-			let user = entry.data().clone();
-			let id = entry.id().clone();
-			JWT::try_from(&user)
-				.map_err(|e| Error::from_str(500, e.to_string()))
-				.map(move |jwt| (id, jwt))
-		})
-		.map(|(id, jwt)| {
-			UserSession {
-				user_id: id.clone(),
-				token: jwt.to_string(),
-			}
+	} else {
+		complete_login::<D>(&entry, ip).map(LoginResponse::Session)
+	}
+}
+pub async fn login<'db, D>(
+	credentials: Credentials,
+	ip: Option<IpAddr>,
+) -> Result<LoginResponse, Error>
+	where D: Database<'db, User> + Database<'db, Group> + Database<'db, Role> + Database<'db, LoginAttempt> + Database<'db, RefreshToken>,
+{
+	begin_login::<D>(&credentials, ip)
+}
+/// Login attempts into the account of `user`, latest first
+pub fn login_attempts<'db, D>(user: Id<User>) -> Vec<LoginAttempt>
+	where D: Database<'db, LoginAttempt>,
+{
+	let mut attempts: Vec<LoginAttempt> = <LoginAttempt as DatabaseTable<'db, D>>::filter(|attempt| attempt.user == user)
+		.into_iter()
+		.map(|entry| entry.into_inner())
+		.collect();
+	attempts.sort_by(|a, b| b.time.cmp(&a.time));
+	attempts
+}
+/// Exchange a refresh token for a new session of its user
+pub fn refresh<'db, D>(request: RefreshRequest) -> Result<UserSession, Error>
+	where D: Database<'db, User> + Database<'db, Group> + Database<'db, Role> + Database<'db, RefreshToken>,
+{
+	let id = refresh::take::<D>(&request.refresh_token)
+		.ok_or(Error::from_str(401, "Invalid refresh token."))?;
+	let entry = <User as DatabaseTable<'db, D>>::get(id)
+		.ok_or(Error::from_str(401, "User not found."))?;
+	UserSession::issue::<D>(&entry, role_names::<D>(entry.id))
+		.map_err(|e| Error::from_str(500, e.to_string()))
+}
+/// Revoke the refresh token of a session
+pub fn logout<'db, D>(request: RefreshRequest)
+	where D: Database<'db, RefreshToken>,
+{
+	refresh::revoke::<D>(&request.refresh_token);
+}
+impl UserSession {
+	/// New access and refresh token of a user with the names of their roles
+	pub fn issue<'db, D>(entry: &Entry<User>, roles: Vec<String>) -> jsonwebtoken::errors::Result<Self>
+		where D: Database<'db, RefreshToken>,
+	{
+		let claims = JWTClaims::from(entry).with_roles(roles);
+		let token = JWT::encode(&claims)?;
+		Ok(Self {
+			user_id: entry.id,
+			token: token.to_string(),
+			expires: claims.expires(),
+			refresh_token: refresh::issue::<D>(entry.id),
 		})
+	}
 }
-pub async fn register<'db, D: Database<'db, User>>(user: User) -> Result<UserSession, Error> {
-	if DatabaseTable::<'db, D>::find(|u| u.name() == user.name()).is_none() {
-		let id = DatabaseTable::<'db, D>::insert(user.clone());
-		JWT::try_from(&user)
+/// Create a user with `credentials` and issue their session
+///
+/// Only the credentials are taken from the client, other fields of the new
+/// user, e.g. the second factor, start empty. New users join the group
+/// `MEMBERS` if the server created it with `grant_role`.
+pub async fn register<'db, D>(credentials: Credentials) -> Result<UserSession, Error>
+	where D: Database<'db, User> + Database<'db, Group> + Database<'db, Role> + Database<'db, RefreshToken>,
+{
+	let user = User::from(credentials);
+	if <User as DatabaseTable<'db, D>>::find(|u| u.name() == user.name()).is_none() {
+		let user = hash_password(user)
+			.map_err(|e| Error::from_str(500, e.to_string()))?;
+		let id = <User as DatabaseTable<'db, D>>::insert(user.clone());
+		join_group::<D>(id, MEMBERS);
+		UserSession::issue::<D>(&Entry::new(id, user), role_names::<D>(id))
 			.map_err(|e| Error::from_str(500, e.to_string()))
-			.map(move |jwt| {
-				UserSession {
-					user_id: id.clone(),
-					token: jwt.to_string(),
-				}
-			})
 	} else {
 		Err(Error::from_str(409, "User already exists."))
 	}
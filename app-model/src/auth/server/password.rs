@@ -0,0 +1,98 @@
+//! Password hashes stored in `Credentials::password`
+//!
+//! Passwords are hashed with Argon2id and a random salt per user and stored
+//! as PHC strings (`$argon2id$v=19$...`). Users registered before hashing
+//! still have their password in plain text, which `verify` accepts and
+//! reports as `Verified::Legacy`, so it can be replaced by a hash.
+use argon2::{
+	password_hash::{
+		rand_core::OsRng,
+		PasswordHash,
+		PasswordHasher,
+		PasswordVerifier,
+		SaltString,
+	},
+	Argon2,
+};
+use std::fmt::{
+	self,
+	Display,
+	Formatter,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PasswordError(String);
+impl Display for PasswordError {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		write!(f, "Failed to hash password: {}", self.0)
+	}
+}
+impl std::error::Error for PasswordError {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verified {
+	/// the password matches the stored hash
+	Hash,
+	/// the password matches a stored plain text password, which should be hashed
+	Legacy,
+	Invalid,
+}
+impl Verified {
+	pub fn is_valid(&self) -> bool {
+		!matches!(self, Verified::Invalid)
+	}
+}
+/// Hash `password` with Argon2id and a new salt
+pub fn hash(password: &str) -> Result<String, PasswordError> {
+	let salt = SaltString::generate(&mut OsRng);
+	Argon2::default()
+		.hash_password(password.as_bytes(), &salt)
+		.map(|hash| hash.to_string())
+		.map_err(|e| PasswordError(e.to_string()))
+}
+/// Whether `stored` is a hash, not a legacy plain text password
+pub fn is_hash(stored: &str) -> bool {
+	PasswordHash::new(stored).is_ok()
+}
+/// Verify `password` against a stored hash or legacy plain text password
+pub fn verify(password: &str, stored: &str) -> Verified {
+	match PasswordHash::new(stored) {
+		Ok(hash) => if Argon2::default().verify_password(password.as_bytes(), &hash).is_ok() {
+			Verified::Hash
+		} else {
+			Verified::Invalid
+		},
+		Err(_) => if constant_time_eq(password.as_bytes(), stored.as_bytes()) {
+			Verified::Legacy
+		} else {
+			Verified::Invalid
+		},
+	}
+}
+/// Compare without returning early at the first differing byte
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+	if a.len() != b.len() {
+		return false;
+	}
+	a.iter()
+		.zip(b)
+		.fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	#[test]
+	fn hash_verify() {
+		let stored = hash("my_name_is").unwrap();
+		assert!(stored.starts_with("$argon2id$"));
+		assert!(is_hash(&stored));
+		// salted per hash
+		assert_ne!(stored, hash("my_name_is").unwrap());
+		assert_eq!(verify("my_name_is", &stored), Verified::Hash);
+		assert_eq!(verify("my_name_was", &stored), Verified::Invalid);
+		assert!(!is_hash("my_name_is"));
+		assert_eq!(verify("my_name_is", "my_name_is"), Verified::Legacy);
+		assert_eq!(verify("my_name_i", "my_name_is"), Verified::Invalid);
+	}
+}
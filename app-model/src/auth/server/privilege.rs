@@ -0,0 +1,283 @@
+//! Privileges of users by the roles of their groups
+use crate::{
+	auth::{
+		AuthUser,
+		Group,
+		GroupUpdate,
+		Owned,
+		Privilege,
+		Resource,
+		Role,
+		TableAction,
+	},
+	user::User,
+};
+use database_table::{
+	Database,
+	DatabaseTable,
+	Entry,
+	Id,
+};
+
+/// Roles of the groups `user` is a member of
+pub fn user_roles<'db, D>(user: Id<User>) -> Vec<Entry<Role>>
+	where D: Database<'db, Group> + Database<'db, Role>,
+{
+	let mut ids: Vec<Id<Role>> = Vec::new();
+	for group in <Group as DatabaseTable<'db, D>>::filter(|group| group.is_member(user)) {
+		for role in group.data().roles() {
+			if !ids.contains(role) {
+				ids.push(*role);
+			}
+		}
+	}
+	<Role as DatabaseTable<'db, D>>::get_list(ids)
+}
+/// Names of the roles of `user`, as claimed by their access tokens
+pub fn role_names<'db, D>(user: Id<User>) -> Vec<String>
+	where D: Database<'db, Group> + Database<'db, Role>,
+{
+	user_roles::<D>(user)
+		.into_iter()
+		.map(|role| role.data().name().clone())
+		.collect()
+}
+/// Whether a role of the groups of `user` allows `action` on `resource`
+pub fn has_privilege<'db, D>(user: &AuthUser, action: TableAction, resource: &Resource) -> bool
+	where D: Database<'db, Group> + Database<'db, Role>,
+{
+	user_roles::<D>(user.id)
+		.iter()
+		.any(|role| role.data().allows(action, resource))
+}
+/// Whether `user` may take `action` on the row `id`, `None` if it does not exist
+pub fn has_row_privilege<'db, D, T>(user: &AuthUser, action: TableAction, id: Id<T>) -> Option<bool>
+	where D: Database<'db, Group> + Database<'db, Role> + Database<'db, T>,
+		  T: DatabaseTable<'db, D> + Owned,
+{
+	let entry = <T as DatabaseTable<'db, D>>::get(id)?;
+	let owned = entry.data().is_owner(entry.id, user.id);
+	Some(has_privilege::<D>(user, action, &Resource::row::<T>(owned)))
+}
+/// Group new users join when they register
+pub const MEMBERS: &str = "members";
+/// Give the role `role` to the group `group`, creating both if they do not exist
+///
+/// A role with the same name is kept as it is, as its privileges may have been changed since.
+pub fn grant_role<'db, D>(group: &str, role: Role) -> Id<Group>
+	where D: Database<'db, Group> + Database<'db, Role>,
+{
+	let role = match <Role as DatabaseTable<'db, D>>::find(|stored| stored.name() == role.name()) {
+		Some(stored) => stored.id,
+		None => <Role as DatabaseTable<'db, D>>::insert(role),
+	};
+	match <Group as DatabaseTable<'db, D>>::find(|stored| stored.name() == group) {
+		Some(entry) => {
+			if !entry.data().roles().contains(&role) {
+				let group = entry.data().clone().with_role(role);
+				<Group as DatabaseTable<'db, D>>::update(entry.id, GroupUpdate::default().roles(group.roles().clone()));
+			}
+			entry.id
+		}
+		None => <Group as DatabaseTable<'db, D>>::insert(Group::new(group).with_role(role)),
+	}
+}
+/// Add `user` to the group `group`, returning false if it does not exist
+pub fn join_group<'db, D>(user: Id<User>, group: &str) -> bool
+	where D: Database<'db, Group>,
+{
+	match <Group as DatabaseTable<'db, D>>::find(|stored| stored.name() == group) {
+		Some(entry) => {
+			let mut group = entry.data().clone();
+			group.add_member(user);
+			<Group as DatabaseTable<'db, D>>::update(entry.id, GroupUpdate::default().members(group.members().clone()));
+			true
+		}
+		None => false,
+	}
+}
+/// Add `user` to the group `admins` with the role `admin`, which allows every
+/// action on all tables, creating both if they do not exist
+pub fn make_admin<'db, D>(user: Id<User>)
+	where D: Database<'db, Group> + Database<'db, Role>,
+{
+	let role = [TableAction::Read, TableAction::Create, TableAction::Update, TableAction::Delete]
+		.iter()
+		.fold(Role::new("admin"), |role, action| role.with_privilege(Privilege::any_table(*action)));
+	grant_role::<D>("admins", role);
+	join_group::<D>(user, "admins");
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::project::Project;
+	use database_table::{
+		backend::sqlite::{
+			SqliteSchema,
+			SqliteTable,
+		},
+		serde_json::{
+			json,
+			Value,
+		},
+		SocketCall,
+		SocketReply,
+	};
+	use lazy_static::lazy_static;
+	struct Schema;
+	lazy_static! {
+		static ref DB: SqliteSchema = SqliteSchema::open_in_memory().unwrap();
+	}
+	impl<'db> Database<'db, Group> for Schema {
+		type Table = SqliteTable<'db, Group>;
+		type TableMut = SqliteTable<'db, Group>;
+		fn table() -> Self::Table {
+			DB.table("group")
+		}
+		fn table_mut() -> Self::TableMut {
+			DB.table("group")
+		}
+	}
+	impl<'db> Database<'db, Role> for Schema {
+		type Table = SqliteTable<'db, Role>;
+		type TableMut = SqliteTable<'db, Role>;
+		fn table() -> Self::Table {
+			DB.table("role")
+		}
+		fn table_mut() -> Self::TableMut {
+			DB.table("role")
+		}
+	}
+	impl<'db> Database<'db, Project> for Schema {
+		type Table = SqliteTable<'db, Project>;
+		type TableMut = SqliteTable<'db, Project>;
+		fn table() -> Self::Table {
+			DB.table("project")
+		}
+		fn table_mut() -> Self::TableMut {
+			DB.table("project")
+		}
+	}
+	/// Functions with each requirement, called like the socket of a server would
+	mod api {
+		use super::*;
+		use database_table::*;
+		use define_api::api;
+		api! {
+			#[public]
+			fn greet(name: String, #[serde(default)] excited: bool) -> String {
+				format!("Hello, {}{}", name, if excited { "!" } else { "" })
+			}
+			#[role("admin")]
+			fn purge_projects() -> usize {
+				0
+			}
+			#[owner(Project, id)]
+			fn leave_project(id: Id<Project>) -> Id<Project> {
+				id
+			}
+			#[privilege(Project, read)]
+			fn count_projects() -> usize {
+				<Project as DatabaseTable<'_, Schema>>::get_all().len()
+			}
+			#[privilege(Project, update, id)]
+			fn rename_project(id: Id<Project>, name: String) -> bool {
+				<Project as DatabaseTable<'_, Schema>>::get(id)
+					.map(|entry| entry.data().name() == &name)
+					.unwrap_or(false)
+			}
+		}
+	}
+	mod api_v2 {
+		use super::*;
+		use define_api::api;
+		api! {
+			version = 2;
+			#[public]
+			fn ping() -> bool {
+				true
+			}
+		}
+	}
+	fn call(caller: Option<&AuthUser>, function: &str, parameters: Value) -> SocketReply {
+		api::socket::dispatch(caller, SocketCall {
+			id: 1,
+			function: function.to_string(),
+			parameters,
+			token: None,
+		})
+	}
+	#[test]
+	fn api_checks() {
+		let member = AuthUser { id: Id::new(), name: "member".into(), roles: Vec::new() };
+		let stranger = AuthUser { id: Id::new(), name: "stranger".into(), roles: Vec::new() };
+		let admin = AuthUser { id: Id::new(), name: "admin".into(), roles: vec!["admin".into()] };
+		// public functions need no caller, defaulted parameters may be missing
+		let reply = call(None, "greet", json!({ "name": "you" }));
+		assert_eq!((reply.status, reply.body), (200, json!("Hello, you")));
+		assert_eq!(call(None, "greet", json!({ "name": "you", "excited": true })).body, json!("Hello, you!"));
+		assert_eq!(call(None, "greet", json!({})).status, 422);
+		assert_eq!(call(None, "count_projects", json!({})).status, 401);
+		assert_eq!(call(None, "leave_project", json!({ "id": Id::<Project>::new() })).status, 401);
+
+		assert_eq!(call(Some(&member), "purge_projects", json!({})).status, 403);
+		assert_eq!(call(Some(&admin), "purge_projects", json!({})).status, 200);
+
+		let mut project = Project::new("plans".into());
+		project.add_member(member.id);
+		let mine = <Project as DatabaseTable<'_, Schema>>::insert(project);
+		let other = <Project as DatabaseTable<'_, Schema>>::insert(Project::new("other".into()));
+		assert_eq!(call(Some(&member), "leave_project", json!({ "id": mine })).body, json!(mine));
+		assert_eq!(call(Some(&stranger), "leave_project", json!({ "id": mine })).status, 403);
+		assert_eq!(call(Some(&member), "leave_project", json!({ "id": Id::<Project>::new() })).status, 404);
+
+		assert_eq!(call(Some(&member), "count_projects", json!({})).status, 403);
+		assert_eq!(call(Some(&member), "rename_project", json!({ "id": mine, "name": "plans" })).status, 403);
+		let role = Role::new("editor")
+			.with_privilege(Privilege::all::<Project>(TableAction::Read))
+			.with_privilege(Privilege::owned::<Project>(TableAction::Update));
+		grant_role::<Schema>("editors", role);
+		join_group::<Schema>(member.id, "editors");
+		assert_eq!(call(Some(&member), "count_projects", json!({})).body, json!(2));
+		assert_eq!(call(Some(&member), "rename_project", json!({ "id": mine, "name": "plans" })).body, json!(true));
+		assert_eq!(call(Some(&member), "rename_project", json!({ "id": other, "name": "plans" })).status, 403);
+		assert_eq!(call(Some(&member), "rename_project", json!({ "id": Id::<Project>::new(), "name": "plans" })).status, 404);
+		assert_eq!(call(Some(&stranger), "count_projects", json!({})).status, 403);
+		assert_eq!(call(Some(&member), "unknown", json!({})).status, 404);
+	}
+	#[test]
+	fn api_routes() {
+		let document = api::openapi();
+		let greet = &document["paths"]["/api/call/greet"]["post"];
+		let parameters = &greet["requestBody"]["content"]["application/json"]["schema"];
+		assert_eq!(parameters["required"], json!(["name"]));
+		assert!(parameters["properties"]["excited"].is_object());
+		assert_eq!(greet["security"], json!([]));
+		let versioned = api_v2::openapi();
+		assert!(versioned["paths"]["/api/v2/call/ping"]["post"].is_object());
+		assert!(versioned["paths"]["/api/call/ping"].is_null());
+		let reply = api_v2::socket::dispatch(None, SocketCall {
+			id: 2,
+			function: "ping".to_string(),
+			parameters: json!({}),
+			token: None,
+		});
+		assert_eq!((reply.id, reply.status, reply.body), (2, 200, json!(true)));
+	}
+	#[test]
+	fn members() {
+		let user = Id::new();
+		assert!(!join_group::<Schema>(user, MEMBERS));
+		let group = grant_role::<Schema>(MEMBERS, Role::new("member").with_privilege(Privilege::owned::<Group>(TableAction::Read)));
+		// granting again keeps the stored role
+		assert_eq!(grant_role::<Schema>(MEMBERS, Role::new("member")), group);
+		assert!(join_group::<Schema>(user, MEMBERS));
+		assert_eq!(role_names::<Schema>(user), vec!["member".to_string()]);
+		let caller = AuthUser { id: user, name: "member".into(), roles: Vec::new() };
+		assert!(has_privilege::<Schema>(&caller, TableAction::Read, &Resource::row::<Group>(true)));
+		assert!(!has_privilege::<Schema>(&caller, TableAction::Read, &Resource::table::<Group>()));
+		make_admin::<Schema>(user);
+		assert!(has_privilege::<Schema>(&caller, TableAction::Delete, &Resource::table::<Group>()));
+	}
+}
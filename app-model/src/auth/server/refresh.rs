@@ -0,0 +1,151 @@
+//! Refresh tokens, exchanged for new access tokens when these expire
+//!
+//! Refresh tokens are random and only valid while a `RefreshToken` row of
+//! the database stores their hash, so the stored rows can not be used as
+//! tokens. Refreshing takes the token, the new session gets a new one, and
+//! logging out revokes it.
+use super::{
+	password::constant_time_eq,
+	random_hex,
+	to_hex,
+};
+use crate::user::User;
+use chrono::{
+	DateTime,
+	Duration,
+	Utc,
+};
+use database_table::{
+	Database,
+	DatabaseTable,
+	Id,
+};
+use lazy_static::lazy_static;
+use serde::{
+	Deserialize,
+	Serialize,
+};
+use sha2::{
+	Digest,
+	Sha256,
+};
+use std::sync::RwLock;
+
+lazy_static! {
+	static ref LIFETIME: RwLock<Duration> = RwLock::new(Duration::days(30));
+}
+/// Time until refresh tokens expire
+pub fn set_refresh_lifetime(lifetime: Duration) {
+	*LIFETIME.write().expect("Refresh lifetime poisoned") = lifetime;
+}
+/// A refresh token of a user, stored by its hash
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RefreshToken {
+	pub user: Id<User>,
+	pub hash: String,
+	pub expires: DateTime<Utc>,
+}
+fn hash_token(token: &str) -> String {
+	// tokens are random, so they need no salt
+	to_hex(&Sha256::digest(token.as_bytes()))
+}
+fn find<'db, D>(token: &str) -> Option<Id<RefreshToken>>
+	where D: Database<'db, RefreshToken>,
+{
+	let hash = hash_token(token);
+	<RefreshToken as DatabaseTable<'db, D>>::find(|stored| constant_time_eq(stored.hash.as_bytes(), hash.as_bytes()))
+		.map(|entry| entry.id)
+}
+/// New refresh token of `user`
+pub fn issue<'db, D>(user: Id<User>) -> String
+	where D: Database<'db, RefreshToken>,
+{
+	issue_at::<D>(user, Utc::now())
+}
+fn issue_at<'db, D>(user: Id<User>, now: DateTime<Utc>) -> String
+	where D: Database<'db, RefreshToken>,
+{
+	for expired in <RefreshToken as DatabaseTable<'db, D>>::filter(|token| token.expires <= now) {
+		<RefreshToken as DatabaseTable<'db, D>>::delete(expired.id);
+	}
+	let token = random_hex(32);
+	let lifetime = *LIFETIME.read().expect("Refresh lifetime poisoned");
+	<RefreshToken as DatabaseTable<'db, D>>::insert(RefreshToken {
+		user,
+		hash: hash_token(&token),
+		expires: now + lifetime,
+	});
+	token
+}
+/// Remove a refresh token, returning its user if it was valid
+pub fn take<'db, D>(token: &str) -> Option<Id<User>>
+	where D: Database<'db, RefreshToken>,
+{
+	take_at::<D>(token, Utc::now())
+}
+fn take_at<'db, D>(token: &str, now: DateTime<Utc>) -> Option<Id<User>>
+	where D: Database<'db, RefreshToken>,
+{
+	// only one of concurrent requests with the same token deletes its row
+	<RefreshToken as DatabaseTable<'db, D>>::delete(find::<D>(token)?)
+		.filter(|token| token.expires > now)
+		.map(|token| token.user)
+}
+pub fn revoke<'db, D>(token: &str) -> bool
+	where D: Database<'db, RefreshToken>,
+{
+	find::<D>(token)
+		.and_then(|id| <RefreshToken as DatabaseTable<'db, D>>::delete(id))
+		.is_some()
+}
+/// Revoke all refresh tokens of `user`, e.g. after changing the password
+pub fn revoke_user<'db, D>(user: Id<User>)
+	where D: Database<'db, RefreshToken>,
+{
+	for token in <RefreshToken as DatabaseTable<'db, D>>::filter(|token| token.user == user) {
+		<RefreshToken as DatabaseTable<'db, D>>::delete(token.id);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use database_table::backend::sqlite::{
+		SqliteSchema,
+		SqliteTable,
+	};
+	use lazy_static::lazy_static;
+	struct Schema;
+	lazy_static! {
+		static ref DB: SqliteSchema = SqliteSchema::open_in_memory().unwrap();
+	}
+	impl<'db> Database<'db, RefreshToken> for Schema {
+		type Table = SqliteTable<'db, RefreshToken>;
+		type TableMut = SqliteTable<'db, RefreshToken>;
+		fn table() -> Self::Table {
+			DB.table("refresh_token")
+		}
+		fn table_mut() -> Self::TableMut {
+			DB.table("refresh_token")
+		}
+	}
+	#[test]
+	fn take_revoke() {
+		let user = Id::new();
+		let now = Utc::now();
+		let first = issue_at::<Schema>(user, now);
+		// only the hash is stored
+		let stored = <RefreshToken as DatabaseTable<'_, Schema>>::get_all();
+		assert!(stored.iter().all(|entry| entry.data().hash != first));
+		assert_eq!(take_at::<Schema>(&first, now), Some(user));
+		// tokens are used once
+		assert_eq!(take_at::<Schema>(&first, now), None);
+		let second = issue_at::<Schema>(user, now);
+		assert_eq!(take_at::<Schema>(&second, now + Duration::days(31)), None);
+		let third = issue_at::<Schema>(user, now);
+		let other = issue_at::<Schema>(Id::new(), now);
+		revoke_user::<Schema>(user);
+		assert!(!revoke::<Schema>(&third));
+		assert!(revoke::<Schema>(&other));
+	}
+}
@@ -0,0 +1,272 @@
+//! Enrolment of the second factor and the second step of logins
+//!
+//! A correct password of a user with an enabled second factor yields a
+//! challenge, which is valid for a few minutes and exchanged for a session
+//! together with a code. A challenge is used up by the first attempt and
+//! wrong codes count as failed logins. Codes are checked and used up under
+//! one lock, so each code is accepted once.
+use super::{
+	complete_login,
+	random_hex,
+	reserve_attempt,
+	throttle::{
+		login_throttle,
+		ThrottleKey,
+	},
+	totp,
+	Error,
+};
+use crate::{
+	auth::{
+		Enrolment,
+		Group,
+		LoginAttempt,
+		RefreshToken,
+		Role,
+		SecondFactorRequest,
+		TwoFactor,
+		UserSession,
+	},
+	user::*,
+};
+use chrono::{
+	DateTime,
+	Duration,
+	Utc,
+};
+use database_table::{
+	Database,
+	DatabaseTable,
+	Entry,
+	Id,
+};
+use lazy_static::lazy_static;
+use std::{
+	collections::HashMap,
+	net::IpAddr,
+	sync::Mutex,
+};
+
+lazy_static! {
+	static ref CHALLENGES: Mutex<Challenges> = Mutex::new(Challenges::new(Duration::minutes(5)));
+	/// held while a code is checked and used up
+	static ref CODES: Mutex<()> = Mutex::new(());
+}
+pub fn challenges() -> std::sync::MutexGuard<'static, Challenges> {
+	CHALLENGES.lock().expect("Challenges poisoned")
+}
+fn codes() -> std::sync::MutexGuard<'static, ()> {
+	CODES.lock().expect("Codes poisoned")
+}
+#[derive(Clone, Debug)]
+struct Challenge {
+	user: Id<User>,
+	expires: DateTime<Utc>,
+}
+/// Logins waiting for their second factor
+#[derive(Debug)]
+pub struct Challenges {
+	challenges: HashMap<String, Challenge>,
+	lifetime: Duration,
+}
+impl Challenges {
+	pub fn new(lifetime: Duration) -> Self {
+		Self {
+			challenges: HashMap::new(),
+			lifetime,
+		}
+	}
+	/// New challenge of `user`, whose password was correct
+	pub fn issue(&mut self, user: Id<User>) -> String {
+		self.issue_at(user, Utc::now())
+	}
+	fn issue_at(&mut self, user: Id<User>, now: DateTime<Utc>) -> String {
+		self.challenges.retain(|_, challenge| challenge.expires > now);
+		let token = random_hex(32);
+		self.challenges.insert(token.clone(), Challenge {
+			user,
+			expires: now + self.lifetime,
+		});
+		token
+	}
+	/// Remove a challenge, returning its user if it was valid
+	pub fn remove(&mut self, token: &str) -> Option<Id<User>> {
+		self.remove_at(token, Utc::now())
+	}
+	fn remove_at(&mut self, token: &str, now: DateTime<Utc>) -> Option<Id<User>> {
+		self.challenges
+			.remove(token)
+			.filter(|challenge| challenge.expires > now)
+			.map(|challenge| challenge.user)
+	}
+}
+/// The second factor after using `code`, if it is a valid code or recovery code
+pub fn check_code(two_factor: &TwoFactor, code: &str, now: DateTime<Utc>) -> Option<TwoFactor> {
+	let mut used = two_factor.clone();
+	if let Some(step) = totp::verify_at(&two_factor.secret, code, now, two_factor.last_step) {
+		used.last_step = Some(step);
+		Some(used)
+	} else if let Some(index) = totp::find_recovery_code(&two_factor.recovery_codes, code) {
+		used.recovery_codes.remove(index);
+		Some(used)
+	} else {
+		None
+	}
+}
+fn set_two_factor<'db, D>(user: Id<User>, two_factor: Option<TwoFactor>)
+	where D: Database<'db, User>,
+{
+	<User as DatabaseTable<'db, D>>::update(user, UserUpdate::default().two_factor(two_factor));
+}
+fn get_user<'db, D>(user: Id<User>) -> Result<Entry<User>, Error>
+	where D: Database<'db, User>,
+{
+	<User as DatabaseTable<'db, D>>::get(user)
+		.ok_or(Error::from_str(404, "User not found."))
+}
+/// Start enrolling `user` with a new secret and recovery codes, which take
+/// effect once confirmed with `confirm_two_factor`
+pub fn enrol_two_factor<'db, D>(user: Id<User>, issuer: &str) -> Result<Enrolment, Error>
+	where D: Database<'db, User>,
+{
+	let entry = get_user::<D>(user)?;
+	if entry.data().two_factor_enabled() {
+		return Err(Error::from_str(409, "Two-factor authentication is already enabled."));
+	}
+	let secret = totp::generate_secret();
+	let (recovery_codes, hashes) = totp::generate_recovery_codes();
+	set_two_factor::<D>(user, Some(TwoFactor {
+		secret: secret.clone(),
+		recovery_codes: hashes,
+		enabled: false,
+		last_step: None,
+	}));
+	Ok(Enrolment {
+		uri: totp::provisioning_uri(&secret, entry.data().name(), issuer),
+		secret,
+		recovery_codes,
+	})
+}
+/// Enable the second factor of `user` after checking a code of their app
+pub fn confirm_two_factor<'db, D>(user: Id<User>, code: &str) -> Result<(), Error>
+	where D: Database<'db, User>,
+{
+	confirm_two_factor_at::<D>(user, code, Utc::now())
+}
+pub fn confirm_two_factor_at<'db, D>(user: Id<User>, code: &str, now: DateTime<Utc>) -> Result<(), Error>
+	where D: Database<'db, User>,
+{
+	let _codes = codes();
+	let entry = get_user::<D>(user)?;
+	let two_factor = entry.data()
+		.two_factor()
+		.clone()
+		.ok_or(Error::from_str(404, "Two-factor authentication was not enrolled."))?;
+	// only a code proves the app has the secret
+	let step = totp::verify_at(&two_factor.secret, code, now, two_factor.last_step)
+		.ok_or(Error::from_str(400, "Invalid code."))?;
+	set_two_factor::<D>(user, Some(TwoFactor {
+		enabled: true,
+		last_step: Some(step),
+		..two_factor
+	}));
+	Ok(())
+}
+/// Remove the second factor of `user` with a code or recovery code
+pub fn disable_two_factor<'db, D>(user: Id<User>, code: &str) -> Result<(), Error>
+	where D: Database<'db, User>,
+{
+	disable_two_factor_at::<D>(user, code, Utc::now())
+}
+pub fn disable_two_factor_at<'db, D>(user: Id<User>, code: &str, now: DateTime<Utc>) -> Result<(), Error>
+	where D: Database<'db, User>,
+{
+	let _codes = codes();
+	let entry = get_user::<D>(user)?;
+	match entry.data().two_factor() {
+		Some(two_factor) if two_factor.enabled => {
+			check_code(two_factor, code, now).ok_or(Error::from_str(400, "Invalid code."))?;
+		},
+		// enrolments which were not confirmed can be removed without a code
+		Some(_) => {},
+		None => return Err(Error::from_str(404, "Two-factor authentication is not enabled.")),
+	}
+	set_two_factor::<D>(user, None);
+	Ok(())
+}
+/// Exchange a challenge of `login` and a code for a session
+pub fn second_factor<'db, D>(request: SecondFactorRequest, ip: Option<IpAddr>) -> Result<UserSession, Error>
+	where D: Database<'db, User> + Database<'db, Group> + Database<'db, Role> + Database<'db, LoginAttempt> + Database<'db, RefreshToken>,
+{
+	second_factor_at::<D>(request, ip, Utc::now())
+}
+pub fn second_factor_at<'db, D>(request: SecondFactorRequest, ip: Option<IpAddr>, now: DateTime<Utc>) -> Result<UserSession, Error>
+	where D: Database<'db, User> + Database<'db, Group> + Database<'db, Role> + Database<'db, LoginAttempt> + Database<'db, RefreshToken>,
+{
+	let invalid = || Error::from_str(401, "Invalid or expired challenge.");
+	// concurrent requests with the same challenge can not both get its user
+	let user = challenges().remove_at(&request.challenge, now).ok_or_else(invalid)?;
+	let entry = <User as DatabaseTable<'db, D>>::get(user).ok_or_else(invalid)?;
+	let mut keys = vec![ThrottleKey::Account(entry.data().name().clone())];
+	keys.extend(ip.map(ThrottleKey::Ip));
+	reserve_attempt(&keys)?;
+	let codes = codes();
+	// read again under the lock, as the code may have been used in the meantime
+	let entry = <User as DatabaseTable<'db, D>>::get(user).ok_or_else(invalid)?;
+	let used = entry.data()
+		.two_factor()
+		.as_ref()
+		.and_then(|two_factor| check_code(two_factor, &request.code, now));
+	match used {
+		Some(two_factor) => {
+			set_two_factor::<D>(user, Some(two_factor));
+			drop(codes);
+			login_throttle().release(&keys);
+			complete_login::<D>(&entry, ip)
+		},
+		None => {
+			drop(codes);
+			tracing::warn!("Invalid second factor of {} from {:?}", entry.data().name(), ip);
+			<LoginAttempt as DatabaseTable<'db, D>>::insert(LoginAttempt::new(user, ip, false));
+			let mut throttle = login_throttle();
+			keys.into_iter().for_each(|key| throttle.fail(key));
+			Err(Error::from_str(401, "Invalid code."))
+		},
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use chrono::TimeZone;
+	#[test]
+	fn challenges_expire() {
+		let mut challenges = Challenges::new(Duration::minutes(5));
+		let user = Id::new();
+		let now = Utc.timestamp(1_600_000_000, 0);
+		let token = challenges.issue_at(user, now);
+		assert_eq!(challenges.remove_at(&token, now + Duration::minutes(5)), None);
+		let token = challenges.issue_at(user, now);
+		assert_eq!(challenges.remove_at(&token, now + Duration::minutes(4)), Some(user));
+		// used up by the first attempt
+		assert_eq!(challenges.remove_at(&token, now), None);
+	}
+	#[test]
+	fn codes_and_recovery_codes() {
+		let now = Utc.timestamp(1_600_000_000, 0);
+		let (codes, hashes) = totp::generate_recovery_codes();
+		let two_factor = TwoFactor {
+			secret: totp::generate_secret(),
+			recovery_codes: hashes,
+			enabled: true,
+			last_step: None,
+		};
+		let code = totp::code_at(&two_factor.secret, now).unwrap();
+		let used = check_code(&two_factor, &code, now).unwrap();
+		assert_eq!(used.last_step, Some(totp::step(now)));
+		assert_eq!(check_code(&used, &code, now), None);
+		let recovered = check_code(&used, &codes[0], now).unwrap();
+		assert_eq!(recovered.recovery_codes.len(), totp::RECOVERY_CODES - 1);
+		assert_eq!(check_code(&recovered, &codes[0], now), None);
+	}
+}
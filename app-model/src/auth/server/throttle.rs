@@ -0,0 +1,250 @@
+//! Limits on failed logins per account and per client address
+//!
+//! After `free_attempts` failures, every further attempt has to wait twice as
+//! long as the one before, up to `max_delay`. After `lockout_after` failures
+//! logins are refused for `lockout`. Failures of an account are forgotten when
+//! it logs in, failures of an address only when it made no attempt for
+//! `lockout`, so logging into one account does not allow guessing others.
+//!
+//! Attempts are reserved before the password is checked and count like
+//! failures until they are released or failed, so concurrent attempts can not
+//! all pass the check before the first of them failed.
+use chrono::{
+	DateTime,
+	Duration,
+	Utc,
+};
+use lazy_static::lazy_static;
+use std::{
+	collections::HashMap,
+	net::IpAddr,
+	sync::Mutex,
+};
+
+lazy_static! {
+	static ref LOGIN_THROTTLE: Mutex<LoginThrottle> = Mutex::new(LoginThrottle::default());
+}
+pub fn login_throttle() -> std::sync::MutexGuard<'static, LoginThrottle> {
+	LOGIN_THROTTLE.lock().expect("LoginThrottle poisoned")
+}
+#[derive(Clone, Debug)]
+pub struct ThrottleConfig {
+	/// failures before attempts are delayed
+	pub free_attempts: u32,
+	/// delay after the first delayed attempt
+	pub base_delay: Duration,
+	pub max_delay: Duration,
+	/// failures after which logins are refused for `lockout`
+	pub lockout_after: u32,
+	pub lockout: Duration,
+}
+impl ThrottleConfig {
+	pub fn per_account() -> Self {
+		Self {
+			free_attempts: 3,
+			base_delay: Duration::seconds(1),
+			max_delay: Duration::minutes(1),
+			lockout_after: 10,
+			lockout: Duration::minutes(15),
+		}
+	}
+	/// Looser than per account, as many users may share an address
+	pub fn per_ip() -> Self {
+		Self {
+			free_attempts: 10,
+			base_delay: Duration::seconds(1),
+			max_delay: Duration::minutes(1),
+			lockout_after: 50,
+			lockout: Duration::minutes(15),
+		}
+	}
+	/// Time after the last failure until the next attempt is allowed
+	fn delay(&self, failures: u32) -> Duration {
+		if failures >= self.lockout_after {
+			self.lockout
+		} else if failures >= self.free_attempts {
+			let doublings = (failures - self.free_attempts).min(30);
+			(self.base_delay * 2i32.pow(doublings)).min(self.max_delay)
+		} else {
+			Duration::zero()
+		}
+	}
+}
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ThrottleKey {
+	/// the username tried, whether the user exists or not
+	Account(String),
+	Ip(IpAddr),
+}
+#[derive(Clone, Debug)]
+struct Failures {
+	count: u32,
+	/// reserved attempts which did not fail or succeed yet
+	pending: u32,
+	last: DateTime<Utc>,
+}
+#[derive(Debug)]
+pub struct LoginThrottle {
+	accounts: ThrottleConfig,
+	ips: ThrottleConfig,
+	failures: HashMap<ThrottleKey, Failures>,
+}
+impl Default for LoginThrottle {
+	fn default() -> Self {
+		Self::new(ThrottleConfig::per_account(), ThrottleConfig::per_ip())
+	}
+}
+impl LoginThrottle {
+	pub fn new(accounts: ThrottleConfig, ips: ThrottleConfig) -> Self {
+		Self {
+			accounts,
+			ips,
+			failures: HashMap::new(),
+		}
+	}
+	fn config(&self, key: &ThrottleKey) -> &ThrottleConfig {
+		match key {
+			ThrottleKey::Account(_) => &self.accounts,
+			ThrottleKey::Ip(_) => &self.ips,
+		}
+	}
+	/// Time `key` has to wait before trying again, if any
+	pub fn retry_after(&self, key: &ThrottleKey) -> Option<Duration> {
+		self.retry_after_at(key, Utc::now())
+	}
+	pub fn retry_after_at(&self, key: &ThrottleKey, now: DateTime<Utc>) -> Option<Duration> {
+		let failures = self.failures.get(key)?;
+		let allowed = failures.last + self.config(key).delay(failures.count + failures.pending);
+		Some(allowed - now).filter(|wait| *wait > Duration::zero())
+	}
+	/// Forget failures older than the lockout, unless attempts are pending
+	fn forget_at(&mut self, now: DateTime<Utc>) {
+		let (accounts, ips) = (&self.accounts, &self.ips);
+		self.failures.retain(|key, failures| {
+			let forget = match key {
+				ThrottleKey::Account(_) => accounts.lockout,
+				ThrottleKey::Ip(_) => ips.lockout,
+			};
+			failures.pending > 0 || failures.last + forget > now
+		});
+	}
+	fn entry_at(&mut self, key: ThrottleKey, now: DateTime<Utc>) -> &mut Failures {
+		self.forget_at(now);
+		self.failures.entry(key).or_insert(Failures {
+			count: 0,
+			pending: 0,
+			last: now,
+		})
+	}
+	/// Reserve an attempt for all `keys`, or return the longest time one of them has to wait
+	pub fn reserve(&mut self, keys: &[ThrottleKey]) -> Result<(), Duration> {
+		self.reserve_at(keys, Utc::now())
+	}
+	pub fn reserve_at(&mut self, keys: &[ThrottleKey], now: DateTime<Utc>) -> Result<(), Duration> {
+		if let Some(wait) = keys.iter().filter_map(|key| self.retry_after_at(key, now)).max() {
+			return Err(wait);
+		}
+		for key in keys {
+			self.entry_at(key.clone(), now).pending += 1;
+		}
+		Ok(())
+	}
+	/// Take back the reserved attempt of `keys`, when it did not fail
+	pub fn release(&mut self, keys: &[ThrottleKey]) {
+		for key in keys {
+			if let Some(failures) = self.failures.get_mut(key) {
+				failures.pending = failures.pending.saturating_sub(1);
+				if failures.count == 0 && failures.pending == 0 {
+					self.failures.remove(key);
+				}
+			}
+		}
+	}
+	/// Count a failure of `key`, in place of its reserved attempt if it has one
+	pub fn fail(&mut self, key: ThrottleKey) {
+		self.fail_at(key, Utc::now())
+	}
+	pub fn fail_at(&mut self, key: ThrottleKey, now: DateTime<Utc>) {
+		let failures = self.entry_at(key, now);
+		failures.count += 1;
+		failures.pending = failures.pending.saturating_sub(1);
+		failures.last = now;
+	}
+	pub fn succeed(&mut self, key: &ThrottleKey) {
+		if let ThrottleKey::Account(_) = key {
+			self.failures.remove(key);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	#[test]
+	fn backoff_and_lockout() {
+		let mut throttle = LoginThrottle::default();
+		let key = ThrottleKey::Account("alice".to_string());
+		let now = Utc::now();
+		for _ in 0..3 {
+			assert_eq!(throttle.retry_after_at(&key, now), None);
+			throttle.fail_at(key.clone(), now);
+		}
+		assert_eq!(throttle.retry_after_at(&key, now), Some(Duration::seconds(1)));
+		throttle.fail_at(key.clone(), now);
+		assert_eq!(throttle.retry_after_at(&key, now), Some(Duration::seconds(2)));
+		throttle.fail_at(key.clone(), now);
+		assert_eq!(throttle.retry_after_at(&key, now), Some(Duration::seconds(4)));
+		for _ in 5..10 {
+			throttle.fail_at(key.clone(), now);
+		}
+		assert_eq!(throttle.retry_after_at(&key, now), Some(Duration::minutes(15)));
+		assert_eq!(throttle.retry_after_at(&key, now + Duration::minutes(15)), None);
+		// other accounts are not affected
+		assert_eq!(throttle.retry_after_at(&ThrottleKey::Account("bob".to_string()), now), None);
+		throttle.succeed(&key);
+		assert_eq!(throttle.retry_after_at(&key, now), None);
+	}
+	#[test]
+	fn concurrent_attempts() {
+		let mut throttle = LoginThrottle::default();
+		let keys = [
+			ThrottleKey::Account("alice".to_string()),
+			ThrottleKey::Ip([127, 0, 0, 1].into()),
+		];
+		let now = Utc::now();
+		// pending attempts count like failures
+		for _ in 0..3 {
+			assert_eq!(throttle.reserve_at(&keys, now), Ok(()));
+		}
+		assert_eq!(throttle.reserve_at(&keys, now), Err(Duration::seconds(1)));
+		throttle.release(&keys);
+		assert_eq!(throttle.reserve_at(&keys, now), Ok(()));
+		// failing replaces the reservation
+		for key in &keys {
+			throttle.fail_at(key.clone(), now);
+		}
+		assert_eq!(throttle.reserve_at(&keys, now), Err(Duration::seconds(1)));
+		throttle.release(&keys);
+		throttle.release(&keys);
+		assert_eq!(throttle.reserve_at(&keys, now), Ok(()));
+		throttle.release(&keys);
+		throttle.succeed(&keys[0]);
+		assert_eq!(throttle.retry_after_at(&keys[0], now), None);
+	}
+	#[test]
+	fn ip_failures_expire() {
+		let mut throttle = LoginThrottle::default();
+		let key = ThrottleKey::Ip([127, 0, 0, 1].into());
+		let now = Utc::now();
+		for _ in 0..11 {
+			throttle.fail_at(key.clone(), now);
+		}
+		assert_eq!(throttle.retry_after_at(&key, now), Some(Duration::seconds(2)));
+		// logging in does not reset an address
+		throttle.succeed(&key);
+		assert!(throttle.retry_after_at(&key, now).is_some());
+		let later = now + Duration::minutes(15);
+		throttle.fail_at(key.clone(), later);
+		assert_eq!(throttle.retry_after_at(&key, later), None);
+	}
+}
@@ -0,0 +1,166 @@
+//! Time-based one-time passwords (RFC 6238) and recovery codes
+//!
+//! Codes have 6 digits and change every 30 seconds, computed with HMAC-SHA1,
+//! which is what common authenticator apps expect. Recovery codes are random
+//! and stored as SHA-256 hashes, which is enough for their entropy.
+use super::{
+	random_bytes,
+	random_hex,
+};
+use chrono::{
+	DateTime,
+	Utc,
+};
+use hmac::{
+	Hmac,
+	Mac,
+};
+use sha1::Sha1;
+use sha2::{
+	Digest,
+	Sha256,
+};
+
+pub const DIGITS: u32 = 6;
+pub const STEP_SECS: i64 = 30;
+/// steps before and after the current one whose codes are accepted, as clocks drift
+pub const SKEW: i64 = 1;
+/// 160 bits, as recommended by RFC 4226
+const SECRET_BYTES: usize = 20;
+pub const RECOVERY_CODES: usize = 10;
+const BASE32: base32::Alphabet = base32::Alphabet::RFC4648 { padding: false };
+
+/// New base32 encoded secret
+pub fn generate_secret() -> String {
+	base32::encode(BASE32, &random_bytes(SECRET_BYTES))
+}
+/// HOTP value (RFC 4226) of `key` for `counter`
+pub fn hotp(key: &[u8], counter: u64) -> u32 {
+	let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC takes keys of any length");
+	mac.update(&counter.to_be_bytes());
+	let hash = mac.finalize().into_bytes();
+	let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+	let value = u32::from_be_bytes([hash[offset], hash[offset + 1], hash[offset + 2], hash[offset + 3]]);
+	(value & 0x7fff_ffff) % 10u32.pow(DIGITS)
+}
+/// Number of the time step containing `time`
+pub fn step(time: DateTime<Utc>) -> i64 {
+	time.timestamp().div_euclid(STEP_SECS)
+}
+fn format_code(value: u32) -> String {
+	format!("{:0width$}", value, width = DIGITS as usize)
+}
+/// The code of the base32 encoded `secret` at `time`
+pub fn code_at(secret: &str, time: DateTime<Utc>) -> Option<String> {
+	let key = base32::decode(BASE32, secret)?;
+	Some(format_code(hotp(&key, step(time) as u64)))
+}
+/// The time step of `code` around `now`, if it is later than `last_step`
+pub fn verify_at(secret: &str, code: &str, now: DateTime<Utc>, last_step: Option<i64>) -> Option<i64> {
+	let key = base32::decode(BASE32, secret)?;
+	let code = code.trim();
+	if code.len() != DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+		return None;
+	}
+	let current = step(now);
+	(current - SKEW..=current + SKEW)
+		.filter(|step| last_step.map(|last| *step > last).unwrap_or(true))
+		.find(|step| super::password::constant_time_eq(
+			format_code(hotp(&key, *step as u64)).as_bytes(),
+			code.as_bytes(),
+		))
+}
+/// `otpauth://` URI adding `secret` to authenticator apps, usually shown as QR code
+pub fn provisioning_uri(secret: &str, account: &str, issuer: &str) -> String {
+	format!(
+		"otpauth://totp/{issuer}:{account}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={digits}&period={period}",
+		issuer = percent_encode(issuer),
+		account = percent_encode(account),
+		secret = secret,
+		digits = DIGITS,
+		period = STEP_SECS,
+	)
+}
+fn percent_encode(text: &str) -> String {
+	text.bytes()
+		.map(|b| match b {
+			b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+			_ => format!("%{:02X}", b),
+		})
+		.collect()
+}
+/// New recovery codes as shown to the user, and their hashes to store
+pub fn generate_recovery_codes() -> (Vec<String>, Vec<String>) {
+	(0..RECOVERY_CODES)
+		.map(|_| {
+			let code = random_hex(5);
+			let code = format!("{}-{}", &code[..5], &code[5..]);
+			let hash = hash_recovery_code(&code);
+			(code, hash)
+		})
+		.unzip()
+}
+/// Hash of a recovery code, ignoring case, spaces and dashes
+pub fn hash_recovery_code(code: &str) -> String {
+	let code: String = code.chars()
+		.filter(char::is_ascii_alphanumeric)
+		.map(|c| c.to_ascii_lowercase())
+		.collect();
+	super::to_hex(&Sha256::digest(code.as_bytes()))
+}
+/// Position of the hash of the recovery `code` in `hashes`
+pub fn find_recovery_code(hashes: &[String], code: &str) -> Option<usize> {
+	let hash = hash_recovery_code(code);
+	hashes.iter().position(|stored| super::password::constant_time_eq(stored.as_bytes(), hash.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use chrono::TimeZone;
+	/// base32 of the SHA1 key of the test vectors in RFC 6238
+	const RFC_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+	#[test]
+	fn rfc_6238_vectors() {
+		// the last 6 of the 8 digits in the RFC
+		for (time, code) in [
+			(59, "287082"),
+			(1111111109, "081804"),
+			(1111111111, "050471"),
+			(1234567890, "005924"),
+			(2000000000, "279037"),
+			(20000000000, "353130"),
+		] {
+			assert_eq!(code_at(RFC_SECRET, Utc.timestamp(time, 0)).unwrap(), code);
+		}
+	}
+	#[test]
+	fn verify_window() {
+		let now = Utc.timestamp(1111111111, 0);
+		let previous = code_at(RFC_SECRET, now - chrono::Duration::seconds(30)).unwrap();
+		let step = verify_at(RFC_SECRET, &previous, now, None).unwrap();
+		assert_eq!(step, super::step(now) - 1);
+		// codes are used once
+		assert_eq!(verify_at(RFC_SECRET, &previous, now, Some(step)), None);
+		let old = code_at(RFC_SECRET, now - chrono::Duration::seconds(90)).unwrap();
+		assert_eq!(verify_at(RFC_SECRET, &old, now, None), None);
+		assert_eq!(verify_at(RFC_SECRET, "12345", now, None), None);
+	}
+	#[test]
+	fn provisioning() {
+		let secret = generate_secret();
+		assert_eq!(secret.len(), 32);
+		assert_eq!(
+			provisioning_uri(&secret, "alice bob", "Binance Bot"),
+			format!("otpauth://totp/Binance%20Bot:alice%20bob?secret={}&issuer=Binance%20Bot&algorithm=SHA1&digits=6&period=30", secret),
+		);
+	}
+	#[test]
+	fn recovery_codes() {
+		let (codes, hashes) = generate_recovery_codes();
+		assert_eq!(codes.len(), RECOVERY_CODES);
+		assert_eq!(find_recovery_code(&hashes, &codes[3]), Some(3));
+		assert_eq!(find_recovery_code(&hashes, &codes[3].to_uppercase().replace('-', " ")), Some(3));
+		assert_eq!(find_recovery_code(&hashes, "00000-00000"), None);
+	}
+}
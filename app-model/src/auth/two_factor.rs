@@ -0,0 +1,55 @@
+//! Second factor of logins with time-based one-time passwords (RFC 6238)
+//!
+//! Users enrol by adding the secret to an authenticator app, usually by
+//! scanning the provisioning URI as a QR code, and confirm it with a code.
+//! From then on `login` answers correct passwords with a challenge, which is
+//! exchanged for a session together with a code or a recovery code.
+use crate::auth::UserSession;
+use database_table::JsonSchema;
+use serde::{
+	Deserialize,
+	Serialize,
+};
+
+/// The TOTP secret and recovery codes of a user
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct TwoFactor {
+	/// base32 encoded secret shared with the authenticator app
+	pub secret: String,
+	/// SHA-256 hashes of the unused recovery codes
+	pub recovery_codes: Vec<String>,
+	/// whether the secret was confirmed with a code, before which logins do not need one
+	pub enabled: bool,
+	/// time step of the last accepted code, so codes can not be used twice
+	#[serde(default)]
+	pub last_step: Option<i64>,
+}
+/// Response of `/api/auth/login`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum LoginResponse {
+	Session(UserSession),
+	/// the password was correct, the session is issued for a code at `/api/auth/second_factor`
+	SecondFactorRequired {
+		challenge: String,
+	},
+}
+/// Body of `/api/auth/second_factor`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SecondFactorRequest {
+	pub challenge: String,
+	/// code of the authenticator app or a recovery code
+	pub code: String,
+}
+/// Body of `/api/auth/two_factor/confirm` and `/api/auth/two_factor/disable`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CodeRequest {
+	pub code: String,
+}
+/// Response of `/api/auth/two_factor/enrol`, shown to the user once
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Enrolment {
+	pub secret: String,
+	/// `otpauth://` URI to show as QR code
+	pub uri: String,
+	pub recovery_codes: Vec<String>,
+}
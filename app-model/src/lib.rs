@@ -1,3 +1,5 @@
+// `define_api::api!` refers to this crate as `app_model`, also in its tests
+extern crate self as app_model;
 pub mod auth;
 pub use auth::*;
 pub mod market;
@@ -5,7 +5,7 @@ use components::{
 	Edit,
 	Editor,
 };
-use rql::Id;
+use database_table::Id;
 
 #[derive(Debug, Clone, Default)]
 pub struct Model {
@@ -45,7 +45,7 @@ impl Component for Model {
 									new.data.add_member(id);
 								}
 							}
-							Editor::Remote(_remote) => {}
+							Editor::Remote(..) => {}
 						};
 					}
 					_ => {}
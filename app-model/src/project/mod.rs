@@ -4,11 +4,13 @@ use crate::{
 };
 use database_table::{
     Entry,
+    Id,
+    JsonSchema,
     TableRoutable,
+    Updatable,
 };
 use derive_builder::Builder;
 use enum_paths::AsPath;
-use rql::*;
 use serde::{
     Deserialize,
     Serialize,
@@ -35,7 +37,7 @@ pub mod list;
 #[cfg(target_arch = "wasm32")]
 pub mod profile;
 
-#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, Builder)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, Builder, Updatable, JsonSchema)]
 pub struct Project {
     name: String,
     description: String,
@@ -58,6 +60,11 @@ impl TableRoutable for Project {
         Route::Project(id)
     }
 }
+impl crate::auth::Owned for Project {
+    fn is_owner(&self, _id: Id<Self>, user: Id<User>) -> bool {
+        self.members.contains(&user)
+    }
+}
 
 impl Project {
     pub fn new(name: String) -> Self {
@@ -86,12 +93,18 @@ impl Project {
     pub fn add_member(&mut self, id: Id<User>) {
         self.members.push(id);
     }
+    pub fn remove_member(&mut self, id: Id<User>) {
+        self.members.retain(|member| *member != id);
+    }
     pub fn tasks(&self) -> &Vec<Id<Task>> {
         &self.tasks
     }
     pub fn add_task(&mut self, id: Id<Task>) {
         self.tasks.push(id);
     }
+    pub fn remove_task(&mut self, id: Id<Task>) {
+        self.tasks.retain(|task| *task != id);
+    }
 }
 impl From<Entry<Project>> for Project {
     fn from(entry: Entry<Self>) -> Self {
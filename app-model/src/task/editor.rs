@@ -59,7 +59,7 @@ impl Component for Model {
 									//);
 								}
 							}
-							Editor::Remote(_remote) => {
+							Editor::Remote(..) => {
 								Component::update(
 									&mut self.editor,
 									msg,
@@ -1,10 +1,12 @@
 use crate::user::*;
 use database_table::{
     Entry,
+    Id,
+    JsonSchema,
     TableRoutable,
+    Updatable,
 };
 use enum_paths::AsPath;
-use rql::*;
 use serde::{
     Deserialize,
     Serialize,
@@ -33,7 +35,7 @@ pub mod list;
 #[cfg(target_arch = "wasm32")]
 pub mod profile;
 
-#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, Builder)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, Builder, Updatable, JsonSchema)]
 pub struct Task {
     title: String,
     description: String,
@@ -57,6 +59,11 @@ impl TableRoutable for Task {
         Route::Task(id)
     }
 }
+impl crate::auth::Owned for Task {
+    fn is_owner(&self, _id: Id<Self>, user: Id<User>) -> bool {
+        self.assignees.contains(&user)
+    }
+}
 impl Task {
     pub fn new<S: ToString>(title: S) -> Self {
         Self {
@@ -92,12 +99,18 @@ impl Task {
     pub fn add_assignee(&mut self, id: Id<User>) {
         self.assignees.push(id);
     }
+    pub fn remove_assignee(&mut self, id: Id<User>) {
+        self.assignees.retain(|assignee| *assignee != id);
+    }
     pub fn subtasks(&self) -> &Vec<Id<Self>> {
         &self.subtasks
     }
     pub fn children_mut(&mut self) -> &mut Vec<Id<Self>> {
         &mut self.subtasks
     }
+    pub fn remove_subtask(&mut self, id: Id<Self>) {
+        self.subtasks.retain(|task| *task != id);
+    }
 }
 impl From<Entry<Task>> for Task {
     fn from(entry: Entry<Self>) -> Self {
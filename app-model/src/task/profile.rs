@@ -11,7 +11,7 @@ use components::{
 	Viewable,
 };
 use database_table::Entry;
-use rql::Id;
+use database_table::Id;
 
 #[derive(Debug, Clone)]
 pub struct Model {
@@ -1,10 +1,15 @@
-use crate::auth::credentials::*;
+use crate::auth::{
+    credentials::*,
+    TwoFactor,
+};
 use database_table::{
     Entry,
+    Id,
+    JsonSchema,
     TableRoutable,
+    Updatable,
 };
 use enum_paths::AsPath;
-use rql::*;
 use serde::{
     Deserialize,
     Serialize,
@@ -30,11 +35,38 @@ use {
 #[cfg(target_arch = "wasm32")]
 pub mod profile;
 
-#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize, Updatable, JsonSchema)]
 pub struct User {
     credentials: Credentials,
     full_name: Option<String>,
     followers: Vec<Id<User>>,
+    /// second factor of logins, if the user enrolled one
+    #[serde(default)]
+    two_factor: Option<TwoFactor>,
+}
+/// A user as sent in api responses, without credentials
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct PublicUser {
+    pub id: Id<User>,
+    pub name: String,
+    pub full_name: Option<String>,
+    pub followers: Vec<Id<User>>,
+}
+impl From<&Entry<User>> for PublicUser {
+    fn from(entry: &Entry<User>) -> Self {
+        let user = entry.data();
+        Self {
+            id: entry.id,
+            name: user.name().clone(),
+            full_name: user.full_name().clone(),
+            followers: user.followers().clone(),
+        }
+    }
+}
+impl From<Entry<User>> for PublicUser {
+    fn from(entry: Entry<User>) -> Self {
+        Self::from(&entry)
+    }
 }
 impl From<Credentials> for User {
     fn from(credentials: Credentials) -> Self {
@@ -42,6 +74,7 @@ impl From<Credentials> for User {
             credentials,
             full_name: None,
             followers: vec![],
+            two_factor: None,
         }
     }
 }
@@ -51,6 +84,14 @@ impl Display for User {
     }
 }
 impl User {
+    /// Serialized fields which are also in `PublicUser`
+    pub const PUBLIC_FIELDS: &'static [&'static str] = &["credentials.username", "full_name", "followers"];
+    /// Whether a field path, as returned by `Query::fields`, is a public field or in one
+    pub fn is_public_field(field: &str) -> bool {
+        Self::PUBLIC_FIELDS
+            .iter()
+            .any(|public| field == *public || field.starts_with(&format!("{}.", public)))
+    }
     pub fn empty() -> Self {
         Self::default()
     }
@@ -59,11 +100,13 @@ impl User {
             credentials: Credentials::new(name, password),
             full_name: None,
             followers: vec![],
+            two_factor: None,
         }
     }
     pub fn name(&self) -> &String {
         &self.credentials.username
     }
+    /// The password hash, or the password of users registered before hashing
     pub fn password(&self) -> &String {
         &self.credentials.password
     }
@@ -76,9 +119,19 @@ impl User {
     pub fn followers(&self) -> &Vec<Id<User>> {
         &self.followers
     }
+    pub fn remove_follower(&mut self, id: Id<User>) {
+        self.followers.retain(|follower| *follower != id);
+    }
     pub fn full_name(&self) -> &Option<String> {
         &self.full_name
     }
+    pub fn two_factor(&self) -> &Option<TwoFactor> {
+        &self.two_factor
+    }
+    /// Whether logins need a second factor
+    pub fn two_factor_enabled(&self) -> bool {
+        self.two_factor.as_ref().map(|two_factor| two_factor.enabled).unwrap_or(false)
+    }
 }
 #[derive(Clone, Debug, AsPath)]
 pub enum Route {
@@ -101,6 +154,11 @@ impl From<Entry<User>> for User {
         entry.into_inner()
     }
 }
+impl crate::auth::Owned for User {
+    fn is_owner(&self, id: Id<Self>, user: Id<User>) -> bool {
+        id == user
+    }
+}
 
 #[cfg(target_arch = "wasm32")]
 #[derive(Clone, Debug)]
@@ -125,6 +183,17 @@ impl Viewable for User {
     }
 }
 #[cfg(target_arch = "wasm32")]
+impl PublicUser {
+    pub fn view<Ms: 'static>(&self) -> Node<Ms> {
+        div![
+            h1!["Profile"],
+            p![self.name.as_str()],
+            self.full_name.as_ref().map(|full_name| p![full_name.as_str()]),
+            p![format!("Followers: {}", self.followers.len())],
+        ]
+    }
+}
+#[cfg(target_arch = "wasm32")]
 impl preview::Previewable for User {
     fn preview(&self) -> Node<Msg> {
         div![
@@ -137,3 +206,25 @@ impl preview::Previewable for User {
         ]
     }
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use database_table::{
+        Direction,
+        Query,
+    };
+    #[test]
+    fn public_fields() {
+        let query = Query::new()
+            .eq(".credentials.password", "hash")
+            .sort("credentials.username", Direction::Ascending);
+        assert_eq!(query.fields(), vec!["credentials.password", "credentials.username"]);
+        assert!(!User::is_public_field(&query.fields()[0]));
+        assert!(User::is_public_field(&query.fields()[1]));
+        assert!(User::is_public_field("followers.0"));
+        assert!(!User::is_public_field("credentials"));
+        assert!(!User::is_public_field("full_name_hash"));
+        let query = Query::new().eq(".two_factor.secret", "secret");
+        assert!(!User::is_public_field(&query.fields()[0]));
+    }
+}
@@ -1,7 +1,14 @@
 use super::*;
-use crate::project;
+use crate::{
+	auth::{
+		session,
+		second_factor,
+		LoginAttempt,
+		TwoFactorSettings,
+	},
+	project,
+};
 use components::{
-	remote,
 	Component,
 	Init,
 	Viewable,
@@ -19,49 +26,112 @@ impl From<User> for UserProfile {
 
 #[derive(Debug, Clone)]
 pub struct Model {
-	pub entry: remote::Remote<User>,
+	pub id: Id<User>,
+	/// sent by the parent with `Msg::Got`, from the `get_user` api function
+	pub user: Option<PublicUser>,
 	pub projects: project::list::Model,
+	/// login attempts, only shown to the user themselves
+	pub attempts: Vec<LoginAttempt>,
+	/// only for the profile of the logged in user
+	pub two_factor: Option<TwoFactorSettings>,
+}
+/// Load the login attempts and add the second factor settings if the profile is of the logged in user
+fn init_account(id: Id<User>, orders: &mut impl Orders<Msg>) -> Option<TwoFactorSettings> {
+	if session::get().map(|session| session.user_id) == Some(id) {
+		orders.perform_cmd(async {
+			Msg::Attempts(session::login_attempts().await.map_err(|e| format!("{:?}", e)))
+		});
+		Some(TwoFactorSettings::default())
+	} else {
+		None
+	}
 }
 impl Init<Id<User>> for Model {
 	fn init(id: Id<User>, orders: &mut impl Orders<Msg>) -> Model {
+		let two_factor = init_account(id, orders);
 		Model {
-			entry: Init::init(id.clone(), &mut orders.proxy(Msg::Entry)),
+			id,
+			user: None,
 			projects: Init::init(id.clone(), &mut orders.proxy(Msg::ProjectList)),
+			attempts: Vec::new(),
+			two_factor,
 		}
 	}
 }
 impl Init<Entry<User>> for Model {
 	fn init(entry: Entry<User>, orders: &mut impl Orders<Msg>) -> Model {
+		let two_factor = init_account(entry.id, orders);
 		Model {
-			entry: remote::Remote::from(entry.clone()),
+			id: entry.id,
+			user: Some(PublicUser::from(&entry)),
 			projects: Init::init(entry.id, &mut orders.proxy(Msg::ProjectList)),
+			attempts: Vec::new(),
+			two_factor,
 		}
 	}
 }
 
 #[derive(Clone, Debug)]
 pub enum Msg {
-	Entry(remote::Msg<User>),
+	Got(Result<Option<PublicUser>, String>),
 	ProjectList(project::list::Msg),
+	Attempts(Result<Vec<LoginAttempt>, String>),
+	TwoFactor(second_factor::Msg),
 }
 impl Component for Model {
 	type Msg = Msg;
 	fn update(&mut self, msg: Self::Msg, orders: &mut impl Orders<Self::Msg>) {
 		match msg {
-			Msg::Entry(msg) => {
-				Component::update(&mut self.entry, msg, &mut orders.proxy(Msg::Entry))
+			Msg::Got(res) => {
+				match res {
+					Ok(user) => self.user = user,
+					Err(e) => seed::log(e),
+				}
 			}
 			Msg::ProjectList(msg) => {
 				Component::update(&mut self.projects, msg, &mut orders.proxy(Msg::ProjectList))
 			}
+			Msg::Attempts(res) => {
+				match res {
+					Ok(attempts) => self.attempts = attempts,
+					Err(e) => seed::log(e),
+				}
+			}
+			Msg::TwoFactor(msg) => {
+				if let Some(two_factor) = &mut self.two_factor {
+					Component::update(two_factor, msg, &mut orders.proxy(Msg::TwoFactor))
+				}
+			}
 		}
 	}
 }
 impl Viewable for Model {
 	fn view(&self) -> Node<Msg> {
 		div![
-			self.entry.view().map_msg(Msg::Entry),
+			self.user.as_ref().map(PublicUser::view),
 			self.projects.view().map_msg(Msg::ProjectList),
+			self.view_attempts(),
+			self.two_factor.as_ref().map(|two_factor| two_factor.view().map_msg(Msg::TwoFactor)),
+		]
+	}
+}
+impl Model {
+	fn view_attempts(&self) -> Node<Msg> {
+		if self.attempts.is_empty() {
+			return empty![];
+		}
+		div![
+			h3!["Login attempts"],
+			table![
+				tr![th!["Time"], th!["Address"], th!["Result"]],
+				self.attempts.iter().map(|attempt| {
+					tr![
+						td![attempt.time.format("%Y-%m-%d %H:%M:%S UTC").to_string()],
+						td![attempt.ip.map(|ip| ip.to_string()).unwrap_or_else(|| "unknown".to_string())],
+						td![if attempt.success { "Success" } else { "Failed" }],
+					]
+				})
+			],
 		]
 	}
 }
@@ -12,6 +12,10 @@ use components::{
 	Init,
 	Viewable,
 };
+use database_table::{
+	set_client_config,
+	ClientConfig,
+};
 use navbar::Navbar;
 use webapi::WebApi;
 use seed::{
@@ -38,6 +42,10 @@ fn init_tracing() {
 #[wasm_bindgen(start)]
 pub fn render() {
 	init_tracing();
+	// send api calls to the server this app was loaded from
+	if let Ok(base_url) = app_model::get_base_url() {
+		set_client_config(ClientConfig::new(base_url));
+	}
 	debug!("Starting App");
 	App::start(
 		"app",
@@ -27,7 +27,7 @@ use seed::{
 	prelude::*,
 };
 use tracing::debug;
-use rql::Id;
+use database_table::Id;
 
 #[derive(Debug)]
 pub struct SubscriptionChart {
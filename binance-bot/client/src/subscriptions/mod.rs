@@ -20,24 +20,29 @@ use shared::{
 		PriceSubscription,
 		Route,
 	},
+	ClientMessage,
+	Feed,
 };
 use database_table::{
+	Change,
 	Entry,
-	RemoteTable,
+	Watch,
 };
 #[allow(unused)]
 use tracing::{
 	instrument,
 	debug,
+	error,
 	info,
 };
-use rql::*;
+use database_table::Id;
 
 #[derive(Debug)]
 pub struct Subscriptions {
 	subscriptions: HashMap<Id<PriceSubscription>, SubscriptionInfo>,
 	editor: Option<Editor<PriceSubscription>>,
 	server_msg_sub: SubHandle,
+	change_sub: SubHandle,
 	update_list: bool,
 }
 impl Subscriptions {
@@ -60,6 +65,7 @@ impl Init<Route> for Subscriptions {
 		// TODO add components for list and entry
 		orders.send_msg(Msg::GetList);
 		Self {
+			change_sub: orders.subscribe_with_handle(Msg::Changed),
 			server_msg_sub: orders.subscribe_with_handle(|msg: Response| {
 				debug!("Received Subscription Response");
 				match msg {
@@ -85,6 +91,7 @@ pub enum Msg {
 	Editor(<Editor<PriceSubscription> as Component>::Msg),
 	SetList(Vec<Entry<PriceSubscription>>),
 	Subscription(Id<PriceSubscription>, subscription::Msg),
+	Changed(Change<PriceSubscription>),
 }
 impl Component for Subscriptions {
 	type Msg = Msg;
@@ -100,10 +107,11 @@ impl Component for Subscriptions {
 				debug!("Msg::GetList...");
 				orders.perform_cmd(async move {
 					debug!("Calling command..");
-					PriceSubscription::get_all()
-                        .await
+					shared::api::get_pricesubscriptions()
+						.await
 						.map(Msg::SetList)
-                        .expect("Failed to get SubscriptionList")
+						.map_err(|e| error!("Failed to get SubscriptionList: {}", e))
+						.ok()
 				});
 			},
 			Msg::SetList(list) => {
@@ -117,12 +125,14 @@ impl Component for Subscriptions {
 				})
 				.collect();
 				self.update_list = true;
+				// watch once the list could be read, e.g. after logging in
+				orders.notify(ClientMessage::Watch(Feed::PriceSubscription(Watch::Table)));
 			},
 			Msg::Editor(msg) => {
 				debug!("Editor Msg {:#?}", msg);
 				if let Some(ed) = &mut self.editor {
+					// new subscriptions arrive as changes
 					let new = if msg.is_response() {
-						orders.send_msg(Msg::GetList);
 						Some(None)
 					} else {
 						None
@@ -141,6 +151,25 @@ impl Component for Subscriptions {
 				}
 				self.update_list = true;
 			},
+			Msg::Changed(change) => {
+				debug!("Subscription changed");
+				match change {
+					Change::Inserted(entry) => {
+						let id = entry.id.clone();
+						self.subscriptions.insert(
+							id.clone(),
+							SubscriptionInfo::init(entry, &mut orders.proxy(move |msg| Msg::Subscription(id, msg)))
+						);
+					},
+					Change::Updated(entry) => {
+						orders.send_msg(Msg::Subscription(entry.id, subscription::Msg::Updated(entry.data)));
+					},
+					Change::Deleted(id) => {
+						self.subscriptions.remove(&id);
+					},
+				}
+				self.update_list = true;
+			},
 		}
 	}
 }
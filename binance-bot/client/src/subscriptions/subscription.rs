@@ -28,7 +28,7 @@ use database_table::{
 	Entry,
 	TableRoutable,
 };
-use rql::*;
+use database_table::Id;
 #[allow(unused)]
 use tracing::{
 	instrument,
@@ -73,6 +73,8 @@ pub enum Msg {
 	OpenEditor,
 	Editor(<Editor<PriceSubscription> as Component>::Msg),
 	Chart(chart::Msg),
+	/// the subscription was changed on the server
+	Updated(PriceSubscription),
 }
 impl Init<Entry<PriceSubscription>> for SubscriptionInfo {
 	fn init(entry: Entry<PriceSubscription>, orders: &mut impl Orders<Msg>) -> Self {
@@ -106,6 +108,9 @@ impl Component for SubscriptionInfo {
 			Msg::Chart(msg) => {
 				self.chart.update(msg, &mut orders.proxy(Msg::Chart));
 			}
+			Msg::Updated(subscription) => {
+				self.subscription = subscription;
+			}
 		}
 	}
 }
@@ -1,6 +1,7 @@
 use shared::{
     ClientMessage,
     ServerMessage,
+    TableChange,
 };
 use components::{
     Component,
@@ -89,6 +90,14 @@ impl Component for WebApi {
                         trace!("ServerMessage::Subscription");
                         orders.notify(response)
                     },
+                    ServerMessage::Change(change) => {
+                        trace!("ServerMessage::Change");
+                        match change {
+                            TableChange::PriceSubscription(change) => orders.notify(change),
+                        }
+                    },
+                    // resolved by the transport of the websocket
+                    ServerMessage::Reply(_) => {},
                 };
             }
         }
@@ -3,6 +3,11 @@ use shared::{
     ServerMessage,
     WebsocketCommand,
 };
+use database_table::{
+    set_transport,
+    SocketTransport,
+};
+use futures::StreamExt;
 use components::{
     Component,
     Init,
@@ -25,6 +30,8 @@ pub struct WebSocket {
     websocket_reconnector: Option<StreamHandle>,
     open: bool,
     msg_queue: Vec<ClientMessage>,
+    /// api calls waiting for `ServerMessage::Reply`
+    transport: SocketTransport,
 }
 #[derive(Clone, Debug)]
 pub enum Msg {
@@ -40,12 +47,17 @@ impl Init<()> for WebSocket {
     fn init(_: (), orders: &mut impl Orders<Msg>) -> Self {
         let host = app_model::get_host().unwrap();
         //debug!("Host: {}", host);
+        // send calls of api functions through this socket
+        let (transport, calls) = SocketTransport::new();
+        set_transport(transport.clone());
+        orders.stream(calls.map(|call| Msg::SendMessage(ClientMessage::Call(call))));
         Self {
             host: host.clone(),
             websocket: Some(Self::create_websocket(&host, orders)),
             websocket_reconnector: None,
             open: false,
             msg_queue: Vec::new(),
+            transport,
         }
     }
 }
@@ -154,6 +166,11 @@ impl Component for WebSocket {
                 //debug!("{:#?}", msg);
                 self.push_message(msg);
             }
+            Msg::MessageReceived(ServerMessage::Reply(reply)) => {
+                if !self.transport.reply(reply) {
+                    debug!("Reply to unknown or timed out call");
+                }
+            }
             Msg::MessageReceived(_) => {
             }
             Msg::Command(cmd) => {
@@ -33,6 +33,11 @@ use clap::{
     App,
     Arg,
 };
+use interpreter::intent::{
+    Intent,
+    IntentRegistry,
+    Recognition,
+};
 #[cfg(feature = "actix_server")]
 use futures_core::stream::Stream;
 #[cfg(feature = "actix_server")]
@@ -49,6 +54,7 @@ use tracing::{
 use lazy_static::lazy_static;
 lazy_static! {
     pub static ref STDIN: Arc<RwLock<Stdin>> = Arc::new(RwLock::new(async_std::io::stdin()));
+    static ref INTENTS: IntentRegistry = IntentRegistry::default();
 }
 #[cfg_attr(feature = "actix_server", derive(Message))]
 #[cfg_attr(feature = "actix_server", rtype(result = "()"))]
@@ -122,7 +128,24 @@ pub async fn run_command(text: String) -> Result<String, Error> {
                 app.usage().to_string()
             }
         }
-        Err(err) => format!("{}", err),
+        Err(err) => match interpret(&text).await? {
+            Some(reply) => reply,
+            None => format!("{}", err),
+        },
+    })
+}
+/// Fall back to intent recognition for sentences that are not commands
+async fn interpret(text: &str) -> Result<Option<String>, Error> {
+    Ok(match INTENTS.recognize(text) {
+        Recognition::Intent(Intent::GetPrice { symbol }) => {
+            let price = binance().await.get_symbol_price(&symbol)
+                .await
+                .map_err(|e| e.to_string())?;
+            Some(format!("{:#?}", price))
+        }
+        Recognition::Intent(intent) => Some(format!("Can not {} here.", intent)),
+        Recognition::Unknown => None,
+        suggestion => Some(suggestion.to_string()),
     })
 }
 
@@ -6,7 +6,7 @@ pub mod error;
 pub mod keys;
 pub mod subscriptions;
 pub mod telegram;
-pub mod database;
+pub use shared::database;
 pub mod websocket;
 pub mod server;
 
@@ -47,6 +47,25 @@ pub use server::*;
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
     let _tracing = init_tracing();
+    // `migrate --dry-run` only reports what would be migrated
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let migrate_only = args.first().map(String::as_str) == Some("migrate");
+    let options = database_table::MigrationOptions {
+        dry_run: migrate_only && args.iter().any(|arg| arg == "--dry-run"),
+        backup: Some("binance_bot_database.backup".into()),
+    };
+    let report = database::migrate(&options)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+    for table in &report.tables {
+        info!(
+            "{} {} rows of {} from version {} to {}",
+            if report.dry_run { "Would migrate" } else { "Migrated" },
+            table.rows, table.table, table.from, table.to,
+        );
+    }
+    if migrate_only {
+        return Ok(());
+    }
     let _sys = actor_sys();
     server::run().await
 }
@@ -9,17 +9,34 @@ use crate::{
 };
 use app_model::{
     auth::{
+        keys::KeyRing,
+        set_jwt_provider,
+        AuthUser,
+        CodeRequest,
+        confirm_two_factor,
+        disable_two_factor,
+        enrol_two_factor,
+        Group,
+        JWTProvider,
         login,
+        login_attempts,
+        LoginAttempt,
+        LoginResponse,
+        RefreshToken,
+        logout,
+        refresh,
         register,
+        second_factor,
         Credentials,
+        RefreshRequest,
+        Role,
+        SecondFactorRequest,
+        UserSession,
     },
     user::User,
 };
 use async_std::net::SocketAddr;
 use chrono::Utc;
-use shared::{
-    PriceSubscription,
-};
 use tide::{
     Body,
     Middleware,
@@ -41,13 +58,7 @@ use tracing::{
     warn,
 };
 use database::Schema;
-use database_table::{
-    Database,
-    DatabaseTable,
-    TableRoutable,
-};
-use enum_paths::AsPath;
-use std::fmt::Debug;
+use database_table::Database;
 
 macro_rules! client_file {
     ($path:expr) => {
@@ -66,7 +77,7 @@ macro_rules! index {
 }
 #[async_trait::async_trait]
 trait ServeSession<'db, DB>
-    where DB: Database<'db, User> + 'db,
+    where DB: Database<'db, User> + Database<'db, Group> + Database<'db, Role> + Database<'db, LoginAttempt> + Database<'db, RefreshToken> + 'db,
 {
     type Api;
     type Response;
@@ -74,24 +85,15 @@ trait ServeSession<'db, DB>
     fn serve(api: &mut Self::Api);
     async fn login_handler(mut req: Self::Request) -> Self::Response;
     async fn logout_handler(mut req: Self::Request) -> Self::Response;
+    async fn logout_everywhere_handler(mut req: Self::Request) -> Self::Response;
+    async fn refresh_handler(mut req: Self::Request) -> Self::Response;
+    async fn attempts_handler(req: Self::Request) -> Self::Response;
+    async fn second_factor_handler(mut req: Self::Request) -> Self::Response;
+    async fn enrol_handler(req: Self::Request) -> Self::Response;
+    async fn confirm_handler(mut req: Self::Request) -> Self::Response;
+    async fn disable_handler(mut req: Self::Request) -> Self::Response;
     async fn registration_handler(mut req: Self::Request) -> Self::Response;
 }
-#[async_trait::async_trait]
-trait ServeTable<'db, R, T, DB>
-    where T: TableRoutable + DatabaseTable<'db, DB> + 'db,
-          DB: Database<'db, T> + 'db,
-          R: Router<T> + 'static
-{
-    type Api;
-    type Response;
-    type Request;
-    fn serve(api: &mut Self::Api);
-    async fn post_handler(req: Self::Request) -> Self::Response;
-    async fn get_handler(req: Self::Request) -> Self::Response;
-    async fn get_list_handler(req: Self::Request) -> Self::Response;
-    async fn delete_handler(req: Self::Request) -> Self::Response;
-}
-
 use shared::{
     Route,
     Router,
@@ -108,6 +110,7 @@ impl TideServer {
         new.server.with(TraceMiddleware::new());
         new.server.with(Self::session_middleware());
         new.server.with(Self::session_validator_middleware());
+        new.server.with(session_service::CsrfMiddleware::new());
         new.api();
         new.wss();
         new.root();
@@ -134,22 +137,25 @@ impl TideServer {
         debug!("Routing {}", route);
         let mut api = tide::new();
         Self::auth(&mut api);
-        <Self as ServeTable<'_, Route, PriceSubscription, Schema>>
-            ::serve(&mut api);
         api.at("/price_history").nest(price_api());
         self.server.at(&route).nest(api);
+        shared::api::endpoints::serve(&mut self.server);
     }
     async fn wss_handler(request: Request<()>) -> tide::Result {
+        // watches on the socket are authorized with the token of the session opening it,
+        // until api calls send a newer one
+        let token = AuthUser::tide_token(&request);
         WebSocket::new(async move |_, ws| {
-            websocket::connection(ws).await;
+            websocket::connection(ws, token.clone()).await;
             Ok(())
         })
         .call(request).await
     }
-    fn session_middleware() -> tide::sessions::SessionMiddleware<tide::sessions::MemoryStore> {
+    /// Sessions are kept in the `SessionService`, so they outlive restarts
+    fn session_middleware() -> tide::sessions::SessionMiddleware<session_service::TideSessionStore> {
         tide::sessions::SessionMiddleware::new(
-            tide::sessions::MemoryStore::new(),
-            session_service::generate_secret().as_bytes(),
+            session_service::TideSessionStore::new(),
+            session_secret().as_bytes(),
         )
         .with_cookie_name("session")
         .with_session_ttl(Some(std::time::Duration::from_secs(
@@ -185,7 +191,7 @@ impl TideServer {
 }
 #[async_trait::async_trait]
 impl<DB> ServeSession<'static, DB> for TideServer
-    where DB: Database<'static, User> + 'static,
+    where DB: Database<'static, User> + Database<'static, Group> + Database<'static, Role> + Database<'static, LoginAttempt> + Database<'static, RefreshToken> + 'static,
 {
     type Api = tide::Server<()>;
     type Response = tide::Result;
@@ -195,78 +201,90 @@ impl<DB> ServeSession<'static, DB> for TideServer
         auth.at("/login").post(<Self as ServeSession<'static, DB>>::login_handler);
         auth.at("/register").post(<Self as ServeSession<'static, DB>>::registration_handler);
         auth.at("/logout").post(<Self as ServeSession<'static, DB>>::logout_handler);
+        auth.at("/logout_everywhere").post(<Self as ServeSession<'static, DB>>::logout_everywhere_handler);
+        auth.at("/refresh").post(<Self as ServeSession<'static, DB>>::refresh_handler);
+        auth.at("/attempts").get(<Self as ServeSession<'static, DB>>::attempts_handler);
+        auth.at("/second_factor").post(<Self as ServeSession<'static, DB>>::second_factor_handler);
+        auth.at("/two_factor/enrol").post(<Self as ServeSession<'static, DB>>::enrol_handler);
+        auth.at("/two_factor/confirm").post(<Self as ServeSession<'static, DB>>::confirm_handler);
+        auth.at("/two_factor/disable").post(<Self as ServeSession<'static, DB>>::disable_handler);
         server.at("/auth").nest(auth);
     }
     async fn login_handler(mut req: Self::Request) -> Self::Response {
         let credentials: Credentials = req.body_json().await?;
-        match login::<DB>(credentials).await {
-            Ok(session) => {
-                req.session_mut()
-                    .insert("session", session)
-                    .map(|_| Response::new(200))
-                    .map_err(|e| tide::Error::from_str(500, e.to_string()))
-            }
-            Err(e) => Err(e),
+        let response = login::<DB>(credentials, Self::peer_ip(&req)).await?;
+        if let LoginResponse::Session(session) = &response {
+            Self::start_session(&mut req, session)?;
         }
+        Ok(Body::from_json(&response)?.into())
     }
-    async fn logout_handler(mut req: Self::Request) -> Self::Response {
-        req.session_mut().remove("session");
+    async fn second_factor_handler(mut req: Self::Request) -> Self::Response {
+        let request: SecondFactorRequest = req.body_json().await?;
+        let session = second_factor::<DB>(request, Self::peer_ip(&req))?;
+        Self::start_session(&mut req, &session)?;
+        Ok(Body::from_json(&session)?.into())
+    }
+    async fn enrol_handler(req: Self::Request) -> Self::Response {
+        let user = AuthUser::from_tide(&req)?;
+        let enrolment = enrol_two_factor::<DB>(user.id, "Binance Bot")?;
+        Ok(Body::from_json(&enrolment)?.into())
+    }
+    async fn confirm_handler(mut req: Self::Request) -> Self::Response {
+        let user = AuthUser::from_tide(&req)?;
+        let request: CodeRequest = req.body_json().await?;
+        confirm_two_factor::<DB>(user.id, &request.code)?;
         Ok(Response::new(200))
     }
-    async fn registration_handler(mut req: Self::Request) -> Self::Response {
-        let user: User = req.body_json().await?;
-        match register::<database::Schema>(user).await {
-            Ok(_session) => Ok(Response::new(200)),
-            Err(e) => Err(tide::Error::from_str(500, e.to_string())),
-        }
+    async fn disable_handler(mut req: Self::Request) -> Self::Response {
+        let user = AuthUser::from_tide(&req)?;
+        let request: CodeRequest = req.body_json().await?;
+        disable_two_factor::<DB>(user.id, &request.code)?;
+        Ok(Response::new(200))
     }
-}
-
-#[async_trait::async_trait]
-impl<R, T, DB> ServeTable<'static, R, T, DB> for TideServer
-    where T: TableRoutable + DatabaseTable<'static, DB> + Debug + 'static,
-          DB: Database<'static, T> + 'static,
-          R: Router<T> + AsPath + 'static
-{
-    type Api = tide::Server<()>;
-    type Response = tide::Result<Body>;
-    type Request = Request<()>;
-
-    /// Serve DatabaseTable at TableRoutable::table_route()
-    fn serve(api: &mut Self::Api) {
-        let mut t = tide::new();
-        t.at("/")
-            .get(<Self as ServeTable<'_, R, T, DB>>::get_list_handler)
-            .post(<Self as ServeTable<'_, R, T, DB>>::post_handler);
-        t.at("/:id")
-            .get(<Self as ServeTable<'_, R, T, DB>>::get_handler)
-            .delete(<Self as ServeTable<'_, R, T, DB>>::delete_handler);
-        let route = R::route_sub(T::table_route()).prefix();
-        debug!("Routing {}", route);
-        api.at(&route).nest(t);
+    async fn logout_handler(mut req: Self::Request) -> Self::Response {
+        if let Some(session) = req.session().get::<UserSession>("session") {
+            logout::<DB>(RefreshRequest { refresh_token: session.refresh_token });
+        }
+        req.session_mut().remove("session");
+        Ok(Response::new(200))
     }
-    async fn post_handler(mut req: Self::Request) -> Self::Response {
-        let s: T = req.body_json().await?;
-        let id = T::insert(s);
-        let body = Body::from_json(&id)?;
-        debug!("{:#?}", body);
-        Ok(body)
+    /// End all sessions of the caller and revoke their refresh tokens, e.g. after losing a device
+    async fn logout_everywhere_handler(mut req: Self::Request) -> Self::Response {
+        let user = AuthUser::from_tide(&req)?;
+        app_model::auth::refresh::revoke_user::<DB>(user.id);
+        let ended = session_service::session_service().end_user_sessions(user.id);
+        debug!("Ended {} sessions of {}", ended, user.name);
+        req.session_mut().destroy();
+        Ok(Response::new(200))
     }
-    async fn get_handler(req: Self::Request) -> Self::Response {
-        let id: rql::Id<T> = req.param("id")?.parse()?;
-        let r = T::get(id);
-        Ok(Body::from_json(&r)?)
+    /// Renew the session with the refresh token in the body, or in the session cookie
+    async fn refresh_handler(mut req: Self::Request) -> Self::Response {
+        let request = match req.body_json::<RefreshRequest>().await {
+            Ok(request) => request,
+            Err(_) => {
+                let session = req.session()
+                    .get::<UserSession>("session")
+                    .ok_or_else(|| tide::Error::from_str(401, "Missing refresh token."))?;
+                RefreshRequest { refresh_token: session.refresh_token }
+            }
+        };
+        let session = refresh::<DB>(request)?;
+        req.session_mut()
+            .insert("session", session.clone())
+            .map_err(|e| tide::Error::from_str(500, e.to_string()))?;
+        Ok(Body::from_json(&session)?.into())
     }
-    async fn get_list_handler(_req: Self::Request) -> Self::Response {
-        debug!("Get subscription list handler");
-        let list = T::get_all();
-        debug!("Result: {:?}", list);
-        Ok(Body::from_json(&list)?)
+    /// Login attempts into the account of the caller
+    async fn attempts_handler(req: Self::Request) -> Self::Response {
+        let user = AuthUser::from_tide(&req)?;
+        Ok(Body::from_json(&login_attempts::<DB>(user.id))?.into())
     }
-    async fn delete_handler(req: Self::Request) -> Self::Response {
-        let id: rql::Id<T> = req.param("id")?.parse()?;
-        let r = T::delete(id);
-        Ok(Body::from_json(&r)?)
+    async fn registration_handler(mut req: Self::Request) -> Self::Response {
+        let credentials: Credentials = req.body_json().await?;
+        // errors keep their status, e.g. 409 for a taken name
+        let session = register::<DB>(credentials).await?;
+        Self::start_session(&mut req, &session)?;
+        Ok(Body::from_json(&session)?.into())
     }
 }
 
@@ -284,6 +302,33 @@ async fn price_history_handler(_: Request<()>) -> tide::Result<Body> {
         Err(e) => Err(tide::Error::from_str(500, e.to_string())),
     }
 }
+impl TideServer {
+    /// The peer address, as forwarding headers can be set by the client
+    fn peer_ip(req: &Request<()>) -> Option<std::net::IpAddr> {
+        req.peer_addr()
+            .and_then(|addr| addr.parse::<SocketAddr>().ok())
+            .map(|addr| addr.ip())
+    }
+    fn start_session(req: &mut Request<()>, session: &UserSession) -> tide::Result<()> {
+        req.session_mut()
+            .insert("session", session)
+            .map_err(|e| tide::Error::from_str(500, e.to_string()))
+    }
+}
+/// Secret signing the session cookies, created on the first start
+/// so cookies of stored sessions stay valid
+fn session_secret() -> String {
+    let path = keys::to_key_path("session.secret");
+    std::fs::read_to_string(path.as_ref())
+        .map(|secret| secret.trim_end().to_string())
+        .unwrap_or_else(|_| {
+            let secret = session_service::generate_secret();
+            if let Err(e) = std::fs::write(path.as_ref(), &secret) {
+                error!("Failed to write {}: {}", path.as_ref().display(), e);
+            }
+            secret
+        })
+}
 fn price_api() -> tide::Server<()> {
     let mut api = tide::new();
     api.at("/").get(price_history_handler);
@@ -291,6 +336,12 @@ fn price_api() -> tide::Server<()> {
 }
 
 pub async fn run() -> std::io::Result<()> {
+    set_jwt_provider(JWTProvider::with_keys(KeyRing::from_env_in(keys::to_key_path("jwt"))));
+    session_service::set_session_service(session_service::SessionService::new(
+        session_service::FileStore::open("binance_bot_sessions.jsonl")?,
+    ));
+    tokio::spawn(session_service::run_cleaner());
+    database::grant_member_role();
     let _telegram_actor = actor_sys_mut()
         .await
         .actor_of::<TelegramActor>("telegram-actor")
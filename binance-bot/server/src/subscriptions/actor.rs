@@ -28,7 +28,7 @@ use std::{
 	collections::HashMap,
 	result::Result,
 };
-use rql::*;
+use database_table::Id;
 use riker::actors::*;
 
 type CacheActorMap = HashMap<Id<PriceSubscription>, ActorRef<<SubscriptionCacheActor as Actor>::Msg>>;
@@ -16,7 +16,7 @@ use tracing::{
 	info,
 	trace,
 };
-use rql::*;
+use database_table::Id;
 use riker::actors::*;
 use async_std::{
 	task::JoinHandle,
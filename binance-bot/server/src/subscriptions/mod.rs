@@ -20,6 +20,8 @@ use database_table::{
 	Database,
 	Entry,
 	DatabaseTable,
+	Id,
+	TableRead,
 };
 use async_std::{
 	sync::{
@@ -47,7 +49,6 @@ use tracing::{
 	debug,
 	info,
 };
-use rql::*;
 
 #[derive(Clone, Debug)]
 pub enum Error {
@@ -157,8 +158,9 @@ pub struct StaticSubscriptions {
 impl StaticSubscriptions {
 	fn load_subscriptions_table() -> HashMap<Id<PriceSubscription>, Arc<RwLock<SubscriptionCache>>> {
 		<PriceSubscription as DatabaseTable<'_, Schema>>::table()
-			.rows()
-			.map(|row| (row.id.clone(), Arc::new(RwLock::new(SubscriptionCache::from(row.data.clone())))))
+			.entries()
+			.into_iter()
+			.map(|entry| (entry.id, Arc::new(RwLock::new(SubscriptionCache::from(entry.data)))))
 			.collect()
 	}
 	pub fn new() -> Self {
@@ -181,8 +183,7 @@ impl StaticSubscriptions {
 			Ok(id)
 		} else {
 			let sub = PriceSubscription::from(request);
-			let id = DatabaseTable::<'_, Schema>::table_mut()
-				.insert(sub.clone());
+			let id = <PriceSubscription as DatabaseTable<'_, Schema>>::insert(sub.clone());
 			self.subscriptions.insert(id.clone(), Arc::new(RwLock::new(SubscriptionCache::from(sub))));
 			self.new_subscriptions = true;
 			Ok(id)
@@ -1,12 +1,17 @@
 use crate::{
+	database::SUBSCRIPTION_CHANGES,
 	subscriptions::SubscriptionsActor,
 	websocket,
 };
 use shared::{
 	ClientMessage,
+	Feed,
 	ServerMessage,
+	TableChange,
 	WebsocketCommand,
 };
+use database_table::ListenerId;
+use app_model::auth::AuthUser;
 #[allow(unused)]
 use tracing::{
 	debug,
@@ -35,14 +40,57 @@ pub struct ConnectionActor {
 	id: usize,
 	sender: Sender<ServerMessage>,
 	subscriptions: Option<ActorRef<<SubscriptionsActor as Actor>::Msg>>,
+	/// watched feeds and their change listeners
+	feeds: Vec<(Feed, ListenerId)>,
+	/// token of the latest api call, or of the session opening the socket
+	token: Option<String>,
 }
 impl ConnectionActor {
 	pub fn actor_name(id: usize) -> String {
 		format!("Connection_{}", id)
 	}
-	pub async fn create(sender: Sender<ServerMessage>) -> Result<ActorRef<<ConnectionActor as Actor>::Msg>, CreateError> {
+	pub async fn create(sender: Sender<ServerMessage>, token: Option<String>) -> Result<ActorRef<<ConnectionActor as Actor>::Msg>, CreateError> {
 		let id = websocket::new_connection_id();
-		crate::actor_sys().await.actor_of_args::<ConnectionActor, _>(&Self::actor_name(id), (id, sender))
+		crate::actor_sys().await.actor_of_args::<ConnectionActor, _>(&Self::actor_name(id), (id, sender, token))
+	}
+	/// The user of the connection's token, checked on every use so expired tokens are refused
+	fn caller(&self) -> Option<AuthUser> {
+		self.token.as_deref().and_then(AuthUser::from_token)
+	}
+	/// Whether the caller may read the rows of `feed`, as the api functions of its table require
+	fn may_watch(&self, feed: &Feed) -> bool {
+		match feed {
+			// `rest_api!(PriceSubscription)` lets every authenticated user read
+			Feed::PriceSubscription(_) => self.caller().is_some(),
+		}
+	}
+	/// Forward changes matching `feed` to this connection
+	fn watch(&mut self, myself: ActorRef<<Self as Actor>::Msg>, feed: Feed) {
+		if self.feeds.iter().any(|(other, _)| *other == feed) {
+			return;
+		}
+		if !self.may_watch(&feed) {
+			debug!("Refusing unauthorized watch of {:?}", feed);
+			return;
+		}
+		let listener = match &feed {
+			Feed::PriceSubscription(watch) => {
+				let watch = watch.clone();
+				SUBSCRIPTION_CHANGES.subscribe(move |change| if watch.matches(change) {
+					myself.tell(ServerMessage::Change(TableChange::PriceSubscription(change.clone())), None);
+				})
+			}
+		};
+		self.feeds.push((feed, listener));
+	}
+	fn unwatch(&mut self, feed: &Feed) {
+		let (removed, feeds): (Vec<_>, Vec<_>) = self.feeds.drain(..).partition(|(other, _)| other == feed);
+		self.feeds = feeds;
+		for (feed, listener) in removed {
+			match feed {
+				Feed::PriceSubscription(_) => SUBSCRIPTION_CHANGES.unsubscribe(listener),
+			}
+		}
 	}
 }
 impl Actor for ConnectionActor {
@@ -60,6 +108,9 @@ impl Actor for ConnectionActor {
 	}
 	fn post_stop(&mut self) {
 		debug!("Stopped connection actor");
+		for (feed, _) in self.feeds.clone() {
+			self.unwatch(&feed);
+		}
         //if let Some(actor) = self.subscriptions.clone() {
 		//    ctx.run(async move {
 		//	    crate::actor_sys().await.stop(actor);
@@ -92,9 +143,17 @@ impl Receive<Msg> for ConnectionActor {
 }
 impl Receive<ClientMessage> for ConnectionActor {
 	type Msg = ConnectionActorMsg;
-	fn receive(&mut self, _ctx: &Context<Self::Msg>, msg: ClientMessage, sender: RkSender) {
+	fn receive(&mut self, ctx: &Context<Self::Msg>, msg: ClientMessage, sender: RkSender) {
 		trace!("ClientMessage in ConnectionActor");
 		match msg {
+			ClientMessage::Watch(feed) => self.watch(ctx.myself(), feed),
+			ClientMessage::Unwatch(feed) => self.unwatch(&feed),
+			ClientMessage::Call(call) => {
+				// the client sends the token of its current session with every call
+				self.token = call.token.clone();
+				let reply = shared::api::socket::dispatch(self.caller().as_ref(), call);
+				self.sender.try_send(ServerMessage::Reply(reply)).unwrap()
+			},
 			ClientMessage::Subscriptions(req) => if let Some(actor) = &self.subscriptions {
 				actor.tell(req, sender);
 			} else {
@@ -107,16 +166,28 @@ impl Receive<ServerMessage> for ConnectionActor {
 	type Msg = ConnectionActorMsg;
 	fn receive(&mut self, _ctx: &Context<Self::Msg>, msg: ServerMessage, _sender: RkSender) {
 		trace!("ServerMessage in ConnectionActor");
+		if let ServerMessage::Change(_) = msg {
+			// stop watching when the token expired
+			if self.caller().is_none() {
+				debug!("Dropping watches of connection {} without valid token", self.id);
+				for (feed, _) in self.feeds.clone() {
+					self.unwatch(&feed);
+				}
+				return;
+			}
+		}
 		self.sender.try_send(msg).unwrap()
 	}
 }
-impl ActorFactoryArgs<(usize, Sender<ServerMessage>)> for ConnectionActor {
-	fn create_args((id, sender): (usize, Sender<ServerMessage>)) -> Self {
+impl ActorFactoryArgs<(usize, Sender<ServerMessage>, Option<String>)> for ConnectionActor {
+	fn create_args((id, sender, token): (usize, Sender<ServerMessage>, Option<String>)) -> Self {
 		debug!("Creating ConnectionActor");
 		Self {
 			id,
 			sender,
 			subscriptions: None,
+			feeds: Vec::new(),
+			token,
 		}
 	}
 }
@@ -92,7 +92,7 @@ pub async fn send_messages<Tx>(receiver: Receiver<ServerMessage>, tx: Tx) -> Res
 		.forward(tx.sink_map_err(|e| e.to_string()))
 		.await
 }
-pub async fn connection(ws: WebSocketConnection) {
+pub async fn connection(ws: WebSocketConnection, token: Option<String>) {
 	let (sink, stream) = ws.split();
 	let rx = stream.map(|msg| msg.map(|msg| WebsocketPacket(msg.into_data())));
 	let tx = sink.with(async move |msg: WebsocketPacket| {
@@ -104,7 +104,7 @@ pub async fn connection(ws: WebSocketConnection) {
 	let (sender, receiver) = channel(CHANNEL_BUFFER_SIZE);
 
 	// create a connection actor with a ServerMessage sender
-	let connection = ConnectionActor::create(sender).await.unwrap();
+	let connection = ConnectionActor::create(sender, token).await.unwrap();
 	let connection2 = connection.clone();
 	// spawn listener for websocket stream
 	let ws_listener = async_std::task::spawn(async move {
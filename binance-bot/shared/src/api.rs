@@ -0,0 +1,22 @@
+//! Functions of the server, called by the client through the generated client functions
+#![allow(unused_imports)]
+#[cfg(not(target_arch = "wasm32"))]
+use crate::database::Schema;
+use crate::PriceSubscription;
+use app_model::auth::{
+    AuthUser,
+    Owned,
+    Resource,
+    TableAction,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use app_model::auth::{
+    has_privilege,
+    has_row_privilege,
+};
+use database_table::*;
+use define_api::api;
+
+api! {
+    rest_api!(PriceSubscription, privileges);
+}
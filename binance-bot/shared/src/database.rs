@@ -0,0 +1,157 @@
+use crate::subscriptions::PriceSubscription;
+use rql::*;
+use lazy_static::lazy_static;
+use database_table::{
+	backend::rql::RqlMigrationStore,
+	serde_json::Value,
+	ChangeFeed,
+	Database,
+	MigrationError,
+	MigrationOptions,
+	MigrationReport,
+	Migrations,
+	SchemaVersion,
+};
+use app_model::{
+	auth::{
+		grant_role,
+		Group,
+		LoginAttempt,
+		Privilege,
+		RefreshToken,
+		Role,
+		TableAction,
+		MEMBERS,
+	},
+	user::User,
+};
+
+schema! {
+	pub Schema {
+		user: User,
+		subscription: PriceSubscription,
+		group: Group,
+		role: Role,
+		login_attempt: LoginAttempt,
+		refresh_token: RefreshToken,
+		schema_version: SchemaVersion,
+	}
+}
+// the tables of `Schema` with untyped rows, for migrations
+schema! {
+	RawSchema {
+		user: Value,
+		subscription: Value,
+		group: Value,
+		role: Value,
+		login_attempt: Value,
+		refresh_token: Value,
+		schema_version: SchemaVersion,
+	}
+}
+
+lazy_static! {
+	pub static ref DB: Schema = Schema::new("binance_bot_database", rql::BinaryStable).unwrap();
+	pub static ref SUBSCRIPTION_CHANGES: ChangeFeed<PriceSubscription> = ChangeFeed::new();
+}
+
+impl<'db> Database<'db, User> for Schema {
+	type Table = TableGuard<'db, User>;
+	type TableMut = TableGuardMut<'db, User>;
+	fn table() -> Self::Table {
+		DB.user()
+	}
+	fn table_mut() -> Self::TableMut {
+		DB.user_mut()
+	}
+}
+impl<'db> Database<'db, PriceSubscription> for Schema {
+	type Table = TableGuard<'db, PriceSubscription>;
+	type TableMut = TableGuardMut<'db, PriceSubscription>;
+	fn table() -> Self::Table {
+		DB.subscription()
+	}
+	fn table_mut() -> Self::TableMut {
+		DB.subscription_mut()
+	}
+	fn changes() -> Option<&'db ChangeFeed<PriceSubscription>> {
+		Some(&*SUBSCRIPTION_CHANGES)
+	}
+}
+impl<'db> Database<'db, Group> for Schema {
+	type Table = TableGuard<'db, Group>;
+	type TableMut = TableGuardMut<'db, Group>;
+	fn table() -> Self::Table {
+		DB.group()
+	}
+	fn table_mut() -> Self::TableMut {
+		DB.group_mut()
+	}
+}
+impl<'db> Database<'db, Role> for Schema {
+	type Table = TableGuard<'db, Role>;
+	type TableMut = TableGuardMut<'db, Role>;
+	fn table() -> Self::Table {
+		DB.role()
+	}
+	fn table_mut() -> Self::TableMut {
+		DB.role_mut()
+	}
+}
+impl<'db> Database<'db, LoginAttempt> for Schema {
+	type Table = TableGuard<'db, LoginAttempt>;
+	type TableMut = TableGuardMut<'db, LoginAttempt>;
+	fn table() -> Self::Table {
+		DB.login_attempt()
+	}
+	fn table_mut() -> Self::TableMut {
+		DB.login_attempt_mut()
+	}
+}
+impl<'db> Database<'db, RefreshToken> for Schema {
+	type Table = TableGuard<'db, RefreshToken>;
+	type TableMut = TableGuardMut<'db, RefreshToken>;
+	fn table() -> Self::Table {
+		DB.refresh_token()
+	}
+	fn table_mut() -> Self::TableMut {
+		DB.refresh_token_mut()
+	}
+}
+impl<'db> Database<'db, SchemaVersion> for Schema {
+	type Table = TableGuard<'db, SchemaVersion>;
+	type TableMut = TableGuardMut<'db, SchemaVersion>;
+	fn table() -> Self::Table {
+		DB.schema_version()
+	}
+	fn table_mut() -> Self::TableMut {
+		DB.schema_version_mut()
+	}
+}
+/// Let registered users manage the price subscriptions, which are shared by all users
+pub fn grant_member_role() {
+	let role = [TableAction::Read, TableAction::Create, TableAction::Update, TableAction::Delete]
+		.iter()
+		.fold(Role::new("member"), |role, action| role.with_privilege(Privilege::all::<PriceSubscription>(*action)));
+	grant_role::<Schema>(MEMBERS, role);
+}
+/// Migrations of all tables, register new ones at the end
+pub fn migrations() -> Migrations {
+	Migrations::new()
+}
+/// Upgrade the stored tables to the current types
+///
+/// Must run before `DB` is first used, rows which no longer match their
+/// type can not be loaded by it.
+pub fn migrate(options: &MigrationOptions) -> Result<MigrationReport, MigrationError> {
+	let raw = RawSchema::new("binance_bot_database", rql::BinaryStable)
+		.map_err(|e| MigrationError::Store(e.to_string()))?;
+	let store = RqlMigrationStore::new("binance_bot_database", || raw.schema_version_mut())
+		.table("user", || raw.user_mut())
+		.table("subscription", || raw.subscription_mut())
+		.table("group", || raw.group_mut())
+		.table("role", || raw.role_mut())
+		.table("login_attempt", || raw.login_attempt_mut())
+		.table("refresh_token", || raw.refresh_token_mut());
+	migrations().run(&store, options)
+}
@@ -1,3 +1,6 @@
+pub mod api;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod database;
 pub mod subscriptions;
 pub use subscriptions::PriceSubscription;
 
@@ -6,8 +9,12 @@ use app_model::{
     user::Route as UserRoute,
 };
 use database_table::{
+    Change,
     Routable,
     Route as DbRoute,
+    SocketCall,
+    SocketReply,
+    Watch,
 };
 use enum_paths::AsPath;
 use serde::{
@@ -28,15 +35,40 @@ pub enum WebsocketCommand {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum ClientMessage {
     Subscriptions(subscriptions::Request),
+    /// receive changes of a table as `ServerMessage::Change`
+    Watch(Feed),
+    Unwatch(Feed),
+    /// call of an api function, answered with `ServerMessage::Reply`
+    Call(SocketCall),
 }
 impl Routable for ClientMessage {
     type Route = Route;
     fn route(&self) -> Self::Route {
         match self {
             ClientMessage::Subscriptions(req) => Route::Subscriptions(req.route()),
+            ClientMessage::Watch(feed) | ClientMessage::Unwatch(feed) => feed.route(),
+            ClientMessage::Call(_) => Route::Api(ApiRoute::Root),
         }
     }
 }
+/// Tables clients can watch
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Feed {
+    PriceSubscription(Watch<PriceSubscription>),
+}
+impl Routable for Feed {
+    type Route = Route;
+    fn route(&self) -> Self::Route {
+        match self {
+            Feed::PriceSubscription(Watch::Table) => Route::Subscriptions(subscriptions::Route::List),
+            Feed::PriceSubscription(Watch::Entry(id)) => Route::Subscriptions(subscriptions::Route::Entry(*id)),
+        }
+    }
+}
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum TableChange {
+    PriceSubscription(Change<PriceSubscription>),
+}
 
 impl TryFrom<String> for ClientMessage {
     type Error = String;
@@ -48,6 +80,8 @@ impl TryFrom<String> for ClientMessage {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum ServerMessage {
     Subscriptions(subscriptions::Response),
+    Change(TableChange),
+    Reply(SocketReply),
 }
 
 #[derive(Clone, Debug, AsPath)]
@@ -2,6 +2,7 @@ use app_model::market::PriceHistory;
 use database_table::Routable;
 use database_table::{
     Entry,
+    Id,
     Route as DbRoute,
 };
 use openlimits::model::Interval;
@@ -11,7 +12,6 @@ use serde::{
 };
 
 use enum_paths::AsPath;
-use rql::*;
 
 pub mod subscription;
 pub use subscription::PriceSubscription;
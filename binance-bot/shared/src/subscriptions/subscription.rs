@@ -4,19 +4,23 @@ use serde::{
 };
 
 use {
-    crate::{
-        subscriptions::Route,
-        ApiRoute,
-        Route as CrateRoute,
-    },
+    crate::subscriptions::Route,
     database_table::{
-        Routed,
+        Id,
+        JsonSchema,
         TableRoutable,
+        Updatable,
     },
-    rql::*,
 };
 #[cfg(target_arch = "wasm32")]
 use {
+    crate::api,
+    database_table::{
+        Entry,
+        Page,
+        Query,
+        RemoteTable,
+    },
     components::{
         Component,
         Edit,
@@ -27,7 +31,7 @@ use {
     },
 };
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Updatable, JsonSchema)]
 pub struct PriceSubscription {
     pub market_pair: String,
 }
@@ -69,13 +73,37 @@ impl TableRoutable for PriceSubscription {
         Route::Entry(id)
     }
 }
-impl Routed for PriceSubscription {
-    type AbsoluteRoute = CrateRoute;
-    fn to_absolute_route(route: <Self as TableRoutable>::Route) -> Self::AbsoluteRoute {
-        CrateRoute::Api(ApiRoute::Subscriptions(route))
+/// Remote table through the generated api clients, for `components::Editor`
+#[cfg(target_arch = "wasm32")]
+#[async_trait::async_trait(?Send)]
+impl RemoteTable for PriceSubscription {
+    type Error = String;
+    async fn get(id: Id<Self>) -> Result<Option<Entry<Self>>, Self::Error> {
+        api::get_pricesubscription(id).await.map_err(|e| e.to_string())
+    }
+    async fn delete(id: Id<Self>) -> Result<Option<Self>, Self::Error> {
+        api::delete_pricesubscription(id).await.map_err(|e| e.to_string())
+    }
+    async fn get_all() -> Result<Vec<Entry<Self>>, Self::Error> {
+        api::get_pricesubscriptions().await.map_err(|e| e.to_string())
+    }
+    async fn query(query: Query) -> Result<Page<Self>, Self::Error> {
+        api::query_pricesubscriptions(query).await.map_err(|e| e.to_string())
+    }
+    async fn update(id: Id<Self>, update: <Self as Updatable>::Update) -> Result<Option<Self>, Self::Error> {
+        api::update_pricesubscription(id, update).await.map_err(|e| e.to_string())
+    }
+    async fn post(data: Self) -> Result<Id<Self>, Self::Error> {
+        api::post_pricesubscription(data).await.map_err(|e| e.to_string())
     }
 }
 
+/// Subscriptions are shared by all users, only privileges on all subscriptions apply to them
+impl app_model::auth::Owned for PriceSubscription {
+    fn is_owner(&self, _id: Id<Self>, _user: Id<app_model::user::User>) -> bool {
+        false
+    }
+}
 impl From<String> for PriceSubscription {
     fn from(market_pair: String) -> Self {
         Self { market_pair }
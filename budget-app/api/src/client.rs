@@ -11,9 +11,8 @@ use database_table::{
 };
 use enum_paths::AsPath;
 use futures::future::FutureExt;
-use rql::Id;
+use database_table::Id;
 use seed::{
     self,
     Url,
 };
-use updatable::*;
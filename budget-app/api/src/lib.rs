@@ -12,19 +12,37 @@ mod server;
 pub use server::*;
 
 use app_model::{
-    project::Project,
+    auth::{
+        AuthUser,
+        Group,
+        Owned,
+        Resource,
+        Role,
+        TableAction,
+    },
+    project::{
+        Project,
+        ProjectUpdate,
+    },
     task::Task,
-    user::User,
+    user::{
+        PublicUser,
+        User,
+    },
+};
+#[cfg(not(target_arch = "wasm32"))]
+use app_model::auth::{
+    has_privilege,
+    has_row_privilege,
 };
 use database_table::*;
 use define_api::api;
 use futures::future::FutureExt;
 use interpreter::*;
-use rql::*;
 use seqraph::*;
-use updatable::*;
 
 api! {
+    #[owner(Project, id)]
     fn get_project_tasks(id: Id<Project>) -> Vec<Entry<Task>> {
         let ids = <Project as DatabaseTable<'_, Schema>>::get(id)
             .map(|entry| entry.data().tasks().clone())
@@ -32,23 +50,93 @@ api! {
         <Task as DatabaseTable<'_, Schema>>::get_list(ids)
     }
     fn get_user_projects(id: Id<User>) -> Vec<Entry<Project>> {
-        <Project as DatabaseTable<'_, Schema>>::filter(|project| project.members().contains(&id))
+        <Project as DatabaseTable<'_, Schema>>::filter_by(&*PROJECT_MEMBERS, &id)
+    }
+    #[owner(Project, project)]
+    fn project_create_subtask(project: Id<Project>, task: Task) -> Result<Id<Task>, RequestError> {
+        let mut tasks = <Project as DatabaseTable<'_, Schema>>::get(project)
+            .ok_or_else(|| RequestError::NotFound(format!("project {}", project)))?
+            .data()
+            .tasks()
+            .clone();
+        let id = Transaction::<Schema>::run(|tx| {
+            let id = tx.insert(task)?;
+            tasks.push(id);
+            tx.update(project, ProjectUpdate::default().tasks(tasks))?;
+            Ok::<_, TransactionError>(id)
+        })?;
+        Ok(id)
+    }
+    fn get_my_projects(user: AuthUser) -> Vec<Entry<Project>> {
+        <Project as DatabaseTable<'_, Schema>>::filter_by(&*PROJECT_MEMBERS, &user.id)
+    }
+    #[owner(Project, id)]
+    fn get_project_history(id: Id<Project>) -> Vec<AuditRecord<Project>> {
+        history::<Schema, Project>(id)
+    }
+    fn get_task_history(user: AuthUser, id: Id<Task>) -> Result<Vec<AuditRecord<Task>>, RequestError> {
+        check_task_access(&user, TableAction::Read, id)?;
+        Ok(history::<Schema, Task>(id))
+    }
+    #[owner(Project, id)]
+    fn restore_project(id: Id<Project>) -> Result<Project, RequestError> {
+        <Project as DatabaseTable<'_, Schema>>::restore(id)
+            .ok_or_else(|| RequestError::NotFound(format!("deleted project {}", id)))
+    }
+    fn restore_task(user: AuthUser, id: Id<Task>) -> Result<Task, RequestError> {
+        check_task_access(&user, TableAction::Update, id)?;
+        <Task as DatabaseTable<'_, Schema>>::restore(id)
+            .ok_or_else(|| RequestError::NotFound(format!("deleted task {}", id)))
+    }
+    #[public]
+    fn interpret_text(text: String) -> String {
+        interpreter::intent::IntentRegistry::default()
+            .recognize(&text)
+            .to_string()
     }
-    //fn project_create_subtask(project: Id<Project>, task: Task) -> Id<Task> {
-    //  let id = <Task as DatabaseTable>::insert(task);
-    //  <Project as DatabaseTable>::update(project, Project::update().tasks(vec![id.clone()]));
-    //  id
-    //}
-    //fn interpret_text(text: String) -> String {
-    //    let mut g = TG.lock().unwrap();
-    //    g.read_sequence(text.chars());
-    //    g.write_to_file("graphs/g1").unwrap();
-    //    "Done".into()
-    //}
     //fn query_text(query: String) -> Option<NodeInfo<char>> {
     //    TG.lock().unwrap().query(query.chars())
     //}
-    rest_api!(User);
-    rest_api!(Project);
-    rest_api!(Task);
+    fn get_user(id: Id<User>) -> Option<PublicUser> {
+        <User as DatabaseTable<'_, Schema>>::get(id).map(PublicUser::from)
+    }
+    fn get_users() -> Vec<PublicUser> {
+        <User as DatabaseTable<'_, Schema>>::get_all()
+            .into_iter()
+            .map(PublicUser::from)
+            .collect()
+    }
+    /// Users matching the query, which may only use the fields of `PublicUser`,
+    /// so neither passwords nor second factors can be probed
+    fn query_users(query: Query) -> Result<Vec<PublicUser>, RequestError> {
+        if let Some(field) = query.fields().into_iter().find(|field| !User::is_public_field(field)) {
+            return Err(RequestError::Forbidden(format!("query by {}", field)));
+        }
+        Ok(<User as DatabaseTable<'_, Schema>>::query(&query)
+            .items
+            .into_iter()
+            .map(PublicUser::from)
+            .collect())
+    }
+    #[owner(User, id)]
+    fn delete_user(id: Id<User>) -> Result<bool, DeleteError> {
+        <User as DatabaseTable<'_, Schema>>::try_delete(id).map(|user| user.is_some())
+    }
+    rest_api!(Project, owner);
+    rest_api!(Task, privileges);
+    rest_api!(Group, privileges);
+    rest_api!(Role, privileges);
+}
+/// Err unless `user` is a member of the project of the task `id`, or may
+/// take `action` on all tasks
+#[cfg(not(target_arch = "wasm32"))]
+fn check_task_access(user: &AuthUser, action: TableAction, id: Id<Task>) -> Result<(), RequestError> {
+    let member = <Project as DatabaseTable<'_, Schema>>::find(|project| project.tasks().contains(&id))
+        .map(|project| project.data().is_owner(project.id, user.id))
+        .unwrap_or(false);
+    if member || has_privilege::<Schema>(user, action, &Resource::table::<Task>()) {
+        Ok(())
+    } else {
+        Err(RequestError::Forbidden(format!("task {}", id)))
+    }
 }
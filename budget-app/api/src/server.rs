@@ -1,16 +1,44 @@
 use app_model::{
     auth::{
+        begin_login,
+        confirm_two_factor,
         credentials::*,
+        disable_two_factor,
+        enrol_two_factor,
+        grant_role,
+        hash_password,
+        join_group,
         jwt::*,
+        login_attempts,
+        make_admin,
+        role_names,
+        second_factor,
+        AuthUser,
+        CodeRequest,
+        Enrolment,
+        Group,
+        LoginAttempt,
+        LoginResponse,
+        Privilege,
+        RefreshRequest,
+        RefreshToken,
+        Role,
+        SecondFactorRequest,
+        TableAction,
+        MEMBERS,
     },
     project::Project,
     task::Task,
     user::User,
     UserSession,
 };
-use database_table::*;
+use database_table::{
+    backend::rql::RqlMigrationStore,
+    *,
+};
 use lazy_static::lazy_static;
 use rocket::{
+    get,
     http::*,
     post,
     request::FromParam,
@@ -19,86 +47,379 @@ use rocket::{
 use rocket_contrib::json::Json;
 use rql::*;
 //use seqraph::*;
-use std::convert::TryFrom;
-use std::sync::Mutex;
+use std::{
+    net::SocketAddr,
+    sync::Mutex,
+};
 
 schema! {
     pub Schema {
         user: User,
         task: Task,
         project: Project,
+        task_audit: AuditRecord<Task>,
+        project_audit: AuditRecord<Project>,
+        task_trash: Tombstone<Task>,
+        project_trash: Tombstone<Project>,
+        group: Group,
+        role: Role,
+        login_attempt: LoginAttempt,
+        refresh_token: RefreshToken,
+        schema_version: SchemaVersion,
+    }
+}
+// the tables of `Schema` with untyped rows, for migrations
+schema! {
+    RawSchema {
+        user: serde_json::Value,
+        task: serde_json::Value,
+        project: serde_json::Value,
+        task_audit: serde_json::Value,
+        project_audit: serde_json::Value,
+        task_trash: serde_json::Value,
+        project_trash: serde_json::Value,
+        group: serde_json::Value,
+        role: serde_json::Value,
+        login_attempt: serde_json::Value,
+        refresh_token: serde_json::Value,
+        schema_version: SchemaVersion,
     }
 }
 lazy_static! {
     //pub static ref TG: Mutex<SequenceGraph<char>> = Mutex::new(SequenceGraph::new());
     pub static ref DB: Schema = Schema::new("test_database", rql::BinaryStable).unwrap();
+    pub static ref JOURNAL: Journal = Journal::with_file("test_database/journal.jsonl")
+        .table::<Schema, User>()
+        .table::<Schema, Project>()
+        .table::<Schema, Task>()
+        .table::<Schema, AuditRecord<Task>>()
+        .table::<Schema, AuditRecord<Project>>()
+        .table::<Schema, Tombstone<Task>>()
+        .table::<Schema, Tombstone<Project>>()
+        .table::<Schema, Group>()
+        .table::<Schema, Role>()
+        .table::<Schema, LoginAttempt>()
+        .table::<Schema, RefreshToken>()
+        .table::<Schema, SchemaVersion>();
+    pub static ref USER_NAME: HashIndex<User, String> = HashIndex::unique("user.name", |user| user.name().clone());
+    pub static ref PROJECT_MEMBERS: HashIndex<Project, Id<User>> = HashIndex::multi("project.members", |project| project.members().clone());
+    pub static ref TASK_TRASH: HashIndex<Tombstone<Task>, Id<Task>> = Tombstone::row_index("task_trash.row");
+    pub static ref PROJECT_TRASH: HashIndex<Tombstone<Project>, Id<Project>> = Tombstone::row_index("project_trash.row");
 }
 impl<'db> Database<'db, User> for Schema {
-    fn table() -> TableGuard<'db, User> {
-        DB.user()
+    type Table = Journaled<'db, TableGuard<'db, User>>;
+    type TableMut = Journaled<'db, TableGuardMut<'db, User>>;
+    fn table() -> Self::Table {
+        JOURNAL.read(|| DB.user())
+    }
+    fn table_mut() -> Self::TableMut {
+        JOURNAL.write::<Self, User, _, _>(|| DB.user_mut())
     }
-    fn table_mut() -> TableGuardMut<'db, User> {
-        DB.user_mut()
+    fn indexes() -> Vec<&'db dyn TableIndex<User>> {
+        vec![&*USER_NAME]
+    }
+    fn references() -> Vec<Box<dyn Reference<User> + 'db>> {
+        vec![
+            Relation::<Schema, Project, User>::new(
+                "project.members",
+                DeletePolicy::Restrict,
+                |project| project.members().clone(),
+                Project::remove_member,
+            ).boxed(),
+            Relation::<Schema, Task, User>::new(
+                "task.assignees",
+                DeletePolicy::Nullify,
+                |task| task.assignees().clone(),
+                Task::remove_assignee,
+            ).boxed(),
+            Relation::<Schema, User, User>::new(
+                "user.followers",
+                DeletePolicy::Nullify,
+                |user| user.followers().clone(),
+                User::remove_follower,
+            ).boxed(),
+        ]
     }
 }
 impl<'db> Database<'db, Project> for Schema {
-    fn table() -> TableGuard<'db, Project> {
-        DB.project()
+    type Table = Journaled<'db, TableGuard<'db, Project>>;
+    type TableMut = Journaled<'db, TableGuardMut<'db, Project>>;
+    fn table() -> Self::Table {
+        JOURNAL.read(|| DB.project())
+    }
+    fn table_mut() -> Self::TableMut {
+        JOURNAL.write::<Self, Project, _, _>(|| DB.project_mut())
     }
-    fn table_mut() -> TableGuardMut<'db, Project> {
-        DB.project_mut()
+    fn indexes() -> Vec<&'db dyn TableIndex<Project>> {
+        vec![&*PROJECT_MEMBERS]
+    }
+    fn audit(record: AuditRecord<Project>) {
+        <Schema as Database<'db, AuditRecord<Project>>>::insert(record);
+    }
+    fn trash() -> Option<Box<dyn Trash<Project> + 'db>> {
+        Some(TombstoneTable::<Schema, Project>::new(&*PROJECT_TRASH).boxed())
     }
 }
 impl<'db> Database<'db, Task> for Schema {
-    fn table() -> TableGuard<'db, Task> {
-        DB.task()
+    type Table = Journaled<'db, TableGuard<'db, Task>>;
+    type TableMut = Journaled<'db, TableGuardMut<'db, Task>>;
+    fn table() -> Self::Table {
+        JOURNAL.read(|| DB.task())
+    }
+    fn table_mut() -> Self::TableMut {
+        JOURNAL.write::<Self, Task, _, _>(|| DB.task_mut())
+    }
+    fn references() -> Vec<Box<dyn Reference<Task> + 'db>> {
+        vec![
+            Relation::<Schema, Project, Task>::new(
+                "project.tasks",
+                DeletePolicy::Nullify,
+                |project| project.tasks().clone(),
+                Project::remove_task,
+            ).boxed(),
+            Relation::<Schema, Task, Task>::new(
+                "task.subtasks",
+                DeletePolicy::Nullify,
+                |task| task.subtasks().clone(),
+                Task::remove_subtask,
+            ).boxed(),
+        ]
+    }
+    fn audit(record: AuditRecord<Task>) {
+        <Schema as Database<'db, AuditRecord<Task>>>::insert(record);
+    }
+    fn trash() -> Option<Box<dyn Trash<Task> + 'db>> {
+        Some(TombstoneTable::<Schema, Task>::new(&*TASK_TRASH).boxed())
+    }
+}
+impl<'db> Database<'db, AuditRecord<Task>> for Schema {
+    type Table = Journaled<'db, TableGuard<'db, AuditRecord<Task>>>;
+    type TableMut = Journaled<'db, TableGuardMut<'db, AuditRecord<Task>>>;
+    fn table() -> Self::Table {
+        JOURNAL.read(|| DB.task_audit())
     }
-    fn table_mut() -> TableGuardMut<'db, Task> {
-        DB.task_mut()
+    fn table_mut() -> Self::TableMut {
+        JOURNAL.write::<Self, AuditRecord<Task>, _, _>(|| DB.task_audit_mut())
     }
 }
+impl<'db> Database<'db, AuditRecord<Project>> for Schema {
+    type Table = Journaled<'db, TableGuard<'db, AuditRecord<Project>>>;
+    type TableMut = Journaled<'db, TableGuardMut<'db, AuditRecord<Project>>>;
+    fn table() -> Self::Table {
+        JOURNAL.read(|| DB.project_audit())
+    }
+    fn table_mut() -> Self::TableMut {
+        JOURNAL.write::<Self, AuditRecord<Project>, _, _>(|| DB.project_audit_mut())
+    }
+}
+impl<'db> Database<'db, Tombstone<Task>> for Schema {
+    type Table = Journaled<'db, TableGuard<'db, Tombstone<Task>>>;
+    type TableMut = Journaled<'db, TableGuardMut<'db, Tombstone<Task>>>;
+    fn table() -> Self::Table {
+        JOURNAL.read(|| DB.task_trash())
+    }
+    fn table_mut() -> Self::TableMut {
+        JOURNAL.write::<Self, Tombstone<Task>, _, _>(|| DB.task_trash_mut())
+    }
+    fn indexes() -> Vec<&'db dyn TableIndex<Tombstone<Task>>> {
+        vec![&*TASK_TRASH]
+    }
+}
+impl<'db> Database<'db, Tombstone<Project>> for Schema {
+    type Table = Journaled<'db, TableGuard<'db, Tombstone<Project>>>;
+    type TableMut = Journaled<'db, TableGuardMut<'db, Tombstone<Project>>>;
+    fn table() -> Self::Table {
+        JOURNAL.read(|| DB.project_trash())
+    }
+    fn table_mut() -> Self::TableMut {
+        JOURNAL.write::<Self, Tombstone<Project>, _, _>(|| DB.project_trash_mut())
+    }
+    fn indexes() -> Vec<&'db dyn TableIndex<Tombstone<Project>>> {
+        vec![&*PROJECT_TRASH]
+    }
+}
+impl<'db> Database<'db, Group> for Schema {
+    type Table = Journaled<'db, TableGuard<'db, Group>>;
+    type TableMut = Journaled<'db, TableGuardMut<'db, Group>>;
+    fn table() -> Self::Table {
+        JOURNAL.read(|| DB.group())
+    }
+    fn table_mut() -> Self::TableMut {
+        JOURNAL.write::<Self, Group, _, _>(|| DB.group_mut())
+    }
+}
+impl<'db> Database<'db, Role> for Schema {
+    type Table = Journaled<'db, TableGuard<'db, Role>>;
+    type TableMut = Journaled<'db, TableGuardMut<'db, Role>>;
+    fn table() -> Self::Table {
+        JOURNAL.read(|| DB.role())
+    }
+    fn table_mut() -> Self::TableMut {
+        JOURNAL.write::<Self, Role, _, _>(|| DB.role_mut())
+    }
+}
+impl<'db> Database<'db, LoginAttempt> for Schema {
+    type Table = Journaled<'db, TableGuard<'db, LoginAttempt>>;
+    type TableMut = Journaled<'db, TableGuardMut<'db, LoginAttempt>>;
+    fn table() -> Self::Table {
+        JOURNAL.read(|| DB.login_attempt())
+    }
+    fn table_mut() -> Self::TableMut {
+        JOURNAL.write::<Self, LoginAttempt, _, _>(|| DB.login_attempt_mut())
+    }
+}
+impl<'db> Database<'db, RefreshToken> for Schema {
+    type Table = Journaled<'db, TableGuard<'db, RefreshToken>>;
+    type TableMut = Journaled<'db, TableGuardMut<'db, RefreshToken>>;
+    fn table() -> Self::Table {
+        JOURNAL.read(|| DB.refresh_token())
+    }
+    fn table_mut() -> Self::TableMut {
+        JOURNAL.write::<Self, RefreshToken, _, _>(|| DB.refresh_token_mut())
+    }
+}
+impl<'db> Database<'db, SchemaVersion> for Schema {
+    type Table = Journaled<'db, TableGuard<'db, SchemaVersion>>;
+    type TableMut = Journaled<'db, TableGuardMut<'db, SchemaVersion>>;
+    fn table() -> Self::Table {
+        JOURNAL.read(|| DB.schema_version())
+    }
+    fn table_mut() -> Self::TableMut {
+        JOURNAL.write::<Self, SchemaVersion, _, _>(|| DB.schema_version_mut())
+    }
+}
+impl<'db> Transactional<'db> for Schema {
+    fn begin() -> std::result::Result<(), TransactionError> {
+        JOURNAL.begin()
+    }
+    fn commit() -> std::result::Result<(), TransactionError> {
+        JOURNAL.commit()
+    }
+    fn rollback() -> std::result::Result<(), TransactionError> {
+        JOURNAL.rollback()
+    }
+}
+/// Migrations of all tables, register new ones at the end
+pub fn migrations() -> Migrations {
+    Migrations::new()
+}
+/// Upgrade the stored tables to the current types
+///
+/// Must run before `DB` is first used, rows which no longer match their
+/// type can not be loaded by it.
+pub fn migrate(options: &MigrationOptions) -> std::result::Result<MigrationReport, MigrationError> {
+    let raw = RawSchema::new("test_database", rql::BinaryStable)
+        .map_err(|e| MigrationError::Store(e.to_string()))?;
+    let store = RqlMigrationStore::new("test_database", || raw.schema_version_mut())
+        .table("user", || raw.user_mut())
+        .table("task", || raw.task_mut())
+        .table("project", || raw.project_mut())
+        .table("task_audit", || raw.task_audit_mut())
+        .table("project_audit", || raw.project_audit_mut())
+        .table("task_trash", || raw.task_trash_mut())
+        .table("project_trash", || raw.project_trash_mut())
+        .table("group", || raw.group_mut())
+        .table("role", || raw.role_mut())
+        .table("login_attempt", || raw.login_attempt_mut())
+        .table("refresh_token", || raw.refresh_token_mut());
+    migrations().run(&store, options)
+}
+/// Report dangling references in all tables, removing them if `repair` is set
+pub fn check_integrity(repair: bool) -> Vec<Dangling> {
+    let mut dangling = <User as DatabaseTable<'_, Schema>>::check_references(repair);
+    dangling.extend(<Task as DatabaseTable<'_, Schema>>::check_references(repair));
+    dangling
+}
+/// All tables of the schema, for the backup tool
+pub fn backup() -> Backup<'static, Schema> {
+    Backup::new()
+        .table::<User>("user")
+        .table::<Task>("task")
+        .table::<Project>("project")
+        .table::<AuditRecord<Task>>("task_audit")
+        .table::<AuditRecord<Project>>("project_audit")
+        .table::<Tombstone<Task>>("task_trash")
+        .table::<Tombstone<Project>>("project_trash")
+        .table::<Group>("group")
+        .table::<Role>("role")
+        .table::<LoginAttempt>("login_attempt")
+        .table::<RefreshToken>("refresh_token")
+        .table::<SchemaVersion>("schema_version")
+}
+/// Give the user `name` every privilege, e.g. `server admin <name>`
+pub fn grant_admin(name: &str) -> std::result::Result<(), String> {
+    let user = <User as DatabaseTable<'_, Schema>>::find_by(&*USER_NAME, &name.to_string())
+        .ok_or_else(|| format!("User {} not found", name))?;
+    make_admin::<Schema>(user.id);
+    Ok(())
+}
+
+/// Let registered users read and change the tasks assigned to them through
+/// the REST functions, other tasks of their projects are reached through
+/// the functions of the project
+pub fn grant_member_role() {
+    let role = [TableAction::Read, TableAction::Update, TableAction::Delete]
+        .iter()
+        .fold(Role::new("member"), |role, action| role.with_privilege(Privilege::owned::<Task>(*action)));
+    grant_role::<Schema>(MEMBERS, role);
+}
 
+/// Status of an error of the auth functions of `app_model`
+fn auth_status(error: app_model::auth::Error) -> Status {
+    Status::from_code(error.status().into()).unwrap_or(Status::InternalServerError)
+}
 #[post("/api/auth/login", data = "<credentials>")]
-pub fn login(credentials: Json<Credentials>) -> std::result::Result<Json<UserSession>, Status> {
-    let credentials = credentials.into_inner();
-    <User as DatabaseTable<'_, Schema>>::find(|user| *user.name() == credentials.username)
-        .ok_or(Status::NotFound)
-        .and_then(|entry| {
-            let user = entry.data();
-            if *user.password() == credentials.password {
-                Ok(entry)
-            } else {
-                Err(Status::Unauthorized)
-            }
-        })
-        .and_then(|entry| {
-            let user = entry.data().clone();
-            let id = entry.id().clone();
-            JWT::try_from(&user)
-                .map_err(|_| Status::InternalServerError)
-                .map(move |jwt| (id, jwt))
-        })
-        .map(|(id, jwt)| {
-            Json(UserSession {
-                user_id: id.clone(),
-                token: jwt.to_string(),
-            })
-        })
-}
-#[post("/api/auth/register", data = "<user>")]
-pub fn register(user: Json<User>) -> std::result::Result<Json<UserSession>, Status> {
-    let user = user.into_inner();
-    if <User as DatabaseTable<'_, Schema>>::find(|u| u.name() == user.name()).is_none() {
-        let id = <User as DatabaseTable<'_, Schema>>::insert(user.clone());
-        JWT::try_from(&user)
-            .map_err(|_| Status::InternalServerError)
-            .map(move |jwt| {
-                Json(UserSession {
-                    user_id: id.clone(),
-                    token: jwt.to_string(),
-                })
-            })
-    } else {
-        Err(Status::Conflict)
-    }
+pub fn login(credentials: Json<Credentials>, remote: Option<SocketAddr>) -> std::result::Result<Json<LoginResponse>, Status> {
+    begin_login::<Schema>(&credentials, remote.map(|addr| addr.ip()))
+        .map(Json)
+        .map_err(auth_status)
+}
+/// Second step of logins into accounts with a second factor
+#[post("/api/auth/second_factor", data = "<request>")]
+pub fn login_second_factor(request: Json<SecondFactorRequest>, remote: Option<SocketAddr>) -> std::result::Result<Json<UserSession>, Status> {
+    second_factor::<Schema>(request.into_inner(), remote.map(|addr| addr.ip()))
+        .map(Json)
+        .map_err(auth_status)
+}
+#[post("/api/auth/two_factor/enrol")]
+pub fn two_factor_enrol(user: AuthUser) -> std::result::Result<Json<Enrolment>, Status> {
+    enrol_two_factor::<Schema>(user.id, "Budget App")
+        .map(Json)
+        .map_err(auth_status)
+}
+#[post("/api/auth/two_factor/confirm", data = "<request>")]
+pub fn two_factor_confirm(user: AuthUser, request: Json<CodeRequest>) -> std::result::Result<(), Status> {
+    confirm_two_factor::<Schema>(user.id, &request.code).map_err(auth_status)
+}
+#[post("/api/auth/two_factor/disable", data = "<request>")]
+pub fn two_factor_disable(user: AuthUser, request: Json<CodeRequest>) -> std::result::Result<(), Status> {
+    disable_two_factor::<Schema>(user.id, &request.code).map_err(auth_status)
+}
+/// Login attempts into the account of the caller
+#[get("/api/auth/attempts")]
+pub fn attempts(user: AuthUser) -> Json<Vec<LoginAttempt>> {
+    Json(login_attempts::<Schema>(user.id))
+}
+#[post("/api/auth/refresh", data = "<request>")]
+pub fn refresh(request: Json<RefreshRequest>) -> std::result::Result<Json<UserSession>, Status> {
+    app_model::auth::refresh::<Schema>(request.into_inner())
+        .map(Json)
+        .map_err(auth_status)
+}
+#[post("/api/auth/logout", data = "<request>")]
+pub fn logout(request: Json<RefreshRequest>) -> Status {
+    app_model::auth::logout::<Schema>(request.into_inner());
+    Status::Ok
+}
+/// Only the credentials are taken from the client, other fields of the user start empty
+#[post("/api/auth/register", data = "<credentials>")]
+pub fn register(credentials: Json<Credentials>) -> std::result::Result<Json<UserSession>, Status> {
+    let user = hash_password(User::from(credentials.into_inner()))
+        .map_err(|_| Status::InternalServerError)?;
+    let id = <User as DatabaseTable<'_, Schema>>::try_insert(user.clone())
+        .map_err(|_| Status::Conflict)?;
+    join_group::<Schema>(id, MEMBERS);
+    UserSession::issue::<Schema>(&Entry::new(id, user), role_names::<Schema>(id))
+        .map(Json)
+        .map_err(|_| Status::InternalServerError)
 }
@@ -10,12 +10,10 @@ extern crate enum_paths;
 extern crate futures;
 extern crate interpreter;
 extern crate lazy_static;
-extern crate rql;
 extern crate seed;
 extern crate seqraph;
 extern crate serde;
 extern crate serde_json;
-extern crate updatable;
 extern crate url;
 extern crate wasm_bindgen;
 extern crate wasm_bindgen_futures;
@@ -9,7 +9,7 @@ use app_model::{
 	project,
 	task,
 	user,
-	user::User,
+	user::PublicUser,
 };
 use components::{
 	list,
@@ -17,7 +17,12 @@ use components::{
 	Init,
 	Viewable,
 };
-use database_table::Routable;
+use database_table::{
+	Direction,
+	Query,
+	Routable,
+};
+use enum_paths::AsPath;
 use seed::{
 	prelude::*,
 	*,
@@ -30,7 +35,7 @@ pub enum Page {
 	Login(login::Login),
 	Register(register::Register),
 	UserProfile(user::profile::Model),
-	UserList(list::List<User>),
+	UserList(Vec<PublicUser>),
 	ProjectList(project::list::Model),
 	ProjectProfile(project::profile::Model),
 	TaskProfile(task::profile::Model),
@@ -53,12 +58,18 @@ impl Init<Route> for Page {
 			Route::User(route) => {
 				match route {
 					user::Route::Users => {
-						Self::UserList(Init::init(
-							list::Msg::GetAll,
-							&mut orders.proxy(Msg::UserList),
-						))
+						let query = Query::new()
+							.sort("credentials.username", Direction::Ascending)
+							.offset(0, 20);
+						orders.perform_cmd(async move {
+							Msg::UserList(api::query_users(query).await.map_err(|e| e.to_string()))
+						});
+						Self::UserList(Vec::new())
 					}
 					user::Route::User(id) => {
+						orders.perform_cmd(async move {
+							Msg::UserProfile(user::profile::Msg::Got(api::get_user(id).await.map_err(|e| e.to_string())))
+						});
 						Self::UserProfile(Init::init(id, &mut orders.proxy(Msg::UserProfile)))
 					}
 				}
@@ -95,7 +106,7 @@ impl From<Page> for Route {
 			Page::Login(_) => Route::Auth(auth::Route::Login),
 			Page::Register(_) => Route::Auth(auth::Route::Register),
 			Page::UserList(_) => Route::User(user::Route::Users),
-			Page::UserProfile(profile) => Route::User(profile.entry.route()),
+			Page::UserProfile(profile) => Route::User(user::Route::User(profile.id)),
 			Page::ProjectProfile(profile) => Route::Project(profile.entry.route()),
 			Page::ProjectList(_) => Route::Project(project::Route::Projects),
 			Page::TaskProfile(profile) => Route::Task(profile.entry.route()),
@@ -107,7 +118,7 @@ pub enum Msg {
 	Home(home::Msg),
 	Login(login::Msg),
 	Register(register::Msg),
-	UserList(list::Msg<User>),
+	UserList(Result<Vec<PublicUser>, String>),
 	UserProfile(user::profile::Msg),
 	ProjectList(project::list::Msg),
 	ProjectProfile(project::profile::Msg),
@@ -145,11 +156,10 @@ impl Component for Page {
 					_ => {}
 				}
 			}
-			Msg::UserList(msg) => {
-				match self {
-					Self::UserList(list) => {
-						list.update(msg, &mut orders.proxy(Msg::UserList));
-					}
+			Msg::UserList(res) => {
+				match (self, res) {
+					(Self::UserList(list), Ok(users)) => *list = users,
+					(_, Err(e)) => seed::log(e),
 					_ => {}
 				}
 			}
@@ -195,7 +205,14 @@ impl Viewable for Page {
 			Self::Home(model) => model.view().map_msg(Msg::Home),
 			Self::Login(model) => model.view().map_msg(Msg::Login),
 			Self::Register(model) => model.view().map_msg(Msg::Register),
-			Self::UserList(model) => model.view().map_msg(Msg::UserList),
+			Self::UserList(users) => ul![
+				users.iter().map(|entry| li![
+					a![
+						entry.name.as_str(),
+						attrs! { At::Href => Route::User(user::Route::User(entry.id)).as_path() },
+					],
+				])
+			],
 			Self::UserProfile(model) => model.view().map_msg(Msg::UserProfile),
 			Self::ProjectList(model) => model.view().map_msg(Msg::ProjectList),
 			Self::ProjectProfile(model) => model.view().map_msg(Msg::ProjectProfile),
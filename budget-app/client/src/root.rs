@@ -14,7 +14,11 @@ use components::{
 	Init,
 	Viewable,
 };
-use database_table::Routable;
+use database_table::{
+	set_client_config,
+	ClientConfig,
+	Routable,
+};
 use enum_paths::{
 	AsPath,
 	ParsePath,
@@ -27,6 +31,10 @@ use seed::{
 #[wasm_bindgen(start)]
 pub fn render() {
 	std::panic::set_hook(Box::new(console_error_panic_hook::hook));
+	// send api calls to the server this app was loaded from
+	if let Ok(base_url) = app_model::get_base_url() {
+		set_client_config(ClientConfig::new(base_url));
+	}
 	App::start(
 		"app",
 		|url, orders| Model::init(url, orders),
@@ -10,6 +10,10 @@ use rocket::{
 };
 use define_api::rest_handlers;
 use anyhow::anyhow;
+use tracing::{
+	info,
+	warn,
+};
 use std::io::Result;
 use std::path::Path;
 use std::str::FromStr;
@@ -96,6 +100,83 @@ fn token_valid(token: JWT) {
 	let _ = token;
 }
 fn main() {
+	tracing_subscriber::fmt::init();
+	let args: Vec<String> = std::env::args().skip(1).collect();
+	// roll back a transaction interrupted by a crash before the tables are used
+	match api::JOURNAL.recover() {
+		Ok(0) => {}
+		Ok(rows) => info!("Rolled back {} rows of an unfinished transaction", rows),
+		Err(e) => {
+			eprintln!("{}", e);
+			std::process::exit(1);
+		}
+	}
+	// `migrate --dry-run` only reports, everything else needs migrated tables
+	let dry_run = args.first().map(String::as_str) == Some("migrate")
+		&& args.iter().any(|arg| arg == "--dry-run");
+	let options = database_table::MigrationOptions {
+		dry_run,
+		backup: Some("test_database.backup".into()),
+	};
+	match api::migrate(&options) {
+		Ok(report) => {
+			for table in &report.tables {
+				info!(
+					"{} {} rows of {} from version {} to {}",
+					if dry_run { "Would migrate" } else { "Migrated" },
+					table.rows, table.table, table.from, table.to,
+				);
+			}
+		}
+		Err(e) => {
+			eprintln!("{}", e);
+			std::process::exit(1);
+		}
+	}
+	if args.first().map(String::as_str) == Some("migrate") {
+		return;
+	}
+	if args.first().map(String::as_str) == Some("backup") {
+		match api::backup().command(&args[1..]) {
+			Ok(report) => println!("{}", report),
+			Err(e) => {
+				eprintln!("{}", e);
+				std::process::exit(1);
+			}
+		}
+		return;
+	}
+	if args.first().map(String::as_str) == Some("admin") {
+		let name = args.get(1).map(String::as_str).unwrap_or_default();
+		if let Err(e) = api::grant_admin(name) {
+			eprintln!("{}", e);
+			std::process::exit(1);
+		}
+		return;
+	}
+	// `integrity --repair` removes the dangling references reported here
+	if args.first().map(String::as_str) == Some("integrity") {
+		let repair = args.iter().any(|arg| arg == "--repair");
+		for dangling in api::check_integrity(repair) {
+			println!(
+				"{} dangling reference {} from {} in {}",
+				if repair { "Removed" } else { "Found" },
+				dangling.target, dangling.row, dangling.relation,
+			);
+		}
+		return;
+	}
+	let dangling = api::check_integrity(false);
+	for dangling in &dangling {
+		warn!(
+			"Dangling reference {} from {} in {}",
+			dangling.target, dangling.row, dangling.relation,
+		);
+	}
+	if !dangling.is_empty() {
+		warn!("Run `server integrity --repair` to remove {} dangling references", dangling.len());
+	}
+	api::grant_member_role();
 	rocket::custom(
 		rocket::Config::build(rocket::config::Environment::Staging)
 			.address("0.0.0.0")
@@ -103,6 +184,8 @@ fn main() {
 			.finalize()
 			.unwrap(),
 	)
+	// served over plain http
+	.attach(session_service::CsrfFairing::new().secure(false))
 	.mount(
 		"/",
 		vec![
@@ -118,15 +201,33 @@ fn main() {
 				get_img_file,
 				api::login,
 				api::register,
+				api::refresh,
+				api::logout,
+				api::attempts,
+				api::login_second_factor,
+				api::two_factor_enrol,
+				api::two_factor_confirm,
+				api::two_factor_disable,
 				api::handlers::get_user_projects,
 				api::handlers::get_project_tasks,
-				//api::handlers::project_create_subtask,
+				api::handlers::project_create_subtask,
+				api::handlers::get_my_projects,
+				api::handlers::get_project_history,
+				api::handlers::get_task_history,
+				api::handlers::restore_project,
+				api::handlers::restore_task,
 				api::handlers::interpret_text,
 				api::handlers::query_text,
+				api::handlers::openapi_json,
+				api::handlers::get_user,
+				api::handlers::get_users,
+				api::handlers::query_users,
+				api::handlers::delete_user,
 			],
 			rest_handlers!(Task),
 			rest_handlers!(Project),
-			rest_handlers!(User),
+			rest_handlers!(Group),
+			rest_handlers!(Role),
 		]
 		.concat(),
 	)
@@ -1,6 +1,9 @@
 use crate::currency::Currency;
 use crate::currency::*;
-use crate::interpreter::parse::*;
+use crate::interpreter::{
+	intent::Intent,
+	parse::*,
+};
 use crate::purpose::{
 	Purpose,
 	Purposes,
@@ -191,6 +194,45 @@ impl<'a> Parse<'a> for Transaction<Euro> {
 		)
 	);
 }
+fn subject_of(name: String) -> Subject {
+	match name.to_lowercase().as_str() {
+		"i" | "me" => Subject::Me,
+		_ => Subject::from(name),
+	}
+}
+impl Transaction<Euro> {
+	/// Build a transaction from a recognized `Intent::RecordTransaction`
+	pub fn from_intent(intent: Intent) -> Option<Self> {
+		match intent {
+			Intent::RecordTransaction {
+				date,
+				subject,
+				action,
+				amount,
+				counterpart,
+				purpose,
+			} => {
+				let mut t = Transaction::default();
+				if let Some(d) = date {
+					t.set_date(d);
+				}
+				t.set_amount(match action {
+					Action::Get => Euro::from(amount),
+					Action::Give => -Euro::from(amount),
+				});
+				t.set_sender(subject_of(subject));
+				if let Some(name) = counterpart {
+					t.set_recipient(subject_of(name));
+				}
+				if let Some(p) = purpose {
+					t.add_purpose(p.as_str());
+				}
+				Some(t)
+			}
+			_ => None,
+		}
+	}
+}
 mod tests {
 	#[allow(unused)]
 	use super::*;
@@ -235,4 +277,17 @@ mod tests {
 				}
 		);
 	}
+	#[test]
+	fn from_intent() {
+		use crate::interpreter::intent::IntentRegistry;
+		let intent = IntentRegistry::default()
+			.recognize("I got 20 Euros from Alice for rent")
+			.intent()
+			.unwrap();
+		let t = Transaction::from_intent(intent).unwrap();
+		assert_eq!(t.amount, Euro::from(20));
+		assert_eq!(t.sender, Subject::Me);
+		assert_eq!(t.recipient, Some(Subject::from("Alice")));
+		assert_eq!(t.purposes, Some(Purposes::from(vec![Purpose::from("rent")])));
+	}
 }
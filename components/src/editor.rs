@@ -9,8 +9,10 @@ use crate::{
 use database_table::{
     Entry,
     RemoteTable,
+    Updatable,
 };
-use rql::Id;
+use database_table::Id;
+use futures::future::FutureExt;
 use seed::{
     prelude::*,
     *,
@@ -34,7 +36,8 @@ impl<T: RemoteTable + Edit + Debug> Edit for Entry<T> {
 
 #[derive(Debug, Clone)]
 pub enum Editor<T: RemoteTable> {
-    Remote(remote::Remote<T>),
+    /// remote entry with the data it was loaded with, to send only changed fields
+    Remote(remote::Remote<T>, Option<T>),
     New(newdata::NewData<T>),
 }
 impl<T: RemoteTable + Default> Default for Editor<T> {
@@ -49,12 +52,13 @@ impl<T: RemoteTable + Component> From<Entry<T>> for Editor<T> {
 }
 impl<T: RemoteTable> From<remote::Remote<T>> for Editor<T> {
     fn from(model: remote::Remote<T>) -> Self {
-        Self::Remote(model)
+        let original = model.entry().map(|entry| entry.data.clone());
+        Self::Remote(model, original)
     }
 }
 impl<T: RemoteTable + Component + Debug + Clone> Init<Id<T>> for Editor<T> {
     fn init(id: Id<T>, orders: &mut impl Orders<Msg<T>>) -> Self {
-        Self::Remote(Init::init(id, &mut orders.proxy(Msg::Remote)))
+        Self::Remote(Init::init(id, &mut orders.proxy(Msg::Remote)), None)
     }
 }
 #[derive(Debug, Clone)]
@@ -63,17 +67,19 @@ pub enum Msg<T: Component + RemoteTable + std::fmt::Debug> {
     Submit,
     New(newdata::Msg<T>),
     Remote(remote::Msg<T>),
+    Updated(Result<Option<T>, <T as RemoteTable>::Error>),
 }
 impl<T: Component + RemoteTable + std::fmt::Debug> Msg<T> {
     pub fn is_response(&self) -> bool {
         match self {
             Self::New(msg) => msg.is_response(),
             Self::Remote(msg) => msg.is_response(),
+            Self::Updated(_) => true,
             _ => false
         }
     }
 }
-impl<T: Component + RemoteTable + Debug + Clone> Component for Editor<T> {
+impl<T: Component + RemoteTable + Updatable + Debug + Clone> Component for Editor<T> {
     type Msg = Msg<T>;
     fn update(&mut self, msg: Msg<T>, orders: &mut impl Orders<Msg<T>>) {
         debug!("Editor message: {:#?}", msg);
@@ -82,14 +88,32 @@ impl<T: Component + RemoteTable + Debug + Clone> Component for Editor<T> {
             Msg::Submit => {
                 match self {
                     Self::New(new) => new.update(newdata::Msg::Post, &mut orders.proxy(Msg::New)),
-                    Self::Remote(remote) => {
-                        remote.update(
-                            remote::Msg::Entry(entry::Msg::Update),
-                            &mut orders.proxy(Msg::Remote),
-                            )
+                    Self::Remote(remote, original) => {
+                        if let (Some(entry), Some(original)) = (remote.entry(), original) {
+                            let update = original.diff(&entry.data);
+                            if update != Default::default() {
+                                orders.perform_cmd(
+                                    <T as RemoteTable>::update(entry.id, update).map(Msg::Updated)
+                                );
+                            }
+                        }
                     }
                 }
             }
+            Msg::Updated(res) => {
+                match res {
+                    Ok(Some(data)) => {
+                        if let Self::Remote(remote, original) = self {
+                            if let Some(entry) = remote.entry_mut() {
+                                entry.data = data.clone();
+                            }
+                            *original = Some(data);
+                        }
+                    }
+                    Ok(None) => debug!("Updated entry not found"),
+                    Err(e) => error!("{:?}", e),
+                }
+            }
             Msg::New(msg) => {
                 match self {
                     Self::New(new) => new.update(msg, &mut orders.proxy(Msg::New)),
@@ -98,7 +122,12 @@ impl<T: Component + RemoteTable + Debug + Clone> Component for Editor<T> {
             }
             Msg::Remote(msg) => {
                 match self {
-                    Self::Remote(remote) => remote.update(msg, &mut orders.proxy(Msg::Remote)),
+                    Self::Remote(remote, original) => {
+                        remote.update(msg, &mut orders.proxy(Msg::Remote));
+                        if original.is_none() {
+                            *original = remote.entry().map(|entry| entry.data.clone());
+                        }
+                    }
                     _ => {}
                 }
             }
@@ -135,7 +164,7 @@ impl<T: Component + RemoteTable + Edit + Debug + Clone> Viewable for Editor<T> {
                         "Create"
                     ],
                     ],
-                    Self::Remote(entry) =>
+                    Self::Remote(entry, _) =>
                         div![
                         h1!["Edit"],
                         entry.edit().map_msg(Msg::Remote),
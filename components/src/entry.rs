@@ -24,9 +24,6 @@ pub enum Msg<T: RemoteTable + Component + Debug> {
     Delete,
     Deleted(Result<Option<T>, <T as RemoteTable>::Error>),
 
-    Update,
-    Updated(Result<Option<T>, <T as RemoteTable>::Error>),
-
     Data(<T as Component>::Msg),
 }
 use futures::future::FutureExt;
@@ -66,22 +63,6 @@ impl<T: RemoteTable + Component> Component for Entry<T> {
                     }
                 }
             }
-            Msg::Update => {
-                //orders.perform_cmd(
-                //	<T as RemoteTable>::update(self.id, T::Update::from(self.data.clone()))
-                //		.map(|res| Msg::Updated(res))
-                //);
-            }
-            Msg::Updated(res) => {
-                match res {
-                    Ok(r) => {
-                        seed::log(r);
-                    }
-                    Err(e) => {
-                        seed::log(e);
-                    }
-                }
-            }
             Msg::Data(msg) => {
                 self.data.update(msg, &mut orders.proxy(Msg::Data));
             }
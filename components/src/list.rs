@@ -5,54 +5,157 @@ use crate::{
     Viewable,
 };
 use database_table::{
+    serde_json,
+    Change,
     Entry,
+    Id,
+    Page,
+    Pagination,
+    Query,
     RemoteTable,
 };
 use seed::{
     prelude::*,
     *,
 };
+use serde::Serialize;
 use std::fmt::Debug;
 use std::default::Default;
+use std::rc::Rc;
 use std::result::Result;
 
 #[derive(Debug, Clone, Default)]
 pub struct List<D: RemoteTable, T: Component + RemoteTable<D> = D> {
+    /// ids of the items, to apply changes
+    ids: Vec<Id<D>>,
     items: Vec<T>,
+    /// query of the current page, if paging
+    query: Option<Query>,
+    total: usize,
+    /// subscription to changes notified by the app, see `Msg::Changed`
+    changes: Option<Rc<SubHandle>>,
     _ty: std::marker::PhantomData<D>,
 }
 impl<D: RemoteTable, T: Component + RemoteTable<D>> List<D, T> {
     pub fn new() -> Self {
         Self {
+            ids: Vec::new(),
             items: Vec::new(),
+            query: None,
+            total: 0,
+            changes: None,
             _ty: Default::default(),
         }
     }
+    /// offset and limit of the current page
+    fn window(&self) -> Option<(usize, usize)> {
+        match self.query.as_ref()?.pagination {
+            Some(Pagination::Offset { offset, limit }) => Some((offset, limit)),
+            _ => None,
+        }
+    }
+    /// query for the page starting at `offset`
+    fn page_query(&self, offset: usize) -> Option<Query> {
+        let (_, limit) = self.window()?;
+        self.query.clone().map(|query| query.offset(offset, limit))
+    }
 }
 impl<D: RemoteTable, T: Component + RemoteTable<D>> Init<Msg<D, T>> for List<D, T> {
     fn init(msg: Msg<D, T>, orders: &mut impl Orders<Msg<D, T>>) -> Self {
         orders.send_msg(msg);
-        Self::new()
+        Self {
+            changes: Some(Rc::new(orders.subscribe_with_handle(Msg::<D, T>::Changed))),
+            ..Self::new()
+        }
     }
 }
 impl<D: RemoteTable, T: Component + RemoteTable<D>> From<Vec<Entry<D>>> for List<D, T> {
     fn from(entries: Vec<Entry<D>>) -> Self {
-        Self {
-            items: into_items(entries),
-            _ty: Default::default(),
-        }
+        let mut list = Self::new();
+        list.set_entries(entries);
+        list
     }
 }
-fn into_items<D: RemoteTable, T: Component + RemoteTable<D>>(entries: Vec<Entry<D>>) -> Vec<T> {
-    entries.into_iter().map(|e| e.into_inner().into()).collect()
+impl<D: RemoteTable, T: Component + RemoteTable<D>> List<D, T> {
+    fn set_entries(&mut self, entries: Vec<Entry<D>>) {
+        let (ids, items) = entries
+            .into_iter()
+            .map(|e| (e.id, e.into_inner().into()))
+            .unzip();
+        self.ids = ids;
+        self.items = items;
+    }
+    fn position(&self, id: Id<D>) -> Option<usize> {
+        self.ids.iter().position(|other| *other == id)
+    }
+    fn remove(&mut self, index: usize) {
+        self.total = self.total.saturating_sub(1);
+        self.ids.remove(index);
+        self.items.remove(index);
+    }
+}
+impl<D: RemoteTable + Serialize, T: Component + RemoteTable<D>> List<D, T> {
+    /// Whether `data` passes the filters of the current query
+    fn matches(&self, data: &D) -> bool {
+        match &self.query {
+            Some(query) => {
+                serde_json::to_value(data)
+                    .map(|row| query.matches(&row))
+                    .unwrap_or(false)
+            }
+            None => true,
+        }
+    }
+    /// Apply a change pushed by the server, ignoring rows outside of the query
+    fn apply(&mut self, change: Change<D>) {
+        match change {
+            Change::Inserted(entry) => {
+                if !self.matches(entry.data()) {
+                    return;
+                }
+                self.total += 1;
+                // inserted rows would move the current page
+                if self.window().is_none() {
+                    self.ids.push(entry.id);
+                    self.items.push(entry.into_inner().into());
+                }
+            }
+            Change::Updated(entry) => {
+                let matches = self.matches(entry.data());
+                match self.position(entry.id) {
+                    Some(index) if matches => self.items[index] = entry.into_inner().into(),
+                    Some(index) => self.remove(index),
+                    None if matches && self.window().is_none() => {
+                        self.total += 1;
+                        self.ids.push(entry.id);
+                        self.items.push(entry.into_inner().into());
+                    }
+                    None => {}
+                }
+            }
+            Change::Deleted(id) => {
+                if let Some(index) = self.position(id) {
+                    self.remove(index);
+                }
+            }
+        }
+    }
 }
 #[derive(Debug, Clone)]
 pub enum Msg<D: RemoteTable, T: Component + RemoteTable<D> = D> {
     GetAll,
     All(Result<Vec<Entry<D>>, <T as RemoteTable<D>>::Error>),
+    /// get the page of a query with offset pagination
+    Query(Query),
+    Page(Result<Page<D>, <T as RemoteTable<D>>::Error>),
+    NextPage,
+    PreviousPage,
+    /// a change pushed by the server, lists created with `init` receive
+    /// every `Change<D>` notified with `orders.notify`
+    Changed(Change<D>),
     Item(usize, <T as Component>::Msg),
 }
-impl<D: RemoteTable, T: Component + RemoteTable<D>> Component for List<D, T> {
+impl<D: RemoteTable + Serialize, T: Component + RemoteTable<D>> Component for List<D, T> {
     type Msg = Msg<D, T>;
     fn update(&mut self, msg: Self::Msg, orders: &mut impl Orders<Msg<D, T>>) {
         match msg {
@@ -63,12 +166,48 @@ impl<D: RemoteTable, T: Component + RemoteTable<D>> Component for List<D, T> {
             }
             Msg::All(res) => {
                 match res {
-                    Ok(entries) => self.items = into_items(entries),
+                    Ok(entries) => self.set_entries(entries),
                     Err(e) => {
                         seed::log(e);
                     }
                 }
             }
+            Msg::Query(query) => {
+                self.query = Some(query.clone());
+                orders.perform_cmd(
+                    <T as RemoteTable<D>>::query(query).map(Msg::<D, T>::Page)
+                    );
+            }
+            Msg::Page(res) => {
+                match res {
+                    Ok(page) => {
+                        self.total = page.total;
+                        self.set_entries(page.items);
+                    }
+                    Err(e) => {
+                        seed::log(e);
+                    }
+                }
+            }
+            Msg::NextPage => {
+                if let Some((offset, limit)) = self.window() {
+                    if offset + limit < self.total {
+                        if let Some(query) = self.page_query(offset + limit) {
+                            orders.send_msg(Msg::Query(query));
+                        }
+                    }
+                }
+            }
+            Msg::PreviousPage => {
+                if let Some((offset, limit)) = self.window() {
+                    if offset > 0 {
+                        if let Some(query) = self.page_query(offset.saturating_sub(limit)) {
+                            orders.send_msg(Msg::Query(query));
+                        }
+                    }
+                }
+            }
+            Msg::Changed(change) => self.apply(change),
             Msg::Item(index, msg) => {
                 //if let preview::Msg::Entry(entry::Msg::Deleted(_)) = msg {
                 //	self.previews.remove(index);
@@ -84,9 +223,37 @@ impl<D: RemoteTable, T: Component + RemoteTable<D>> Component for List<D, T> {
 }
 impl<D: RemoteTable, T: Component + preview::Previewable + RemoteTable<D>> Viewable for List<D, T> {
     fn view(&self) -> Node<Msg<D, T>> {
-        div![ul![self.items.iter().enumerate().map(|(i, item)| {
-            li![item.preview()
-                .map_msg(move |msg| Msg::Item(i.clone(), msg))]
-        })]]
+        div![
+            ul![self.items.iter().enumerate().map(|(i, item)| {
+                li![item.preview()
+                    .map_msg(move |msg| Msg::Item(i.clone(), msg))]
+            })],
+            self.window().map(|(offset, _)| {
+                div![
+                    button![
+                        attrs! {
+                            At::Type => "button",
+                            At::Disabled => (offset == 0).as_at_value(),
+                        },
+                        ev(Ev::Click, |_| Msg::<D, T>::PreviousPage),
+                        "Previous"
+                    ],
+                    span![format!(
+                        "{}-{} of {}",
+                        (offset + 1).min(self.total),
+                        offset + self.items.len(),
+                        self.total,
+                    )],
+                    button![
+                        attrs! {
+                            At::Type => "button",
+                            At::Disabled => (offset + self.items.len() >= self.total).as_at_value(),
+                        },
+                        ev(Ev::Click, |_| Msg::<D, T>::NextPage),
+                        "Next"
+                    ],
+                ]
+            }),
+        ]
     }
 }
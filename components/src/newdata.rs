@@ -4,7 +4,7 @@ use crate::{
     Viewable,
 };
 use database_table::RemoteTable;
-use rql::Id;
+use database_table::Id;
 use seed::prelude::*;
 use std::fmt::Debug;
 #[allow(unused)]
@@ -9,11 +9,12 @@ use crate::{
     Viewable,
 };
 use database_table::{
+    Change,
     Entry,
     Routable,
     RemoteTable,
 };
-use rql::Id;
+use database_table::Id;
 use seed::{
     prelude::*,
     *,
@@ -25,6 +26,20 @@ pub enum Remote<T: RemoteTable> {
     Loading(Id<T>),
     Ready(Entry<T>),
 }
+impl<T: RemoteTable> Remote<T> {
+    pub fn entry(&self) -> Option<&Entry<T>> {
+        match self {
+            Self::Ready(entry) => Some(entry),
+            Self::Loading(_) => None,
+        }
+    }
+    pub fn entry_mut(&mut self) -> Option<&mut Entry<T>> {
+        match self {
+            Self::Ready(entry) => Some(entry),
+            Self::Loading(_) => None,
+        }
+    }
+}
 impl<T: RemoteTable + Component + Debug> Init<Id<T>> for Remote<T> {
     fn init(id: Id<T>, orders: &mut impl Orders<Msg<T>>) -> Self {
         orders.send_msg(Msg::Get);
@@ -41,6 +56,8 @@ pub enum Msg<T: Component + RemoteTable> {
     Get,
     Got(Option<Entry<T>>),
     Entry(entry::Msg<T>),
+    /// a change pushed by the server, forwarded by the parent
+    Changed(Change<T>),
 }
 impl<T: Component + RemoteTable> Msg<T> {
     pub fn is_response(&self) -> bool {
@@ -69,6 +86,11 @@ impl<T: RemoteTable + Component + Debug> Component for Remote<T> {
                             *self = Self::Ready(entry);
                         }
                     }
+                    Msg::Changed(Change::Inserted(entry)) | Msg::Changed(Change::Updated(entry)) => {
+                        if entry.id == *id {
+                            *self = Self::Ready(entry);
+                        }
+                    }
                     _ => {}
                 }
             }
@@ -80,6 +102,11 @@ impl<T: RemoteTable + Component + Debug> Component for Remote<T> {
                     Msg::Get => {
                         entry.update(entry::Msg::Refresh, &mut orders.proxy(Msg::Entry));
                     }
+                    Msg::Changed(Change::Updated(new)) => {
+                        if new.id == entry.id {
+                            *entry = new;
+                        }
+                    }
                     _ => {}
                 }
             }
@@ -0,0 +1,190 @@
+//! Audit log and soft delete
+//!
+//! `Database::audit` is called with an `AuditRecord` for every change made
+//! through the `Database` methods. Records can be stored in a table of the
+//! same schema and read back with `history`:
+//! ```rust,ignore
+//! impl<'db> Database<'db, Task> for Schema {
+//!     ...
+//!     fn audit(record: AuditRecord<Task>) {
+//!         <Schema as Database<'db, AuditRecord<Task>>>::insert(record);
+//!     }
+//!     fn trash() -> Option<Box<dyn Trash<Task> + 'db>> {
+//!         Some(TombstoneTable::<Schema, Task>::new(&*TASK_TRASH).boxed())
+//!     }
+//! }
+//! impl<'db> Database<'db, Tombstone<Task>> for Schema {
+//!     ...
+//!     fn indexes() -> Vec<&'db dyn TableIndex<Tombstone<Task>>> {
+//!         vec![&*TASK_TRASH]
+//!     }
+//! }
+//! as_actor(user_id, || DatabaseTable::<'db, Schema>::delete(task_id));
+//! DatabaseTable::<'db, Schema>::restore(task_id);
+//! let history = history::<Schema, Task>(task_id);
+//! ```
+//! With a `Trash`, deleted rows are only marked as deleted and hidden from
+//! reads, until they are restored or purged. `TASK_TRASH` is a
+//! `Tombstone::row_index`, so tombstones are looked up by row.
+use crate::{
+    Database,
+    DatabaseTable,
+    HashIndex,
+    Id,
+    JsonSchema,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use std::{
+    cell::Cell,
+    collections::HashSet,
+    marker::PhantomData,
+    time::SystemTime,
+};
+use uuid::Uuid;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum Action {
+    Inserted,
+    Updated,
+    Deleted,
+    Restored,
+    /// permanently deleted
+    Purged,
+}
+/// A change to a row of `T`, with the data before and after it
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct AuditRecord<T> {
+    pub row: Id<T>,
+    pub action: Action,
+    pub time: SystemTime,
+    /// uuid of the acting user, see `as_actor`
+    pub actor: Option<Uuid>,
+    pub before: Option<T>,
+    pub after: Option<T>,
+}
+impl<T> AuditRecord<T> {
+    /// Record of a change made now by the current actor
+    pub fn new(row: Id<T>, action: Action, before: Option<T>, after: Option<T>) -> Self {
+        Self {
+            row,
+            action,
+            time: SystemTime::now(),
+            actor: current_actor(),
+            before,
+            after,
+        }
+    }
+    pub fn actor<A>(&self) -> Option<Id<A>> {
+        self.actor.map(Id::from_uuid)
+    }
+}
+thread_local! {
+    static ACTOR: Cell<Option<Uuid>> = const { Cell::new(None) };
+}
+/// Resets the actor when `as_actor` returns or panics
+struct ActorGuard(Option<Uuid>);
+impl Drop for ActorGuard {
+    fn drop(&mut self) {
+        ACTOR.with(|actor| actor.set(self.0));
+    }
+}
+/// Run `f` with `actor` recorded as the author of all changes on this thread
+pub fn as_actor<A, R, F>(actor: Id<A>, f: F) -> R
+    where F: FnOnce() -> R
+{
+    let _guard = ActorGuard(ACTOR.with(|current| current.replace(Some(*actor.uuid()))));
+    f()
+}
+pub fn current_actor() -> Option<Uuid> {
+    ACTOR.with(|actor| actor.get())
+}
+/// Audit records of the row `id` stored in `D`, oldest first
+pub fn history<'db, D, T>(id: Id<T>) -> Vec<AuditRecord<T>>
+    where D: Database<'db, AuditRecord<T>>,
+          AuditRecord<T>: DatabaseTable<'db, D>,
+{
+    let mut records: Vec<AuditRecord<T>> = <D as Database<'db, AuditRecord<T>>>::filter(|record| record.row == id)
+        .into_iter()
+        .map(|entry| entry.into_inner())
+        .collect();
+    records.sort_by_key(|record| record.time);
+    records
+}
+
+/// Marks the row `row` as deleted
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Tombstone<T> {
+    pub row: Id<T>,
+    pub time: SystemTime,
+    pub actor: Option<Uuid>,
+}
+impl<T> Tombstone<T> {
+    /// Unique index of tombstones by row, for `TombstoneTable`
+    pub fn row_index(name: &'static str) -> HashIndex<Self, Id<T>> {
+        HashIndex::unique(name, |tombstone| tombstone.row)
+    }
+}
+/// Type erased store of the soft deleted rows of `T`
+pub trait Trash<T> {
+    fn ids(&self) -> HashSet<Id<T>>;
+    fn contains(&self, id: Id<T>) -> bool;
+    /// Mark `id` as deleted
+    fn put(&self, id: Id<T>);
+    /// Unmark `id`, false if it was not deleted
+    fn take(&self, id: Id<T>) -> bool;
+}
+/// Trash storing `Tombstone`s in a table of `D`
+///
+/// `index` has to be returned by `Database::indexes` of the tombstone table.
+pub struct TombstoneTable<'db, D, T> {
+    index: &'db HashIndex<Tombstone<T>, Id<T>>,
+    _ty: PhantomData<fn() -> &'db (D, T)>,
+}
+impl<'db, D, T> TombstoneTable<'db, D, T>
+    where D: Database<'db, T> + Database<'db, Tombstone<T>> + 'db,
+          T: DatabaseTable<'db, D>,
+          Tombstone<T>: DatabaseTable<'db, D>,
+{
+    pub fn new(index: &'db HashIndex<Tombstone<T>, Id<T>>) -> Self {
+        Self {
+            index,
+            _ty: PhantomData,
+        }
+    }
+    pub fn boxed(self) -> Box<dyn Trash<T> + 'db> {
+        Box::new(self)
+    }
+}
+impl<'db, D, T> Trash<T> for TombstoneTable<'db, D, T>
+    where D: Database<'db, T> + Database<'db, Tombstone<T>> + 'db,
+          T: DatabaseTable<'db, D>,
+          Tombstone<T>: DatabaseTable<'db, D>,
+{
+    fn ids(&self) -> HashSet<Id<T>> {
+        <D as Database<'db, Tombstone<T>>>::get_all()
+            .into_iter()
+            .map(|entry| entry.data.row)
+            .collect()
+    }
+    fn contains(&self, id: Id<T>) -> bool {
+        <D as Database<'db, Tombstone<T>>>::find_by(self.index, &id).is_some()
+    }
+    fn put(&self, id: Id<T>) {
+        if !self.contains(id) {
+            <D as Database<'db, Tombstone<T>>>::insert(Tombstone {
+                row: id,
+                time: SystemTime::now(),
+                actor: current_actor(),
+            });
+        }
+    }
+    fn take(&self, id: Id<T>) -> bool {
+        match <D as Database<'db, Tombstone<T>>>::find_by(self.index, &id) {
+            Some(entry) => <D as Database<'db, Tombstone<T>>>::delete(entry.id).is_some(),
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,5 @@
+//! Implementations of `TableRead` and `TableWrite` for storage backends
+#[cfg(feature = "rql")]
+pub mod rql;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
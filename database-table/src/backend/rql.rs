@@ -0,0 +1,216 @@
+//! [rql](https://github.com/kaikalii/rql) backend
+//!
+//! Table guards of an `rql` schema implement `TableRead` and `TableWrite`,
+//! so a `Database` impl only needs to name them:
+//! ```rust,ignore
+//! impl<'db> Database<'db, User> for Schema {
+//!     type Table = TableGuard<'db, User>;
+//!     type TableMut = TableGuardMut<'db, User>;
+//!     fn table() -> Self::Table {
+//!         DB.user()
+//!     }
+//!     fn table_mut() -> Self::TableMut {
+//!         DB.user_mut()
+//!     }
+//! }
+//! ```
+use crate::{
+    Entry,
+    Id,
+    MigrationStore,
+    RawRow,
+    SchemaVersion,
+    TableRead,
+    TableWrite,
+};
+pub use ::rql::{
+    Row,
+    TableGuard,
+    TableGuardMut,
+};
+use serde_json::Value;
+use std::{
+    fs,
+    io,
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+
+/// rql ids and `Id`s are both uuids, so they convert through their string form
+pub fn to_rql_id<T>(id: Id<T>) -> ::rql::Id<T> {
+    id.to_string()
+        .parse()
+        .unwrap_or_else(|_| panic!("Invalid rql id {}", id))
+}
+pub fn from_rql_id<T>(id: ::rql::Id<T>) -> Id<T> {
+    id.to_string()
+        .parse()
+        .unwrap_or_else(|_| panic!("Invalid rql id {}", id))
+}
+impl<T> From<Row<'_, T>> for Entry<T>
+    where T: Clone
+{
+    fn from(row: Row<'_, T>) -> Self {
+        Self::new(from_rql_id(row.id), (*row.data).clone())
+    }
+}
+macro_rules! impl_table_read {
+    ($guard:ident) => {
+        impl<'db, T: Clone> TableRead<T> for $guard<'db, T> {
+            fn get(&self, id: Id<T>) -> Option<T> {
+                (**self)
+                    .get(to_rql_id(id))
+                    .cloned()
+            }
+            fn entries(&self) -> Vec<Entry<T>> {
+                (**self)
+                    .rows()
+                    .map(|row| row.into())
+                    .collect()
+            }
+            fn filter(&self, f: &dyn Fn(&T) -> bool) -> Vec<Entry<T>> {
+                (**self)
+                    .wher(|row| f(row.data))
+                    .map(|row| row.into())
+                    .collect()
+            }
+            fn find(&self, f: &dyn Fn(&T) -> bool) -> Option<Entry<T>> {
+                (**self)
+                    .find(|row| f(row.data))
+                    .map(|row| row.into())
+            }
+        }
+    };
+}
+impl_table_read!(TableGuard);
+impl_table_read!(TableGuardMut);
+
+impl<'db, T: Clone> TableWrite<T> for TableGuardMut<'db, T> {
+    fn insert(&mut self, data: T) -> Id<T> {
+        from_rql_id((**self).insert(data))
+    }
+    fn delete(&mut self, id: Id<T>) -> Option<T> {
+        (**self).delete_one(to_rql_id(id))
+    }
+    fn insert_with_id(&mut self, id: Id<T>, data: T) -> Result<Option<T>, T> {
+        Ok((**self).insert_with_id(to_rql_id(id), data))
+    }
+    fn modify<F>(&mut self, id: Id<T>, f: F) -> Option<T>
+        where F: FnOnce(&mut T)
+    {
+        (**self)
+            .get_mut(to_rql_id(id))
+            .map(|data| {
+                f(data);
+                data.clone()
+            })
+    }
+}
+
+type RawTable<'a> = Box<dyn Fn() -> TableGuardMut<'a, Value> + 'a>;
+/// Migration store on the files of an rql schema
+///
+/// Rows which no longer deserialize into their type can only be read as
+/// `Value`s, so the tables are opened through a second schema with `Value`
+/// rows and a table of `SchemaVersion`s, before the typed schema is used:
+/// ```rust,ignore
+/// schema! {
+///     RawSchema {
+///         task: Value,
+///         schema_version: SchemaVersion,
+///     }
+/// }
+/// let raw = RawSchema::new("test_database", rql::BinaryStable)?;
+/// let store = RqlMigrationStore::new("test_database", || raw.schema_version_mut())
+///     .table("task", || raw.task_mut());
+/// migrations().run(&store, &options)?;
+/// ```
+/// Reading rows as `Value`s needs a representation storing field names,
+/// e.g. `HumanReadable` or `BinaryStable`.
+pub struct RqlMigrationStore<'a> {
+    dir: PathBuf,
+    versions: Box<dyn Fn() -> TableGuardMut<'a, SchemaVersion> + 'a>,
+    tables: Vec<(&'static str, RawTable<'a>)>,
+}
+impl<'a> RqlMigrationStore<'a> {
+    /// Store for the schema `name`, keeping versions in the table returned by `versions`
+    pub fn new<F>(name: &str, versions: F) -> Self
+        where F: Fn() -> TableGuardMut<'a, SchemaVersion> + 'a
+    {
+        Self {
+            dir: PathBuf::from(name),
+            versions: Box::new(versions),
+            tables: Vec::new(),
+        }
+    }
+    pub fn table<F>(mut self, name: &'static str, table: F) -> Self
+        where F: Fn() -> TableGuardMut<'a, Value> + 'a
+    {
+        self.tables.push((name, Box::new(table)));
+        self
+    }
+    fn find(&self, name: &str) -> Result<&RawTable<'a>, io::Error> {
+        self.tables
+            .iter()
+            .find(|(table, _)| *table == name)
+            .map(|(_, table)| table)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("Unknown table {}", name)))
+    }
+}
+impl MigrationStore for RqlMigrationStore<'_> {
+    type Error = io::Error;
+    fn version(&self, table: &str) -> Result<u32, Self::Error> {
+        Ok((self.versions)()
+            .find(&|row| row.table == table)
+            .map(|entry| entry.data.version)
+            .unwrap_or(0))
+    }
+    fn rows(&self, table: &str) -> Result<Vec<RawRow>, Self::Error> {
+        Ok((self.find(table)?)()
+            .entries()
+            .into_iter()
+            .map(|entry| (entry.id.to_string(), entry.data))
+            .collect())
+    }
+    fn write(&self, table: &str, version: u32, rows: Vec<RawRow>) -> Result<(), Self::Error> {
+        let rows = rows
+            .into_iter()
+            .map(|(id, data)| {
+                id.parse::<Id<Value>>()
+                    .map(|id| (id, data))
+                    .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid id {}", id)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut guard = (self.find(table)?)();
+        for (id, data) in rows {
+            let _ = guard.insert_with_id(id, data);
+        }
+        drop(guard);
+        let mut versions = (self.versions)();
+        match versions.find(&|row| row.table == table) {
+            Some(entry) => {
+                versions.modify(entry.id, |row| row.version = version);
+            }
+            None => {
+                versions.insert(SchemaVersion {
+                    table: table.to_string(),
+                    version,
+                });
+            }
+        }
+        Ok(())
+    }
+    /// Copy the files of the schema directory
+    fn backup(&self, path: &Path) -> Result<(), Self::Error> {
+        fs::create_dir_all(path)?;
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                fs::copy(entry.path(), path.join(entry.file_name()))?;
+            }
+        }
+        Ok(())
+    }
+}
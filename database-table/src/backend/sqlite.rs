@@ -0,0 +1,945 @@
+//! SQLite backend
+//!
+//! Every `DatabaseTable` type is stored in its own SQLite table with an `id`
+//! column and a `data` column holding the row serialized as JSON.
+//! ```rust,ignore
+//! lazy_static! {
+//!     pub static ref DB: SqliteSchema = SqliteSchema::open("example_database.sqlite").unwrap();
+//! }
+//! impl<'db> Database<'db, User> for Schema {
+//!     type Table = SqliteTable<'db, User>;
+//!     type TableMut = SqliteTable<'db, User>;
+//!     fn table() -> Self::Table {
+//!         DB.table("user")
+//!     }
+//!     fn table_mut() -> Self::TableMut {
+//!         DB.table("user")
+//!     }
+//! }
+//! ```
+//! All tables share one connection, so a table guard must be dropped
+//! before another table of the same schema is accessed.
+//!
+//! While a thread has a transaction open, other threads block when
+//! accessing any table until it is committed or rolled back.
+use crate::{
+    Entry,
+    Id,
+    MigrationStore,
+    RawRow,
+    TableRead,
+    TableWrite,
+    TransactionError,
+};
+use rusqlite::{
+    params,
+    Connection,
+    OptionalExtension,
+};
+use serde::{
+    de::DeserializeOwned,
+    Serialize,
+};
+use std::{
+    collections::HashSet,
+    marker::PhantomData,
+    path::Path,
+    sync::{
+        Condvar,
+        Mutex,
+        MutexGuard,
+    },
+    thread::{
+        self,
+        ThreadId,
+    },
+};
+#[allow(unused)]
+use tracing::{
+    debug,
+    error,
+};
+
+pub use rusqlite::Error;
+
+#[derive(Debug)]
+struct Inner {
+    connection: Connection,
+    tables: HashSet<&'static str>,
+}
+#[derive(Debug)]
+pub struct SqliteSchema {
+    inner: Mutex<Inner>,
+    /// thread with an open transaction
+    owner: Mutex<Option<ThreadId>>,
+    released: Condvar,
+}
+impl SqliteSchema {
+    /// Open or create the database file at `path`
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Connection::open(path).map(Self::from)
+    }
+    pub fn open_in_memory() -> Result<Self, Error> {
+        Connection::open_in_memory().map(Self::from)
+    }
+    /// Lock the connection, waiting for transactions of other threads
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        let current = thread::current().id();
+        let mut owner = self.owner.lock().expect("SQLite transaction poisoned");
+        while owner.map(|owner| owner != current).unwrap_or(false) {
+            owner = self.released.wait(owner).expect("SQLite transaction poisoned");
+        }
+        self.inner.lock().expect("SQLite connection poisoned")
+    }
+    /// Lock the connection and return the table called `name`, creating it if needed
+    pub fn table<T>(&self, name: &'static str) -> SqliteTable<'_, T> {
+        let mut inner = self.lock();
+        if !inner.tables.contains(name) {
+            inner.connection
+                .execute(
+                    &format!(
+                        "CREATE TABLE IF NOT EXISTS \"{}\" (id TEXT PRIMARY KEY NOT NULL, data TEXT NOT NULL)",
+                        name,
+                    ),
+                    params![],
+                )
+                .unwrap_or_else(|e| panic!("Failed to create table {}: {}", name, e));
+            inner.tables.insert(name);
+        }
+        SqliteTable {
+            inner,
+            name,
+            _ty: PhantomData,
+        }
+    }
+    /// Begin a transaction owned by the current thread
+    pub fn begin(&self) -> Result<(), Error> {
+        let current = thread::current().id();
+        let mut owner = self.owner.lock().expect("SQLite transaction poisoned");
+        while owner.is_some() {
+            if *owner == Some(current) {
+                return Err(Error::SqliteFailure(
+                    rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_MISUSE),
+                    Some("Transaction already open on this thread".into()),
+                ));
+            }
+            owner = self.released.wait(owner).expect("SQLite transaction poisoned");
+        }
+        self.inner.lock()
+            .expect("SQLite connection poisoned")
+            .connection
+            .execute_batch("BEGIN IMMEDIATE")?;
+        *owner = Some(current);
+        Ok(())
+    }
+    pub fn commit(&self) -> Result<(), Error> {
+        self.finish("COMMIT")
+    }
+    pub fn rollback(&self) -> Result<(), Error> {
+        self.finish("ROLLBACK")
+    }
+    /// End the transaction of the current thread with `sql`
+    fn finish(&self, sql: &str) -> Result<(), Error> {
+        let mut owner = self.owner.lock().expect("SQLite transaction poisoned");
+        if *owner != Some(thread::current().id()) {
+            return Err(Error::SqliteFailure(
+                rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_MISUSE),
+                Some("No transaction open on this thread".into()),
+            ));
+        }
+        let inner = self.inner.lock().expect("SQLite connection poisoned");
+        let result = inner.connection.execute_batch(sql);
+        if result.is_err() && !inner.connection.is_autocommit() {
+            // a failed commit leaves the transaction open
+            if let Err(e) = inner.connection.execute_batch("ROLLBACK") {
+                error!("Failed to roll back transaction: {}", e);
+            }
+        }
+        *owner = None;
+        self.released.notify_all();
+        result
+    }
+}
+impl From<Error> for TransactionError {
+    fn from(e: Error) -> Self {
+        Self::Backend(e.to_string())
+    }
+}
+impl Inner {
+    fn create_versions(&self) -> Result<(), Error> {
+        self.connection
+            .execute(
+                "CREATE TABLE IF NOT EXISTS _versions (name TEXT PRIMARY KEY NOT NULL, version INTEGER NOT NULL)",
+                params![],
+            )
+            .map(|_| ())
+    }
+}
+/// Schema versions are stored in the `_versions` table
+impl MigrationStore for SqliteSchema {
+    type Error = Error;
+    fn version(&self, table: &str) -> Result<u32, Self::Error> {
+        let inner = self.lock();
+        inner.create_versions()?;
+        inner.connection
+            .query_row(
+                "SELECT version FROM _versions WHERE name = ?1",
+                params![table],
+                |row| row.get::<_, u32>(0),
+            )
+            .optional()
+            .map(|version| version.unwrap_or(0))
+    }
+    fn rows(&self, table: &str) -> Result<Vec<RawRow>, Self::Error> {
+        let inner = self.lock();
+        let exists = inner.connection
+            .query_row(
+                "SELECT name FROM sqlite_master WHERE type = 'table' AND name = ?1",
+                params![table],
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some();
+        if !exists {
+            return Ok(Vec::new());
+        }
+        let mut stmt = inner.connection.prepare(&format!("SELECT id, data FROM \"{}\" ORDER BY rowid", table))?;
+        let rows = stmt
+            .query_map(params![], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+            .collect::<Result<Vec<_>, Error>>()?;
+        rows.into_iter()
+            .map(|(id, data)| {
+                serde_json::from_str(&data)
+                    .map(|data| (id, data))
+                    .map_err(|e| Error::FromSqlConversionFailure(1, rusqlite::types::Type::Text, Box::new(e)))
+            })
+            .collect()
+    }
+    fn write(&self, table: &str, version: u32, rows: Vec<RawRow>) -> Result<(), Self::Error> {
+        let mut inner = self.lock();
+        inner.create_versions()?;
+        let transaction = inner.connection.transaction()?;
+        transaction.execute(
+            &format!("CREATE TABLE IF NOT EXISTS \"{}\" (id TEXT PRIMARY KEY NOT NULL, data TEXT NOT NULL)", table),
+            params![],
+        )?;
+        for (id, data) in rows {
+            transaction.execute(
+                &format!("UPDATE \"{}\" SET data = ?2 WHERE id = ?1", table),
+                params![id, data.to_string()],
+            )?;
+        }
+        transaction.execute(
+            "INSERT OR REPLACE INTO _versions (name, version) VALUES (?1, ?2)",
+            params![table, version],
+        )?;
+        transaction.commit()
+    }
+    fn backup(&self, path: &Path) -> Result<(), Self::Error> {
+        let inner = self.lock();
+        inner.connection
+            .execute("VACUUM INTO ?1", params![path.to_string_lossy()])
+            .map(|_| ())
+    }
+}
+impl From<Connection> for SqliteSchema {
+    fn from(connection: Connection) -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                connection,
+                tables: HashSet::new(),
+            }),
+            owner: Mutex::new(None),
+            released: Condvar::new(),
+        }
+    }
+}
+/// Guard for the table of `T`, holding the lock on the connection
+pub struct SqliteTable<'db, T> {
+    inner: MutexGuard<'db, Inner>,
+    name: &'static str,
+    _ty: PhantomData<T>,
+}
+impl<T> SqliteTable<'_, T> {
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+    pub fn connection(&self) -> &Connection {
+        &self.inner.connection
+    }
+    fn decode(&self, id: &str, data: &str) -> Option<Entry<T>>
+        where T: DeserializeOwned
+    {
+        match (id.parse(), serde_json::from_str(data)) {
+            (Ok(id), Ok(data)) => Some(Entry::new(id, data)),
+            (Err(e), _) => {
+                error!("Invalid id {} in table {}: {}", id, self.name, e);
+                None
+            }
+            (_, Err(e)) => {
+                error!("Invalid data for {} in table {}: {}", id, self.name, e);
+                None
+            }
+        }
+    }
+}
+impl<T> TableRead<T> for SqliteTable<'_, T>
+    where T: Serialize + DeserializeOwned
+{
+    fn get(&self, id: Id<T>) -> Option<T> {
+        self.connection()
+            .query_row(
+                &format!("SELECT id, data FROM \"{}\" WHERE id = ?1", self.name),
+                params![id.to_string()],
+                |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+            )
+            .optional()
+            .unwrap_or_else(|e| {
+                error!("Failed to get {} from table {}: {}", id, self.name, e);
+                None
+            })
+            .and_then(|(id, data)| self.decode(&id, &data))
+            .map(Entry::into_inner)
+    }
+    fn entries(&self) -> Vec<Entry<T>> {
+        let query = format!("SELECT id, data FROM \"{}\" ORDER BY rowid", self.name);
+        let rows = self.connection()
+            .prepare(&query)
+            .and_then(|mut stmt| {
+                stmt.query_map(params![], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+                    .collect::<Result<Vec<_>, Error>>()
+            });
+        match rows {
+            Ok(rows) => {
+                rows.iter()
+                    .filter_map(|(id, data)| self.decode(id, data))
+                    .collect()
+            }
+            Err(e) => {
+                error!("Failed to read table {}: {}", self.name, e);
+                Vec::new()
+            }
+        }
+    }
+}
+impl<T> TableWrite<T> for SqliteTable<'_, T>
+    where T: Serialize + DeserializeOwned
+{
+    fn insert(&mut self, data: T) -> Id<T> {
+        let id = Id::new();
+        let data = serde_json::to_string(&data)
+            .unwrap_or_else(|e| panic!("Failed to serialize row for table {}: {}", self.name, e));
+        self.connection()
+            .execute(
+                &format!("INSERT INTO \"{}\" (id, data) VALUES (?1, ?2)", self.name),
+                params![id.to_string(), data],
+            )
+            .unwrap_or_else(|e| panic!("Failed to insert into table {}: {}", self.name, e));
+        id
+    }
+    fn delete(&mut self, id: Id<T>) -> Option<T> {
+        let data = self.get(id)?;
+        match self.connection()
+            .execute(
+                &format!("DELETE FROM \"{}\" WHERE id = ?1", self.name),
+                params![id.to_string()],
+            )
+        {
+            Ok(_) => Some(data),
+            Err(e) => {
+                error!("Failed to delete {} from table {}: {}", id, self.name, e);
+                None
+            }
+        }
+    }
+    fn insert_with_id(&mut self, id: Id<T>, data: T) -> Result<Option<T>, T> {
+        let previous = self.get(id);
+        let json = serde_json::to_string(&data)
+            .unwrap_or_else(|e| panic!("Failed to serialize row for table {}: {}", self.name, e));
+        self.connection()
+            .execute(
+                &format!("INSERT OR REPLACE INTO \"{}\" (id, data) VALUES (?1, ?2)", self.name),
+                params![id.to_string(), json],
+            )
+            .unwrap_or_else(|e| panic!("Failed to insert into table {}: {}", self.name, e));
+        Ok(previous)
+    }
+    fn modify<F>(&mut self, id: Id<T>, f: F) -> Option<T>
+        where F: FnOnce(&mut T)
+    {
+        let mut data = self.get(id)?;
+        f(&mut data);
+        let json = serde_json::to_string(&data)
+            .unwrap_or_else(|e| panic!("Failed to serialize row for table {}: {}", self.name, e));
+        match self.connection()
+            .execute(
+                &format!("UPDATE \"{}\" SET data = ?2 WHERE id = ?1", self.name),
+                params![id.to_string(), json],
+            )
+        {
+            Ok(_) => Some(data),
+            Err(e) => {
+                error!("Failed to update {} in table {}: {}", id, self.name, e);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        history,
+        as_actor,
+        Action,
+        AuditRecord,
+        Backup,
+        BackupError,
+        Change,
+        ChangeFeed,
+        Database,
+        DatabaseTable,
+        DeletePolicy,
+        HashIndex,
+        IndexError,
+        Journal,
+        Journaled,
+        OrderedIndex,
+        Reference,
+        RestoreOptions,
+        TableIndex,
+        Relation,
+        Tombstone,
+        TombstoneTable,
+        Transaction,
+        Transactional,
+        Trash,
+        Updatable,
+        Watch,
+    };
+    use lazy_static::lazy_static;
+    use serde::Deserialize;
+
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Updatable)]
+    struct Note {
+        text: String,
+        pinned: bool,
+    }
+    struct Schema;
+    lazy_static! {
+        static ref DB: SqliteSchema = SqliteSchema::open_in_memory().unwrap();
+    }
+    impl<'db> Database<'db, Note> for Schema {
+        type Table = SqliteTable<'db, Note>;
+        type TableMut = SqliteTable<'db, Note>;
+        fn table() -> Self::Table {
+            DB.table("note")
+        }
+        fn table_mut() -> Self::TableMut {
+            DB.table("note")
+        }
+        fn references() -> Vec<Box<dyn Reference<Note> + 'db>> {
+            vec![
+                Relation::<Schema, Folder, Note>::new(
+                    "folder.notes",
+                    DeletePolicy::Nullify,
+                    |folder| folder.notes.clone(),
+                    |folder, id| folder.notes.retain(|note| *note != id),
+                ).boxed(),
+            ]
+        }
+    }
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Updatable)]
+    struct Tag {
+        name: String,
+        weight: u32,
+    }
+    lazy_static! {
+        static ref TAG_NAME: HashIndex<Tag, String> = HashIndex::unique("tag.name", |tag| tag.name.clone());
+        static ref TAG_WEIGHT: OrderedIndex<Tag, u32> = OrderedIndex::new("tag.weight", |tag| tag.weight);
+    }
+    impl<'db> Database<'db, Tag> for Schema {
+        type Table = SqliteTable<'db, Tag>;
+        type TableMut = SqliteTable<'db, Tag>;
+        fn table() -> Self::Table {
+            DB.table("tag")
+        }
+        fn table_mut() -> Self::TableMut {
+            DB.table("tag")
+        }
+        fn indexes() -> Vec<&'db dyn TableIndex<Tag>> {
+            vec![&*TAG_NAME, &*TAG_WEIGHT]
+        }
+    }
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Updatable)]
+    struct Folder {
+        notes: Vec<Id<Note>>,
+    }
+    lazy_static! {
+        static ref FOLDER_CHANGES: ChangeFeed<Folder> = ChangeFeed::new();
+    }
+    impl<'db> Database<'db, Folder> for Schema {
+        type Table = SqliteTable<'db, Folder>;
+        type TableMut = SqliteTable<'db, Folder>;
+        fn table() -> Self::Table {
+            DB.table("folder")
+        }
+        fn table_mut() -> Self::TableMut {
+            DB.table("folder")
+        }
+        fn changes() -> Option<&'db ChangeFeed<Folder>> {
+            Some(&*FOLDER_CHANGES)
+        }
+    }
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Updatable)]
+    struct Doc {
+        title: String,
+    }
+    lazy_static! {
+        static ref DOC_TRASH: HashIndex<Tombstone<Doc>, Id<Doc>> = Tombstone::row_index("doc_trash.row");
+    }
+    impl<'db> Database<'db, Doc> for Schema {
+        type Table = SqliteTable<'db, Doc>;
+        type TableMut = SqliteTable<'db, Doc>;
+        fn table() -> Self::Table {
+            DB.table("doc")
+        }
+        fn table_mut() -> Self::TableMut {
+            DB.table("doc")
+        }
+        fn audit(record: AuditRecord<Doc>) {
+            <Schema as Database<'db, AuditRecord<Doc>>>::insert(record);
+        }
+        fn trash() -> Option<Box<dyn Trash<Doc> + 'db>> {
+            Some(TombstoneTable::<Schema, Doc>::new(&*DOC_TRASH).boxed())
+        }
+    }
+    impl<'db> Database<'db, AuditRecord<Doc>> for Schema {
+        type Table = SqliteTable<'db, AuditRecord<Doc>>;
+        type TableMut = SqliteTable<'db, AuditRecord<Doc>>;
+        fn table() -> Self::Table {
+            DB.table("doc_audit")
+        }
+        fn table_mut() -> Self::TableMut {
+            DB.table("doc_audit")
+        }
+    }
+    impl<'db> Database<'db, Tombstone<Doc>> for Schema {
+        type Table = SqliteTable<'db, Tombstone<Doc>>;
+        type TableMut = SqliteTable<'db, Tombstone<Doc>>;
+        fn table() -> Self::Table {
+            DB.table("doc_trash")
+        }
+        fn table_mut() -> Self::TableMut {
+            DB.table("doc_trash")
+        }
+        fn indexes() -> Vec<&'db dyn TableIndex<Tombstone<Doc>>> {
+            vec![&*DOC_TRASH]
+        }
+    }
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    struct Card {
+        text: String,
+    }
+    impl<'db> Database<'db, Card> for Schema {
+        type Table = SqliteTable<'db, Card>;
+        type TableMut = SqliteTable<'db, Card>;
+        fn table() -> Self::Table {
+            DB.table("card")
+        }
+        fn table_mut() -> Self::TableMut {
+            DB.table("card")
+        }
+    }
+    impl<'db> Transactional<'db> for Schema {
+        fn begin() -> Result<(), TransactionError> {
+            Ok(DB.begin()?)
+        }
+        fn commit() -> Result<(), TransactionError> {
+            Ok(DB.commit()?)
+        }
+        fn rollback() -> Result<(), TransactionError> {
+            Ok(DB.rollback()?)
+        }
+    }
+    /// Tables with transactions of a `Journal`, as used for rql schemas
+    struct JournalSchema;
+    lazy_static! {
+        static ref JOURNAL_DB: SqliteSchema = SqliteSchema::open_in_memory().unwrap();
+        static ref JOURNAL: Journal = Journal::new();
+        static ref CARD_CHANGES: ChangeFeed<Card> = ChangeFeed::new();
+    }
+    impl<'db> Database<'db, Card> for JournalSchema {
+        type Table = Journaled<'db, SqliteTable<'db, Card>>;
+        type TableMut = Journaled<'db, SqliteTable<'db, Card>>;
+        fn table() -> Self::Table {
+            JOURNAL.read(|| JOURNAL_DB.table("card"))
+        }
+        fn table_mut() -> Self::TableMut {
+            JOURNAL.write::<Self, Card, _, _>(|| JOURNAL_DB.table("card"))
+        }
+        fn changes() -> Option<&'db ChangeFeed<Card>> {
+            Some(&*CARD_CHANGES)
+        }
+    }
+    impl<'db> Transactional<'db> for JournalSchema {
+        fn begin() -> Result<(), TransactionError> {
+            JOURNAL.begin()
+        }
+        fn commit() -> Result<(), TransactionError> {
+            JOURNAL.commit()
+        }
+        fn rollback() -> Result<(), TransactionError> {
+            JOURNAL.rollback()
+        }
+    }
+    #[test]
+    fn insert_get_delete() {
+        let note = Note { text: "hello".into(), pinned: false };
+        let id = <Note as DatabaseTable<'_, Schema>>::insert(note.clone());
+        assert_eq!(
+            <Note as DatabaseTable<'_, Schema>>::get(id).map(Entry::into_inner),
+            Some(note.clone())
+        );
+        assert_eq!(
+            <Note as DatabaseTable<'_, Schema>>::find(|n| n.text == "hello").map(|e| e.id),
+            Some(id)
+        );
+        assert_eq!(<Note as DatabaseTable<'_, Schema>>::delete(id), Some(note));
+        assert!(<Note as DatabaseTable<'_, Schema>>::get(id).is_none());
+    }
+    #[test]
+    fn update() {
+        let note = Note { text: "draft".into(), pinned: false };
+        let id = <Note as DatabaseTable<'_, Schema>>::insert(note.clone());
+        let pinned = Note { pinned: true, ..note.clone() };
+        let update = note.diff(&pinned);
+        assert_eq!(update, NoteUpdate::default().pinned(true));
+        assert_eq!(
+            <Note as DatabaseTable<'_, Schema>>::update(id, update),
+            Some(pinned.clone())
+        );
+        assert_eq!(
+            <Note as DatabaseTable<'_, Schema>>::get(id).map(Entry::into_inner),
+            Some(pinned)
+        );
+        assert!(<Note as DatabaseTable<'_, Schema>>::update(Id::new(), NoteUpdate::default()).is_none());
+    }
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Updatable)]
+    struct Labelled<T> {
+        label: Option<String>,
+        value: T,
+    }
+    #[test]
+    fn update_optional_fields() {
+        let mut row = Labelled { label: Some("old".to_string()), value: 1u32 };
+        let cleared = Labelled { label: None, value: 1 };
+        let json = serde_json::to_string(&row.diff(&cleared)).unwrap();
+        assert_eq!(json, r#"{"label":null}"#);
+        // null clears the field, a missing field leaves it unchanged
+        let update: LabelledUpdate<u32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(update, LabelledUpdate::default().label(None));
+        assert_eq!(serde_json::from_str::<LabelledUpdate<u32>>("{}").unwrap(), LabelledUpdate::default());
+        row.apply(update);
+        assert_eq!(row, cleared);
+    }
+    #[test]
+    fn migrate() {
+        use crate::{
+            MigrationOptions,
+            Migrations,
+        };
+        use serde_json::{
+            json,
+            Value,
+        };
+        let db = SqliteSchema::open_in_memory().unwrap();
+        let id = db.table::<Value>("task").insert(json!({ "title": "old" }));
+        let migrations = Migrations::new()
+            .register("task", 1, "add priority", |mut row| {
+                row["priority"] = json!(0);
+                Ok(row)
+            })
+            .register("task", 2, "rename title to name", |mut row| {
+                let title = row["title"].take();
+                row["name"] = title;
+                row.as_object_mut().unwrap().remove("title");
+                Ok(row)
+            });
+        let report = migrations.run(&db, &MigrationOptions { dry_run: true, backup: None }).unwrap();
+        assert_eq!((report.tables[0].from, report.tables[0].to, report.tables[0].rows), (0, 2, 1));
+        assert_eq!(db.version("task").unwrap(), 0);
+        migrations.run(&db, &MigrationOptions::default()).unwrap();
+        assert_eq!(db.version("task").unwrap(), 2);
+        assert_eq!(db.table::<Value>("task").get(id), Some(json!({ "name": "old", "priority": 0 })));
+        let report = migrations.run(&db, &MigrationOptions::default()).unwrap();
+        assert!(report.tables.is_empty());
+    }
+    #[test]
+    fn nullify_and_repair() {
+        let note = |text: &str| Note { text: text.into(), pinned: false };
+        let a = <Note as DatabaseTable<'_, Schema>>::insert(note("a"));
+        let b = <Note as DatabaseTable<'_, Schema>>::insert(note("b"));
+        let folder = <Folder as DatabaseTable<'_, Schema>>::insert(Folder { notes: vec![a, b] });
+        <Note as DatabaseTable<'_, Schema>>::delete(a);
+        assert_eq!(
+            <Folder as DatabaseTable<'_, Schema>>::get(folder).map(Entry::into_inner),
+            Some(Folder { notes: vec![b] })
+        );
+        // delete without applying policies
+        <Schema as Database<'_, Note>>::table_mut().delete(b);
+        let dangling = <Note as DatabaseTable<'_, Schema>>::check_references(false);
+        assert!(dangling.iter().any(|d| d.row == folder.to_string() && d.target == b.to_string()));
+        <Note as DatabaseTable<'_, Schema>>::check_references(true);
+        assert_eq!(
+            <Folder as DatabaseTable<'_, Schema>>::get(folder).map(Entry::into_inner),
+            Some(Folder { notes: vec![] })
+        );
+    }
+    #[test]
+    fn indexes() {
+        let tag = |name: &str, weight| Tag { name: name.into(), weight };
+        let rust = <Tag as DatabaseTable<'_, Schema>>::insert(tag("rust", 3));
+        let sql = <Tag as DatabaseTable<'_, Schema>>::insert(tag("sql", 1));
+        <Tag as DatabaseTable<'_, Schema>>::insert(tag("serde", 2));
+        assert!(matches!(
+            <Tag as DatabaseTable<'_, Schema>>::try_insert(tag("rust", 5)),
+            Err(IndexError::Unique { .. })
+        ));
+        assert_eq!(
+            <Tag as DatabaseTable<'_, Schema>>::find_by(&*TAG_NAME, &"rust".to_string()).map(|e| e.id),
+            Some(rust)
+        );
+        let names: Vec<String> = <Tag as DatabaseTable<'_, Schema>>::range_by(&*TAG_WEIGHT, 2..)
+            .into_iter()
+            .map(|e| e.data.name)
+            .collect();
+        assert_eq!(names, vec!["serde".to_string(), "rust".to_string()]);
+        assert!(<Tag as DatabaseTable<'_, Schema>>::try_update(sql, TagUpdate::default().name("rust".into())).is_err());
+        <Tag as DatabaseTable<'_, Schema>>::update(sql, TagUpdate::default().name("sqlite".into()));
+        assert!(<Tag as DatabaseTable<'_, Schema>>::find_by(&*TAG_NAME, &"sql".to_string()).is_none());
+        <Tag as DatabaseTable<'_, Schema>>::delete(rust);
+        assert!(<Tag as DatabaseTable<'_, Schema>>::try_insert(tag("rust", 4)).is_ok());
+        // a reset index can not check keys until it is rebuilt
+        TAG_NAME.reset();
+        assert!(matches!(TAG_NAME.check(None, &tag("serde", 1)), Err(IndexError::NotBuilt { .. })));
+        assert!(matches!(
+            <Tag as DatabaseTable<'_, Schema>>::try_insert(tag("serde", 1)),
+            Err(IndexError::Unique { .. })
+        ));
+    }
+    #[test]
+    fn transaction() {
+        let tag = |name: &str| Tag { name: name.into(), weight: 0 };
+        let (folder, note) = Transaction::<Schema>::run(|tx| {
+            let note = tx.insert(Note { text: "in folder".into(), pinned: false })?;
+            let folder = tx.insert(Folder { notes: vec![note] })?;
+            Ok::<_, TransactionError>((folder, note))
+        }).unwrap();
+        assert_eq!(
+            <Folder as DatabaseTable<'_, Schema>>::get(folder).map(Entry::into_inner),
+            Some(Folder { notes: vec![note] })
+        );
+        // a failing write rolls back the whole transaction
+        let result = Transaction::<Schema>::run(|tx| {
+            tx.delete::<Note>(note)?;
+            tx.insert(tag("transaction"))?;
+            tx.insert(tag("transaction"))
+        });
+        assert!(matches!(result, Err(TransactionError::Index(IndexError::Unique { .. }))));
+        assert!(<Note as DatabaseTable<'_, Schema>>::get(note).is_some());
+        assert_eq!(
+            <Folder as DatabaseTable<'_, Schema>>::get(folder).map(Entry::into_inner),
+            Some(Folder { notes: vec![note] })
+        );
+        assert!(<Tag as DatabaseTable<'_, Schema>>::find_by(&*TAG_NAME, &"transaction".to_string()).is_none());
+        // dropping a transaction rolls it back
+        {
+            let mut tx = Transaction::<Schema>::begin().unwrap();
+            tx.update(note, NoteUpdate::default().pinned(true)).unwrap();
+        }
+        assert_eq!(<Note as DatabaseTable<'_, Schema>>::get(note).map(|e| e.data.pinned), Some(false));
+        // other threads wait for the commit
+        let tx = Transaction::<Schema>::begin().unwrap();
+        let reader = std::thread::spawn(move || {
+            <Note as DatabaseTable<'_, Schema>>::get(note).map(|e| e.data.pinned)
+        });
+        <Schema as Database<'_, Note>>::update(note, NoteUpdate::default().pinned(true));
+        tx.commit().unwrap();
+        assert_eq!(reader.join().unwrap(), Some(true));
+    }
+    #[test]
+    fn journal() {
+        use std::sync::{
+            Arc,
+            Mutex,
+        };
+        let card = |text: &str| Card { text: text.into() };
+        let kept = <Card as DatabaseTable<'_, JournalSchema>>::insert(card("kept"));
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let listener = {
+            let received = received.clone();
+            CARD_CHANGES.subscribe(move |change| received.lock().unwrap().push(change.clone()))
+        };
+        // a rolled back transaction restores all rows and publishes nothing
+        let result = Transaction::<JournalSchema>::run(|tx| {
+            tx.insert(card("new"))?;
+            <JournalSchema as Database<'_, Card>>::table_mut().modify(kept, |card| card.text = "changed".into());
+            tx.delete::<Card>(kept)?;
+            Err::<(), _>(TransactionError::Backend("failed".into()))
+        });
+        assert!(result.is_err());
+        let texts: Vec<String> = <Card as DatabaseTable<'_, JournalSchema>>::get_all()
+            .into_iter()
+            .map(|entry| entry.data.text)
+            .collect();
+        assert_eq!(texts, vec!["kept".to_string()]);
+        assert!(received.lock().unwrap().is_empty());
+        // deletes are applied right away, changes are published on commit
+        let new = Transaction::<JournalSchema>::run(|tx| {
+            let new = tx.insert(card("new"))?;
+            tx.delete::<Card>(kept)?;
+            assert!(<Card as DatabaseTable<'_, JournalSchema>>::get(kept).is_none());
+            assert!(received.lock().unwrap().is_empty());
+            Ok::<_, TransactionError>(new)
+        }).unwrap();
+        assert!(<Card as DatabaseTable<'_, JournalSchema>>::get(kept).is_none());
+        assert!(<Card as DatabaseTable<'_, JournalSchema>>::get(new).is_some());
+        CARD_CHANGES.unsubscribe(listener);
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        assert!(matches!(&received[1], Change::Deleted(id) if *id == kept));
+        // guards held by the thread beginning a transaction do not block it
+        let guard = JOURNAL.read(|| ());
+        Transaction::<JournalSchema>::run(|tx| {
+            tx.insert(card("while reading"))
+        }).unwrap();
+        drop(guard);
+    }
+    #[test]
+    fn journal_recover() {
+        let card = |text: &str| Card { text: text.into() };
+        let kept = <Card as DatabaseTable<'_, JournalSchema>>::insert(card("kept"));
+        let added = <Card as DatabaseTable<'_, JournalSchema>>::insert(card("added"));
+        // the file left by a transaction which changed `kept` and inserted `added`
+        let path = std::env::temp_dir().join(format!("journal-{}.jsonl", Id::<()>::new()));
+        let table = std::any::type_name::<Card>();
+        std::fs::write(&path, format!(
+            "{}\n{}\n{{\"incomplete",
+            serde_json::json!([table, [kept, {"text": "before"}]]),
+            serde_json::json!([table, [added, null]]),
+        )).unwrap();
+        let journal = Journal::with_file(&path).table::<JournalSchema, Card>();
+        assert_eq!(journal.recover().unwrap(), 2);
+        assert!(!path.exists());
+        assert!(<Card as DatabaseTable<'_, JournalSchema>>::get(added).is_none());
+        let restored = <Card as DatabaseTable<'_, JournalSchema>>::get(kept).unwrap();
+        assert_eq!(restored.data.text, "before");
+        // nothing to recover without a file
+        assert_eq!(journal.recover().unwrap(), 0);
+    }
+    #[test]
+    fn changes() {
+        use std::sync::{
+            Arc,
+            Mutex,
+        };
+        let folder = <Folder as DatabaseTable<'_, Schema>>::insert(Folder { notes: vec![] });
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let watch = Watch::Entry(folder);
+        let listener = {
+            let received = received.clone();
+            FOLDER_CHANGES.subscribe(move |change| if watch.matches(change) {
+                received.lock().unwrap().push(change.clone());
+            })
+        };
+        let note = <Note as DatabaseTable<'_, Schema>>::insert(Note { text: "watched".into(), pinned: false });
+        <Folder as DatabaseTable<'_, Schema>>::update(folder, FolderUpdate::default().notes(vec![note]));
+        // removing the note through its relation is a change of the folder too
+        <Note as DatabaseTable<'_, Schema>>::delete(note);
+        <Folder as DatabaseTable<'_, Schema>>::insert(Folder { notes: vec![] });
+        <Folder as DatabaseTable<'_, Schema>>::delete(folder);
+        FOLDER_CHANGES.unsubscribe(listener);
+        <Folder as DatabaseTable<'_, Schema>>::insert(Folder { notes: vec![] });
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 3);
+        assert!(matches!(&received[0], Change::Updated(entry) if entry.data.notes == vec![note]));
+        assert!(matches!(&received[1], Change::Updated(entry) if entry.data.notes.is_empty()));
+        assert!(matches!(&received[2], Change::Deleted(id) if *id == folder));
+        // listeners are called without the feed locked, so they can unsubscribe themselves
+        let folder = <Folder as DatabaseTable<'_, Schema>>::insert(Folder { notes: vec![] });
+        let watch = Watch::Entry(folder);
+        let once = Arc::new(Mutex::new(None));
+        let calls = Arc::new(Mutex::new(0));
+        let listener = {
+            let once = once.clone();
+            let calls = calls.clone();
+            FOLDER_CHANGES.subscribe(move |change| if watch.matches(change) {
+                *calls.lock().unwrap() += 1;
+                if let Some(listener) = once.lock().unwrap().take() {
+                    FOLDER_CHANGES.unsubscribe(listener);
+                }
+            })
+        };
+        *once.lock().unwrap() = Some(listener);
+        <Folder as DatabaseTable<'_, Schema>>::update(folder, FolderUpdate::default().notes(vec![note]));
+        <Folder as DatabaseTable<'_, Schema>>::delete(folder);
+        assert_eq!(*calls.lock().unwrap(), 1);
+    }
+    #[test]
+    fn audit_and_restore() {
+        let user = Id::<()>::new();
+        let id = as_actor(user, || <Doc as DatabaseTable<'_, Schema>>::insert(Doc { title: "draft".into() }));
+        <Doc as DatabaseTable<'_, Schema>>::update(id, DocUpdate::default().title("final".into()));
+        assert_eq!(<Doc as DatabaseTable<'_, Schema>>::delete(id), Some(Doc { title: "final".into() }));
+        assert!(<Doc as DatabaseTable<'_, Schema>>::get(id).is_none());
+        assert!(<Doc as DatabaseTable<'_, Schema>>::get_all().iter().all(|entry| entry.id != id));
+        assert_eq!(<Doc as DatabaseTable<'_, Schema>>::restore(id), Some(Doc { title: "final".into() }));
+        assert!(<Doc as DatabaseTable<'_, Schema>>::get(id).is_some());
+        assert!(<Doc as DatabaseTable<'_, Schema>>::restore(id).is_none());
+        <Doc as DatabaseTable<'_, Schema>>::purge(id);
+        let history = history::<Schema, Doc>(id);
+        let actions: Vec<Action> = history.iter().map(|record| record.action).collect();
+        assert_eq!(actions, vec![Action::Inserted, Action::Updated, Action::Deleted, Action::Restored, Action::Purged]);
+        assert_eq!(history[0].actor(), Some(user));
+        assert_eq!(history[1].actor::<()>(), None);
+        assert_eq!(history[1].before, Some(Doc { title: "draft".into() }));
+        assert!(<Doc as DatabaseTable<'_, Schema>>::restore(id).is_none());
+    }
+    #[test]
+    fn backup_and_restore() {
+        let dir = std::env::temp_dir().join(format!("backup-{}", Id::<()>::new()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (full, incremental) = (dir.join("full.jsonl"), dir.join("incremental.jsonl"));
+        let backup = Backup::<Schema>::new().table::<Card>("card");
+        let card = |text: &str| Card { text: text.into() };
+        let a = <Card as DatabaseTable<'_, Schema>>::insert(card("a"));
+        let b = <Card as DatabaseTable<'_, Schema>>::insert(card("b"));
+        backup.export(&full).unwrap();
+        <Schema as Database<'_, Card>>::table_mut().modify(a, |card| card.text = "a2".into());
+        <Card as DatabaseTable<'_, Schema>>::delete(b);
+        let c = <Card as DatabaseTable<'_, Schema>>::insert(card("c"));
+        let report = backup.export_incremental(&[&full], &incremental).unwrap();
+        assert_eq!((report.tables[0].rows, report.tables[0].deleted), (2, 1));
+        let report = backup.verify(&[&full, &incremental]).unwrap();
+        assert_eq!(report.tables[0].rows, 2);
+
+        let report = backup.restore(&[&full], &RestoreOptions::default()).unwrap();
+        assert_eq!(report.tables[0].deleted, 1);
+        assert_eq!(<Card as DatabaseTable<'_, Schema>>::get(a).unwrap().data, card("a"));
+        assert_eq!(<Card as DatabaseTable<'_, Schema>>::get(b).unwrap().data, card("b"));
+        assert!(<Card as DatabaseTable<'_, Schema>>::get(c).is_none());
+        backup.command(&["restore", full.to_str().unwrap(), incremental.to_str().unwrap()]).unwrap();
+        assert_eq!(<Card as DatabaseTable<'_, Schema>>::get(a).unwrap().data, card("a2"));
+        assert!(<Card as DatabaseTable<'_, Schema>>::get(b).is_none());
+        assert_eq!(<Card as DatabaseTable<'_, Schema>>::get(c).unwrap().data, card("c"));
+
+        assert!(matches!(backup.verify(&[&incremental]), Err(BackupError::Base { .. })));
+        assert!(matches!(backup.command(&["verify"]), Err(BackupError::Usage(_))));
+        let tampered = std::fs::read_to_string(&incremental).unwrap().replace("a2", "a3");
+        std::fs::write(&incremental, tampered).unwrap();
+        assert!(matches!(backup.verify(&[&full, &incremental]), Err(BackupError::Checksum { .. })));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
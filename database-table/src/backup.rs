@@ -0,0 +1,687 @@
+//! Backups of whole schemas
+//!
+//! A `Backup` lists the tables of a schema and writes all of their rows to a
+//! JSON-lines archive, one row with its id per line:
+//! ```rust,ignore
+//! let backup = Backup::<Schema>::new()
+//!     .table::<User>("user")
+//!     .table::<Task>("task");
+//! backup.export("full.jsonl")?;
+//! backup.export_incremental(&["full.jsonl"], "monday.jsonl")?;
+//! backup.verify(&["full.jsonl", "monday.jsonl"])?;
+//! backup.restore(&["full.jsonl", "monday.jsonl"], &RestoreOptions::default())?;
+//! ```
+//! An incremental archive only holds the rows changed since the archives it
+//! is based on, so archives are always read as a chain starting with a full
+//! one. Every archive ends with a checksum, and all archives of a chain are
+//! verified before anything is restored.
+//!
+//! Exports read all tables in one transaction, so an archive is a consistent
+//! snapshot of a running server. Rows are restored with their ids, bypassing
+//! audit logs and change feeds.
+use crate::{
+    Database,
+    DatabaseTable,
+    Id,
+    TableRead,
+    TableWrite,
+    Transaction,
+    TransactionError,
+    Transactional,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use serde_json::Value;
+use std::{
+    collections::{
+        BTreeMap,
+        HashSet,
+    },
+    fmt::{
+        self,
+        Display,
+    },
+    fs::{
+        self,
+        File,
+    },
+    io::{
+        self,
+        BufRead,
+        BufReader,
+        BufWriter,
+        Write,
+    },
+    marker::PhantomData,
+    path::{
+        Path,
+        PathBuf,
+    },
+    time::SystemTime,
+};
+#[allow(unused)]
+use tracing::{
+    debug,
+    info,
+};
+
+/// Version of the archive format written by `Backup`
+pub const ARCHIVE_FORMAT: u32 = 1;
+
+pub const BACKUP_USAGE: &str = "\
+usage: backup export <archive>
+       backup incremental <archive> <base archives...>
+       backup verify <archives...>
+       backup restore [--dry-run] <archives...>";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ArchiveHeader {
+    pub format: u32,
+    pub created: SystemTime,
+    pub tables: Vec<String>,
+    /// checksum of the archive this one is incremental to
+    pub base: Option<String>,
+}
+/// A line of an archive
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ArchiveLine {
+    Header(ArchiveHeader),
+    Row {
+        table: String,
+        id: String,
+        data: Value,
+    },
+    /// row deleted since the base archive
+    Deleted {
+        table: String,
+        id: String,
+    },
+    /// number of rows and checksum of all previous lines
+    End {
+        rows: usize,
+        checksum: String,
+    },
+}
+#[derive(Clone, Debug, PartialEq)]
+pub enum BackupError {
+    Io(String),
+    /// line of an archive which is not an `ArchiveLine`
+    Parse {
+        archive: PathBuf,
+        line: usize,
+        error: String,
+    },
+    /// archive without header or end, or of another format
+    Format {
+        archive: PathBuf,
+        error: String,
+    },
+    Checksum {
+        archive: PathBuf,
+    },
+    /// incremental archive not based on the previous archive of the chain
+    Base {
+        archive: PathBuf,
+    },
+    UnknownTable(String),
+    Row {
+        table: String,
+        id: String,
+        error: String,
+    },
+    /// the backend can not create the row with its id
+    Unsupported {
+        table: String,
+        id: String,
+    },
+    Usage(String),
+}
+impl Display for BackupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "Backup failed: {}", e),
+            Self::Parse { archive, line, error } => {
+                write!(f, "Invalid line {} in {}: {}", line, archive.display(), error)
+            }
+            Self::Format { archive, error } => write!(f, "Invalid archive {}: {}", archive.display(), error),
+            Self::Checksum { archive } => write!(f, "Checksum of {} does not match", archive.display()),
+            Self::Base { archive } => {
+                write!(f, "{} is not based on the previous archive", archive.display())
+            }
+            Self::UnknownTable(table) => write!(f, "Unknown table {}", table),
+            Self::Row { table, id, error } => write!(f, "Invalid row {} of table {}: {}", id, table, error),
+            Self::Unsupported { table, id } => {
+                write!(f, "Backend can not restore row {} of table {} with its id", id, table)
+            }
+            Self::Usage(usage) => write!(f, "{}", usage),
+        }
+    }
+}
+impl std::error::Error for BackupError {}
+impl From<io::Error> for BackupError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e.to_string())
+    }
+}
+impl From<TransactionError> for BackupError {
+    fn from(e: TransactionError) -> Self {
+        Self::Io(e.to_string())
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RestoreOptions {
+    /// only report what would be restored
+    pub dry_run: bool,
+}
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TableSummary {
+    pub table: String,
+    /// rows written, or rows in the table after restoring
+    pub rows: usize,
+    /// rows marked as deleted, or removed from the table when restoring
+    pub deleted: usize,
+}
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BackupReport {
+    /// archive written, or chain of archives read
+    pub archives: Vec<PathBuf>,
+    pub tables: Vec<TableSummary>,
+    /// checksum of the last archive
+    pub checksum: String,
+    pub dry_run: bool,
+}
+impl Display for BackupReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for archive in &self.archives {
+            writeln!(f, "{}", archive.display())?;
+        }
+        for table in &self.tables {
+            write!(f, "  {}: {} rows", table.table, table.rows)?;
+            if table.deleted > 0 {
+                write!(f, ", {} deleted", table.deleted)?;
+            }
+            writeln!(f)?;
+        }
+        write!(f, "checksum {}", self.checksum)?;
+        if self.dry_run {
+            write!(f, " (dry run)")?;
+        }
+        Ok(())
+    }
+}
+
+/// FNV-1a, stable across platforms and compiler versions
+#[derive(Clone, Copy)]
+struct Checksum(u64);
+impl Checksum {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    fn hex(&self) -> String {
+        format!("{:016x}", self.0)
+    }
+}
+
+/// Serialized rows of a table by id
+type Rows = BTreeMap<String, Value>;
+
+/// A parsed archive, with `None` data for deleted rows
+struct Archive {
+    header: ArchiveHeader,
+    rows: Vec<(String, String, Option<Value>)>,
+    checksum: String,
+}
+impl Archive {
+    fn read(path: &Path) -> Result<Self, BackupError> {
+        let format_error = |error: &str| BackupError::Format {
+            archive: path.to_path_buf(),
+            error: error.to_string(),
+        };
+        let mut checksum = Checksum::new();
+        let mut header = None;
+        let mut rows = Vec::new();
+        let mut end = None;
+        for (n, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+            let line = line?;
+            if end.is_some() {
+                return Err(format_error("content after end"));
+            }
+            let parsed: ArchiveLine = serde_json::from_str(&line).map_err(|e| {
+                BackupError::Parse {
+                    archive: path.to_path_buf(),
+                    line: n + 1,
+                    error: e.to_string(),
+                }
+            })?;
+            match (parsed, header.is_some()) {
+                (ArchiveLine::Header(h), false) => {
+                    if h.format != ARCHIVE_FORMAT {
+                        return Err(format_error(&format!("unsupported format {}", h.format)));
+                    }
+                    header = Some(h);
+                }
+                (_, false) | (ArchiveLine::Header(_), true) => {
+                    return Err(format_error("archive must start with one header"));
+                }
+                (ArchiveLine::Row { table, id, data }, _) => rows.push((table, id, Some(data))),
+                (ArchiveLine::Deleted { table, id }, _) => rows.push((table, id, None)),
+                (ArchiveLine::End { rows: count, checksum: expected }, _) => {
+                    if count != rows.len() {
+                        return Err(format_error(&format!("expected {} rows, found {}", count, rows.len())));
+                    }
+                    if expected != checksum.hex() {
+                        return Err(BackupError::Checksum {
+                            archive: path.to_path_buf(),
+                        });
+                    }
+                    end = Some(expected);
+                }
+            }
+            checksum.write(line.as_bytes());
+            checksum.write(b"\n");
+        }
+        match (header, end) {
+            (Some(header), Some(checksum)) => Ok(Self { header, rows, checksum }),
+            (None, _) => Err(format_error("archive is empty")),
+            (_, None) => Err(format_error("archive is incomplete")),
+        }
+    }
+}
+/// Writes an archive next to `path` and moves it there when finished
+struct ArchiveWriter {
+    file: BufWriter<File>,
+    path: PathBuf,
+    partial: PathBuf,
+    checksum: Checksum,
+    rows: usize,
+}
+impl ArchiveWriter {
+    fn create(path: &Path, header: ArchiveHeader) -> Result<Self, BackupError> {
+        let partial = PathBuf::from(format!("{}.partial", path.display()));
+        let mut writer = Self {
+            file: BufWriter::new(File::create(&partial)?),
+            path: path.to_path_buf(),
+            partial,
+            checksum: Checksum::new(),
+            rows: 0,
+        };
+        writer.line(&ArchiveLine::Header(header))?;
+        Ok(writer)
+    }
+    fn line(&mut self, line: &ArchiveLine) -> Result<(), BackupError> {
+        let mut json = serde_json::to_string(line).map_err(|e| BackupError::Io(e.to_string()))?;
+        json.push('\n');
+        self.file.write_all(json.as_bytes())?;
+        self.checksum.write(json.as_bytes());
+        if let ArchiveLine::Row { .. } | ArchiveLine::Deleted { .. } = line {
+            self.rows += 1;
+        }
+        Ok(())
+    }
+    /// Write the end of the archive and return its checksum
+    fn finish(mut self) -> Result<String, BackupError> {
+        let checksum = self.checksum.hex();
+        self.line(&ArchiveLine::End {
+            rows: self.rows,
+            checksum: checksum.clone(),
+        })?;
+        self.file.flush()?;
+        fs::rename(&self.partial, &self.path)?;
+        Ok(checksum)
+    }
+}
+
+/// Type erased table of a `Backup`
+trait BackupTable {
+    fn name(&self) -> &'static str;
+    /// All stored rows, including soft deleted ones
+    fn rows(&self) -> Result<Rows, BackupError>;
+    fn check(&self, id: &str, data: &Value) -> Result<(), BackupError>;
+    /// Create the rows of `rows` missing in the table, returning their ids
+    fn create(&self, rows: &Rows) -> Result<HashSet<String>, BackupError>;
+    /// Overwrite the rows not `created` and delete rows not in `rows`,
+    /// returning the number of deleted rows
+    fn replace(&self, rows: &Rows, created: &HashSet<String>) -> Result<usize, BackupError>;
+}
+struct TypedTable<'db, D, T> {
+    name: &'static str,
+    _ty: PhantomData<fn() -> &'db (D, T)>,
+}
+impl<'db, D, T> TypedTable<'db, D, T>
+    where D: Database<'db, T>,
+          T: DatabaseTable<'db, D>,
+{
+    fn decode(&self, id: &str, data: &Value) -> Result<(Id<T>, T), BackupError> {
+        let row_error = |error: String| BackupError::Row {
+            table: self.name.to_string(),
+            id: id.to_string(),
+            error,
+        };
+        let row = id.parse().map_err(|e: uuid::Error| row_error(e.to_string()))?;
+        let data = T::deserialize(data).map_err(|e| row_error(e.to_string()))?;
+        Ok((row, data))
+    }
+    fn unsupported(&self, id: &str) -> BackupError {
+        BackupError::Unsupported {
+            table: self.name.to_string(),
+            id: id.to_string(),
+        }
+    }
+}
+impl<'db, D, T> BackupTable for TypedTable<'db, D, T>
+    where D: Database<'db, T>,
+          T: DatabaseTable<'db, D>,
+{
+    fn name(&self) -> &'static str {
+        self.name
+    }
+    fn rows(&self) -> Result<Rows, BackupError> {
+        D::table()
+            .entries()
+            .into_iter()
+            .map(|entry| {
+                let id = entry.id.to_string();
+                serde_json::to_value(&entry.data)
+                    .map(|data| (id.clone(), data))
+                    .map_err(|e| BackupError::Row {
+                        table: self.name.to_string(),
+                        id,
+                        error: e.to_string(),
+                    })
+            })
+            .collect()
+    }
+    fn check(&self, id: &str, data: &Value) -> Result<(), BackupError> {
+        self.decode(id, data).map(|_| ())
+    }
+    fn create(&self, rows: &Rows) -> Result<HashSet<String>, BackupError> {
+        let mut table = D::table_mut();
+        let mut created = HashSet::new();
+        for (id, data) in rows {
+            let (row, data) = self.decode(id, data)?;
+            if table.get(row).is_none() {
+                table.insert_with_id(row, data).map_err(|_| self.unsupported(id))?;
+                created.insert(id.clone());
+            }
+        }
+        Ok(created)
+    }
+    fn replace(&self, rows: &Rows, created: &HashSet<String>) -> Result<usize, BackupError> {
+        let mut deleted = 0;
+        {
+            let mut table = D::table_mut();
+            for entry in table.entries() {
+                if !rows.contains_key(&entry.id.to_string()) {
+                    table.delete(entry.id);
+                    deleted += 1;
+                }
+            }
+            for (id, data) in rows {
+                if !created.contains(id) {
+                    let (row, data) = self.decode(id, data)?;
+                    table.insert_with_id(row, data).map_err(|_| self.unsupported(id))?;
+                }
+            }
+        }
+        for index in D::indexes() {
+            index.reset();
+        }
+        Ok(deleted)
+    }
+}
+
+/// Tables of the schema `D` to back up
+pub struct Backup<'db, D> {
+    tables: Vec<Box<dyn BackupTable + 'db>>,
+    _ty: PhantomData<fn() -> &'db D>,
+}
+impl<'db, D> Default for Backup<'db, D> {
+    fn default() -> Self {
+        Self {
+            tables: Vec::new(),
+            _ty: PhantomData,
+        }
+    }
+}
+impl<'db, D: 'db> Backup<'db, D> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Include the table for `T`, stored as `name` in archives
+    pub fn table<T>(mut self, name: &'static str) -> Self
+        where D: Database<'db, T>,
+              T: DatabaseTable<'db, D>,
+    {
+        assert!(
+            self.tables.iter().all(|table| table.name() != name),
+            "Table {} registered twice", name,
+        );
+        self.tables.push(Box::new(TypedTable::<D, T> {
+            name,
+            _ty: PhantomData,
+        }));
+        self
+    }
+    fn find(&self, name: &str) -> Result<&dyn BackupTable, BackupError> {
+        self.tables
+            .iter()
+            .find(|table| table.name() == name)
+            .map(|table| &**table)
+            .ok_or_else(|| BackupError::UnknownTable(name.to_string()))
+    }
+    fn header(&self, base: Option<String>) -> ArchiveHeader {
+        ArchiveHeader {
+            format: ARCHIVE_FORMAT,
+            created: SystemTime::now(),
+            tables: self.tables.iter().map(|table| table.name().to_string()).collect(),
+            base,
+        }
+    }
+    /// Verify a chain of archives and return the rows of each table after the last one
+    fn read<P: AsRef<Path>>(&self, chain: &[P]) -> Result<(BTreeMap<String, Rows>, String), BackupError> {
+        let mut state: BTreeMap<String, Rows> = BTreeMap::new();
+        let mut base: Option<String> = None;
+        for path in chain {
+            let path = path.as_ref();
+            debug!("Reading archive {}", path.display());
+            let archive = Archive::read(path)?;
+            if archive.header.base != base {
+                return Err(BackupError::Base {
+                    archive: path.to_path_buf(),
+                });
+            }
+            for table in &archive.header.tables {
+                self.find(table)?;
+                state.entry(table.clone()).or_default();
+            }
+            let mut seen = HashSet::new();
+            for (table, id, data) in archive.rows {
+                let rows = state.get_mut(&table).ok_or_else(|| BackupError::Format {
+                    archive: path.to_path_buf(),
+                    error: format!("table {} is not in the header", table),
+                })?;
+                if !seen.insert((table.clone(), id.clone())) {
+                    return Err(BackupError::Format {
+                        archive: path.to_path_buf(),
+                        error: format!("row {} of table {} appears twice", id, table),
+                    });
+                }
+                match data {
+                    Some(data) => {
+                        self.find(&table)?.check(&id, &data)?;
+                        rows.insert(id, data);
+                    }
+                    None => {
+                        rows.remove(&id);
+                    }
+                }
+            }
+            base = Some(archive.checksum);
+        }
+        let checksum = base.ok_or_else(|| BackupError::Usage("No archives given".to_string()))?;
+        Ok((state, checksum))
+    }
+    /// Write all rows of all tables to a new archive at `path`
+    pub fn export<P: AsRef<Path>>(&self, path: P) -> Result<BackupReport, BackupError>
+        where D: Transactional<'db>,
+    {
+        let path = path.as_ref();
+        // other threads can not write while the transaction is open
+        let transaction = Transaction::<D>::begin()?;
+        let mut writer = ArchiveWriter::create(path, self.header(None))?;
+        let mut tables = Vec::new();
+        for table in &self.tables {
+            let rows = table.rows()?;
+            tables.push(TableSummary {
+                table: table.name().to_string(),
+                rows: rows.len(),
+                deleted: 0,
+            });
+            for (id, data) in rows {
+                writer.line(&ArchiveLine::Row {
+                    table: table.name().to_string(),
+                    id,
+                    data,
+                })?;
+            }
+        }
+        transaction.commit()?;
+        let checksum = writer.finish()?;
+        info!("Exported schema to {}", path.display());
+        Ok(BackupReport {
+            archives: vec![path.to_path_buf()],
+            tables,
+            checksum,
+            dry_run: false,
+        })
+    }
+    /// Write the rows changed since the archives of `base` to a new archive at `path`
+    pub fn export_incremental<B, P>(&self, base: &[B], path: P) -> Result<BackupReport, BackupError>
+        where D: Transactional<'db>,
+              B: AsRef<Path>,
+              P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let (previous, base) = self.read(base)?;
+        let transaction = Transaction::<D>::begin()?;
+        let mut writer = ArchiveWriter::create(path, self.header(Some(base)))?;
+        let mut tables = Vec::new();
+        for table in &self.tables {
+            let name = table.name().to_string();
+            let mut summary = TableSummary {
+                table: name.clone(),
+                ..Default::default()
+            };
+            let rows = table.rows()?;
+            let empty = Rows::new();
+            let before = previous.get(&name).unwrap_or(&empty);
+            for (id, data) in &rows {
+                if before.get(id) != Some(data) {
+                    summary.rows += 1;
+                    writer.line(&ArchiveLine::Row {
+                        table: name.clone(),
+                        id: id.clone(),
+                        data: data.clone(),
+                    })?;
+                }
+            }
+            for id in before.keys().filter(|id| !rows.contains_key(*id)) {
+                summary.deleted += 1;
+                writer.line(&ArchiveLine::Deleted {
+                    table: name.clone(),
+                    id: id.clone(),
+                })?;
+            }
+            tables.push(summary);
+        }
+        transaction.commit()?;
+        let checksum = writer.finish()?;
+        info!("Exported changes to {}", path.display());
+        Ok(BackupReport {
+            archives: vec![path.to_path_buf()],
+            tables,
+            checksum,
+            dry_run: false,
+        })
+    }
+    /// Check a chain of archives without restoring it
+    pub fn verify<P: AsRef<Path>>(&self, chain: &[P]) -> Result<BackupReport, BackupError> {
+        let (state, checksum) = self.read(chain)?;
+        Ok(BackupReport {
+            archives: chain.iter().map(|path| path.as_ref().to_path_buf()).collect(),
+            tables: state
+                .into_iter()
+                .map(|(table, rows)| TableSummary {
+                    table,
+                    rows: rows.len(),
+                    deleted: 0,
+                })
+                .collect(),
+            checksum,
+            dry_run: false,
+        })
+    }
+    /// Replace the tables of the chain of archives with the rows they hold
+    pub fn restore<P: AsRef<Path>>(&self, chain: &[P], options: &RestoreOptions) -> Result<BackupReport, BackupError> {
+        let (state, checksum) = self.read(chain)?;
+        let mut tables = Vec::new();
+        if options.dry_run {
+            for (name, rows) in &state {
+                let current = self.find(name)?.rows()?;
+                tables.push(TableSummary {
+                    table: name.clone(),
+                    rows: rows.len(),
+                    deleted: current.keys().filter(|id| !rows.contains_key(*id)).count(),
+                });
+            }
+        } else {
+            // create missing rows first, so invalid rows fail before anything is replaced
+            let mut created = Vec::new();
+            for (name, rows) in &state {
+                created.push(self.find(name)?.create(rows)?);
+            }
+            for ((name, rows), created) in state.iter().zip(created) {
+                let deleted = self.find(name)?.replace(rows, &created)?;
+                info!("Restored {} rows of {}, deleted {}", rows.len(), name, deleted);
+                tables.push(TableSummary {
+                    table: name.clone(),
+                    rows: rows.len(),
+                    deleted,
+                });
+            }
+        }
+        Ok(BackupReport {
+            archives: chain.iter().map(|path| path.as_ref().to_path_buf()).collect(),
+            tables,
+            checksum,
+            dry_run: options.dry_run,
+        })
+    }
+    /// Run the backup tool with command line arguments, see `BACKUP_USAGE`
+    pub fn command<S: AsRef<str>>(&self, args: &[S]) -> Result<BackupReport, BackupError>
+        where D: Transactional<'db>,
+    {
+        let args: Vec<&str> = args.iter().map(|arg| arg.as_ref()).collect();
+        match &args[..] {
+            ["export", path] => self.export(path),
+            ["incremental", path, base @ ..] if !base.is_empty() => self.export_incremental(base, path),
+            ["verify", chain @ ..] if !chain.is_empty() => self.verify(chain),
+            ["restore", "--dry-run", chain @ ..] if !chain.is_empty() => {
+                self.restore(chain, &RestoreOptions {
+                    dry_run: true,
+                })
+            }
+            ["restore", chain @ ..] if !chain.is_empty() => self.restore(chain, &RestoreOptions::default()),
+            _ => Err(BackupError::Usage(BACKUP_USAGE.to_string())),
+        }
+    }
+}
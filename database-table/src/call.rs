@@ -0,0 +1,160 @@
+//! Errors of functions defined with `define_api::api!`
+//!
+//! An api function may return `Result<T, E>` where `E: ApiError`. The
+//! generated handler responds with the status of the error and the error as
+//! JSON body, and the generated client returns it as `CallError::Api`:
+//! ```rust,ignore
+//! api! {
+//!     fn restore_task(id: Id<Task>) -> Result<Task, RequestError> {
+//!         <Task as DatabaseTable<'_, Schema>>::restore(id)
+//!             .ok_or_else(|| RequestError::NotFound(id.to_string()))
+//!     }
+//! }
+//! match api::restore_task(id).await {
+//!     Ok(task) => ...,
+//!     Err(CallError::Api { error: RequestError::NotFound(_), .. }) => ...,
+//!     Err(e) => error!("{}", e),
+//! }
+//! ```
+use crate::{
+    DeleteError,
+    IndexError,
+    JsonSchema,
+    TransactionError,
+};
+use serde::{
+    de::DeserializeOwned,
+    Deserialize,
+    Serialize,
+};
+use std::fmt::{
+    self,
+    Debug,
+    Display,
+};
+
+/// Error types of api functions, sent to clients with an HTTP status
+pub trait ApiError: Serialize + DeserializeOwned {
+    fn status(&self) -> u16;
+}
+/// General errors of api functions
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub enum RequestError {
+    NotFound(String),
+    /// request failed validation
+    Invalid(String),
+    Conflict(String),
+    Forbidden(String),
+    Internal(String),
+}
+impl Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NotFound(what) => write!(f, "Not found: {}", what),
+            Self::Invalid(e) => write!(f, "Invalid request: {}", e),
+            Self::Conflict(e) => write!(f, "Conflict: {}", e),
+            Self::Forbidden(e) => write!(f, "Forbidden: {}", e),
+            Self::Internal(e) => write!(f, "Internal error: {}", e),
+        }
+    }
+}
+impl std::error::Error for RequestError {}
+impl ApiError for RequestError {
+    fn status(&self) -> u16 {
+        match self {
+            Self::NotFound(_) => 404,
+            Self::Invalid(_) => 422,
+            Self::Conflict(_) => 409,
+            Self::Forbidden(_) => 403,
+            Self::Internal(_) => 500,
+        }
+    }
+}
+impl ApiError for String {
+    fn status(&self) -> u16 {
+        400
+    }
+}
+impl ApiError for IndexError {
+    fn status(&self) -> u16 {
+        match self {
+            Self::Unique { .. } => 409,
+            Self::NotBuilt { .. } => 500,
+        }
+    }
+}
+impl ApiError for DeleteError {
+    fn status(&self) -> u16 {
+        409
+    }
+}
+impl ApiError for TransactionError {
+    fn status(&self) -> u16 {
+        match self {
+            Self::Backend(_) => 500,
+            Self::Index(_) | Self::Delete(_) => 409,
+        }
+    }
+}
+impl From<IndexError> for RequestError {
+    fn from(e: IndexError) -> Self {
+        Self::Conflict(e.to_string())
+    }
+}
+impl From<DeleteError> for RequestError {
+    fn from(e: DeleteError) -> Self {
+        Self::Conflict(e.to_string())
+    }
+}
+impl From<TransactionError> for RequestError {
+    fn from(e: TransactionError) -> Self {
+        match e {
+            TransactionError::Backend(e) => Self::Internal(e),
+            e => Self::Conflict(e.to_string()),
+        }
+    }
+}
+
+/// Error of a call to an api function from a generated client
+///
+/// `E` is the error type of the function, `()` for functions not returning a `Result`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CallError<E = ()> {
+    /// error returned by the function
+    Api {
+        status: u16,
+        error: E,
+    },
+    /// error status without an error of the function, e.g. from a request guard
+    Status(u16),
+    /// the request could not be sent or the response not be read
+    Transport(String),
+}
+impl<E> CallError<E> {
+    pub fn transport<T: Debug>(e: T) -> Self {
+        Self::Transport(format!("{:?}", e))
+    }
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            Self::Api { status, .. } | Self::Status(status) => Some(*status),
+            Self::Transport(_) => None,
+        }
+    }
+    /// Error returned by the function
+    pub fn api(self) -> Option<E> {
+        match self {
+            Self::Api { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+impl<E: Debug> Display for CallError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Api { status, error } => write!(f, "Call failed with status {}: {:?}", status, error),
+            Self::Status(status) => write!(f, "Call failed with status {}", status),
+            Self::Transport(e) => write!(f, "Call failed: {}", e),
+        }
+    }
+}
+impl<E: Debug> std::error::Error for CallError<E> {}
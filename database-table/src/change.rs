@@ -0,0 +1,121 @@
+//! Change feeds
+//!
+//! A `ChangeFeed` is notified of every row inserted, updated or deleted
+//! through the `Database` methods. Feeds are declared as statics and
+//! returned by `Database::changes`:
+//! ```rust,ignore
+//! lazy_static! {
+//!     pub static ref TASK_CHANGES: ChangeFeed<Task> = ChangeFeed::new();
+//! }
+//! impl<'db> Database<'db, Task> for Schema {
+//!     ...
+//!     fn changes() -> Option<&'db ChangeFeed<Task>> {
+//!         Some(&*TASK_CHANGES)
+//!     }
+//! }
+//! let watch = Watch::Entry(id);
+//! let listener = TASK_CHANGES.subscribe(move |change| if watch.matches(change) {
+//!     debug!("{:?}", change);
+//! });
+//! TASK_CHANGES.unsubscribe(listener);
+//! ```
+//! Changes made inside a `Transaction` are published when it is committed,
+//! and never if it is rolled back.
+use crate::{
+    Entry,
+    Id,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use std::sync::{
+    atomic::{
+        AtomicUsize,
+        Ordering,
+    },
+    Arc,
+    RwLock,
+};
+
+/// A change to a row of `T`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Change<T> {
+    Inserted(Entry<T>),
+    Updated(Entry<T>),
+    Deleted(Id<T>),
+}
+impl<T> Change<T> {
+    pub fn id(&self) -> Id<T> {
+        match self {
+            Self::Inserted(entry) | Self::Updated(entry) => entry.id,
+            Self::Deleted(id) => *id,
+        }
+    }
+}
+/// Rows of `T` a subscriber is interested in
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Watch<T> {
+    Table,
+    Entry(Id<T>),
+}
+impl<T> Watch<T> {
+    pub fn matches(&self, change: &Change<T>) -> bool {
+        match self {
+            Self::Table => true,
+            Self::Entry(id) => change.id() == *id,
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ListenerId(usize);
+
+type Listener<T> = Arc<dyn Fn(&Change<T>) + Send + Sync>;
+
+/// Listeners to the changes of the table for `T`
+pub struct ChangeFeed<T> {
+    listeners: RwLock<Vec<(ListenerId, Listener<T>)>>,
+    next: AtomicUsize,
+}
+impl<T> Default for ChangeFeed<T> {
+    fn default() -> Self {
+        Self {
+            listeners: RwLock::new(Vec::new()),
+            next: AtomicUsize::new(0),
+        }
+    }
+}
+impl<T> ChangeFeed<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Call `f` with every following change until unsubscribed
+    pub fn subscribe<F>(&self, f: F) -> ListenerId
+        where F: Fn(&Change<T>) + Send + Sync + 'static
+    {
+        let id = ListenerId(self.next.fetch_add(1, Ordering::Relaxed));
+        self.listeners
+            .write()
+            .expect("ChangeFeed poisoned")
+            .push((id, Arc::new(f)));
+        id
+    }
+    pub fn unsubscribe(&self, id: ListenerId) {
+        self.listeners
+            .write()
+            .expect("ChangeFeed poisoned")
+            .retain(|(other, _)| *other != id);
+    }
+    /// Call the listeners without holding the lock, so they can subscribe and unsubscribe
+    pub fn publish(&self, change: &Change<T>) {
+        let listeners: Vec<Listener<T>> = self.listeners
+            .read()
+            .expect("ChangeFeed poisoned")
+            .iter()
+            .map(|(_, listener)| listener.clone())
+            .collect();
+        for listener in listeners {
+            listener(change);
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use rql::*;
+use crate::Id;
 
 #[derive(
     Clone,
@@ -30,13 +30,6 @@ impl<T> Entry<T> {
         self.data
     }
 }
-impl<T> From<Row<'_, T>> for Entry<T>
-    where T: Clone
-{
-    fn from(row: Row<'_, T>) -> Self {
-        Self::new(row.id, (*row.data).clone())
-    }
-}
 impl<T> From<Id<T>> for Entry<T>
     where T: Clone + Default
 {
@@ -0,0 +1,99 @@
+use serde::{
+    Deserialize,
+    Deserializer,
+    Serialize,
+    Serializer,
+};
+use std::{
+    cmp::Ordering,
+    fmt::{
+        self,
+        Debug,
+        Display,
+    },
+    hash::{
+        Hash,
+        Hasher,
+    },
+    marker::PhantomData,
+    str::FromStr,
+};
+use uuid::Uuid;
+
+/// Backend independent identifier of a row in the table for `T`
+pub struct Id<T> {
+    uuid: Uuid,
+    _ty: PhantomData<T>,
+}
+impl<T> Id<T> {
+    pub fn new() -> Self {
+        Self::from_uuid(Uuid::new_v4())
+    }
+    pub fn from_uuid(uuid: Uuid) -> Self {
+        Self {
+            uuid,
+            _ty: PhantomData,
+        }
+    }
+    pub fn uuid(&self) -> &Uuid {
+        &self.uuid
+    }
+}
+impl<T> Default for Id<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl<T> Clone for Id<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<T> Copy for Id<T> {}
+impl<T> PartialEq for Id<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.uuid == other.uuid
+    }
+}
+impl<T> Eq for Id<T> {}
+impl<T> PartialOrd for Id<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl<T> Ord for Id<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.uuid.cmp(&other.uuid)
+    }
+}
+impl<T> Hash for Id<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.uuid.hash(state)
+    }
+}
+impl<T> Debug for Id<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Id({})", self.uuid)
+    }
+}
+impl<T> Display for Id<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.uuid)
+    }
+}
+impl<T> FromStr for Id<T> {
+    type Err = uuid::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Uuid::from_str(s).map(Self::from_uuid)
+    }
+}
+impl<T> Serialize for Id<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.uuid.serialize(serializer)
+    }
+}
+impl<'de, T> Deserialize<'de> for Id<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Uuid::deserialize(deserializer).map(Self::from_uuid)
+    }
+}
@@ -0,0 +1,246 @@
+//! Secondary indexes and unique constraints
+//!
+//! Indexes are kept in memory, built from the table on first use and
+//! maintained by the `Database` methods `insert`, `update` and `delete`.
+//! They are declared as statics and returned by `Database::indexes`:
+//! ```rust,ignore
+//! lazy_static! {
+//!     pub static ref USER_NAME: HashIndex<User, String> =
+//!         HashIndex::unique("user.name", |user| user.name().clone());
+//! }
+//! impl<'db> Database<'db, User> for Schema {
+//!     ...
+//!     fn indexes() -> Vec<&'db dyn TableIndex<User>> {
+//!         vec![&*USER_NAME]
+//!     }
+//! }
+//! let user = DatabaseTable::<'db, Schema>::find_by(&*USER_NAME, &name);
+//! ```
+use crate::{
+    Entry,
+    Id,
+    JsonSchema,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use std::{
+    collections::{
+        BTreeMap,
+        HashMap,
+    },
+    fmt::{
+        self,
+        Debug,
+        Display,
+    },
+    hash::Hash,
+    marker::PhantomData,
+    ops::RangeBounds,
+    sync::RwLock,
+};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub enum IndexError {
+    Unique {
+        index: String,
+        key: String,
+    },
+    /// the index was reset after it was built, e.g. by a rolled back transaction
+    NotBuilt {
+        index: String,
+    },
+}
+impl Display for IndexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Unique { index, key } => write!(f, "Duplicate key {} in unique index {}", key, index),
+            Self::NotBuilt { index } => write!(f, "Index {} is not built", index),
+        }
+    }
+}
+impl std::error::Error for IndexError {}
+
+/// Type erased index on the rows of `T`
+pub trait TableIndex<T> {
+    fn name(&self) -> &'static str;
+    /// Build the index from `load`, unless it is built already
+    fn build_with(&self, load: &dyn Fn() -> Vec<Entry<T>>);
+    /// Err if storing `data` as row `id` would violate a unique constraint,
+    /// or if the index is not built and can not tell
+    fn check(&self, id: Option<Id<T>>, data: &T) -> Result<(), IndexError>;
+    fn insert(&self, id: Id<T>, data: &T);
+    fn remove(&self, id: Id<T>, data: &T);
+    /// Drop the index, it is rebuilt on next use
+    fn reset(&self);
+}
+/// Map from index keys to row ids
+pub trait KeyMap<K, T>: Default {
+    fn ids(&self, key: &K) -> Option<&Vec<Id<T>>>;
+    fn ids_mut(&mut self, key: K) -> &mut Vec<Id<T>>;
+    fn remove_key(&mut self, key: &K);
+}
+impl<K: Hash + Eq, T> KeyMap<K, T> for HashMap<K, Vec<Id<T>>> {
+    fn ids(&self, key: &K) -> Option<&Vec<Id<T>>> {
+        self.get(key)
+    }
+    fn ids_mut(&mut self, key: K) -> &mut Vec<Id<T>> {
+        self.entry(key).or_default()
+    }
+    fn remove_key(&mut self, key: &K) {
+        self.remove(key);
+    }
+}
+impl<K: Ord, T> KeyMap<K, T> for BTreeMap<K, Vec<Id<T>>> {
+    fn ids(&self, key: &K) -> Option<&Vec<Id<T>>> {
+        self.get(key)
+    }
+    fn ids_mut(&mut self, key: K) -> &mut Vec<Id<T>> {
+        self.entry(key).or_default()
+    }
+    fn remove_key(&mut self, key: &K) {
+        self.remove(key);
+    }
+}
+enum Keys<T, K> {
+    Single(fn(&T) -> K),
+    Multi(fn(&T) -> Vec<K>),
+}
+/// Index on keys of type `K` computed from rows of `T`
+pub struct Index<T, K, M> {
+    name: &'static str,
+    keys: Keys<T, K>,
+    unique: bool,
+    map: RwLock<Option<M>>,
+    _ty: PhantomData<fn() -> T>,
+}
+pub type HashIndex<T, K> = Index<T, K, HashMap<K, Vec<Id<T>>>>;
+pub type OrderedIndex<T, K> = Index<T, K, BTreeMap<K, Vec<Id<T>>>>;
+
+impl<T, K, M> Index<T, K, M>
+    where K: Clone + Debug,
+          M: KeyMap<K, T>,
+{
+    fn with_keys(name: &'static str, keys: Keys<T, K>, unique: bool) -> Self {
+        Self {
+            name,
+            keys,
+            unique,
+            map: RwLock::new(None),
+            _ty: PhantomData,
+        }
+    }
+    pub fn new(name: &'static str, key: fn(&T) -> K) -> Self {
+        Self::with_keys(name, Keys::Single(key), false)
+    }
+    /// Index rejecting rows with a key already used by another row
+    pub fn unique(name: &'static str, key: fn(&T) -> K) -> Self {
+        Self::with_keys(name, Keys::Single(key), true)
+    }
+    /// Index on every key in a collection field, e.g. the members of a project
+    pub fn multi(name: &'static str, keys: fn(&T) -> Vec<K>) -> Self {
+        Self::with_keys(name, Keys::Multi(keys), false)
+    }
+    fn keys(&self, data: &T) -> Vec<K> {
+        match &self.keys {
+            Keys::Single(key) => vec![key(data)],
+            Keys::Multi(keys) => keys(data),
+        }
+    }
+    /// Ids of the rows with `key`, empty until the index is built
+    pub fn lookup(&self, key: &K) -> Vec<Id<T>> {
+        self.map
+            .read()
+            .expect("Index poisoned")
+            .as_ref()
+            .and_then(|map| map.ids(key).cloned())
+            .unwrap_or_default()
+    }
+}
+impl<T, K> OrderedIndex<T, K>
+    where K: Ord + Clone + Debug,
+{
+    /// Ids of the rows with keys in `range`, in key order
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Vec<Id<T>> {
+        self.map
+            .read()
+            .expect("Index poisoned")
+            .as_ref()
+            .map(|map| {
+                map.range(range)
+                    .flat_map(|(_, ids)| ids.iter().cloned())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+impl<T, K, M> TableIndex<T> for Index<T, K, M>
+    where K: Clone + Debug,
+          M: KeyMap<K, T>,
+{
+    fn name(&self) -> &'static str {
+        self.name
+    }
+    fn build_with(&self, load: &dyn Fn() -> Vec<Entry<T>>) {
+        if self.map.read().expect("Index poisoned").is_some() {
+            return;
+        }
+        let mut map = self.map.write().expect("Index poisoned");
+        if map.is_none() {
+            let mut new = M::default();
+            for entry in load() {
+                for key in self.keys(&entry.data) {
+                    new.ids_mut(key).push(entry.id);
+                }
+            }
+            *map = Some(new);
+        }
+    }
+    fn check(&self, id: Option<Id<T>>, data: &T) -> Result<(), IndexError> {
+        if !self.unique {
+            return Ok(());
+        }
+        let map = self.map.read().expect("Index poisoned");
+        match map.as_ref() {
+            Some(map) => {
+                self.keys(data)
+                    .into_iter()
+                    .find(|key| {
+                        map.ids(key)
+                            .map(|ids| ids.iter().any(|other| Some(*other) != id))
+                            .unwrap_or(false)
+                    })
+                    .map(|key| Err(IndexError::Unique {
+                        index: self.name.to_string(),
+                        key: format!("{:?}", key),
+                    }))
+                    .unwrap_or(Ok(()))
+            }
+            None => Err(IndexError::NotBuilt {
+                index: self.name.to_string(),
+            }),
+        }
+    }
+    fn insert(&self, id: Id<T>, data: &T) {
+        if let Some(map) = self.map.write().expect("Index poisoned").as_mut() {
+            for key in self.keys(data) {
+                map.ids_mut(key).push(id);
+            }
+        }
+    }
+    fn remove(&self, id: Id<T>, data: &T) {
+        if let Some(map) = self.map.write().expect("Index poisoned").as_mut() {
+            for key in self.keys(data) {
+                let ids = map.ids_mut(key.clone());
+                ids.retain(|other| *other != id);
+                if ids.is_empty() {
+                    map.remove_key(&key);
+                }
+            }
+        }
+    }
+    fn reset(&self) {
+        *self.map.write().expect("Index poisoned") = None;
+    }
+}
@@ -0,0 +1,405 @@
+//! Transactions for backends without them
+//!
+//! A `Journal` implements `Transactional` for a schema whose backend can not
+//! group writes, e.g. an `rql` schema. While a thread has a transaction
+//! open, other threads block when accessing any table of the schema, and
+//! the previous values of the rows written are recorded to restore them on
+//! rollback.
+//! ```rust,ignore
+//! lazy_static! {
+//!     pub static ref JOURNAL: Journal = Journal::with_file("database/journal.jsonl")
+//!         .table::<Schema, Task>()
+//!         .table::<Schema, Project>();
+//! }
+//! impl<'db> Database<'db, Task> for Schema {
+//!     type Table = Journaled<'db, TableGuard<'db, Task>>;
+//!     type TableMut = Journaled<'db, TableGuardMut<'db, Task>>;
+//!     fn table() -> Self::Table {
+//!         JOURNAL.read(|| DB.task())
+//!     }
+//!     fn table_mut() -> Self::TableMut {
+//!         JOURNAL.write::<Self, Task, _, _>(|| DB.task_mut())
+//!     }
+//! }
+//! impl<'db> Transactional<'db> for Schema {
+//!     fn begin() -> Result<(), TransactionError> {
+//!         JOURNAL.begin()
+//!     }
+//!     fn commit() -> Result<(), TransactionError> {
+//!         JOURNAL.commit()
+//!     }
+//!     fn rollback() -> Result<(), TransactionError> {
+//!         JOURNAL.rollback()
+//!     }
+//! }
+//! ```
+//! A journal with a file also writes the previous values to it before a row
+//! is written, and removes it on commit or rollback. A file left by a crash
+//! holds the writes of an unfinished transaction, which `recover` rolls back
+//! before the schema is used:
+//! ```rust,ignore
+//! JOURNAL.recover()?;
+//! ```
+//! The tables written in transactions have to be registered with `table`
+//! for this. A journal without a file can not roll back after a crash.
+use crate::{
+    Database,
+    DatabaseTable,
+    Entry,
+    Id,
+    Page,
+    Query,
+    TableRead,
+    TableWrite,
+    TransactionError,
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    fs::{
+        self,
+        File,
+    },
+    io::{
+        self,
+        BufRead,
+        BufReader,
+        Write,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+    sync::{
+        Condvar,
+        Mutex,
+        MutexGuard,
+    },
+    thread::{
+        self,
+        ThreadId,
+    },
+};
+#[allow(unused)]
+use tracing::{
+    debug,
+    error,
+};
+
+/// Function restoring a serialized previous row
+type Undo = fn(Value);
+/// Undo function with the row
+type Op = (Undo, Value);
+
+#[derive(Default)]
+struct State {
+    /// thread with an open transaction
+    owner: Option<ThreadId>,
+    /// whether writes of the owner are recorded, not while undoing them
+    recording: bool,
+    /// number of table guards held by each thread other than the owner
+    readers: HashMap<ThreadId, usize>,
+    undo: Vec<Op>,
+    /// file of the open transaction
+    file: Option<File>,
+    /// error writing the file, the transaction is rolled back instead of committed
+    failed: Option<String>,
+}
+/// Transaction state of a schema
+#[derive(Default)]
+pub struct Journal {
+    state: Mutex<State>,
+    released: Condvar,
+    path: Option<PathBuf>,
+    /// undo functions of the tables by type name, for `recover`
+    tables: Vec<(&'static str, Undo)>,
+}
+fn backend_error(e: io::Error) -> TransactionError {
+    TransactionError::Backend(format!("Journal file: {}", e))
+}
+impl Journal {
+    /// Journal kept in memory only
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Journal writing the previous rows of open transactions to `path`
+    pub fn with_file<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: Some(path.as_ref().to_path_buf()),
+            ..Self::default()
+        }
+    }
+    /// Register the table for `T` in `D`, so `recover` can restore its rows
+    pub fn table<'db, D, T>(mut self) -> Self
+        where D: Database<'db, T>,
+              T: DatabaseTable<'db, D>,
+    {
+        self.tables.push((std::any::type_name::<T>(), undo::<D, T>));
+        self
+    }
+    /// Roll back the transaction interrupted by a crash, returning the number of rows restored
+    pub fn recover(&self) -> Result<usize, TransactionError> {
+        let path = match &self.path {
+            Some(path) if path.exists() => path,
+            _ => return Ok(0),
+        };
+        let mut ops = Vec::new();
+        for line in BufReader::new(File::open(path).map_err(backend_error)?).lines() {
+            let line = line.map_err(backend_error)?;
+            // the last line may be incomplete, its row was not written yet
+            let (table, row) = match serde_json::from_str::<(String, Value)>(&line) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    debug!("Skipping incomplete journal entry: {}", e);
+                    continue;
+                }
+            };
+            let undo = self.tables
+                .iter()
+                .find(|(name, _)| *name == table)
+                .map(|(_, undo)| *undo)
+                .ok_or_else(|| TransactionError::Backend(format!("Journal table {} is not registered", table)))?;
+            ops.push((undo, row));
+        }
+        let restored = ops.len();
+        for (undo, row) in ops.into_iter().rev() {
+            undo(row);
+        }
+        fs::remove_file(path).map_err(backend_error)?;
+        Ok(restored)
+    }
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().expect("Journal poisoned")
+    }
+    /// Wait for transactions of other threads, returns the thread if the access was counted
+    fn enter(&self) -> Option<ThreadId> {
+        let current = thread::current().id();
+        let mut state = self.lock();
+        if state.owner == Some(current) {
+            return None;
+        }
+        while state.owner.is_some() {
+            state = self.released.wait(state).expect("Journal poisoned");
+        }
+        *state.readers.entry(current).or_insert(0) += 1;
+        Some(current)
+    }
+    fn leave(&self, reader: ThreadId) {
+        let mut state = self.lock();
+        if let Some(count) = state.readers.get_mut(&reader) {
+            *count -= 1;
+            if *count == 0 {
+                state.readers.remove(&reader);
+            }
+        }
+        drop(state);
+        self.released.notify_all();
+    }
+    /// Guard returned by `guard`, taken when no other thread has a transaction open
+    pub fn read<G, F>(&self, guard: F) -> Journaled<'_, G>
+        where F: FnOnce() -> G
+    {
+        let reader = self.enter();
+        Journaled {
+            guard: guard(),
+            journal: self,
+            reader,
+            undo: None,
+        }
+    }
+    /// Guard for the table of `T` in `D`, recording writes of transactions
+    pub fn write<'db, D, T, G, F>(&self, guard: F) -> Journaled<'_, G>
+        where D: Database<'db, T>,
+              T: DatabaseTable<'db, D>,
+              F: FnOnce() -> G,
+    {
+        let mut journaled = self.read(guard);
+        journaled.undo = Some(undo::<D, T>);
+        journaled
+    }
+    /// Record `row` to restore it with `undo` if the current thread's transaction is rolled back
+    fn record(&self, table: &'static str, undo: Undo, row: Value) {
+        let mut state = self.lock();
+        if !state.recording || state.owner != Some(thread::current().id()) {
+            return;
+        }
+        if let Some(file) = &mut state.file {
+            let mut line = serde_json::to_string(&(table, &row)).unwrap_or_default();
+            line.push('\n');
+            let written = file.write_all(line.as_bytes())
+                .and_then(|_| file.sync_data());
+            if let Err(e) = written {
+                error!("Failed to write journal: {}", e);
+                state.failed = Some(e.to_string());
+            }
+        }
+        state.undo.push((undo, row));
+    }
+    /// Begin a transaction owned by the current thread
+    ///
+    /// Waits until other threads released their table guards, guards of the
+    /// current thread are not waited for, as they could never be released.
+    pub fn begin(&self) -> Result<(), TransactionError> {
+        let current = thread::current().id();
+        let mut state = self.lock();
+        while state.owner.is_some() || state.readers.keys().any(|reader| *reader != current) {
+            if state.owner == Some(current) {
+                return Err(TransactionError::Backend("Transaction already open on this thread".into()));
+            }
+            state = self.released.wait(state).expect("Journal poisoned");
+        }
+        if let Some(path) = &self.path {
+            state.file = Some(File::create(path).map_err(backend_error)?);
+        }
+        state.failed = None;
+        state.owner = Some(current);
+        state.recording = true;
+        Ok(())
+    }
+    /// Stop recording and take the undo operations of the current thread's transaction
+    fn finish(&self) -> Result<Vec<Op>, TransactionError> {
+        let mut state = self.lock();
+        if state.owner != Some(thread::current().id()) {
+            return Err(TransactionError::Backend("No transaction open on this thread".into()));
+        }
+        state.recording = false;
+        Ok(std::mem::take(&mut state.undo))
+    }
+    /// Remove the file of the finished transaction and let other threads continue
+    fn release(&self) -> Result<(), TransactionError> {
+        let mut state = self.lock();
+        let result = match (state.file.take(), &self.path) {
+            (Some(_), Some(path)) => fs::remove_file(path).map_err(backend_error),
+            _ => Ok(()),
+        };
+        state.owner = None;
+        drop(state);
+        self.released.notify_all();
+        result
+    }
+    /// Keep the writes of the transaction
+    ///
+    /// With a file, removing it commits the transaction. If the file could
+    /// not be written, the transaction is rolled back.
+    pub fn commit(&self) -> Result<(), TransactionError> {
+        let failed = self.lock().failed.take();
+        if let Some(e) = failed {
+            self.rollback()?;
+            return Err(TransactionError::Backend(format!("Journal file: {}", e)));
+        }
+        self.finish()?;
+        self.release()
+    }
+    /// Restore the rows written by the transaction, latest write first
+    pub fn rollback(&self) -> Result<(), TransactionError> {
+        let undo = self.finish()?;
+        for (undo, row) in undo.into_iter().rev() {
+            undo(row);
+        }
+        self.release()
+    }
+}
+/// Restore a row written in a rolled back transaction
+fn undo<'db, D, T>(row: Value)
+    where D: Database<'db, T>,
+          T: DatabaseTable<'db, D>,
+{
+    match serde_json::from_value::<(Id<T>, Option<T>)>(row) {
+        Ok((id, Some(previous))) => {
+            if D::table_mut().insert_with_id(id, previous).is_err() {
+                error!("Failed to restore row {}", id);
+            }
+        }
+        Ok((id, None)) => {
+            D::table_mut().delete(id);
+        }
+        Err(e) => error!("Invalid journal entry: {}", e),
+    }
+    for index in D::indexes() {
+        index.reset();
+    }
+}
+/// Table guard of a schema with a `Journal`
+pub struct Journaled<'j, G> {
+    guard: G,
+    journal: &'j Journal,
+    /// thread counted as reader while this guard is held
+    reader: Option<ThreadId>,
+    /// set for guards returned by `Journal::write`
+    undo: Option<Undo>,
+}
+impl<G> Journaled<'_, G> {
+    /// Record the previous value of the row `id` before it is written in a transaction
+    fn record<T: Serialize>(&self, id: Id<T>, previous: Option<&T>) {
+        if let Some(undo) = self.undo {
+            match serde_json::to_value((id, previous)) {
+                Ok(row) => self.journal.record(std::any::type_name::<T>(), undo, row),
+                Err(e) => error!("Failed to record row {}: {}", id, e),
+            }
+        }
+    }
+}
+impl<G> Drop for Journaled<'_, G> {
+    fn drop(&mut self) {
+        if let Some(reader) = self.reader {
+            self.journal.leave(reader);
+        }
+    }
+}
+impl<G, T> TableRead<T> for Journaled<'_, G>
+    where G: TableRead<T>,
+{
+    fn get(&self, id: Id<T>) -> Option<T> {
+        self.guard.get(id)
+    }
+    fn entries(&self) -> Vec<Entry<T>> {
+        self.guard.entries()
+    }
+    fn filter(&self, f: &dyn Fn(&T) -> bool) -> Vec<Entry<T>> {
+        self.guard.filter(f)
+    }
+    fn find(&self, f: &dyn Fn(&T) -> bool) -> Option<Entry<T>> {
+        self.guard.find(f)
+    }
+    fn query(&self, query: &Query) -> Page<T>
+        where T: Serialize
+    {
+        self.guard.query(query)
+    }
+}
+impl<G, T> TableWrite<T> for Journaled<'_, G>
+    where G: TableWrite<T>,
+          T: Serialize + Clone,
+{
+    /// New rows get their id before they are written, so the insert is recorded first
+    fn insert(&mut self, data: T) -> Id<T> {
+        let id = Id::new();
+        self.record(id, None);
+        match self.guard.insert_with_id(id, data) {
+            Ok(_) => id,
+            Err(data) => {
+                let id = self.guard.insert(data);
+                self.record(id, None);
+                id
+            }
+        }
+    }
+    fn delete(&mut self, id: Id<T>) -> Option<T> {
+        let previous = self.guard.get(id)?;
+        self.record(id, Some(&previous));
+        self.guard.delete(id)
+    }
+    fn insert_with_id(&mut self, id: Id<T>, data: T) -> Result<Option<T>, T> {
+        let previous = self.guard.get(id);
+        self.record(id, previous.as_ref());
+        self.guard.insert_with_id(id, data)
+    }
+    fn modify<F>(&mut self, id: Id<T>, f: F) -> Option<T>
+        where F: FnOnce(&mut T)
+    {
+        let previous = self.guard.get(id)?;
+        self.record(id, Some(&previous));
+        self.guard.modify(id, f)
+    }
+}
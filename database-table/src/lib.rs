@@ -1,34 +1,378 @@
+extern crate self as database_table;
+
 pub mod entry;
 pub use entry::*;
+pub mod id;
+pub use id::*;
 pub mod table;
 pub use table::*;
 pub mod route;
 pub use route::*;
+pub mod backend;
+pub mod update;
+pub use update::*;
+pub mod query;
+pub use query::*;
+pub mod migration;
+pub use migration::*;
+pub mod relation;
+pub use relation::*;
+pub mod index;
+pub use index::*;
+pub mod transaction;
+pub use transaction::*;
+pub mod journal;
+pub use journal::*;
+pub mod change;
+pub use change::*;
+pub mod audit;
+pub use audit::*;
+pub mod backup;
+pub use backup::*;
+pub mod call;
+pub use call::*;
+pub mod transport;
+pub use transport::*;
+pub mod socket;
+pub use socket::*;
+pub mod schema;
+pub use schema::*;
+pub mod openapi;
+pub use openapi::*;
 
-use rql::*;
+use tracing::{
+    debug,
+    error,
+};
+pub use serde_json;
+pub use define_api::{
+    JsonSchema,
+    Updatable,
+};
 
+/// Err if `data` violates a unique index, or an index was reset and must be rebuilt
+fn check_indexes<T>(indexes: &[&dyn TableIndex<T>], id: Option<Id<T>>, data: &T) -> Result<(), IndexError> {
+    indexes
+        .iter()
+        .try_for_each(|index| index.check(id, data))
+}
+/// Read access to the rows of a table
+pub trait TableRead<T> {
+    fn get(&self, id: Id<T>) -> Option<T>;
+    fn entries(&self) -> Vec<Entry<T>>;
+    fn filter(&self, f: &dyn Fn(&T) -> bool) -> Vec<Entry<T>> {
+        self.entries()
+            .into_iter()
+            .filter(|entry| f(entry.data()))
+            .collect()
+    }
+    fn find(&self, f: &dyn Fn(&T) -> bool) -> Option<Entry<T>> {
+        self.entries()
+            .into_iter()
+            .find(|entry| f(entry.data()))
+    }
+    fn query(&self, query: &Query) -> Page<T>
+        where T: serde::Serialize
+    {
+        query.run(self.entries())
+    }
+}
+/// Write access to the rows of a table
+pub trait TableWrite<T> : TableRead<T> {
+    fn insert(&mut self, data: T) -> Id<T>;
+    fn delete(&mut self, id: Id<T>) -> Option<T>;
+    /// Insert `data` as the row `id`, returning the row it replaced.
+    /// Gives `data` back if the backend can not create rows with a given id.
+    fn insert_with_id(&mut self, id: Id<T>, data: T) -> Result<Option<T>, T>;
+    /// Apply `f` to the row and return its new value
+    fn modify<F>(&mut self, id: Id<T>, f: F) -> Option<T>
+        where F: FnOnce(&mut T);
+}
+/// Access to the table for `T` in a database backend
+///
+/// `table` and `table_mut` return guards from the backend, all other
+/// methods are implemented on top of them.
 pub trait Database<'db, T: DatabaseTable<'db, Self>> : Sized {
-    fn table() -> TableGuard<'db, T>;
-    fn table_mut() -> TableGuardMut<'db, T>;
+    type Table: TableRead<T>;
+    type TableMut: TableWrite<T>;
+    fn table() -> Self::Table;
+    fn table_mut() -> Self::TableMut;
+    /// Indexes on the table for `T`
+    fn indexes() -> Vec<&'db dyn TableIndex<T>> {
+        Vec::new()
+    }
+    /// Build all indexes not built yet
+    fn build_indexes(indexes: &[&'db dyn TableIndex<T>]) {
+        for index in indexes {
+            index.build_with(&|| Self::get_all());
+        }
+    }
+    /// Feed notified of changes to the table for `T`
+    fn changes() -> Option<&'db ChangeFeed<T>> {
+        None
+    }
+    fn publish(change: Change<T>) {
+        if let Some(feed) = Self::changes() {
+            feed.publish(&change);
+        }
+    }
+    /// Called with every change made through `Database`, e.g. to store an audit log
+    fn audit(_record: AuditRecord<T>) {}
+    /// Audit and publish a change, after the commit of a transaction open on this thread
+    fn notify(record: AuditRecord<T>, change: Change<T>) {
+        if !transaction::is_open() {
+            Self::audit(record);
+            Self::publish(change);
+            return;
+        }
+        let notify: fn(serde_json::Value) = |value| {
+            match serde_json::from_value::<(AuditRecord<T>, Change<T>)>(value) {
+                Ok((record, change)) => {
+                    Self::audit(record);
+                    Self::publish(change);
+                }
+                Err(e) => error!("Invalid pending change: {}", e),
+            }
+        };
+        match serde_json::to_value((record, change)) {
+            Ok(value) => transaction::after_commit(notify, value),
+            Err(e) => error!("Failed to queue change: {}", e),
+        }
+    }
+    /// Store of soft deleted rows, `None` if rows of `T` are deleted permanently
+    fn trash() -> Option<Box<dyn Trash<T> + 'db>> {
+        None
+    }
+    fn is_deleted(id: Id<T>) -> bool {
+        Self::trash()
+            .map(|trash| trash.contains(id))
+            .unwrap_or(false)
+    }
+    /// Remove soft deleted rows from `entries`
+    fn visible(entries: Vec<Entry<T>>) -> Vec<Entry<T>> {
+        match Self::trash() {
+            Some(trash) => {
+                let deleted = trash.ids();
+                entries
+                    .into_iter()
+                    .filter(|entry| !deleted.contains(&entry.id))
+                    .collect()
+            }
+            None => entries,
+        }
+    }
+    /// Insert a row, unless it violates a unique index
+    fn try_insert(obj: T) -> Result<Id<T>, IndexError> {
+        let indexes = Self::indexes();
+        // holding the table guard makes checking and inserting atomic
+        let mut table = loop {
+            Self::build_indexes(&indexes);
+            let table = Self::table_mut();
+            match check_indexes(&indexes, None, &obj) {
+                Err(IndexError::NotBuilt { index }) => debug!("Rebuilding reset index {}", index),
+                result => break result.map(|_| table)?,
+            }
+        };
+        let id = table.insert(obj.clone());
+        for index in &indexes {
+            index.insert(id, &obj);
+        }
+        // listeners may access the table
+        drop(table);
+        Self::notify(
+            AuditRecord::new(id, Action::Inserted, None, Some(obj.clone())),
+            Change::Inserted(Entry::new(id, obj)),
+        );
+        Ok(id)
+    }
+    /// Insert a row
+    ///
+    /// Panics if a unique index is violated, use `try_insert` to handle this.
     fn insert(obj: T) -> Id<T> {
-        Self::table_mut()
-            .insert(obj)
+        Self::try_insert(obj)
+            .unwrap_or_else(|e| panic!("{}", e))
     }
     fn get(id: Id<T>) -> Option<Entry<T>> {
-        Self::table()
-            .get(id)
-            .map(|data| Entry::from((id, data.clone())))
+        let data = Self::table().get(id)?;
+        if Self::is_deleted(id) {
+            None
+        } else {
+            Some(Entry::from((id, data)))
+        }
+    }
+    /// Relations of other tables referencing rows of `T`
+    fn references() -> Vec<Box<dyn Reference<T> + 'db>> {
+        Vec::new()
+    }
+    /// Delete a row, or move it to the trash if there is one
+    fn try_delete(id: Id<T>) -> Result<Option<T>, DeleteError> {
+        match Self::trash() {
+            Some(trash) => {
+                Self::check_restricted(id, &Self::references())?;
+                let data = match Self::get(id) {
+                    Some(entry) => entry.into_inner(),
+                    None => return Ok(None),
+                };
+                trash.put(id);
+                Self::notify(
+                    AuditRecord::new(id, Action::Deleted, Some(data.clone()), None),
+                    Change::Deleted(id),
+                );
+                Ok(Some(data))
+            }
+            None => Self::try_purge(id),
+        }
+    }
+    /// Err if a reference with the `Restrict` policy refers to `id`
+    fn check_restricted(id: Id<T>, references: &[Box<dyn Reference<T> + 'db>]) -> Result<(), DeleteError> {
+        for reference in references.iter().filter(|r| r.policy() == DeletePolicy::Restrict) {
+            let rows = reference.referencing(id);
+            if !rows.is_empty() {
+                return Err(DeleteError::Restricted {
+                    relation: reference.name().to_string(),
+                    rows,
+                });
+            }
+        }
+        Ok(())
+    }
+    /// Permanently delete a row and apply the delete policies of its references
+    fn try_purge(id: Id<T>) -> Result<Option<T>, DeleteError> {
+        let references = Self::references();
+        Self::check_restricted(id, &references)?;
+        let indexes = Self::indexes();
+        let data = Self::table_mut().delete(id);
+        if let Some(data) = &data {
+            for index in &indexes {
+                index.remove(id, data);
+            }
+            for reference in &references {
+                reference.on_delete(id);
+            }
+            let action = match Self::trash() {
+                Some(trash) => {
+                    trash.take(id);
+                    Action::Purged
+                }
+                None => Action::Deleted,
+            };
+            Self::notify(
+                AuditRecord::new(id, action, Some(data.clone()), None),
+                Change::Deleted(id),
+            );
+        }
+        Ok(data)
+    }
+    fn purge(id: Id<T>) -> Option<T> {
+        Self::try_purge(id)
+            .unwrap_or_else(|e| {
+                error!("Failed to purge {}: {}", id, e);
+                None
+            })
+    }
+    /// Restore a row from the trash
+    fn restore(id: Id<T>) -> Option<T> {
+        if !Self::trash()?.take(id) {
+            return None;
+        }
+        let data = Self::table().get(id)?;
+        Self::notify(
+            AuditRecord::new(id, Action::Restored, None, Some(data.clone())),
+            Change::Inserted(Entry::new(id, data.clone())),
+        );
+        Some(data)
     }
     fn delete(id: Id<T>) -> Option<T> {
-        Self::table_mut()
-          .delete_one(id)
+        Self::try_delete(id)
+            .unwrap_or_else(|e| {
+                error!("Failed to delete {}: {}", id, e);
+                None
+            })
     }
-    fn get_all() -> Vec<Entry<T>> {
-        Self::table()
-            .rows()
-            .map(|row| row.into())
+    /// Find references to missing rows of `T`, removing them if `repair` is set
+    fn check_references(repair: bool) -> Vec<Dangling> {
+        Self::references()
+            .iter()
+            .flat_map(|reference| {
+                if repair {
+                    reference.repair()
+                } else {
+                    reference.dangling()
+                }
+            })
             .collect()
     }
+    /// Update a row, unless the result violates a unique index
+    fn try_update(id: Id<T>, update: T::Update) -> Result<Option<T>, IndexError>
+        where T: Updatable
+    {
+        Self::try_modify(id, |data| data.apply(update.clone()))
+    }
+    fn update(id: Id<T>, update: T::Update) -> Option<T>
+        where T: Updatable
+    {
+        Self::try_update(id, update)
+            .unwrap_or_else(|e| {
+                error!("Failed to update {}: {}", id, e);
+                None
+            })
+    }
+    /// Change a row with `f`, unless the result violates a unique index
+    ///
+    /// Like `try_update`, the change is indexed, audited and published.
+    fn try_modify<F>(id: Id<T>, f: F) -> Result<Option<T>, IndexError>
+        where F: Fn(&mut T)
+    {
+        let indexes = Self::indexes();
+        let (mut table, old, new) = loop {
+            Self::build_indexes(&indexes);
+            let table = Self::table_mut();
+            let old = match table.get(id) {
+                Some(old) => old,
+                None => return Ok(None),
+            };
+            let mut new = old.clone();
+            f(&mut new);
+            match check_indexes(&indexes, Some(id), &new) {
+                Err(IndexError::NotBuilt { index }) => debug!("Rebuilding reset index {}", index),
+                result => break result.map(|_| (table, old, new))?,
+            }
+        };
+        let new = table.modify(id, |data| *data = new);
+        if let Some(new) = &new {
+            for index in &indexes {
+                index.remove(id, &old);
+                index.insert(id, new);
+            }
+        }
+        drop(table);
+        if let Some(new) = &new {
+            Self::notify(
+                AuditRecord::new(id, Action::Updated, Some(old), Some(new.clone())),
+                Change::Updated(Entry::new(id, new.clone())),
+            );
+        }
+        Ok(new)
+    }
+    fn modify<F>(id: Id<T>, f: F) -> Option<T>
+        where F: Fn(&mut T)
+    {
+        Self::try_modify(id, f)
+            .unwrap_or_else(|e| {
+                error!("Failed to modify {}: {}", id, e);
+                None
+            })
+    }
+    fn get_all() -> Vec<Entry<T>> {
+        let entries = Self::table().entries();
+        Self::visible(entries)
+    }
+    fn query(query: &Query) -> Page<T> {
+        match Self::trash() {
+            Some(_) => query.run(Self::get_all()),
+            None => Self::table().query(query),
+        }
+    }
     fn get_list(ids: Vec<Id<T>>) -> Vec<Entry<T>> {
         ids.iter()
             .filter_map(|id|
@@ -39,16 +383,39 @@ pub trait Database<'db, T: DatabaseTable<'db, Self>> : Sized {
     fn filter<F>(f: F) -> Vec<Entry<T>>
         where F: Fn(&T) -> bool
     {
-        Self::table()
-            .wher(|row| f(row.data))
-            .map(|row| row.into())
-            .collect()
+        let entries = Self::table().filter(&f);
+        Self::visible(entries)
     }
     fn find<F>(f: F) -> Option<Entry<T>>
         where F: Fn(&T) -> bool
     {
-        Self::table()
-            .find(|row| f(row.data))
-            .map(|row| row.into())
+        match Self::trash() {
+            Some(_) => Self::filter(f).into_iter().next(),
+            None => Self::table().find(&f),
+        }
+    }
+    /// Rows with `key` in `index`
+    fn filter_by<K, M>(index: &'db Index<T, K, M>, key: &K) -> Vec<Entry<T>>
+        where K: Clone + std::fmt::Debug + 'db,
+              M: KeyMap<K, T> + 'db,
+    {
+        Self::build_indexes(&[index]);
+        Self::get_list(index.lookup(key))
+    }
+    fn find_by<K, M>(index: &'db Index<T, K, M>, key: &K) -> Option<Entry<T>>
+        where K: Clone + std::fmt::Debug + 'db,
+              M: KeyMap<K, T> + 'db,
+    {
+        Self::filter_by(index, key)
+            .into_iter()
+            .next()
+    }
+    /// Rows with keys in `range`, in key order
+    fn range_by<K, R>(index: &'db OrderedIndex<T, K>, range: R) -> Vec<Entry<T>>
+        where K: Ord + Clone + std::fmt::Debug + 'db,
+              R: std::ops::RangeBounds<K>,
+    {
+        Self::build_indexes(&[index]);
+        Self::get_list(index.range(range))
     }
 }
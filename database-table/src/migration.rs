@@ -0,0 +1,228 @@
+//! Schema migrations
+//!
+//! Every table has a schema version, starting at 0. A `Migration` upgrades
+//! the serialized rows of one table from `version - 1` to `version`.
+//! ```rust,ignore
+//! let migrations = Migrations::new()
+//!     .register("task", 1, "add priority", |mut row| {
+//!         row["priority"] = json!(0);
+//!         Ok(row)
+//!     });
+//! migrations.run(&*DB, &MigrationOptions {
+//!     dry_run: false,
+//!     backup: Some("backup.sqlite".into()),
+//! })?;
+//! ```
+//! All rows are migrated in memory before anything is written, so a failing
+//! migration leaves the database unchanged.
+//!
+//! `SqliteSchema` stores versions in its own table. For rql schemas,
+//! `backend::rql::RqlMigrationStore` reads the table files as `Value`s and
+//! keeps versions in a table of `SchemaVersion`s.
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use serde_json::Value;
+use std::{
+    fmt::{
+        self,
+        Display,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+#[allow(unused)]
+use tracing::{
+    debug,
+    info,
+};
+
+/// Id and serialized data of a stored row
+pub type RawRow = (String, Value);
+
+/// Schema version of a table, for backends storing versions as rows
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SchemaVersion {
+    pub table: String,
+    pub version: u32,
+}
+/// Backend access to untyped rows and schema versions
+pub trait MigrationStore {
+    type Error: Display;
+    fn version(&self, table: &str) -> Result<u32, Self::Error>;
+    fn rows(&self, table: &str) -> Result<Vec<RawRow>, Self::Error>;
+    /// Replace the rows of `table` and set its version
+    fn write(&self, table: &str, version: u32, rows: Vec<RawRow>) -> Result<(), Self::Error>;
+    /// Copy the whole database to `path`
+    fn backup(&self, path: &Path) -> Result<(), Self::Error>;
+}
+pub struct Migration {
+    pub table: &'static str,
+    pub version: u32,
+    pub description: &'static str,
+    pub migrate: fn(Value) -> Result<Value, String>,
+}
+#[derive(Clone, Debug, Default)]
+pub struct MigrationOptions {
+    /// only report what would be migrated
+    pub dry_run: bool,
+    /// back up the database to this path before writing
+    pub backup: Option<PathBuf>,
+}
+#[derive(Clone, Debug, PartialEq)]
+pub struct TableReport {
+    pub table: &'static str,
+    pub from: u32,
+    pub to: u32,
+    pub rows: usize,
+}
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MigrationReport {
+    pub tables: Vec<TableReport>,
+    pub dry_run: bool,
+}
+#[derive(Clone, Debug, PartialEq)]
+pub enum MigrationError {
+    Store(String),
+    /// no migration registered for a version between the stored and the latest one
+    Missing {
+        table: &'static str,
+        version: u32,
+    },
+    Row {
+        table: &'static str,
+        version: u32,
+        id: String,
+        error: String,
+    },
+}
+impl Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Store(e) => write!(f, "Migration store error: {}", e),
+            Self::Missing { table, version } => {
+                write!(f, "Missing migration of table {} to version {}", table, version)
+            }
+            Self::Row { table, version, id, error } => {
+                write!(f, "Failed to migrate row {} of table {} to version {}: {}", id, table, version, error)
+            }
+        }
+    }
+}
+impl std::error::Error for MigrationError {}
+
+/// Registry of migrations for all tables of a schema
+#[derive(Default)]
+pub struct Migrations {
+    migrations: Vec<Migration>,
+}
+impl Migrations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn register(
+        mut self,
+        table: &'static str,
+        version: u32,
+        description: &'static str,
+        migrate: fn(Value) -> Result<Value, String>,
+    ) -> Self {
+        assert!(version > 0, "Migration versions start at 1");
+        assert!(
+            !self.migrations.iter().any(|m| m.table == table && m.version == version),
+            "Migration of table {} to version {} registered twice", table, version,
+        );
+        self.migrations.push(Migration {
+            table,
+            version,
+            description,
+            migrate,
+        });
+        self
+    }
+    /// Tables with registered migrations, in registration order
+    pub fn tables(&self) -> Vec<&'static str> {
+        let mut tables: Vec<&'static str> = Vec::new();
+        for migration in &self.migrations {
+            if !tables.contains(&migration.table) {
+                tables.push(migration.table);
+            }
+        }
+        tables
+    }
+    /// Version of `table` after all migrations
+    pub fn latest(&self, table: &str) -> u32 {
+        self.migrations
+            .iter()
+            .filter(|m| m.table == table)
+            .map(|m| m.version)
+            .max()
+            .unwrap_or(0)
+    }
+    /// Migrations to apply to `table` stored at `version`, in order
+    fn pending(&self, table: &'static str, version: u32) -> Result<Vec<&Migration>, MigrationError> {
+        (version + 1..=self.latest(table))
+            .map(|v| {
+                self.migrations
+                    .iter()
+                    .find(|m| m.table == table && m.version == v)
+                    .ok_or(MigrationError::Missing { table, version: v })
+            })
+            .collect()
+    }
+    /// Upgrade all tables in `store` to their latest version
+    pub fn run<S: MigrationStore>(&self, store: &S, options: &MigrationOptions) -> Result<MigrationReport, MigrationError> {
+        let store_error = |e: S::Error| MigrationError::Store(e.to_string());
+        let mut migrated = Vec::new();
+        for table in self.tables() {
+            let from = store.version(table).map_err(store_error)?;
+            let pending = self.pending(table, from)?;
+            if pending.is_empty() {
+                continue;
+            }
+            let mut rows = store.rows(table).map_err(store_error)?;
+            for migration in pending {
+                debug!("Migrating {} to version {}: {}", table, migration.version, migration.description);
+                rows = rows
+                    .into_iter()
+                    .map(|(id, data)| {
+                        match (migration.migrate)(data) {
+                            Ok(data) => Ok((id, data)),
+                            Err(error) => {
+                                Err(MigrationError::Row {
+                                    table,
+                                    version: migration.version,
+                                    id,
+                                    error,
+                                })
+                            }
+                        }
+                    })
+                    .collect::<Result<_, _>>()?;
+            }
+            migrated.push((TableReport {
+                table,
+                from,
+                to: self.latest(table),
+                rows: rows.len(),
+            }, rows));
+        }
+        if !options.dry_run && !migrated.is_empty() {
+            if let Some(path) = &options.backup {
+                info!("Backing up database to {}", path.display());
+                store.backup(path).map_err(store_error)?;
+            }
+            for (report, rows) in &migrated {
+                info!("Migrated {} rows of {} from version {} to {}", report.rows, report.table, report.from, report.to);
+                store.write(report.table, report.to, rows.clone()).map_err(store_error)?;
+            }
+        }
+        Ok(MigrationReport {
+            tables: migrated.into_iter().map(|(report, _)| report).collect(),
+            dry_run: options.dry_run,
+        })
+    }
+}
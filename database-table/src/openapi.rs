@@ -0,0 +1,283 @@
+//! OpenAPI 3.1 documents of functions defined with `define_api::api!`
+//!
+//! `api!` generates `openapi()`, returning the document of all functions
+//! in the block including those of `rest_api!`, and the route
+//! `handlers::openapi_json` serving it at `/api/openapi.json`, or at
+//! `/api/v<n>/openapi.json` for blocks with `version = <n>;`:
+//! ```rust,ignore
+//! rocket::ignite()
+//!     .mount("/", routes![api::handlers::openapi_json])
+//! ```
+//! Parameter and result types have to implement `JsonSchema`, usually with
+//! `#[derive(JsonSchema)]`.
+use crate::{
+    object_schema,
+    Schemas,
+};
+use serde_json::{
+    json,
+    Map,
+    Value,
+};
+
+/// Who may call an operation, from the authorization attributes of the function
+#[derive(Clone, Debug, PartialEq)]
+pub enum Access {
+    Public,
+    Authenticated,
+    Role(String),
+    /// the caller must own the row with the id in the parameter
+    Owner(String),
+    /// a role of the caller must allow the action on the table,
+    /// or on the row with the id in the parameter
+    Privilege {
+        action: String,
+        table: String,
+        param: Option<String>,
+    },
+}
+/// A call to an api function
+#[derive(Clone, Debug, PartialEq)]
+pub struct Operation {
+    /// name of the function
+    pub name: String,
+    pub path: String,
+    /// doc comments of the function
+    pub description: Option<String>,
+    /// schema of the JSON object of the parameters
+    pub parameters: Value,
+    pub result: Value,
+    /// schema of the error of functions returning a `Result`
+    pub error: Option<Value>,
+    pub access: Vec<Access>,
+    /// marked `#[deprecated]`, e.g. adapters of older api versions
+    pub deprecated: bool,
+}
+impl Operation {
+    fn is_public(&self) -> bool {
+        self.access.contains(&Access::Public)
+    }
+    fn to_json(&self) -> Value {
+        let mut responses = Map::new();
+        responses.insert("200".to_string(), response("Result of the function", Some(&self.result)));
+        if !self.is_public() {
+            responses.insert("401".to_string(), response("Missing or invalid token", None));
+        }
+        let restricted = self.access
+            .iter()
+            .any(|access| matches!(access, Access::Role(_) | Access::Owner(_) | Access::Privilege { .. }));
+        if restricted {
+            responses.insert("403".to_string(), response("The caller is not allowed to call the function", None));
+        }
+        let row = self.access
+            .iter()
+            .any(|access| matches!(access, Access::Owner(_) | Access::Privilege { param: Some(_), .. }));
+        if row {
+            responses.insert("404".to_string(), response("The owned row does not exist", None));
+        }
+        if let Some(error) = &self.error {
+            responses.insert("default".to_string(), response("Error returned by the function", Some(error)));
+        }
+        let requirements: Vec<String> = self.access
+            .iter()
+            .filter_map(|access| {
+                match access {
+                    Access::Role(role) => Some(format!("Requires the role `{}`.", role)),
+                    Access::Owner(param) => Some(format!("The caller must own `{}`.", param)),
+                    Access::Privilege { action, table, param: None } =>
+                        Some(format!("Requires the privilege to {} `{}`.", action, table)),
+                    Access::Privilege { action, table, param: Some(param) } =>
+                        Some(format!("Requires the privilege to {} the `{}` `{}`.", action, table, param)),
+                    _ => None,
+                }
+            })
+            .collect();
+        let description = self.description
+            .iter()
+            .cloned()
+            .chain(requirements)
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let mut operation = json!({
+            "operationId": self.name,
+            "requestBody": {
+                "required": true,
+                "content": {
+                    "application/json": {
+                        "schema": self.parameters,
+                    },
+                },
+            },
+            "responses": responses,
+        });
+        if !description.is_empty() {
+            operation["description"] = json!(description);
+        }
+        if self.deprecated {
+            operation["deprecated"] = json!(true);
+        }
+        operation["security"] = if self.is_public() {
+            json!([])
+        } else {
+            json!([{ "bearer": [] }])
+        };
+        operation
+    }
+}
+fn response(description: &str, schema: Option<&Value>) -> Value {
+    let mut response = json!({ "description": description });
+    if let Some(schema) = schema {
+        response["content"] = json!({
+            "application/json": {
+                "schema": schema,
+            },
+        });
+    }
+    response
+}
+/// Builder of an OpenAPI document
+#[derive(Clone, Debug, Default)]
+pub struct OpenApi {
+    pub title: String,
+    pub version: String,
+    pub schemas: Schemas,
+    operations: Vec<Operation>,
+}
+impl OpenApi {
+    pub fn new<S1: ToString, S2: ToString>(title: S1, version: S2) -> Self {
+        Self {
+            title: title.to_string(),
+            version: version.to_string(),
+            ..Default::default()
+        }
+    }
+    pub fn operation(&mut self, operation: Operation) {
+        self.operations.push(operation);
+    }
+    pub fn to_json(&self) -> Value {
+        let paths: Map<String, Value> = self.operations
+            .iter()
+            .map(|operation| (operation.path.clone(), json!({ "post": operation.to_json() })))
+            .collect();
+        json!({
+            "openapi": "3.1.0",
+            "info": {
+                "title": self.title,
+                "version": self.version,
+            },
+            "paths": paths,
+            "components": {
+                "schemas": self.schemas.to_json(),
+                "securitySchemes": {
+                    "bearer": {
+                        "type": "http",
+                        "scheme": "bearer",
+                        "bearerFormat": "JWT",
+                    },
+                },
+            },
+        })
+    }
+}
+/// Schema of the parameters `(name, schema, optional)` of a function
+pub fn parameters_schema(parameters: Vec<(&str, Value, bool)>) -> Value {
+    let mut schema = object_schema(parameters);
+    schema["additionalProperties"] = json!(false);
+    schema
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Entry,
+        Id,
+        JsonSchema,
+    };
+
+    /// A card of a board
+    #[derive(JsonSchema)]
+    #[allow(unused)]
+    struct Card {
+        title: String,
+        #[serde(rename = "text")]
+        description: Option<String>,
+        #[serde(default)]
+        tags: Vec<String>,
+        #[serde(skip)]
+        cache: u32,
+        column: Column,
+    }
+    #[derive(JsonSchema)]
+    #[allow(unused)]
+    enum Column {
+        Todo,
+        Done,
+        Blocked(String),
+    }
+    #[test]
+    fn openapi_document() {
+        let mut api = OpenApi::new("board", "1.0.0");
+        let parameters = parameters_schema(vec![
+            ("id", Id::<Card>::json_schema(&mut api.schemas), false),
+        ]);
+        let result = Option::<Entry<Card>>::json_schema(&mut api.schemas);
+        api.operation(Operation {
+            name: "get_card".to_string(),
+            path: "/api/call/get_card".to_string(),
+            description: Some("Get a card".to_string()),
+            parameters,
+            result,
+            error: None,
+            access: vec![Access::Owner("id".to_string())],
+            deprecated: false,
+        });
+        let document = api.to_json();
+        let card = &document["components"]["schemas"]["Card"];
+        assert_eq!(card["description"], "A card of a board");
+        assert_eq!(card["required"], json!(["title", "column"]));
+        assert_eq!(card["properties"]["text"]["anyOf"][1], json!({ "type": "null" }));
+        assert!(card["properties"]["cache"].is_null());
+        assert_eq!(card["properties"]["column"]["$ref"], "#/components/schemas/Column");
+        assert_eq!(
+            document["components"]["schemas"]["Column"]["oneOf"],
+            json!([
+                { "type": "string", "enum": ["Todo", "Done"] },
+                {
+                    "type": "object",
+                    "properties": { "Blocked": { "type": "string" } },
+                    "required": ["Blocked"],
+                },
+            ])
+        );
+        let operation = &document["paths"]["/api/call/get_card"]["post"];
+        assert_eq!(operation["operationId"], "get_card");
+        assert_eq!(operation["security"], json!([{ "bearer": [] }]));
+        assert_eq!(
+            operation["responses"]["200"]["content"]["application/json"]["schema"]["anyOf"][0]["properties"]["data"]["$ref"],
+            "#/components/schemas/Card"
+        );
+        assert!(operation["responses"]["404"].is_object());
+        assert!(operation["responses"]["default"].is_null());
+        assert!(operation["deprecated"].is_null());
+
+        api.operation(Operation {
+            name: "post_card".to_string(),
+            path: "/api/call/post_card".to_string(),
+            description: None,
+            parameters: parameters_schema(vec![]),
+            result: json!({}),
+            error: None,
+            access: vec![Access::Privilege {
+                action: "create".to_string(),
+                table: "card".to_string(),
+                param: None,
+            }],
+            deprecated: false,
+        });
+        let operation = &api.to_json()["paths"]["/api/call/post_card"]["post"];
+        assert_eq!(operation["description"], "Requires the privilege to create `card`.");
+        assert!(operation["responses"]["403"].is_object());
+        assert!(operation["responses"]["404"].is_null());
+    }
+}
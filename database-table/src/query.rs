@@ -0,0 +1,357 @@
+use crate::{
+    Entry,
+    JsonSchema,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use serde_json::Value;
+use std::cmp::Ordering;
+#[allow(unused)]
+use tracing::{
+    debug,
+    error,
+};
+
+/// Serializable description of a query on a table
+///
+/// Fields are named by their serialized name, nested fields are separated
+/// by dots, e.g. `credentials.username`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Query {
+    #[serde(default)]
+    pub filters: Vec<Filter>,
+    #[serde(default)]
+    pub sort: Option<Sort>,
+    #[serde(default)]
+    pub pagination: Option<Pagination>,
+}
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub enum Filter {
+    /// field is equal to value
+    Eq {
+        field: String,
+        value: Value,
+    },
+    /// field is within the inclusive bounds
+    Range {
+        field: String,
+        min: Option<Value>,
+        max: Option<Value>,
+    },
+}
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub enum Direction {
+    #[default]
+    Ascending,
+    Descending,
+}
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Sort {
+    pub field: String,
+    #[serde(default)]
+    pub direction: Direction,
+}
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub enum Pagination {
+    Offset {
+        offset: usize,
+        limit: usize,
+    },
+    /// rows after the position `after`, taken from `Page::next`
+    ///
+    /// Rows are ordered by the sort field and then by id, so the position
+    /// stays valid when its row is deleted.
+    Cursor {
+        after: Option<String>,
+        limit: usize,
+    },
+}
+/// Position after a row, serialized into `Page::next`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Cursor {
+    /// value of the sort field
+    key: Option<Value>,
+    id: String,
+}
+impl Cursor {
+    /// Cursor of a cursor string, if it was returned by `Page::next`
+    fn parse(cursor: &str) -> Option<Self> {
+        match serde_json::from_str(cursor) {
+            Ok(cursor) => Some(cursor),
+            Err(e) => {
+                error!("Invalid cursor {}: {}", cursor, e);
+                None
+            }
+        }
+    }
+}
+/// Result of a query
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Page<T> {
+    pub items: Vec<Entry<T>>,
+    /// number of rows matching the filters
+    pub total: usize,
+    /// cursor for the next page, when using cursor pagination
+    pub next: Option<String>,
+}
+impl<T> Default for Page<T> {
+    fn default() -> Self {
+        Self {
+            items: Vec::new(),
+            total: 0,
+            next: None,
+        }
+    }
+}
+impl Query {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filters.push(filter);
+        self
+    }
+    pub fn eq<S: ToString, V: Serialize>(self, field: S, value: V) -> Self {
+        self.filter(Filter::Eq {
+            field: field.to_string(),
+            value: to_value(value),
+        })
+    }
+    pub fn range<S: ToString, V: Serialize>(self, field: S, min: Option<V>, max: Option<V>) -> Self {
+        self.filter(Filter::Range {
+            field: field.to_string(),
+            min: min.map(to_value),
+            max: max.map(to_value),
+        })
+    }
+    pub fn sort<S: ToString>(mut self, field: S, direction: Direction) -> Self {
+        self.sort = Some(Sort {
+            field: field.to_string(),
+            direction,
+        });
+        self
+    }
+    pub fn offset(mut self, offset: usize, limit: usize) -> Self {
+        self.pagination = Some(Pagination::Offset { offset, limit });
+        self
+    }
+    pub fn after(mut self, after: Option<String>, limit: usize) -> Self {
+        self.pagination = Some(Pagination::Cursor { after, limit });
+        self
+    }
+    /// Fields the query filters or sorts by, as paths without empty keys
+    pub fn fields(&self) -> Vec<String> {
+        self.filters
+            .iter()
+            .map(|filter| {
+                match filter {
+                    Filter::Eq { field, .. } | Filter::Range { field, .. } => field.as_str(),
+                }
+            })
+            .chain(self.sort.iter().map(|sort| sort.field.as_str()))
+            .map(field_path)
+            .collect()
+    }
+    /// True if the serialized row passes all filters
+    pub fn matches(&self, row: &Value) -> bool {
+        self.filters.iter().all(|filter| filter.matches(row))
+    }
+    /// Filter, sort and paginate `entries`
+    pub fn run<T: Serialize>(&self, entries: Vec<Entry<T>>) -> Page<T> {
+        let mut rows: Vec<(Value, Entry<T>)> = entries
+            .into_iter()
+            .filter_map(|entry| {
+                match serde_json::to_value(&entry.data) {
+                    Ok(value) => Some((value, entry)),
+                    Err(e) => {
+                        error!("Failed to serialize {} for query: {}", entry.id, e);
+                        None
+                    }
+                }
+            })
+            .filter(|(value, _)| self.matches(value))
+            .collect();
+        let total = rows.len();
+        if let Some(sort) = &self.sort {
+            rows.sort_by(|(a, _), (b, _)| sort.compare(a, b));
+        }
+        let entries = |rows: Vec<(Value, Entry<T>)>| rows.into_iter().map(|(_, entry)| entry);
+        match &self.pagination {
+            None => Page {
+                items: entries(rows).collect(),
+                total,
+                next: None,
+            },
+            Some(Pagination::Offset { offset, limit }) => Page {
+                items: entries(rows).skip(*offset).take(*limit).collect(),
+                total,
+                next: None,
+            },
+            Some(Pagination::Cursor { after, limit }) => self.seek(rows, after.as_deref(), *limit, total),
+        }
+    }
+    /// Page of `limit` sorted rows after the cursor `after`
+    ///
+    /// Invalid cursors start at the first row.
+    fn seek<T>(&self, rows: Vec<(Value, Entry<T>)>, after: Option<&str>, limit: usize, total: usize) -> Page<T> {
+        let mut rows: Vec<(Cursor, Entry<T>)> = rows
+            .into_iter()
+            .map(|(value, entry)| {
+                let key = self.sort.as_ref().and_then(|sort| field_value(&value, &sort.field)).cloned();
+                (Cursor { key, id: entry.id.to_string() }, entry)
+            })
+            .collect();
+        rows.sort_by(|(a, _), (b, _)| self.cursor_order(a, b));
+        let start = after
+            .and_then(Cursor::parse)
+            .map(|after| {
+                rows.iter()
+                    .take_while(|(cursor, _)| self.cursor_order(cursor, &after) != Ordering::Greater)
+                    .count()
+            })
+            .unwrap_or(0);
+        let mut rows = rows.into_iter().skip(start);
+        let page: Vec<(Cursor, Entry<T>)> = rows.by_ref().take(limit).collect();
+        let next = match (rows.next(), page.last()) {
+            (Some(_), Some((last, _))) => serde_json::to_string(last).ok(),
+            _ => None,
+        };
+        Page {
+            items: page.into_iter().map(|(_, entry)| entry).collect(),
+            total,
+            next,
+        }
+    }
+    /// Order of rows by the sort field, then by id
+    fn cursor_order(&self, a: &Cursor, b: &Cursor) -> Ordering {
+        self.sort
+            .as_ref()
+            .map(|sort| sort.compare_keys(a.key.as_ref(), b.key.as_ref()))
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| a.id.cmp(&b.id))
+    }
+}
+impl Filter {
+    pub fn matches(&self, row: &Value) -> bool {
+        match self {
+            Self::Eq { field, value } => {
+                field_value(row, field)
+                    .map(|v| compare(v, value) == Some(Ordering::Equal))
+                    .unwrap_or(false)
+            }
+            Self::Range { field, min, max } => {
+                field_value(row, field)
+                    .map(|v| {
+                        min.as_ref().map(|min| matches!(compare(v, min), Some(Ordering::Greater) | Some(Ordering::Equal))).unwrap_or(true)
+                            && max.as_ref().map(|max| matches!(compare(v, max), Some(Ordering::Less) | Some(Ordering::Equal))).unwrap_or(true)
+                    })
+                    .unwrap_or(false)
+            }
+        }
+    }
+}
+impl Sort {
+    /// Order of two serialized rows, rows missing the field come last
+    pub fn compare(&self, a: &Value, b: &Value) -> Ordering {
+        self.compare_keys(field_value(a, &self.field), field_value(b, &self.field))
+    }
+    /// Order of two values of the sort field, missing values come last
+    fn compare_keys(&self, a: Option<&Value>, b: Option<&Value>) -> Ordering {
+        let ord = match (a, b) {
+            (Some(a), Some(b)) => compare(a, b).unwrap_or(Ordering::Equal),
+            (Some(_), None) => return Ordering::Less,
+            (None, Some(_)) => return Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        };
+        match self.direction {
+            Direction::Ascending => ord,
+            Direction::Descending => ord.reverse(),
+        }
+    }
+}
+fn to_value<V: Serialize>(value: V) -> Value {
+    serde_json::to_value(value).unwrap_or(Value::Null)
+}
+/// Field path without the empty keys `field_value` skips
+fn field_path(field: &str) -> String {
+    field.split('.')
+        .filter(|key| !key.is_empty())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+/// Value of a dot separated field path in a serialized row
+fn field_value<'a>(row: &'a Value, field: &str) -> Option<&'a Value> {
+    field.split('.')
+        .filter(|key| !key.is_empty())
+        .try_fold(row, |value, key| value.get(key))
+}
+/// Compare numbers, strings, bools and nulls, other values are unordered
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        (Value::Null, Value::Null) => Some(Ordering::Equal),
+        (a, b) if a == b => Some(Ordering::Equal),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Id;
+
+    #[derive(Clone, Debug, PartialEq, Serialize)]
+    struct Item {
+        name: String,
+        price: u32,
+    }
+    fn entries() -> Vec<Entry<Item>> {
+        vec![("a", 3), ("b", 1), ("c", 2), ("d", 5), ("e", 4)]
+            .into_iter()
+            .map(|(name, price)| Entry::new(Id::new(), Item { name: name.into(), price }))
+            .collect()
+    }
+    fn names(page: &Page<Item>) -> Vec<&str> {
+        page.items.iter().map(|e| e.data.name.as_str()).collect()
+    }
+    #[test]
+    fn filter_sort_offset() {
+        let query = Query::new()
+            .range("price", Some(2), None)
+            .sort("price", Direction::Descending)
+            .offset(1, 2);
+        assert_eq!(query.fields(), vec!["price", "price"]);
+        assert_eq!(Query::new().eq(".name..first", "a").fields(), vec!["name.first"]);
+        let page = query.run(entries());
+        assert_eq!(page.total, 4);
+        assert_eq!(names(&page), vec!["e", "a"]);
+        let page = Query::new().eq("name", "c").run(entries());
+        assert_eq!(names(&page), vec!["c"]);
+    }
+    #[test]
+    fn cursor() {
+        let entries = entries();
+        let query = Query::new().sort("name", Direction::Ascending);
+        let first = query.clone().after(None, 2).run(entries.clone());
+        assert_eq!(names(&first), vec!["a", "b"]);
+        let second = query.clone().after(first.next.clone(), 2).run(entries.clone());
+        assert_eq!(names(&second), vec!["c", "d"]);
+        let last = query.after(second.next.clone(), 2).run(entries.clone());
+        assert_eq!(names(&last), vec!["e"]);
+        assert_eq!(last.next, None);
+        assert_eq!(last.total, 5);
+        // the row of a cursor may be deleted in the meantime
+        let remaining: Vec<_> = entries.into_iter().filter(|entry| entry.data.name != "b").collect();
+        let cursor = serde_json::to_string(&Cursor { key: Some(Value::from("b")), id: Id::<Item>::new().to_string() }).unwrap();
+        let second = Query::new()
+            .sort("name", Direction::Ascending)
+            .after(Some(cursor), 2)
+            .run(remaining);
+        assert_eq!(names(&second), vec!["c", "d"]);
+        assert!(second.next.is_some());
+    }
+}
@@ -0,0 +1,201 @@
+//! Relations between tables
+//!
+//! A `Relation` describes a field of `S` holding `Id<T>`s and what happens to
+//! rows of `S` when a referenced row of `T` is deleted. Relations are
+//! declared by the `Database` of the referenced table:
+//! ```rust,ignore
+//! impl<'db> Database<'db, Task> for Schema {
+//!     ...
+//!     fn references() -> Vec<Box<dyn Reference<Task> + 'db>> {
+//!         vec![
+//!             Relation::<Schema, Project, Task>::new(
+//!                 "project.tasks",
+//!                 DeletePolicy::Nullify,
+//!                 |project| project.tasks().clone(),
+//!                 Project::remove_task,
+//!             ).boxed(),
+//!         ]
+//!     }
+//! }
+//! ```
+use crate::{
+    Database,
+    DatabaseTable,
+    Entry,
+    Id,
+    JsonSchema,
+    TableRead,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use std::{
+    fmt::{
+        self,
+        Display,
+    },
+    marker::PhantomData,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeletePolicy {
+    /// refuse to delete rows which are referenced
+    Restrict,
+    /// delete all referencing rows
+    Cascade,
+    /// remove the id from all referencing rows
+    Nullify,
+}
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub enum DeleteError {
+    Restricted {
+        relation: String,
+        rows: Vec<String>,
+    },
+}
+impl Display for DeleteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Restricted { relation, rows } => {
+                write!(f, "Row is referenced by {} rows through {}", rows.len(), relation)
+            }
+        }
+    }
+}
+impl std::error::Error for DeleteError {}
+
+/// A reference to a row which does not exist
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dangling {
+    pub relation: &'static str,
+    /// id of the referencing row
+    pub row: String,
+    /// missing id
+    pub target: String,
+}
+/// Type erased relation from any table to `T`
+pub trait Reference<T> {
+    fn name(&self) -> &'static str;
+    fn policy(&self) -> DeletePolicy;
+    /// ids of the rows referencing `id`
+    fn referencing(&self, id: Id<T>) -> Vec<String>;
+    /// Apply the delete policy after `id` was deleted
+    fn on_delete(&self, id: Id<T>);
+    fn dangling(&self) -> Vec<Dangling>;
+    /// Remove dangling ids and return them
+    fn repair(&self) -> Vec<Dangling>;
+}
+pub struct Relation<'db, D, S, T> {
+    name: &'static str,
+    policy: DeletePolicy,
+    ids: fn(&S) -> Vec<Id<T>>,
+    remove: fn(&mut S, Id<T>),
+    _ty: PhantomData<fn() -> &'db D>,
+}
+impl<'db, D, S, T> Relation<'db, D, S, T>
+    where D: Database<'db, S> + Database<'db, T> + 'db,
+          S: DatabaseTable<'db, D>,
+          T: DatabaseTable<'db, D>,
+{
+    pub fn new(
+        name: &'static str,
+        policy: DeletePolicy,
+        ids: fn(&S) -> Vec<Id<T>>,
+        remove: fn(&mut S, Id<T>),
+    ) -> Self {
+        Self {
+            name,
+            policy,
+            ids,
+            remove,
+            _ty: PhantomData,
+        }
+    }
+    pub fn boxed(self) -> Box<dyn Reference<T> + 'db> {
+        Box::new(self)
+    }
+    /// All rows of `S`, including soft deleted ones
+    fn rows(&self) -> Vec<Entry<S>> {
+        <D as Database<'db, S>>::table().entries()
+    }
+    /// True if `id` is stored, even if it is soft deleted
+    fn exists(id: Id<T>) -> bool {
+        <D as Database<'db, T>>::table().get(id).is_some()
+    }
+    /// Remove `targets` from `row` through `Database`, so the change is indexed, audited and published
+    fn remove_ids(&self, row: Id<S>, targets: &[Id<T>]) {
+        let remove = self.remove;
+        <D as Database<'db, S>>::modify(row, |data| {
+            for target in targets {
+                remove(data, *target)
+            }
+        });
+    }
+}
+impl<'db, D, S, T> Reference<T> for Relation<'db, D, S, T>
+    where D: Database<'db, S> + Database<'db, T> + 'db,
+          S: DatabaseTable<'db, D>,
+          T: DatabaseTable<'db, D>,
+{
+    fn name(&self) -> &'static str {
+        self.name
+    }
+    fn policy(&self) -> DeletePolicy {
+        self.policy
+    }
+    fn referencing(&self, id: Id<T>) -> Vec<String> {
+        <D as Database<'db, S>>::filter(|row| (self.ids)(row).contains(&id))
+            .into_iter()
+            .map(|entry| entry.id.to_string())
+            .collect()
+    }
+    fn on_delete(&self, id: Id<T>) {
+        let rows = self.rows()
+            .into_iter()
+            .filter(|entry| (self.ids)(&entry.data).contains(&id));
+        for entry in rows {
+            match self.policy {
+                DeletePolicy::Cascade => {
+                    <D as Database<'db, S>>::delete(entry.id);
+                }
+                DeletePolicy::Nullify | DeletePolicy::Restrict => {
+                    self.remove_ids(entry.id, &[id]);
+                }
+            }
+        }
+    }
+    fn dangling(&self) -> Vec<Dangling> {
+        self.rows()
+            .into_iter()
+            .flat_map(|entry| {
+                (self.ids)(&entry.data)
+                    .into_iter()
+                    .filter(|target| !Self::exists(*target))
+                    .map(move |target| Dangling {
+                        relation: self.name,
+                        row: entry.id.to_string(),
+                        target: target.to_string(),
+                    })
+            })
+            .collect()
+    }
+    fn repair(&self) -> Vec<Dangling> {
+        let mut repaired = Vec::new();
+        for entry in self.rows() {
+            let missing: Vec<Id<T>> = (self.ids)(&entry.data)
+                .into_iter()
+                .filter(|target| !Self::exists(*target))
+                .collect();
+            if !missing.is_empty() {
+                self.remove_ids(entry.id, &missing);
+                repaired.extend(missing.into_iter().map(|target| Dangling {
+                    relation: self.name,
+                    row: entry.id.to_string(),
+                    target: target.to_string(),
+                }));
+            }
+        }
+        repaired
+    }
+}
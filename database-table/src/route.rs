@@ -1,4 +1,4 @@
-use rql::*;
+use crate::Id;
 use enum_paths::{
     AsPath,
     ParsePath,
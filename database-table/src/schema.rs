@@ -0,0 +1,384 @@
+//! JSON schemas of the types sent to and from api functions
+//!
+//! `#[derive(JsonSchema)]` describes structs and enums as serde serializes
+//! them, respecting the attributes `rename`, `rename_all`, `default`, `skip`,
+//! `flatten` and `transparent` and the enum representations set by `tag`,
+//! `content` and `untagged`. Schemas of named types are collected in
+//! `Schemas` and referenced by name, generic types are inlined.
+use crate::{
+    Entry,
+    Id,
+};
+use serde_json::{
+    json,
+    Map,
+    Value,
+};
+use std::{
+    collections::{
+        BTreeMap,
+        BTreeSet,
+        HashMap,
+        HashSet,
+        VecDeque,
+    },
+    sync::Arc,
+    time::SystemTime,
+};
+use uuid::Uuid;
+
+/// Types with a JSON schema of their serialized form
+pub trait JsonSchema {
+    fn json_schema(schemas: &mut Schemas) -> Value;
+    /// may be missing from objects, e.g. `Option<T>`
+    fn optional() -> bool {
+        false
+    }
+}
+/// Schemas of named types, referenced as `#/components/schemas/<name>`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Schemas {
+    schemas: BTreeMap<String, Value>,
+}
+impl Schemas {
+    /// Reference to the schema `name`, defined by `define` when it is first used
+    pub fn reference<F>(&mut self, name: &str, define: F) -> Value
+        where F: FnOnce(&mut Self) -> Value
+    {
+        if !self.schemas.contains_key(name) {
+            // placeholder for recursive types
+            self.schemas.insert(name.to_string(), Value::Null);
+            let schema = define(self);
+            self.schemas.insert(name.to_string(), schema);
+        }
+        json!({ "$ref": format!("#/components/schemas/{}", name) })
+    }
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.schemas.get(name)
+    }
+    pub fn to_json(&self) -> Value {
+        Value::Object(self.schemas.clone().into_iter().collect())
+    }
+}
+/// Schema of an object with the properties `(name, schema, optional)`
+pub fn object_schema(properties: Vec<(&str, Value, bool)>) -> Value {
+    let required: Vec<&str> = properties
+        .iter()
+        .filter(|(_, _, optional)| !optional)
+        .map(|(name, _, _)| *name)
+        .collect();
+    let properties: Map<String, Value> = properties
+        .into_iter()
+        .map(|(name, schema, _)| (name.to_string(), schema))
+        .collect();
+    let mut schema = json!({
+        "type": "object",
+        "properties": properties,
+    });
+    if !required.is_empty() {
+        schema["required"] = json!(required);
+    }
+    schema
+}
+/// Schema of an externally tagged enum variant
+pub fn variant_schema(name: &str, schema: Value) -> Value {
+    object_schema(vec![(name, schema, false)])
+}
+/// Schema of the property naming the variant of a tagged enum
+pub fn tag_schema(name: &str) -> Value {
+    json!({ "type": "string", "const": name })
+}
+/// Schema of an internally tagged variant, `schema` with the property `tag` naming it
+pub fn tagged_schema(schemas: &Schemas, tag: &str, name: &str, schema: Value) -> Value {
+    let tagged = object_schema(vec![(tag, tag_schema(name), false)]);
+    flatten_schema(schemas, tagged, schema, false)
+}
+/// Add the properties of `schema` to the object `into`, as serde does for
+/// `#[serde(flatten)]` fields. Schemas which are no objects with properties,
+/// e.g. enums, are combined with `allOf`.
+pub fn flatten_schema(schemas: &Schemas, mut into: Value, schema: Value, optional: bool) -> Value {
+    let mut schema = match schema["$ref"].as_str().and_then(|r| r.rsplit('/').next()) {
+        Some(name) => schemas.get(name).cloned().unwrap_or(Value::Null),
+        None => schema,
+    };
+    if optional {
+        // an `Option` of an object, whose properties may all be missing
+        if let Some(some) = schema["anyOf"].get(0).cloned() {
+            schema = some;
+        }
+    }
+    if let Some(additional) = schema.get("additionalProperties") {
+        into["additionalProperties"] = additional.clone();
+        return into;
+    }
+    let properties = match schema.get("properties").and_then(Value::as_object) {
+        Some(properties) => properties.clone(),
+        None => return json!({ "allOf": [into, schema] }),
+    };
+    for (name, property) in properties {
+        into["properties"][&name] = property;
+    }
+    if !optional {
+        if let Some(required) = schema["required"].as_array() {
+            let mut all = into["required"].as_array().cloned().unwrap_or_default();
+            all.extend(required.iter().cloned());
+            into["required"] = Value::Array(all);
+        }
+    }
+    into
+}
+/// Schema of the `Update` of an `Updatable` type, all fields of `T` are optional
+pub fn update_schema<T: JsonSchema>(schemas: &mut Schemas) -> Value {
+    let mut schema = T::json_schema(schemas);
+    if let Some(name) = schema["$ref"].as_str().and_then(|r| r.rsplit('/').next()) {
+        schema = schemas.get(name).cloned().unwrap_or(Value::Null);
+    }
+    if let Value::Object(object) = &mut schema {
+        object.remove("required");
+    }
+    schema
+}
+
+macro_rules! impl_schema {
+    ($($ty:ty => $schema:tt,)*) => {
+        $(
+            impl JsonSchema for $ty {
+                fn json_schema(_: &mut Schemas) -> Value {
+                    json!($schema)
+                }
+            }
+        )*
+    };
+}
+impl_schema! {
+    () => { "type": "null" },
+    bool => { "type": "boolean" },
+    char => { "type": "string", "minLength": 1, "maxLength": 1 },
+    String => { "type": "string" },
+    str => { "type": "string" },
+    i8 => { "type": "integer", "format": "int8" },
+    i16 => { "type": "integer", "format": "int16" },
+    i32 => { "type": "integer", "format": "int32" },
+    i64 => { "type": "integer", "format": "int64" },
+    isize => { "type": "integer", "format": "int64" },
+    u8 => { "type": "integer", "format": "uint8", "minimum": 0 },
+    u16 => { "type": "integer", "format": "uint16", "minimum": 0 },
+    u32 => { "type": "integer", "format": "uint32", "minimum": 0 },
+    u64 => { "type": "integer", "format": "uint64", "minimum": 0 },
+    usize => { "type": "integer", "format": "uint64", "minimum": 0 },
+    f32 => { "type": "number", "format": "float" },
+    f64 => { "type": "number", "format": "double" },
+    Uuid => { "type": "string", "format": "uuid" },
+    Value => {},
+}
+impl<T> JsonSchema for Id<T> {
+    fn json_schema(_: &mut Schemas) -> Value {
+        json!({ "type": "string", "format": "uuid" })
+    }
+}
+impl<T: JsonSchema> JsonSchema for Entry<T> {
+    fn json_schema(schemas: &mut Schemas) -> Value {
+        object_schema(vec![
+            ("id", Id::<T>::json_schema(schemas), false),
+            ("data", T::json_schema(schemas), false),
+        ])
+    }
+}
+impl JsonSchema for SystemTime {
+    fn json_schema(schemas: &mut Schemas) -> Value {
+        object_schema(vec![
+            ("secs_since_epoch", u64::json_schema(schemas), false),
+            ("nanos_since_epoch", u32::json_schema(schemas), false),
+        ])
+    }
+}
+impl<T: JsonSchema> JsonSchema for Option<T> {
+    fn json_schema(schemas: &mut Schemas) -> Value {
+        json!({ "anyOf": [T::json_schema(schemas), { "type": "null" }] })
+    }
+    fn optional() -> bool {
+        true
+    }
+}
+impl<T: JsonSchema + ?Sized> JsonSchema for &T {
+    fn json_schema(schemas: &mut Schemas) -> Value {
+        T::json_schema(schemas)
+    }
+}
+impl<T: JsonSchema + ?Sized> JsonSchema for Box<T> {
+    fn json_schema(schemas: &mut Schemas) -> Value {
+        T::json_schema(schemas)
+    }
+}
+impl<T: JsonSchema + ?Sized> JsonSchema for Arc<T> {
+    fn json_schema(schemas: &mut Schemas) -> Value {
+        T::json_schema(schemas)
+    }
+}
+fn array_schema(items: Value, unique: bool) -> Value {
+    let mut schema = json!({
+        "type": "array",
+        "items": items,
+    });
+    if unique {
+        schema["uniqueItems"] = json!(true);
+    }
+    schema
+}
+impl<T: JsonSchema> JsonSchema for Vec<T> {
+    fn json_schema(schemas: &mut Schemas) -> Value {
+        array_schema(T::json_schema(schemas), false)
+    }
+}
+impl<T: JsonSchema> JsonSchema for [T] {
+    fn json_schema(schemas: &mut Schemas) -> Value {
+        array_schema(T::json_schema(schemas), false)
+    }
+}
+impl<T: JsonSchema> JsonSchema for VecDeque<T> {
+    fn json_schema(schemas: &mut Schemas) -> Value {
+        array_schema(T::json_schema(schemas), false)
+    }
+}
+impl<T: JsonSchema, S> JsonSchema for HashSet<T, S> {
+    fn json_schema(schemas: &mut Schemas) -> Value {
+        array_schema(T::json_schema(schemas), true)
+    }
+}
+impl<T: JsonSchema> JsonSchema for BTreeSet<T> {
+    fn json_schema(schemas: &mut Schemas) -> Value {
+        array_schema(T::json_schema(schemas), true)
+    }
+}
+/// maps are serialized as objects, keys as strings
+impl<K, V: JsonSchema, S> JsonSchema for HashMap<K, V, S> {
+    fn json_schema(schemas: &mut Schemas) -> Value {
+        json!({
+            "type": "object",
+            "additionalProperties": V::json_schema(schemas),
+        })
+    }
+}
+impl<K, V: JsonSchema> JsonSchema for BTreeMap<K, V> {
+    fn json_schema(schemas: &mut Schemas) -> Value {
+        json!({
+            "type": "object",
+            "additionalProperties": V::json_schema(schemas),
+        })
+    }
+}
+macro_rules! impl_tuple_schema {
+    ($($len:literal => ($($name:ident),+),)*) => {
+        $(
+            impl<$($name: JsonSchema),+> JsonSchema for ($($name,)+) {
+                fn json_schema(schemas: &mut Schemas) -> Value {
+                    json!({
+                        "type": "array",
+                        "prefixItems": [$($name::json_schema(schemas)),+],
+                        "minItems": $len,
+                        "maxItems": $len,
+                    })
+                }
+            }
+        )*
+    };
+}
+impl_tuple_schema! {
+    1 => (A),
+    2 => (A, B),
+    3 => (A, B, C),
+    4 => (A, B, C, D),
+    5 => (A, B, C, D, E),
+    6 => (A, B, C, D, E, F),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::JsonSchema;
+    use serde::Serialize;
+
+    #[derive(Serialize, JsonSchema)]
+    #[serde(rename_all = "camelCase")]
+    struct Settings {
+        dark_mode: bool,
+        #[serde(flatten)]
+        window: Window,
+        #[serde(flatten)]
+        extra: HashMap<String, String>,
+    }
+    #[derive(Serialize, JsonSchema)]
+    #[serde(rename_all = "SCREAMING-KEBAB-CASE")]
+    struct Window {
+        width_px: u32,
+        #[serde(default)]
+        title: Option<String>,
+    }
+    #[derive(Serialize, JsonSchema)]
+    #[serde(tag = "type", rename_all = "snake_case")]
+    #[allow(unused)]
+    enum Event {
+        Started,
+        MovedTo { to_x: i32 },
+        Resized(Window),
+    }
+    #[derive(Serialize, JsonSchema)]
+    #[serde(tag = "kind", content = "value")]
+    #[allow(unused)]
+    enum Counter {
+        Empty,
+        Count(u32),
+    }
+    #[derive(Serialize, JsonSchema)]
+    #[serde(untagged)]
+    enum Either {
+        Number(u32),
+        Nothing,
+    }
+    fn definition<T: JsonSchema>(schemas: &mut Schemas, name: &str) -> Value {
+        T::json_schema(schemas);
+        schemas.get(name).cloned().unwrap()
+    }
+    #[test]
+    fn renamed_and_flattened() {
+        let mut schemas = Schemas::default();
+        let settings = definition::<Settings>(&mut schemas, "Settings");
+        let value = serde_json::to_value(Settings {
+            dark_mode: true,
+            window: Window { width_px: 800, title: None },
+            extra: HashMap::new(),
+        }).unwrap();
+        for key in value.as_object().unwrap().keys() {
+            assert!(settings["properties"][key].is_object(), "{} is missing", key);
+        }
+        assert_eq!(settings["required"], json!(["darkMode", "WIDTH-PX"]));
+        assert_eq!(settings["additionalProperties"], json!({ "type": "string" }));
+    }
+    #[test]
+    fn enum_representations() {
+        let mut schemas = Schemas::default();
+        let event = definition::<Event>(&mut schemas, "Event");
+        assert_eq!(serde_json::to_value(Event::MovedTo { to_x: 1 }).unwrap(), json!({ "type": "moved_to", "to_x": 1 }));
+        assert_eq!(event["oneOf"][0], object_schema(vec![("type", tag_schema("started"), false)]));
+        assert_eq!(event["oneOf"][1]["properties"]["type"], tag_schema("moved_to"));
+        assert_eq!(event["oneOf"][1]["required"], json!(["type", "to_x"]));
+        // newtype variants have the tag next to the fields of their value
+        assert_eq!(event["oneOf"][2]["required"], json!(["type", "WIDTH-PX"]));
+        assert!(event["oneOf"][2]["properties"]["TITLE"].is_object());
+        let counter = definition::<Counter>(&mut schemas, "Counter");
+        assert_eq!(serde_json::to_value(Counter::Count(2)).unwrap(), json!({ "kind": "Count", "value": 2 }));
+        assert_eq!(
+            counter["oneOf"][1],
+            object_schema(vec![
+                ("kind", tag_schema("Count"), false),
+                ("value", json!({ "type": "integer", "format": "uint32", "minimum": 0 }), false),
+            ])
+        );
+        assert_eq!(counter["oneOf"][0]["required"], json!(["kind"]));
+        let either = definition::<Either>(&mut schemas, "Either");
+        assert_eq!(serde_json::to_value(Either::Nothing).unwrap(), Value::Null);
+        assert_eq!(either["anyOf"][1], json!({ "type": "null" }));
+        assert_eq!(serde_json::to_value(Either::Number(3)).unwrap(), json!(3));
+        assert_eq!(either["anyOf"][0]["type"], "integer");
+    }
+}
@@ -0,0 +1,249 @@
+//! Calls of api functions over a websocket
+//!
+//! A `SocketCall` carries an id which the server sends back with its
+//! `SocketReply`, so replies can arrive in any order and between messages
+//! pushed by the server. It also carries the token of the caller, so calls
+//! are authorized with the current session of the client and expired tokens
+//! are refused like on HTTP. The messages are embedded in the protocol of the
+//! application:
+//! ```rust,ignore
+//! enum ClientMessage { Call(SocketCall), ... }
+//! enum ServerMessage { Reply(SocketReply), ... }
+//! // server, with the caller authenticated by the token of the call
+//! ClientMessage::Call(call) => send(ServerMessage::Reply(api::socket::dispatch(caller(&call.token).as_ref(), call))),
+//! // client, generated client functions now call through the socket
+//! let (transport, calls) = SocketTransport::new();
+//! set_transport(transport.clone());
+//! spawn(calls.for_each(|call| send(ClientMessage::Call(call))));
+//! ServerMessage::Reply(reply) => transport.reply(reply),
+//! ```
+use crate::{
+    ApiError,
+    CallRequest,
+    CallResponse,
+    Transport,
+};
+use async_trait::async_trait;
+use futures::{
+    channel::{
+        mpsc,
+        oneshot,
+    },
+    future::{
+        self,
+        Either,
+    },
+};
+use futures_timer::Delay;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{
+            AtomicU64,
+            Ordering,
+        },
+        Arc,
+        Mutex,
+    },
+    time::Duration,
+};
+
+/// Call of the api function `function` with its parameters as JSON object
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SocketCall {
+    pub id: u64,
+    pub function: String,
+    pub parameters: Value,
+    /// `Authorization` header of the call
+    #[serde(default)]
+    pub token: Option<String>,
+}
+/// Reply to the call `id`, with the HTTP status and body the handler would respond with
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SocketReply {
+    pub id: u64,
+    pub status: u16,
+    pub body: Value,
+}
+impl SocketReply {
+    pub fn new<T: Serialize>(id: u64, status: u16, body: &T) -> Self {
+        match serde_json::to_value(body) {
+            Ok(body) => Self {
+                id,
+                status,
+                body,
+            },
+            Err(_) => Self::status(id, 500),
+        }
+    }
+    /// Reply without a body, e.g. for failed authorization
+    pub fn status(id: u64, status: u16) -> Self {
+        Self {
+            id,
+            status,
+            body: Value::Null,
+        }
+    }
+    /// Reply to the result of a function returning a `Result`
+    pub fn result<T: Serialize, E: ApiError>(id: u64, result: Result<T, E>) -> Self {
+        match result {
+            Ok(ok) => Self::new(id, 200, &ok),
+            Err(error) => Self::new(id, error.status(), &Some(error)),
+        }
+    }
+}
+#[derive(Debug)]
+struct Pending {
+    next_id: AtomicU64,
+    calls: Mutex<HashMap<u64, oneshot::Sender<SocketReply>>>,
+}
+/// Transport sending calls through a websocket of the application
+///
+/// Calls are taken from the receiver returned by `new` and sent by the
+/// application, which passes the replies it receives to `reply`.
+/// The `Authorization` header of the `ClientConfig` is sent as the
+/// `token` of each call, other headers are not sent. The server authorizes
+/// every call with its token, not with the session the socket was opened in.
+#[derive(Clone, Debug)]
+pub struct SocketTransport {
+    pending: Arc<Pending>,
+    calls: mpsc::UnboundedSender<SocketCall>,
+    timeout: Duration,
+}
+impl SocketTransport {
+    pub fn new() -> (Self, mpsc::UnboundedReceiver<SocketCall>) {
+        let (calls, receiver) = mpsc::unbounded();
+        (
+            Self {
+                pending: Arc::new(Pending {
+                    next_id: AtomicU64::new(0),
+                    calls: Mutex::new(HashMap::new()),
+                }),
+                calls,
+                timeout: Duration::from_secs(30),
+            },
+            receiver,
+        )
+    }
+    /// Time to wait for replies
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+    /// Resolve the call waiting for `reply`, returns false if there was none
+    pub fn reply(&self, reply: SocketReply) -> bool {
+        let waiting = self.pending.calls
+            .lock()
+            .expect("SocketTransport poisoned")
+            .remove(&reply.id);
+        match waiting {
+            Some(sender) => sender.send(reply).is_ok(),
+            None => false,
+        }
+    }
+    /// Number of calls waiting for replies
+    pub fn pending(&self) -> usize {
+        self.pending.calls.lock().expect("SocketTransport poisoned").len()
+    }
+    fn forget(&self, id: u64) {
+        self.pending.calls.lock().expect("SocketTransport poisoned").remove(&id);
+    }
+}
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl Transport for SocketTransport {
+    async fn send(&self, request: CallRequest) -> Result<CallResponse, String> {
+        let function = request.url
+            .rsplit('/')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        let parameters = serde_json::from_str(&request.body).map_err(|e| e.to_string())?;
+        let token = request.headers
+            .into_iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("authorization"))
+            .map(|(_, value)| value);
+        let id = self.pending.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = oneshot::channel();
+        self.pending.calls
+            .lock()
+            .expect("SocketTransport poisoned")
+            .insert(id, sender);
+        let call = SocketCall {
+            id,
+            function,
+            parameters,
+            token,
+        };
+        if self.calls.unbounded_send(call).is_err() {
+            self.forget(id);
+            return Err("Socket closed".to_string());
+        }
+        match future::select(receiver, Delay::new(self.timeout)).await {
+            Either::Left((Ok(reply), _)) => Ok(CallResponse {
+                status: reply.status,
+                body: reply.body.to_string(),
+            }),
+            Either::Left((Err(_), _)) => Err("Socket closed".to_string()),
+            Either::Right(_) => {
+                self.forget(id);
+                Err(format!("No reply to call {} within {:?}", id, self.timeout))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        remote_call_with,
+        CallError,
+        ClientConfig,
+        RequestError,
+    };
+    use futures::{
+        executor::block_on,
+        StreamExt,
+    };
+
+    #[test]
+    fn socket_calls() {
+        let (transport, mut calls) = SocketTransport::new();
+        let config = ClientConfig::default().header("Authorization", "token");
+        let call = |function: &'static str| remote_call_with::<_, u32, RequestError>(&transport, &config, function, &[1, 2]);
+        let (sum, missing, ()) = block_on(future::join3(
+            call("sum"),
+            call("missing"),
+            async {
+                let sum = calls.next().await.unwrap();
+                let missing = calls.next().await.unwrap();
+                assert_eq!(sum.function, "sum");
+                assert_eq!(sum.parameters, serde_json::json!([1, 2]));
+                assert_eq!(sum.token.as_deref(), Some("token"));
+                // replies may arrive in any order
+                assert!(transport.reply(SocketReply::result::<u32, _>(missing.id, Err(RequestError::NotFound("missing".into())))));
+                assert!(transport.reply(SocketReply::new(sum.id, 200, &3)));
+                assert!(!transport.reply(SocketReply::status(sum.id, 200)));
+            },
+        ));
+        assert_eq!(sum, Ok(3));
+        assert_eq!(
+            missing,
+            Err(CallError::Api {
+                status: 404,
+                error: RequestError::NotFound("missing".to_string()),
+            })
+        );
+
+        let transport = transport.timeout(Duration::from_millis(10));
+        let timeout = block_on(remote_call_with::<_, u32, ()>(&transport, &config, "sum", &[1, 2]));
+        assert!(matches!(timeout, Err(CallError::Transport(_))));
+        assert_eq!(transport.pending(), 0);
+    }
+}
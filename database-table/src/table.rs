@@ -1,7 +1,16 @@
-use rql::*;
 use crate::{
     entry::*,
     route::*,
+    Dangling,
+    DeleteError,
+    Id,
+    Index,
+    IndexError,
+    KeyMap,
+    OrderedIndex,
+    Page,
+    Query,
+    Updatable,
 };
 use async_trait::async_trait;
 use std::fmt::Debug;
@@ -42,9 +51,21 @@ pub trait RemoteTable<T=Self>
     async fn get(id: Id<T>) -> Result<Option<Entry<T>>, Self::Error>;
     async fn delete(id: Id<T>) -> Result<Option<T>, Self::Error>;
     async fn get_all() -> Result<Vec<Entry<T>>, Self::Error>;
-    //async fn update(id: Id<Self>, update: <Self as Updatable>::Update) -> Result<Option<Self>, String>;
+    async fn query(query: Query) -> Result<Page<T>, Self::Error>;
+    async fn update(id: Id<T>, update: <T as Updatable>::Update) -> Result<Option<T>, Self::Error>
+        where T: Updatable;
     async fn post(data: T) -> Result<Id<T>, Self::Error>;
 }
+/// Add the CSRF token of the page to a request changing state
+fn with_csrf_token(request: Request<'_>) -> Request<'_> {
+    #[cfg(target_arch = "wasm32")]
+    {
+        if let Some(token) = crate::csrf_token() {
+            return request.header(seed::browser::fetch::Header::custom(crate::CSRF_HEADER, token));
+        }
+    }
+    request
+}
 async fn fetch<V>(request: Request<'_>) -> Result<V, String>
     where V: 'static + for<'de> Deserialize<'de>,
 {
@@ -75,7 +96,7 @@ impl<T> RemoteTable for T
         let path = Self::to_absolute_route(Self::entry_route(id)).as_path();
         debug!("RemoteTable::delete {}", path);
         fetch(
-            Request::new(path)
+            with_csrf_token(Request::new(path))
                 .method(Method::Delete)
         ).await
     }
@@ -87,11 +108,33 @@ impl<T> RemoteTable for T
                 .method(Method::Get)
         ).await
     }
+    async fn query(query: Query) -> Result<Page<Self>, Self::Error> {
+        let path = format!("{}/query", Self::to_absolute_route(Self::table_route()).as_path());
+        debug!("RemoteTable::query {}", path);
+        fetch(
+            with_csrf_token(Request::new(path))
+                .method(Method::Post)
+                .json(&query)
+                .map_err(|e| format!("{:?}", e))?
+        ).await
+    }
+    async fn update(id: Id<Self>, update: <Self as Updatable>::Update) -> Result<Option<Self>, Self::Error>
+        where Self: Updatable
+    {
+        let path = Self::to_absolute_route(Self::entry_route(id)).as_path();
+        debug!("RemoteTable::update {}", path);
+        fetch(
+            with_csrf_token(Request::new(path))
+                .method(Method::Patch)
+                .json(&update)
+                .map_err(|e| format!("{:?}", e))?
+        ).await
+    }
     async fn post(data: Self) -> Result<Id<Self>, Self::Error> {
         let path = Self::to_absolute_route(Self::table_route()).as_path();
         debug!("RemoteTable::post {}", path);
         fetch(
-            Request::new(path)
+            with_csrf_token(Request::new(path))
                 .method(Method::Post)
                 .json(&data)
                 .map_err(|e| format!("{:?}", e))?
@@ -123,24 +166,65 @@ pub trait DatabaseTable<'db, D>
     + 'db
     where D: crate::Database<'db, Self>,
 {
-    fn table() -> TableGuard<'db, Self> {
+    fn table() -> <D as crate::Database<'db, Self>>::Table {
         D::table()
     }
-    fn table_mut() -> TableGuardMut<'db, Self> {
+    fn table_mut() -> <D as crate::Database<'db, Self>>::TableMut {
         D::table_mut()
     }
     fn insert(obj: Self) -> Id<Self> {
         D::insert(obj)
     }
+    fn try_insert(obj: Self) -> Result<Id<Self>, IndexError> {
+        D::try_insert(obj)
+    }
     fn get(id: Id<Self>) -> Option<Entry<Self>> {
         D::get(id)
     }
     fn delete(id: Id<Self>) -> Option<Self> {
         D::delete(id)
     }
+    fn try_delete(id: Id<Self>) -> Result<Option<Self>, DeleteError> {
+        D::try_delete(id)
+    }
+    fn purge(id: Id<Self>) -> Option<Self> {
+        D::purge(id)
+    }
+    fn try_purge(id: Id<Self>) -> Result<Option<Self>, DeleteError> {
+        D::try_purge(id)
+    }
+    fn restore(id: Id<Self>) -> Option<Self> {
+        D::restore(id)
+    }
+    fn check_references(repair: bool) -> Vec<Dangling> {
+        D::check_references(repair)
+    }
+    fn update(id: Id<Self>, update: <Self as Updatable>::Update) -> Option<Self>
+        where Self: Updatable
+    {
+        D::update(id, update)
+    }
+    fn try_update(id: Id<Self>, update: <Self as Updatable>::Update) -> Result<Option<Self>, IndexError>
+        where Self: Updatable
+    {
+        D::try_update(id, update)
+    }
+    fn try_modify<F>(id: Id<Self>, f: F) -> Result<Option<Self>, IndexError>
+        where F: Fn(&mut Self)
+    {
+        D::try_modify(id, f)
+    }
+    fn modify<F>(id: Id<Self>, f: F) -> Option<Self>
+        where F: Fn(&mut Self)
+    {
+        D::modify(id, f)
+    }
     fn get_all() -> Vec<Entry<Self>> {
         D::get_all()
     }
+    fn query(query: &Query) -> Page<Self> {
+        D::query(query)
+    }
     fn get_list(ids: Vec<Id<Self>>) -> Vec<Entry<Self>> {
         D::get_list(ids)
     }
@@ -154,6 +238,24 @@ pub trait DatabaseTable<'db, D>
     {
         D::find(f)
     }
+    fn filter_by<K, M>(index: &'db Index<Self, K, M>, key: &K) -> Vec<Entry<Self>>
+        where K: Clone + Debug + 'db,
+              M: KeyMap<K, Self> + 'db,
+    {
+        D::filter_by(index, key)
+    }
+    fn find_by<K, M>(index: &'db Index<Self, K, M>, key: &K) -> Option<Entry<Self>>
+        where K: Clone + Debug + 'db,
+              M: KeyMap<K, Self> + 'db,
+    {
+        D::find_by(index, key)
+    }
+    fn range_by<K, R>(index: &'db OrderedIndex<Self, K>, range: R) -> Vec<Entry<Self>>
+        where K: Ord + Clone + Debug + 'db,
+              R: std::ops::RangeBounds<K>,
+    {
+        D::range_by(index, range)
+    }
 }
 impl<'db, T, D> DatabaseTable<'db, D> for T
     where D: crate::Database<'db, T>,
@@ -0,0 +1,232 @@
+//! Transactions spanning several tables of one schema
+//!
+//! Writes made through a `Transaction` are applied to the backend right
+//! away, but only become visible to other threads and durable on disk when
+//! the transaction is committed. Dropping a transaction rolls it back.
+//! Audit records and changes of the writes are held back until the commit,
+//! and dropped on rollback.
+//!
+//! SQLite schemas implement `Transactional` with `SqliteSchema::begin`, other
+//! backends with a `Journal`.
+//! ```rust,ignore
+//! impl<'db> Transactional<'db> for Schema {
+//!     fn begin() -> Result<(), TransactionError> {
+//!         Ok(DB.begin()?)
+//!     }
+//!     fn commit() -> Result<(), TransactionError> {
+//!         Ok(DB.commit()?)
+//!     }
+//!     fn rollback() -> Result<(), TransactionError> {
+//!         Ok(DB.rollback()?)
+//!     }
+//! }
+//! let task = Transaction::<Schema>::run(|tx| {
+//!     let task = tx.insert(Task::new("subtask"))?;
+//!     tx.update(project, ProjectUpdate::default().tasks(tasks))?;
+//!     Ok(task)
+//! })?;
+//! ```
+use crate::{
+    Database,
+    DatabaseTable,
+    DeleteError,
+    Id,
+    IndexError,
+    JsonSchema,
+    Updatable,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use serde_json::Value;
+use std::{
+    cell::RefCell,
+    fmt::{
+        self,
+        Display,
+    },
+    marker::PhantomData,
+};
+#[allow(unused)]
+use tracing::{
+    debug,
+    error,
+};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub enum TransactionError {
+    Backend(String),
+    Index(IndexError),
+    Delete(DeleteError),
+}
+impl Display for TransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Backend(e) => write!(f, "Transaction failed: {}", e),
+            Self::Index(e) => write!(f, "{}", e),
+            Self::Delete(e) => write!(f, "{}", e),
+        }
+    }
+}
+impl std::error::Error for TransactionError {}
+impl From<IndexError> for TransactionError {
+    fn from(e: IndexError) -> Self {
+        Self::Index(e)
+    }
+}
+impl From<DeleteError> for TransactionError {
+    fn from(e: DeleteError) -> Self {
+        Self::Delete(e)
+    }
+}
+
+/// Function notifying listeners of a serialized change
+type Notification = (fn(Value), Value);
+
+thread_local! {
+    /// notifications of the transaction open on this thread, sent when it is committed
+    static PENDING: RefCell<Option<Vec<Notification>>> = const { RefCell::new(None) };
+}
+/// Whether a transaction is open on this thread
+pub(crate) fn is_open() -> bool {
+    PENDING.with(|pending| pending.borrow().is_some())
+}
+/// Call `notify` with `value` after the transaction open on this thread is committed
+pub(crate) fn after_commit(notify: fn(Value), value: Value) {
+    PENDING.with(|pending| {
+        match pending.borrow_mut().as_mut() {
+            Some(pending) => pending.push((notify, value)),
+            None => notify(value),
+        }
+    })
+}
+/// Schemas whose backend can group writes to all of its tables
+///
+/// Between `begin` and `commit` or `rollback` other threads must not see
+/// any of the writes made by the beginning thread.
+pub trait Transactional<'db>: Sized {
+    fn begin() -> Result<(), TransactionError>;
+    fn commit() -> Result<(), TransactionError>;
+    fn rollback() -> Result<(), TransactionError>;
+}
+/// An open transaction on the schema `D`
+pub struct Transaction<'db, D: Transactional<'db>> {
+    /// type names and index resets of the tables written to
+    resets: Vec<(&'static str, fn())>,
+    /// whether this transaction holds the notifications of the thread,
+    /// and not one open on another schema
+    notifies: bool,
+    done: bool,
+    _ty: PhantomData<fn() -> &'db D>,
+}
+impl<'db, D: Transactional<'db>> Transaction<'db, D> {
+    pub fn begin() -> Result<Self, TransactionError> {
+        D::begin()?;
+        let notifies = PENDING.with(|pending| {
+            let mut pending = pending.borrow_mut();
+            if pending.is_none() {
+                *pending = Some(Vec::new());
+                true
+            } else {
+                false
+            }
+        });
+        Ok(Self {
+            resets: Vec::new(),
+            notifies,
+            done: false,
+            _ty: PhantomData,
+        })
+    }
+    /// Run `f` in a transaction, committing it if `f` returns `Ok`
+    pub fn run<R, E, F>(f: F) -> Result<R, E>
+        where E: From<TransactionError>,
+              F: FnOnce(&mut Self) -> Result<R, E>,
+    {
+        let mut transaction = Self::begin()?;
+        let result = f(&mut transaction)?;
+        transaction.commit()?;
+        Ok(result)
+    }
+    fn touch<T>(&mut self)
+        where D: Database<'db, T>,
+              T: DatabaseTable<'db, D>,
+    {
+        let name = std::any::type_name::<T>();
+        if !self.resets.iter().any(|(table, _)| *table == name) {
+            self.resets.push((name, || {
+                for index in <D as Database<'db, T>>::indexes() {
+                    index.reset();
+                }
+            }));
+        }
+    }
+    pub fn insert<T>(&mut self, data: T) -> Result<Id<T>, TransactionError>
+        where D: Database<'db, T>,
+              T: DatabaseTable<'db, D>,
+    {
+        self.touch::<T>();
+        Ok(<D as Database<'db, T>>::try_insert(data)?)
+    }
+    pub fn update<T>(&mut self, id: Id<T>, update: T::Update) -> Result<Option<T>, TransactionError>
+        where D: Database<'db, T>,
+              T: DatabaseTable<'db, D> + Updatable,
+    {
+        self.touch::<T>();
+        Ok(<D as Database<'db, T>>::try_update(id, update)?)
+    }
+    pub fn delete<T>(&mut self, id: Id<T>) -> Result<Option<T>, TransactionError>
+        where D: Database<'db, T>,
+              T: DatabaseTable<'db, D>,
+    {
+        self.touch::<T>();
+        Ok(<D as Database<'db, T>>::try_delete(id)?)
+    }
+    pub fn commit(mut self) -> Result<(), TransactionError> {
+        self.done = true;
+        let result = D::commit();
+        let pending = self.take_pending();
+        match &result {
+            Ok(()) => {
+                for (notify, value) in pending {
+                    notify(value);
+                }
+            }
+            Err(_) => self.reset_indexes(),
+        }
+        result
+    }
+    pub fn rollback(mut self) -> Result<(), TransactionError> {
+        self.done = true;
+        self.take_pending();
+        self.reset_indexes();
+        D::rollback()
+    }
+    /// Notifications held back by this transaction
+    fn take_pending(&self) -> Vec<Notification> {
+        if self.notifies {
+            PENDING.with(|pending| pending.borrow_mut().take()).unwrap_or_default()
+        } else {
+            Vec::new()
+        }
+    }
+    /// Indexes may contain rolled back rows, so they are rebuilt on next use
+    fn reset_indexes(&self) {
+        for (_, reset) in &self.resets {
+            reset();
+        }
+    }
+}
+impl<'db, D: Transactional<'db>> Drop for Transaction<'db, D> {
+    fn drop(&mut self) {
+        if !self.done {
+            debug!("Rolling back dropped transaction");
+            self.take_pending();
+            self.reset_indexes();
+            if let Err(e) = D::rollback() {
+                error!("Failed to roll back transaction: {}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,287 @@
+//! Transports of clients generated by `define_api::api!`
+//!
+//! Generated clients send their calls to the host in the global
+//! `ClientConfig` through the global `Transport`. In the browser calls are
+//! sent with `FetchTransport` by default, native clients have to set one:
+//! ```rust,ignore
+//! set_client_config(
+//!     ClientConfig::new("https://budget.example.com")
+//!         .header("Authorization", token)
+//! );
+//! set_transport(SurfTransport::default());
+//! let tasks = api::remote::get_project_tasks(project).await?;
+//! ```
+use crate::CallError;
+use async_trait::async_trait;
+use serde::{
+    de::DeserializeOwned,
+    Serialize,
+};
+use std::sync::{
+    Arc,
+    RwLock,
+};
+
+/// Where generated clients send their calls
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClientConfig {
+    /// scheme, host and port, e.g. `https://example.com`
+    pub host: String,
+    /// path of the calls, followed by the function name
+    pub prefix: String,
+    /// version of the api, inserted before the last segment of the prefix,
+    /// e.g. `/api/v2/call`
+    pub version: Option<u32>,
+    /// headers sent with every call
+    pub headers: Vec<(String, String)>,
+}
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self::new("http://localhost:8000")
+    }
+}
+impl ClientConfig {
+    pub fn new<S: ToString>(host: S) -> Self {
+        Self {
+            host: host.to_string(),
+            prefix: "/api/call".to_string(),
+            version: None,
+            headers: Vec::new(),
+        }
+    }
+    pub fn prefix<S: ToString>(mut self, prefix: S) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+    /// Send calls to the functions of an `api!` block with `version = <n>;`
+    pub fn version(mut self, version: u32) -> Self {
+        self.version = Some(version);
+        self
+    }
+    /// Add a header, replacing headers with the same name
+    pub fn header<N: ToString, V: ToString>(mut self, name: N, value: V) -> Self {
+        let name = name.to_string();
+        self.headers.retain(|(other, _)| !other.eq_ignore_ascii_case(&name));
+        self.headers.push((name, value.to_string()));
+        self
+    }
+    pub fn url(&self, function: &str) -> String {
+        let prefix = self.prefix.trim_matches('/');
+        let prefix = match (self.version, prefix.rsplit_once('/')) {
+            (Some(version), Some((api, call))) => format!("{}/v{}/{}", api, version, call),
+            (Some(version), None) => format!("v{}/{}", version, prefix),
+            (None, _) => prefix.to_string(),
+        };
+        format!(
+            "{}/{}/{}",
+            self.host.trim_end_matches('/'),
+            prefix,
+            function,
+        )
+    }
+}
+static CLIENT_CONFIG: RwLock<Option<ClientConfig>> = RwLock::new(None);
+
+pub fn set_client_config(config: ClientConfig) {
+    *CLIENT_CONFIG.write().expect("ClientConfig poisoned") = Some(config);
+}
+pub fn client_config() -> ClientConfig {
+    CLIENT_CONFIG
+        .read()
+        .expect("ClientConfig poisoned")
+        .clone()
+        .unwrap_or_default()
+}
+
+/// A call to an api function as an HTTP POST request with a JSON body
+#[derive(Clone, Debug, PartialEq)]
+pub struct CallRequest {
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+#[derive(Clone, Debug, PartialEq)]
+pub struct CallResponse {
+    pub status: u16,
+    pub body: String,
+}
+/// Sends calls of generated clients to the server
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+pub trait Transport {
+    async fn send(&self, request: CallRequest) -> Result<CallResponse, String>;
+}
+static TRANSPORT: RwLock<Option<Arc<dyn Transport + Send + Sync>>> = RwLock::new(None);
+
+pub fn set_transport<T: Transport + Send + Sync + 'static>(transport: T) {
+    *TRANSPORT.write().expect("Transport poisoned") = Some(Arc::new(transport));
+}
+fn transport() -> Option<Arc<dyn Transport + Send + Sync>> {
+    let transport = TRANSPORT.read().expect("Transport poisoned").clone();
+    #[cfg(target_arch = "wasm32")]
+    let transport = transport.or_else(|| Some(Arc::new(FetchTransport)));
+    transport
+}
+/// Call the api function `function` through the global transport
+pub async fn remote_call<P, R, E>(config: &ClientConfig, function: &str, parameters: &P) -> Result<R, CallError<E>>
+    where P: Serialize,
+          R: DeserializeOwned,
+          E: DeserializeOwned,
+{
+    let transport = transport()
+        .ok_or_else(|| CallError::Transport("No transport set, see set_transport".to_string()))?;
+    remote_call_with(&*transport, config, function, parameters).await
+}
+pub async fn remote_call_with<P, R, E>(
+    transport: &(dyn Transport + Send + Sync),
+    config: &ClientConfig,
+    function: &str,
+    parameters: &P,
+) -> Result<R, CallError<E>>
+    where P: Serialize,
+          R: DeserializeOwned,
+          E: DeserializeOwned,
+{
+    let request = CallRequest {
+        url: config.url(function),
+        headers: config.headers.clone(),
+        body: serde_json::to_string(parameters).map_err(CallError::transport)?,
+    };
+    let response = transport.send(request).await.map_err(CallError::Transport)?;
+    if (200..300).contains(&response.status) {
+        serde_json::from_str(&response.body).map_err(CallError::transport)
+    } else {
+        Err(match serde_json::from_str(&response.body) {
+            Ok(error) => CallError::Api {
+                status: response.status,
+                error,
+            },
+            Err(_) => CallError::Status(response.status),
+        })
+    }
+}
+
+/// Cookie with the CSRF token of the session, readable by scripts of the same origin
+pub const CSRF_COOKIE: &str = "csrf";
+/// Header repeating the CSRF token in requests changing state
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+/// The CSRF token in the cookies of the page
+#[cfg(target_arch = "wasm32")]
+pub fn csrf_token() -> Option<String> {
+    seed::browser::util::cookies()?
+        .get(CSRF_COOKIE)
+        .map(|cookie| cookie.value().to_string())
+}
+
+/// Transport using the fetch API of the browser, which sends the CSRF token
+/// of the page with every call
+#[cfg(target_arch = "wasm32")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FetchTransport;
+#[cfg(target_arch = "wasm32")]
+#[async_trait(?Send)]
+impl Transport for FetchTransport {
+    async fn send(&self, request: CallRequest) -> Result<CallResponse, String> {
+        use seed::browser::fetch::{
+            self,
+            Header,
+            Method,
+        };
+        let mut req = fetch::Request::new(request.url)
+            .method(Method::Post)
+            .text(request.body)
+            .header(Header::content_type("application/json; charset=utf-8"));
+        for (name, value) in request.headers {
+            req = req.header(Header::custom(name, value));
+        }
+        if let Some(token) = csrf_token() {
+            req = req.header(Header::custom(CSRF_HEADER, token));
+        }
+        let response = fetch::fetch(req)
+            .await
+            .map_err(|e| format!("{:?}", e))?;
+        let status = response.status().code;
+        let body = response.text().await.map_err(|e| format!("{:?}", e))?;
+        Ok(CallResponse {
+            status,
+            body,
+        })
+    }
+}
+
+/// Native transport using [surf](https://github.com/http-rs/surf)
+#[cfg(feature = "surf")]
+#[derive(Clone, Debug, Default)]
+pub struct SurfTransport {
+    client: surf::Client,
+}
+#[cfg(feature = "surf")]
+impl SurfTransport {
+    pub fn new(client: surf::Client) -> Self {
+        Self {
+            client,
+        }
+    }
+}
+#[cfg(feature = "surf")]
+#[async_trait]
+impl Transport for SurfTransport {
+    async fn send(&self, request: CallRequest) -> Result<CallResponse, String> {
+        let mut req = surf::post(&request.url)
+            .body(request.body)
+            .content_type(surf::http::mime::JSON);
+        for (name, value) in &request.headers {
+            req = req.header(name.as_str(), value.as_str());
+        }
+        let mut response = self.client
+            .send(req)
+            .await
+            .map_err(|e| e.to_string())?;
+        let body = response.body_string().await.map_err(|e| e.to_string())?;
+        Ok(CallResponse {
+            status: response.status().into(),
+            body,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RequestError;
+    use futures::executor::block_on;
+
+    struct Reply(u16, &'static str);
+    #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+    #[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+    impl Transport for Reply {
+        async fn send(&self, request: CallRequest) -> Result<CallResponse, String> {
+            assert_eq!(request.url, "http://localhost:8000/api/v1/sum");
+            assert_eq!(request.body, "[1,2]");
+            Ok(CallResponse {
+                status: self.0,
+                body: self.1.to_string(),
+            })
+        }
+    }
+    #[test]
+    fn remote_calls() {
+        let config = ClientConfig::default()
+            .prefix("/api/v1/")
+            .header("Authorization", "old")
+            .header("authorization", "new");
+        assert_eq!(config.headers, vec![("authorization".to_string(), "new".to_string())]);
+        let call = |reply| block_on(remote_call_with::<_, u32, RequestError>(&reply, &config, "sum", &[1, 2]));
+        assert_eq!(call(Reply(200, "3")), Ok(3));
+        assert_eq!(
+            call(Reply(404, r#"{"NotFound":"sum"}"#)),
+            Err(CallError::Api {
+                status: 404,
+                error: RequestError::NotFound("sum".to_string()),
+            })
+        );
+        assert_eq!(call(Reply(401, "<html></html>")), Err(CallError::Status(401)));
+        assert_eq!(ClientConfig::default().url("sum"), "http://localhost:8000/api/call/sum");
+        assert_eq!(ClientConfig::default().version(2).url("sum"), "http://localhost:8000/api/v2/call/sum");
+    }
+}
@@ -0,0 +1,29 @@
+use serde::{
+    de::DeserializeOwned,
+    Deserialize,
+    Deserializer,
+    Serialize,
+};
+use std::fmt::Debug;
+
+/// Types which can be changed by partial updates
+///
+/// `#[derive(Updatable)]` generates a `<Type>Update` struct holding an
+/// optional value and a builder method for each field.
+pub trait Updatable {
+    type Update: Clone + Debug + Default + PartialEq + Serialize + DeserializeOwned;
+    /// Set all fields contained in `update`
+    fn apply(&mut self, update: Self::Update);
+    /// Update containing the fields of `new` which differ from `self`
+    fn diff(&self, new: &Self) -> Self::Update;
+}
+/// Deserialize a field of an update which is present, even if it is `null`
+///
+/// Used with `#[serde(default)]`, so a missing field is `None` and a `null`
+/// for an `Option` field is `Some(None)`.
+pub fn deserialize_present<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+    where T: Deserialize<'de>,
+          D: Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
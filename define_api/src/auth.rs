@@ -0,0 +1,177 @@
+use quote::quote;
+use proc_macro2::{
+	TokenStream as TokenStream2,
+};
+use syn::{
+	Type,
+	*,
+};
+
+/// Requirements of an api function, declared by attributes
+///
+/// ```rust,ignore
+/// #[public]
+/// fn interpret_text(text: String) -> String { ... }
+/// #[role("admin")]
+/// fn purge_task(id: Id<Task>) -> Option<Task> { ... }
+/// #[owner(Project, id)]
+/// fn get_project_tasks(id: Id<Project>) -> Vec<Entry<Task>> { ... }
+/// #[privilege(Project, update, id)]
+/// fn rename_project(id: Id<Project>, name: String) -> Option<Project> { ... }
+/// fn get_my_projects(user: AuthUser) -> Vec<Entry<Project>> { ... }
+/// ```
+/// Functions without attributes only require an authenticated user.
+/// Parameters of type `AuthUser` are not sent by clients, but injected
+/// from the token of the caller.
+/// Privileges are checked with `has_privilege` and `has_row_privilege`,
+/// which need to be imported with `TableAction` and `Resource`.
+pub enum Requirement {
+	Public,
+	Role(LitStr),
+	/// the caller must own the row of the type with the id in the parameter,
+	/// which may be in the trash, e.g. to restore it
+	Owner(Box<Type>, Ident),
+	/// the roles of the caller must allow the action on the table of the type,
+	/// or on the row with the id in the parameter
+	Privilege(Box<Type>, Ident, Option<Ident>),
+}
+const ATTRIBUTES: [&str; 5] = ["public", "authenticated", "role", "owner", "privilege"];
+const ACTIONS: [&str; 4] = ["read", "create", "update", "delete"];
+
+pub fn is_requirement(attr: &Attribute) -> bool {
+	ATTRIBUTES.iter().any(|name| attr.path.is_ident(name))
+}
+pub fn requirements(item: &ItemFn) -> Vec<Requirement> {
+	let requirements: Vec<Requirement> = item.attrs
+		.iter()
+		.filter(|attr| is_requirement(attr))
+		.filter_map(|attr| {
+			if attr.path.is_ident("public") {
+				Some(Requirement::Public)
+			} else if attr.path.is_ident("role") {
+				Some(Requirement::Role(
+					attr.parse_args().expect("expected #[role(\"name\")]")
+				))
+			} else if attr.path.is_ident("owner") {
+				let (ty, ident) = attr
+					.parse_args_with(|input: syn::parse::ParseStream| {
+						let ty: Type = input.parse()?;
+						input.parse::<Token![,]>()?;
+						let ident: Ident = input.parse()?;
+						Ok((ty, ident))
+					})
+					.expect("expected #[owner(Type, parameter)]");
+				Some(Requirement::Owner(Box::new(ty), ident))
+			} else if attr.path.is_ident("privilege") {
+				let (ty, action, param) = attr
+					.parse_args_with(|input: syn::parse::ParseStream| {
+						let ty: Type = input.parse()?;
+						input.parse::<Token![,]>()?;
+						let action: Ident = input.parse()?;
+						if !ACTIONS.iter().any(|name| action == name) {
+							return Err(Error::new(action.span(), "expected read, create, update or delete"));
+						}
+						let param = if input.parse::<Option<Token![,]>>()?.is_some() {
+							Some(input.parse()?)
+						} else {
+							None
+						};
+						Ok((ty, action, param))
+					})
+					.expect("expected #[privilege(Type, action)] or #[privilege(Type, action, parameter)]");
+				Some(Requirement::Privilege(Box::new(ty), action, param))
+			} else {
+				None
+			}
+		})
+		.collect();
+	if is_public(&requirements) {
+		if requirements.len() > 1 {
+			panic!("public api function {} may not have other requirements", item.sig.ident);
+		}
+		if item.sig.inputs.iter().any(is_auth_user) {
+			panic!("public api function {} may not take an AuthUser", item.sig.ident);
+		}
+	}
+	requirements
+}
+pub fn is_public(requirements: &[Requirement]) -> bool {
+	requirements.iter().any(|r| matches!(r, Requirement::Public))
+}
+/// Parameters injected from the token of the caller
+pub fn is_auth_user(arg: &FnArg) -> bool {
+	match arg {
+		FnArg::Typed(PatType { ty, .. }) => {
+			match &**ty {
+				Type::Path(path) => path.path.segments.last().map(|s| s.ident == "AuthUser").unwrap_or(false),
+				_ => false,
+			}
+		}
+		_ => false,
+	}
+}
+/// Checks run by the handler before calling the function,
+/// `deny` returns the response with the given status from the handler
+pub fn checks<F: Fn(u16) -> TokenStream2>(requirements: &[Requirement], deny: F) -> TokenStream2 {
+	let forbidden = deny(403);
+	let not_found = deny(404);
+	let checks: Vec<TokenStream2> = requirements
+		.iter()
+		.map(|requirement| {
+			match requirement {
+				Requirement::Public => quote! {},
+				Requirement::Role(role) => quote! {
+					if !caller.has_role(#role) {
+						#forbidden
+					}
+				},
+				Requirement::Owner(ty, param) => quote! {
+					match TableRead::get(&<Schema as Database<'_, #ty>>::table(), parameters.#param) {
+						Some(data) if Owned::is_owner(&data, parameters.#param, caller.id) => {},
+						Some(_) => { #forbidden }
+						None => { #not_found }
+					}
+				},
+				Requirement::Privilege(ty, action, None) => {
+					let action = table_action(action);
+					quote! {
+						if !has_privilege::<Schema>(&caller, #action, &Resource::table::<#ty>()) {
+							#forbidden
+						}
+					}
+				},
+				Requirement::Privilege(ty, action, Some(param)) => {
+					let action = table_action(action);
+					quote! {
+						match has_row_privilege::<Schema, #ty>(&caller, #action, parameters.#param) {
+							Some(true) => {},
+							Some(false) => { #forbidden }
+							None => { #not_found }
+						}
+					}
+				},
+			}
+		})
+		.collect();
+	quote! {
+		#(#checks)*
+	}
+}
+/// `TableAction` variant of a lowercase action
+fn table_action(action: &Ident) -> TokenStream2 {
+	let variant = match action.to_string().as_str() {
+		"read" => quote! { Read },
+		"create" => quote! { Create },
+		"update" => quote! { Update },
+		_ => quote! { Delete },
+	};
+	quote! { TableAction::#variant }
+}
+/// Name of the table of a type in privileges, its lowercase name
+pub fn table_name(ty: &Type) -> String {
+	match ty {
+		Type::Path(path) => path.path.segments.last().map(|s| s.ident.to_string().to_lowercase()),
+		_ => None,
+	}
+	.expect("expected the name of a table type")
+}
@@ -12,25 +12,32 @@ use syn::{
 	*,
 };
 
-pub fn define_client(fns: &Vec<ItemFn>) -> TokenStream2 {
-	let calls: Vec<TokenStream2> = fns.iter().map(|f| fetch_call(f.clone())).collect();
+pub fn define_client(fns: &[ItemFn], version: Option<u32>) -> TokenStream2 {
+	let calls: Vec<TokenStream2> = fns.iter().map(|f| client_call(f.clone(), version, true)).collect();
+	let remote_calls: Vec<TokenStream2> = fns.iter().map(|f| client_call(f.clone(), version, false)).collect();
 	quote! {
-		#[cfg(target_arch="wasm32")]
-		use seed::{
-			browser::fetch::{
-				Method,
-				FetchError,
-				Header,
-				Response,
-			},
-		};
 		use app_model::{
 			auth,
 		};
+		#[cfg(target_arch="wasm32")]
+		use database_table::CallError;
 		#(#calls)*
+		/// Clients for native programs, sending calls through `database_table::set_transport`
+		#[cfg(not(target_arch="wasm32"))]
+		pub mod remote {
+			use super::*;
+			use database_table::CallError;
+			#(#remote_calls)*
+		}
 	}
 }
-fn fetch_call(item: ItemFn) -> TokenStream2 {
+/// Client function sending a call to the host of `database_table::client_config`
+fn client_call(item: ItemFn, version: Option<u32>, wasm: bool) -> TokenStream2 {
+	// clients of deprecated functions are deprecated
+	let deprecated: Vec<&Attribute> = item.attrs
+		.iter()
+		.filter(|attr| attr.path.is_ident("deprecated"))
+		.collect();
 	let Signature {
 		ident,  //: Ident
 		inputs, //: Punctuated<FnArg, Comma>
@@ -40,7 +47,13 @@ fn fetch_call(item: ItemFn) -> TokenStream2 {
 
 	let params_ident = format_ident!("{}Parameters", ident.clone());
 	let result_ident = format_ident!("{}Result", ident.clone());
-	let route = format!("/api/call/{}", ident);
+	let name = ident.to_string();
+	// the caller is injected by the server
+	let mut inputs: Punctuated<FnArg, Comma> = inputs
+		.into_iter()
+		.filter(|arg| !crate::auth::is_auth_user(arg))
+		.collect();
+	crate::server::strip_param_attrs(&mut inputs);
 	let members: Punctuated<Ident, Comma> = inputs
 		.iter()
 		.map(|arg| {
@@ -55,29 +68,42 @@ fn fetch_call(item: ItemFn) -> TokenStream2 {
 			}
 		})
 		.collect();
-	let ret_ty: Type = match output {
-		ReturnType::Default => syn::parse_str("()").unwrap(),
-		ReturnType::Type(_arrow, ty) => *ty,
+	let (ret_ty, err_ty): (Type, Type) = match crate::rpc::result_types(&output) {
+		Some(types) => types,
+		None => {
+			let ret_ty = match output {
+				ReturnType::Default => syn::parse_str("()").unwrap(),
+				ReturnType::Type(_arrow, ty) => *ty,
+			};
+			(ret_ty, syn::parse_str("()").unwrap())
+		}
+	};
+	let (cfg, authentication) = if wasm {
+		(
+			quote! { #[cfg(target_arch="wasm32")] },
+			quote! {
+				if let Some(session) = auth::session::get() {
+					config = config.header("Authorization", session.token);
+				}
+			},
+		)
+	} else {
+		(quote! {}, quote! {})
+	};
+	let config = match version {
+		Some(version) => quote! { database_table::client_config().version(#version) },
+		None => quote! { database_table::client_config() },
 	};
 	quote! {
-		#[cfg(target_arch="wasm32")]
-		pub async fn #ident(#inputs) -> Result<#ret_ty, FetchError> {
-			let host = "http://localhost:8000";
-			let url = format!("{}{}", host, #route);
-			let mut req = seed::fetch::Request::new(&url)
-				.method(Method::Post);
-			// authentication
-			if let Some(session) = auth::session::get() {
-				req = req.header(Header::authorization(format!("{}", session.token)));
-			}
-			seed::fetch::fetch(
-				req.json(&#params_ident { #members })?
-			)
-			.await?
-			.check_status()?
-			.json()
-			.await
-			.map(|res: #result_ident| res.0)
+		#cfg
+		#(#deprecated)*
+		pub async fn #ident(#inputs) -> Result<#ret_ty, CallError<#err_ty>> {
+			#[allow(unused_mut)]
+			let mut config = #config;
+			#authentication
+			database_table::remote_call(&config, #name, &#params_ident { #members })
+				.await
+				.map(|res: #result_ident| res.0)
 		}
 	}
 }
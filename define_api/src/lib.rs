@@ -1,7 +1,16 @@
+mod auth;
 mod client;
+mod openapi;
 mod rest;
+#[cfg(feature = "rocket")]
+mod rocket;
 mod rpc;
+mod schema;
 mod server;
+mod socket;
+#[cfg(feature = "tide")]
+mod tide;
+mod update;
 
 use proc_macro::TokenStream;
 use proc_macro2::Span;
@@ -16,6 +25,8 @@ use syn::{
     *,
 };
 struct Items {
+    /// from `version = <n>;` before the items
+    version: Option<u32>,
     items: Vec<Item>,
 }
 impl std::ops::Deref for Items {
@@ -26,11 +37,23 @@ impl std::ops::Deref for Items {
 }
 impl syn::parse::Parse for Items {
     fn parse(input: syn::parse::ParseStream) -> syn::parse::Result<Self> {
+        let version = if input.peek(Ident) && input.peek2(Token![=]) {
+            let ident: Ident = input.parse()?;
+            if ident != "version" {
+                return Err(Error::new(ident.span(), "expected `version = <n>;`"));
+            }
+            input.parse::<Token![=]>()?;
+            let version: LitInt = input.parse()?;
+            input.parse::<Token![;]>()?;
+            Some(version.base10_parse()?)
+        } else {
+            None
+        };
         let mut items = Vec::new();
         while let Ok(item) = input.parse::<Item>() {
             items.push(item);
         }
-        Ok(Items { items })
+        Ok(Items { version, items })
     }
 }
 struct ItemFns {
@@ -51,6 +74,16 @@ impl syn::parse::Parse for ItemFns {
 pub fn rest_api(input: TokenStream) -> TokenStream {
     rest::define_rest_api(input)
 }
+/// Derive `database_table::Updatable` with a `<Type>Update` struct
+#[proc_macro_derive(Updatable)]
+pub fn updatable(input: TokenStream) -> TokenStream {
+    update::derive_updatable(input)
+}
+/// Implement `database_table::JsonSchema` for the serialized form of a type
+#[proc_macro_derive(JsonSchema, attributes(serde))]
+pub fn json_schema(input: TokenStream) -> TokenStream {
+    schema::derive_json_schema(input)
+}
 /// Define server REST endpoints for a type
 #[proc_macro]
 pub fn rest_handlers(input: TokenStream) -> TokenStream {
@@ -65,18 +98,22 @@ pub fn rest_handlers(input: TokenStream) -> TokenStream {
     let get_name = format_ident!("get_{}", ident);
     let post_name = format_ident!("post_{}", ident);
     let get_all_name = format_ident!("get_{}s", ident);
+    let query_name = format_ident!("query_{}s", ident);
     let delete_name = format_ident!("delete_{}", ident);
-    //let update_name = format_ident!("update_{}", ident);
+    let update_name = format_ident!("update_{}", ident);
     TokenStream::from(quote! {
         rocket::routes![
             api::handlers::#get_name,
             api::handlers::#post_name,
             api::handlers::#get_all_name,
+            api::handlers::#query_name,
             api::handlers::#delete_name,
-            //api::handlers::#update_name,
+            api::handlers::#update_name,
         ]
     })
 }
+/// Define api functions with their handlers, clients and OpenAPI document.
+/// With `version = <n>;` before the functions they are served under `/api/v<n>`.
 #[proc_macro]
 pub fn api(input: TokenStream) -> TokenStream {
     // parse Items
@@ -138,12 +175,14 @@ pub fn api(input: TokenStream) -> TokenStream {
     fns.extend(rest_fns.iter().cloned());
 
     let protocol = rpc::define_protocol(&fns);
-    let server = server::define_server(&fns);
-    let client = client::define_client(&fns);
+    let server = server::define_server(&fns, items.version);
+    let client = client::define_client(&fns, items.version);
+    let openapi = openapi::define_openapi(&fns, items.version);
     TokenStream::from(quote! {
         #(#imports)*
         #protocol
         #server
         #client
+        #openapi
     })
 }
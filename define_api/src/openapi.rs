@@ -0,0 +1,141 @@
+use quote::quote;
+use proc_macro2::{
+	TokenStream as TokenStream2,
+};
+use syn::{
+	Type,
+	*,
+};
+use crate::auth::Requirement;
+
+/// Define `openapi()`, returning the OpenAPI document of the functions
+pub fn define_openapi(fns: &[ItemFn], version: Option<u32>) -> TokenStream2 {
+	let operations: Vec<TokenStream2> = fns.iter().map(|item| operation(item, version)).collect();
+	quote! {
+		/// OpenAPI document of the api functions
+		pub fn openapi() -> database_table::serde_json::Value {
+			let mut api = database_table::OpenApi::new(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+			#(#operations)*
+			api.to_json()
+		}
+	}
+}
+/// `<T as Updatable>::Update`
+fn updated_type(ty: &Type) -> Option<&Type> {
+	match ty {
+		Type::Path(TypePath { qself: Some(qself), path }) => {
+			let is_update = path.segments.last().map(|s| s.ident == "Update").unwrap_or(false)
+				&& path.segments.iter().any(|s| s.ident == "Updatable");
+			if is_update {
+				Some(&qself.ty)
+			} else {
+				None
+			}
+		}
+		_ => None,
+	}
+}
+fn schema(ty: &Type) -> TokenStream2 {
+	match updated_type(ty) {
+		Some(updated) => quote! {
+			database_table::update_schema::<#updated>(&mut api.schemas)
+		},
+		None => quote! {
+			<#ty as database_table::JsonSchema>::json_schema(&mut api.schemas)
+		},
+	}
+}
+fn operation(item: &ItemFn, version: Option<u32>) -> TokenStream2 {
+	let ident = &item.sig.ident;
+	let name = ident.to_string();
+	let path = crate::rpc::call_path(version, ident);
+	let deprecated = item.attrs.iter().any(|attr| attr.path.is_ident("deprecated"));
+	let parameters: Vec<TokenStream2> = item.sig.inputs
+		.iter()
+		.filter(|arg| !crate::auth::is_auth_user(arg))
+		.map(|arg| {
+			match arg {
+				FnArg::Typed(PatType { attrs, pat, ty, .. }) => {
+					let name = match &**pat {
+						Pat::Ident(pat) => pat.ident.to_string(),
+						_ => panic!("api function params must have idents"),
+					};
+					let schema = schema(ty);
+					let optional = match updated_type(ty) {
+						_ if crate::schema::has_default(attrs) => quote! { true },
+						Some(_) => quote! { false },
+						None => quote! { <#ty as database_table::JsonSchema>::optional() },
+					};
+					quote! { (#name, #schema, #optional) }
+				}
+				_ => panic!("api functions may not take self parameter"),
+			}
+		})
+		.collect();
+	let (result, error) = match crate::rpc::result_types(&item.sig.output) {
+		Some((ok, err)) => (schema(&ok), {
+			let err = schema(&err);
+			quote! { Some(#err) }
+		}),
+		None => match &item.sig.output {
+			ReturnType::Type(_, ty) => (schema(ty), quote! { None }),
+			ReturnType::Default => (schema(&parse_quote!(())), quote! { None }),
+		},
+	};
+	let description = match crate::schema::docs(&item.attrs) {
+		Some(docs) => quote! { Some(#docs.to_string()) },
+		None => quote! { None },
+	};
+	let requirements = crate::auth::requirements(item);
+	let mut access: Vec<TokenStream2> = requirements
+		.iter()
+		.map(|requirement| {
+			match requirement {
+				Requirement::Public => quote! { database_table::Access::Public },
+				Requirement::Role(role) => quote! { database_table::Access::Role(#role.to_string()) },
+				Requirement::Owner(_, param) => {
+					let param = param.to_string();
+					quote! { database_table::Access::Owner(#param.to_string()) }
+				}
+				Requirement::Privilege(ty, action, param) => {
+					let action = action.to_string();
+					let table = crate::auth::table_name(ty);
+					let param = match param {
+						Some(param) => {
+							let param = param.to_string();
+							quote! { Some(#param.to_string()) }
+						}
+						None => quote! { None },
+					};
+					quote! {
+						database_table::Access::Privilege {
+							action: #action.to_string(),
+							table: #table.to_string(),
+							param: #param,
+						}
+					}
+				}
+			}
+		})
+		.collect();
+	if !crate::auth::is_public(&requirements) {
+		access.insert(0, quote! { database_table::Access::Authenticated });
+	}
+	quote! {
+		{
+			let parameters = database_table::parameters_schema(vec![#(#parameters),*]);
+			let result = #result;
+			let error = #error;
+			api.operation(database_table::Operation {
+				name: #name.to_string(),
+				path: #path.to_string(),
+				description: #description,
+				parameters,
+				result,
+				error,
+				access: vec![#(#access),*],
+				deprecated: #deprecated,
+			});
+		}
+	}
+}
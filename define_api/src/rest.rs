@@ -12,63 +12,130 @@ use syn::{
 	Type,
 	*,
 };
+/// `Type`, `Type, owner` to only let owners update and delete rows, or
+/// `Type, privileges` to check the privileges of the caller in every function
+struct RestApi {
+	ty: Type,
+	access: Access,
+}
+enum Access {
+	Authenticated,
+	Owner,
+	Privileges,
+}
+impl syn::parse::Parse for RestApi {
+	fn parse(input: syn::parse::ParseStream) -> syn::parse::Result<Self> {
+		let ty = input.parse()?;
+		let access = if input.parse::<Option<Token![,]>>()?.is_some() {
+			let ident: Ident = input.parse()?;
+			if ident == "owner" {
+				Access::Owner
+			} else if ident == "privileges" {
+				Access::Privileges
+			} else {
+				return Err(Error::new(ident.span(), "expected `owner` or `privileges`"));
+			}
+		} else {
+			Access::Authenticated
+		};
+		Ok(Self { ty, access })
+	}
+}
+/// Requirement attributes of the functions in the order
+/// get, get_all and query, post, delete, update
+fn requirements(ty: &Type, access: &Access) -> [TokenStream2; 5] {
+	match access {
+		Access::Authenticated => Default::default(),
+		Access::Owner => [
+			quote! {},
+			quote! {},
+			quote! {},
+			quote! { #[owner(#ty, id)] },
+			quote! { #[owner(#ty, id)] },
+		],
+		Access::Privileges => [
+			quote! { #[privilege(#ty, read, id)] },
+			quote! { #[privilege(#ty, read)] },
+			quote! { #[privilege(#ty, create)] },
+			quote! { #[privilege(#ty, delete, id)] },
+			quote! { #[privilege(#ty, update, id)] },
+		],
+	}
+}
 /// Define REST handler code
 pub fn define_rest_api(input: TokenStream) -> TokenStream {
-	let ty = parse_macro_input!(input as Type);
+	let RestApi { ty, access } = parse_macro_input!(input as RestApi);
+	let [get_access, read_access, post_access, delete_access, update_access] = requirements(&ty, &access);
 	let ident = Ident::new(
 		&format!("{}", ty.clone().into_token_stream()).to_lowercase(),
 		Span::call_site(),
 	);
-	let get = define_get(ty.clone(), ident.clone());
-	let get_all = define_get_all(ty.clone(), ident.clone());
-	let post = define_post(ty.clone(), ident.clone());
-	let delete = define_delete(ty.clone(), ident.clone());
-	//let update = define_update(ty.clone(), ident.clone());
+	let get = define_get(ty.clone(), ident.clone(), get_access);
+	let get_all = define_get_all(ty.clone(), ident.clone(), read_access.clone());
+	let query = define_query(ty.clone(), ident.clone(), read_access);
+	let post = define_post(ty.clone(), ident.clone(), post_access);
+	let delete = define_delete(ty.clone(), ident.clone(), delete_access);
+	let update = define_update(ty.clone(), ident.clone(), update_access);
 	TokenStream::from(quote! {
 		#get
 		#get_all
+		#query
 		#post
 		#delete
-		//#update
+		#update
 	})
 }
-fn define_get(ty: Type, ident: Ident) -> TokenStream2 {
+fn define_get(ty: Type, ident: Ident, access: TokenStream2) -> TokenStream2 {
 	let name = format_ident!("get_{}", ident);
 	quote! {
+		#access
 		fn #name(id: Id<#ty>) -> Option<Entry<#ty>> {
 			<#ty as DatabaseTable<'_, Schema>>::get(id)
 		}
 	}
 }
-fn define_post(ty: Type, ident: Ident) -> TokenStream2 {
+fn define_post(ty: Type, ident: Ident, access: TokenStream2) -> TokenStream2 {
 	let name = format_ident!("post_{}", ident);
 	quote! {
-		fn #name(data: #ty) -> Id<#ty> {
-			<#ty as DatabaseTable<'_, Schema>>::insert(data)
+		#access
+		fn #name(data: #ty) -> Result<Id<#ty>, IndexError> {
+			<#ty as DatabaseTable<'_, Schema>>::try_insert(data)
 		}
 	}
 }
-fn define_get_all(ty: Type, ident: Ident) -> TokenStream2 {
+fn define_get_all(ty: Type, ident: Ident, access: TokenStream2) -> TokenStream2 {
 	let name = format_ident!("get_{}s", ident);
 	quote! {
+		#access
 		fn #name() -> Vec<Entry<#ty>> {
 			<#ty as DatabaseTable<'_, Schema>>::get_all()
 		}
 	}
 }
-fn define_delete(ty: Type, ident: Ident) -> TokenStream2 {
+fn define_query(ty: Type, ident: Ident, access: TokenStream2) -> TokenStream2 {
+	let name = format_ident!("query_{}s", ident);
+	quote! {
+		#access
+		fn #name(query: Query) -> Page<#ty> {
+			<#ty as DatabaseTable<'_, Schema>>::query(&query)
+		}
+	}
+}
+fn define_delete(ty: Type, ident: Ident, access: TokenStream2) -> TokenStream2 {
 	let name = format_ident!("delete_{}", ident);
 	quote! {
-		fn #name(id: Id<#ty>) -> Option<#ty> {
-			<#ty as DatabaseTable<'_, Schema>>::delete(id)
+		#access
+		fn #name(id: Id<#ty>) -> Result<Option<#ty>, DeleteError> {
+			<#ty as DatabaseTable<'_, Schema>>::try_delete(id)
+		}
+	}
+}
+fn define_update(ty: Type, ident: Ident, access: TokenStream2) -> TokenStream2 {
+	let name = format_ident!("update_{}", ident);
+	quote! {
+		#access
+		fn #name(id: Id<#ty>, update: <#ty as Updatable>::Update) -> Result<Option<#ty>, IndexError> {
+			<#ty as DatabaseTable<'_, Schema>>::try_update(id, update)
 		}
 	}
 }
-//fn define_update(ty: Type, ident: Ident) -> TokenStream2 {
-//	let name = format_ident!("update_{}", ident);
-//	quote! {
-//		fn #name(id: Id<#ty>, update: <#ty as Updatable>::Update) -> Option<#ty> {
-//			<#ty as DatabaseTable>::update(id, update)
-//		}
-//	}
-//}
@@ -0,0 +1,118 @@
+use quote::{
+	quote,
+	format_ident,
+};
+use proc_macro2::{
+    TokenStream as TokenStream2,
+};
+use syn::{
+	*,
+};
+
+/// Define rocket handlers of the functions in `handlers`
+pub fn define_handlers(fns: &[ItemFn], version: Option<u32>) -> TokenStream2 {
+	let routes: Vec<TokenStream2> = fns.iter().map(|f| route(f.clone(), version)).collect();
+	let openapi_path = format!("{}/openapi.json", crate::rpc::api_path(version));
+	quote! {
+		#[cfg(not(target_arch="wasm32"))]
+		pub mod handlers {
+			use super::*;
+			use jwt::{
+				*,
+			};
+			use rocket::{
+				request::{
+					FromParam,
+				},
+				response::{
+					*,
+				},
+				http::{
+					*,
+				},
+			};
+			use rocket_contrib::{
+				json::{
+					Json,
+				},
+			};
+			#(#routes)*
+			/// OpenAPI document of the api functions
+			#[rocket::get(#openapi_path)]
+			pub fn openapi_json() -> Json<database_table::serde_json::Value> {
+				Json(super::openapi())
+			}
+		}
+	}
+}
+fn route(item: ItemFn, version: Option<u32>) -> TokenStream2 {
+	#[allow(unused)]
+	let ItemFn {
+		attrs, //: Vec<Attribute>
+		vis,   //: Visibility
+		sig,   //: Signature
+		block, //: Box<Block>
+	} = item.clone();
+
+	#[allow(unused)]
+	let Signature {
+		constness,   //: Option<Const>
+		asyncness,   //: Option<Async>
+		unsafety,	//: Option<Unsafe>
+		abi,		 //: Option<Abi>
+		fn_token,	//: Fn
+		ident,	   //: Ident
+		generics,	//: Generics
+		paren_token, //: Paren
+		inputs,	  //: Punctuated<FnArg, Comma>
+		variadic,	//: Option<Dot3>
+		output,	  //: ReturnType
+	} = sig;
+
+	let params_ident = format_ident!("{}Parameters", ident.clone());
+	let result_ident = format_ident!("{}Result", ident.clone());
+
+	let route = crate::rpc::call_path(version, &ident);
+	let requirements = crate::auth::requirements(&item);
+	let checks = crate::auth::checks(&requirements, |status| {
+		quote! {
+			return Err(status::Custom(Status::from_code(#status).unwrap(), Json(None)));
+		}
+	});
+	let public = crate::auth::is_public(&requirements);
+	let caller = if public {
+		quote! {}
+	} else {
+		quote! { caller: AuthUser, }
+	};
+	let call = crate::server::call_function(&ident, &inputs, public);
+	let (err, respond) = match crate::rpc::result_types(&output) {
+		Some((_ok, err)) => (
+			quote! { #err },
+			quote! {
+				result
+					.map(|ok| Json(#result_ident(ok)))
+					.map_err(|error| {
+						let status = Status::from_code(database_table::ApiError::status(&error))
+							.unwrap_or(Status::InternalServerError);
+						status::Custom(status, Json(Some(error)))
+					})
+			},
+		),
+		None => (
+			quote! { () },
+			quote! { Ok(Json(#result_ident(result))) },
+		),
+	};
+	quote! {
+		#[rocket::post(#route, data="<parameters>")]
+		pub fn #ident(#caller parameters: Json<#params_ident>)
+			-> std::result::Result<Json<#result_ident>, status::Custom<Json<Option<#err>>>>
+		{
+			let Json(parameters) = parameters;
+			#checks
+			let result = #call;
+			#respond
+		}
+	}
+}
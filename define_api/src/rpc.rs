@@ -8,10 +8,22 @@ use proc_macro2::{
 use syn::{
 	punctuated::Punctuated,
 	token::*,
+	Type,
 	*,
 };
 
-pub fn define_protocol(fns: &Vec<ItemFn>) -> TokenStream2 {
+/// Path of the api with `version`, e.g. `/api/v2`
+pub fn api_path(version: Option<u32>) -> String {
+	match version {
+		Some(version) => format!("/api/v{}", version),
+		None => "/api".to_string(),
+	}
+}
+/// Path of the function `ident`, e.g. `/api/v2/call/get_tasks`
+pub fn call_path(version: Option<u32>, ident: &Ident) -> String {
+	format!("{}/call/{}", api_path(version), ident)
+}
+pub fn define_protocol(fns: &[ItemFn]) -> TokenStream2 {
 	let protocols: Vec<TokenStream2> = fns.iter().map(|f| protocol(f.clone())).collect();
 	quote! {
 		use serde::{
@@ -21,7 +33,7 @@ pub fn define_protocol(fns: &Vec<ItemFn>) -> TokenStream2 {
 		use std::result::{
 			Result,
 		};
-		use updatable::{
+		use database_table::{
 			Updatable
 		};
 		use app_model::{
@@ -55,6 +67,7 @@ fn params(ident: Ident, inputs: Punctuated<FnArg, Comma>) -> TokenStream2 {
 		brace_token: Brace::default(),
 		named: inputs
 			.iter()
+			.filter(|arg| !crate::auth::is_auth_user(arg))
 			.map(|arg| {
 				match arg {
 					FnArg::Typed(ty) => {
@@ -80,7 +93,39 @@ fn params(ident: Ident, inputs: Punctuated<FnArg, Comma>) -> TokenStream2 {
 		pub struct #ident #fields
 	}
 }
+/// Ok and error type of functions returning `Result<T, E>`
+pub fn result_types(output: &ReturnType) -> Option<(Type, Type)> {
+	let ty = match output {
+		ReturnType::Type(_arrow, ty) => ty,
+		ReturnType::Default => return None,
+	};
+	let segment = match &**ty {
+		Type::Path(path) => path.path.segments.last()?,
+		_ => return None,
+	};
+	if segment.ident != "Result" {
+		return None;
+	}
+	match &segment.arguments {
+		PathArguments::AngleBracketed(args) => {
+			let mut types = args.args.iter().filter_map(|arg| {
+				match arg {
+					GenericArgument::Type(ty) => Some(ty.clone()),
+					_ => None,
+				}
+			});
+			match (types.next(), types.next()) {
+				(Some(ok), Some(err)) => Some((ok, err)),
+				_ => None,
+			}
+		}
+		_ => None,
+	}
+}
+/// The result struct holds only the ok type of functions returning a `Result`,
+/// errors are sent with an error status
 fn result(ident: Ident, ty: ReturnType) -> TokenStream2 {
+	let ok = result_types(&ty).map(|(ok, _)| ok);
 	let fields = match ty {
 		ReturnType::Default => Fields::Unit,
 		ReturnType::Type(_arrow, ty) => {
@@ -90,7 +135,7 @@ fn result(ident: Ident, ty: ReturnType) -> TokenStream2 {
 				vis: Visibility::Inherited,
 				ident: None,
 				colon_token: None,
-				ty: *ty,
+				ty: ok.unwrap_or(*ty),
 			});
 			Fields::Unnamed(FieldsUnnamed {
 				paren_token: Paren::default(),
@@ -0,0 +1,371 @@
+use proc_macro::TokenStream;
+use proc_macro2::{
+	TokenStream as TokenStream2,
+};
+use quote::quote;
+use syn::{
+	ext::IdentExt,
+	punctuated::Punctuated,
+	token::Comma,
+	*,
+};
+/// `#[serde(...)]` attributes of a container, variant or field
+#[derive(Default)]
+struct SerdeAttrs {
+	rename: Option<String>,
+	rename_all: Option<String>,
+	tag: Option<String>,
+	content: Option<String>,
+	untagged: bool,
+	flatten: bool,
+	transparent: bool,
+	/// may be missing when deserializing
+	default: bool,
+	skip: bool,
+}
+const RENAME_RULES: [&str; 8] = [
+	"lowercase",
+	"UPPERCASE",
+	"PascalCase",
+	"camelCase",
+	"snake_case",
+	"SCREAMING_SNAKE_CASE",
+	"kebab-case",
+	"SCREAMING-KEBAB-CASE",
+];
+fn lower_first(name: &str) -> String {
+	let mut chars = name.chars();
+	chars.next().map(|first| first.to_lowercase().chain(chars).collect()).unwrap_or_default()
+}
+/// Name of a variant (in PascalCase) after `#[serde(rename_all = rule)]`
+fn rename_variant(name: &str, rule: &str) -> String {
+	let snake = || {
+		let mut snake = String::new();
+		for (i, c) in name.char_indices() {
+			if c.is_uppercase() && i > 0 {
+				snake.push('_');
+			}
+			snake.push(c.to_ascii_lowercase());
+		}
+		snake
+	};
+	match rule {
+		"lowercase" => name.to_ascii_lowercase(),
+		"UPPERCASE" => name.to_ascii_uppercase(),
+		"camelCase" => lower_first(name),
+		"snake_case" => snake(),
+		"SCREAMING_SNAKE_CASE" => snake().to_ascii_uppercase(),
+		"kebab-case" => snake().replace('_', "-"),
+		"SCREAMING-KEBAB-CASE" => snake().to_ascii_uppercase().replace('_', "-"),
+		_ => name.to_string(),
+	}
+}
+/// Name of a field (in snake_case) after `#[serde(rename_all = rule)]`
+fn rename_field(name: &str, rule: &str) -> String {
+	let pascal = || {
+		let mut pascal = String::new();
+		let mut capitalize = true;
+		for c in name.chars() {
+			if c == '_' {
+				capitalize = true;
+			} else if capitalize {
+				pascal.push(c.to_ascii_uppercase());
+				capitalize = false;
+			} else {
+				pascal.push(c);
+			}
+		}
+		pascal
+	};
+	match rule {
+		"UPPERCASE" | "SCREAMING_SNAKE_CASE" => name.to_ascii_uppercase(),
+		"PascalCase" => pascal(),
+		"camelCase" => lower_first(&pascal()),
+		"kebab-case" => name.replace('_', "-"),
+		"SCREAMING-KEBAB-CASE" => name.to_ascii_uppercase().replace('_', "-"),
+		_ => name.to_string(),
+	}
+}
+
+fn serde_attrs(attrs: &[Attribute]) -> SerdeAttrs {
+	let mut serde = SerdeAttrs::default();
+	let metas = attrs
+		.iter()
+		.filter(|attr| attr.path.is_ident("serde"))
+		.filter_map(|attr| attr.parse_meta().ok())
+		.flat_map(|meta| {
+			match meta {
+				Meta::List(list) => list.nested.into_iter().collect(),
+				_ => Vec::new(),
+			}
+		});
+	for meta in metas {
+		let (path, value) = match meta {
+			NestedMeta::Meta(Meta::Path(path)) => (path, None),
+			NestedMeta::Meta(Meta::NameValue(MetaNameValue { path, lit, .. })) => (path, Some(lit)),
+			_ => continue,
+		};
+		let string = match value {
+			Some(Lit::Str(value)) => Some(value.value()),
+			_ => None,
+		};
+		if path.is_ident("rename") {
+			serde.rename = string;
+		} else if path.is_ident("rename_all") {
+			match string {
+				Some(rule) if RENAME_RULES.contains(&rule.as_str()) => serde.rename_all = Some(rule),
+				rule => panic!("JsonSchema does not support #[serde(rename_all = {:?})]", rule),
+			}
+		} else if path.is_ident("tag") {
+			serde.tag = string;
+		} else if path.is_ident("content") {
+			serde.content = string;
+		} else if path.is_ident("untagged") {
+			serde.untagged = true;
+		} else if path.is_ident("flatten") {
+			serde.flatten = true;
+		} else if path.is_ident("transparent") {
+			serde.transparent = true;
+		} else if path.is_ident("default") || path.is_ident("skip_serializing_if") {
+			serde.default = true;
+		} else if path.is_ident("skip") {
+			serde.skip = true;
+		} else if path.is_ident("skip_serializing") || path.is_ident("skip_deserializing") {
+			// not always sent
+			serde.default = true;
+		}
+	}
+	serde
+}
+/// A field with these attributes may be missing when deserializing
+pub fn has_default(attrs: &[Attribute]) -> bool {
+	serde_attrs(attrs).default
+}
+/// Lines of the doc comments
+pub fn docs(attrs: &[Attribute]) -> Option<String> {
+	let lines: Vec<String> = attrs
+		.iter()
+		.filter(|attr| attr.path.is_ident("doc"))
+		.filter_map(|attr| {
+			match attr.parse_meta() {
+				Ok(Meta::NameValue(MetaNameValue { lit: Lit::Str(doc), .. })) => Some(doc.value().trim().to_string()),
+				_ => None,
+			}
+		})
+		.collect();
+	if lines.is_empty() {
+		None
+	} else {
+		Some(lines.join("\n"))
+	}
+}
+/// Add the doc comments of an item to its schema
+fn describe(schema: TokenStream2, attrs: &[Attribute]) -> TokenStream2 {
+	match docs(attrs) {
+		Some(description) => quote! {
+			{
+				let mut schema = #schema;
+				schema["description"] = database_table::serde_json::Value::from(#description);
+				schema
+			}
+		},
+		None => schema,
+	}
+}
+fn field_schema(ty: &Type) -> TokenStream2 {
+	quote! {
+		<#ty as database_table::JsonSchema>::json_schema(schemas)
+	}
+}
+/// Schema of the fields of a struct or variant with the `serde` attributes of the container
+fn fields_schema(fields: &Fields, serde: &SerdeAttrs) -> TokenStream2 {
+	let kept: Vec<&Field> = fields.iter()
+		.filter(|field| !serde_attrs(&field.attrs).skip)
+		.collect();
+	match fields {
+		Fields::Named(_) if !serde.transparent => named_schema(&kept, serde),
+		Fields::Unit => quote! {
+			database_table::serde_json::json!({ "type": "null" })
+		},
+		_ => match &kept[..] {
+			[field] => field_schema(&field.ty),
+			[] => panic!("JsonSchema needs a field of a transparent struct"),
+			_ => {
+				let items: Vec<TokenStream2> = kept
+					.iter()
+					.map(|field| field_schema(&field.ty))
+					.collect();
+				let len = items.len();
+				quote! {
+					database_table::serde_json::json!({
+						"type": "array",
+						"prefixItems": [#(#items),*],
+						"minItems": #len,
+						"maxItems": #len,
+					})
+				}
+			}
+		},
+	}
+}
+/// Schema of an object, with the properties of flattened fields merged into it
+fn named_schema(fields: &[&Field], container: &SerdeAttrs) -> TokenStream2 {
+	let mut properties = Vec::new();
+	let mut flattened = Vec::new();
+	for field in fields {
+		let serde = serde_attrs(&field.attrs);
+		let ty = &field.ty;
+		let optional = container.default || serde.default;
+		if serde.flatten {
+			let flattened_schema = field_schema(ty);
+			flattened.push(quote! {
+				let flattened = #flattened_schema;
+				schema = database_table::flatten_schema(schemas, schema, flattened, #optional || <#ty as database_table::JsonSchema>::optional());
+			});
+			continue;
+		}
+		let ident = field.ident.as_ref().unwrap().unraw().to_string();
+		let name = serde.rename.unwrap_or_else(|| {
+			match &container.rename_all {
+				Some(rule) => rename_field(&ident, rule),
+				None => ident,
+			}
+		});
+		let schema = describe(field_schema(ty), &field.attrs);
+		properties.push(quote! {
+			(#name, #schema, #optional || <#ty as database_table::JsonSchema>::optional())
+		});
+	}
+	quote! {
+		{
+			#[allow(unused_mut)]
+			let mut schema = database_table::object_schema(vec![#(#properties),*]);
+			#(#flattened)*
+			schema
+		}
+	}
+}
+/// Schema of an enum in the representation chosen by the `serde` attributes
+/// of the container, externally tagged by default, where unit variants are strings
+fn enum_schema(variants: &Punctuated<Variant, Comma>, container: &SerdeAttrs) -> TokenStream2 {
+	let mut units = Vec::new();
+	let mut others = Vec::new();
+	for variant in variants {
+		let serde = serde_attrs(&variant.attrs);
+		if serde.skip {
+			continue;
+		}
+		let name = serde.rename.clone().unwrap_or_else(|| {
+			let ident = variant.ident.unraw().to_string();
+			match &container.rename_all {
+				Some(rule) => rename_variant(&ident, rule),
+				None => ident,
+			}
+		});
+		let unit = matches!(variant.fields, Fields::Unit);
+		let fields = fields_schema(&variant.fields, &serde);
+		let schema = match (&container.tag, &container.content) {
+			_ if container.untagged => fields,
+			(Some(tag), Some(content)) if !unit => quote! {
+				database_table::object_schema(vec![
+					(#tag, database_table::tag_schema(#name), false),
+					(#content, #fields, false),
+				])
+			},
+			(Some(tag), _) if unit => quote! {
+				database_table::object_schema(vec![(#tag, database_table::tag_schema(#name), false)])
+			},
+			(Some(tag), _) => quote! {
+				{
+					let schema = #fields;
+					database_table::tagged_schema(schemas, #tag, #name, schema)
+				}
+			},
+			(None, _) if unit => {
+				units.push(name);
+				continue;
+			},
+			(None, _) => quote! {
+				database_table::variant_schema(#name, #fields)
+			},
+		};
+		others.push(describe(schema, &variant.attrs));
+	}
+	let units = if units.is_empty() {
+		None
+	} else {
+		Some(quote! {
+			database_table::serde_json::json!({
+				"type": "string",
+				"enum": [#(#units),*],
+			})
+		})
+	};
+	match (units, others.is_empty()) {
+		(Some(units), true) => units,
+		// untagged variants may match more than one schema
+		(None, _) if container.untagged => quote! {
+			database_table::serde_json::json!({
+				"anyOf": database_table::serde_json::Value::Array(vec![#(#others),*]),
+			})
+		},
+		(units, _) => {
+			let units = units.into_iter();
+			quote! {
+				database_table::serde_json::json!({
+					"oneOf": database_table::serde_json::Value::Array(vec![#(#units,)* #(#others),*]),
+				})
+			}
+		}
+	}
+}
+/// Implement `database_table::JsonSchema`
+pub fn derive_json_schema(input: TokenStream) -> TokenStream {
+	let DeriveInput {
+		ident,
+		attrs,
+		mut generics,
+		data,
+		..
+	} = parse_macro_input!(input as DeriveInput);
+	let serde = serde_attrs(&attrs);
+	let name = serde.rename.clone().unwrap_or_else(|| ident.unraw().to_string());
+	let schema = match &data {
+		Data::Struct(data) => {
+			let schema = fields_schema(&data.fields, &serde);
+			match &serde.tag {
+				Some(tag) => quote! {
+					{
+						let schema = #schema;
+						database_table::tagged_schema(schemas, #tag, #name, schema)
+					}
+				},
+				None => schema,
+			}
+		},
+		Data::Enum(data) => enum_schema(&data.variants, &serde),
+		Data::Union(_) => panic!("JsonSchema can not be derived for unions"),
+	};
+	let schema = describe(schema, &attrs);
+	// schemas of generic types depend on their parameters and are inlined
+	let generic = generics.type_params().next().is_some();
+	for param in generics.type_params_mut() {
+		param.bounds.push(parse_quote!(database_table::JsonSchema));
+	}
+	let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+	let body = if generic {
+		schema
+	} else {
+		quote! {
+			schemas.reference(#name, |schemas| #schema)
+		}
+	};
+	TokenStream::from(quote! {
+		impl #impl_generics database_table::JsonSchema for #ident #ty_generics #where_clause {
+			#[allow(unused_variables)]
+			fn json_schema(schemas: &mut database_table::Schemas) -> database_table::serde_json::Value {
+				#body
+			}
+		}
+	})
+}
@@ -1,7 +1,4 @@
-use quote::{
-	quote,
-	format_ident,
-};
+use quote::quote;
 use proc_macro2::{
     TokenStream as TokenStream2,
 };
@@ -11,20 +8,33 @@ use syn::{
 	*,
 };
 
-pub fn define_server(fns: &Vec<ItemFn>) -> TokenStream2 {
-	let handlers = define_handlers(&fns);
-	let calls = define_calls(&fns);
+/// Define the functions, the socket dispatch and the handlers of the enabled web frameworks
+#[cfg_attr(not(any(feature = "rocket", feature = "tide")), allow(unused_variables))]
+pub fn define_server(fns: &[ItemFn], version: Option<u32>) -> TokenStream2 {
+	let calls = define_calls(fns);
+	let socket = crate::socket::define_dispatch(fns);
+	#[cfg(feature = "rocket")]
+	let rocket = crate::rocket::define_handlers(fns, version);
+	#[cfg(not(feature = "rocket"))]
+	let rocket = quote! {};
+	#[cfg(feature = "tide")]
+	let tide = crate::tide::define_endpoints(fns, version);
+	#[cfg(not(feature = "tide"))]
+	let tide = quote! {};
 	quote! {
-		#handlers
+		#rocket
+		#tide
+		#socket
 		#calls
 	}
 }
-fn define_calls(fns: &Vec<ItemFn>) -> TokenStream2 {
+fn define_calls(fns: &[ItemFn]) -> TokenStream2 {
 	let calls: Vec<TokenStream2> = fns.iter().map(|f| call(f.clone())).collect();
 
 	quote! {
+		/// The functions, which adapters of older versions can call
 		#[cfg(not(target_arch="wasm32"))]
-		mod call {
+		pub mod call {
 			use super::*;
 			#(#calls)*
 		}
@@ -35,70 +45,29 @@ fn call(item: ItemFn) -> TokenStream2 {
 	item.vis = Visibility::Public(VisPublic {
 		pub_token: Pub::default(),
 	});
+	item.attrs.retain(|attr| !crate::auth::is_requirement(attr) && !attr.path.is_ident("deprecated"));
+	strip_param_attrs(&mut item.sig.inputs);
 	quote! {
 		#item
 	}
 }
-fn define_handlers(fns: &Vec<ItemFn>) -> TokenStream2 {
-	let routes: Vec<TokenStream2> = fns.iter().map(|f| route(f.clone())).collect();
-	quote! {
-		#[cfg(not(target_arch="wasm32"))]
-		pub mod handlers {
-			use super::*;
-			use jwt::{
-				*,
-			};
-			use rocket::{
-				request::{
-					FromParam,
-				},
-				response::{
-					*,
-				},
-				http::{
-					*,
-				},
-			};
-			use rocket_contrib::{
-				json::{
-					Json,
-				},
-			};
-			#(#routes)*
+/// Remove attributes of parameters, like `#[serde(default)]`, which are only used by `<Name>Parameters`
+pub fn strip_param_attrs(inputs: &mut Punctuated<FnArg, Comma>) {
+	for arg in inputs.iter_mut() {
+		if let FnArg::Typed(arg) = arg {
+			arg.attrs.clear();
 		}
 	}
 }
-fn route(item: ItemFn) -> TokenStream2 {
-	#[allow(unused)]
-	let ItemFn {
-		attrs, //: Vec<Attribute>
-		vis,   //: Visibility
-		sig,   //: Signature
-		block, //: Box<Block>
-	} = item.clone();
-
-	#[allow(unused)]
-	let Signature {
-		constness,   //: Option<Const>
-		asyncness,   //: Option<Async>
-		unsafety,	//: Option<Unsafe>
-		abi,		 //: Option<Abi>
-		fn_token,	//: Fn
-		ident,	   //: Ident
-		generics,	//: Generics
-		paren_token, //: Paren
-		inputs,	  //: Punctuated<FnArg, Comma>
-		variadic,	//: Option<Dot3>
-		output,	  //: ReturnType
-	} = sig;
-
-	let params_ident = format_ident!("{}Parameters", ident.clone());
-	let result_ident = format_ident!("{}Result", ident.clone());
-
-	let route = format!("/api/call/{}", ident);
+/// Call of the function with the fields of `parameters` and the `caller`.
+/// Changes are recorded as made by the caller.
+pub fn call_function(ident: &Ident, inputs: &Punctuated<FnArg, Comma>, public: bool) -> TokenStream2 {
 	let args: Punctuated<Expr, Comma> = inputs
 		.iter()
 		.map(|arg| {
+			if crate::auth::is_auth_user(arg) {
+				return syn::parse_str("caller.clone()").unwrap();
+			}
 			match arg {
 				FnArg::Typed(ty) => {
 					let member = format!(
@@ -115,12 +84,9 @@ fn route(item: ItemFn) -> TokenStream2 {
 			}
 		})
 		.collect();
-	quote! {
-		#[rocket::post(#route, data="<parameters>")]
-		pub fn #ident(token: JWT, parameters: Json<#params_ident>) -> Json<#result_ident> {
-			let _ = token;
-			let Json(parameters) = parameters;
-			Json(#result_ident(call::#ident(#args)))
-		}
+	if public {
+		quote! { call::#ident(#args) }
+	} else {
+		quote! { database_table::as_actor(caller.id, || call::#ident(#args)) }
 	}
 }
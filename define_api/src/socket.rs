@@ -0,0 +1,86 @@
+use quote::{
+	quote,
+	format_ident,
+};
+use proc_macro2::{
+    TokenStream as TokenStream2,
+};
+use syn::{
+	*,
+};
+
+/// Define `socket::dispatch`, answering calls received over websockets
+pub fn define_dispatch(fns: &[ItemFn]) -> TokenStream2 {
+	let names: Vec<String> = fns.iter().map(|item| item.sig.ident.to_string()).collect();
+	let idents: Vec<&Ident> = fns.iter().map(|item| &item.sig.ident).collect();
+	let calls: Vec<TokenStream2> = fns.iter().map(call).collect();
+	quote! {
+		#[cfg(not(target_arch="wasm32"))]
+		pub mod socket {
+			use super::*;
+			use database_table::{
+				SocketCall,
+				SocketReply,
+			};
+			/// Call the function of `call` for `caller`, the user authenticated by the token of the call
+			pub fn dispatch(caller: Option<&AuthUser>, call: SocketCall) -> SocketReply {
+				let SocketCall { id, function, parameters, .. } = call;
+				match function.as_str() {
+					#(#names => #idents(id, caller, parameters),)*
+					_ => SocketReply::status(id, 404),
+				}
+			}
+			#(#calls)*
+		}
+	}
+}
+fn call(item: &ItemFn) -> TokenStream2 {
+	let Signature {
+		ident,
+		inputs,
+		output,
+		..
+	} = &item.sig;
+	let params_ident = format_ident!("{}Parameters", ident);
+	let result_ident = format_ident!("{}Result", ident);
+
+	let requirements = crate::auth::requirements(item);
+	let checks = crate::auth::checks(&requirements, |status| {
+		quote! {
+			return SocketReply::status(id, #status);
+		}
+	});
+	let public = crate::auth::is_public(&requirements);
+	let caller = if public {
+		quote! {}
+	} else {
+		quote! {
+			let caller = match caller {
+				Some(caller) => caller.clone(),
+				None => return SocketReply::status(id, 401),
+			};
+		}
+	};
+	let call = crate::server::call_function(ident, inputs, public);
+	let reply = match crate::rpc::result_types(output) {
+		Some(_) => quote! {
+			SocketReply::result(id, result.map(#result_ident))
+		},
+		None => quote! {
+			SocketReply::new(id, 200, &#result_ident(result))
+		},
+	};
+	quote! {
+		#[allow(unused_variables)]
+		fn #ident(id: u64, caller: Option<&AuthUser>, parameters: database_table::serde_json::Value) -> SocketReply {
+			#caller
+			let parameters: #params_ident = match database_table::serde_json::from_value(parameters) {
+				Ok(parameters) => parameters,
+				Err(_) => return SocketReply::status(id, 422),
+			};
+			#checks
+			let result = #call;
+			#reply
+		}
+	}
+}
@@ -0,0 +1,100 @@
+use quote::{
+	quote,
+	format_ident,
+};
+use proc_macro2::{
+    TokenStream as TokenStream2,
+};
+use syn::{
+	*,
+};
+
+/// Define tide endpoints of the functions in `endpoints`
+pub fn define_endpoints(fns: &[ItemFn], version: Option<u32>) -> TokenStream2 {
+	let endpoints: Vec<TokenStream2> = fns.iter().map(endpoint).collect();
+	let routes: Vec<TokenStream2> = fns
+		.iter()
+		.map(|item| {
+			let ident = &item.sig.ident;
+			let route = crate::rpc::call_path(version, ident);
+			quote! {
+				server.at(#route).post(#ident::<State>);
+			}
+		})
+		.collect();
+	let openapi_path = format!("{}/openapi.json", crate::rpc::api_path(version));
+	quote! {
+		#[cfg(not(target_arch="wasm32"))]
+		pub mod endpoints {
+			use super::*;
+			/// Serve the api functions at `/api/call/<name>` and the OpenAPI
+			/// document at `/api/openapi.json`, under `/api/v<n>` if versioned
+			pub fn serve<State>(server: &mut tide::Server<State>)
+				where State: Clone + Send + Sync + 'static
+			{
+				#(#routes)*
+				server.at(#openapi_path).get(openapi_json::<State>);
+			}
+			/// OpenAPI document of the api functions
+			pub async fn openapi_json<State>(_request: tide::Request<State>) -> tide::Result
+				where State: Clone + Send + Sync + 'static
+			{
+				Ok(tide::Body::from_json(&super::openapi())?.into())
+			}
+			fn respond<T: serde::Serialize>(status: u16, body: &T) -> tide::Result {
+				let mut response = tide::Response::new(status);
+				response.set_body(tide::Body::from_json(body)?);
+				Ok(response)
+			}
+			#(#endpoints)*
+		}
+	}
+}
+fn endpoint(item: &ItemFn) -> TokenStream2 {
+	let Signature {
+		ident,
+		inputs,
+		output,
+		..
+	} = &item.sig;
+	let params_ident = format_ident!("{}Parameters", ident);
+	let result_ident = format_ident!("{}Result", ident);
+
+	let requirements = crate::auth::requirements(item);
+	let checks = crate::auth::checks(&requirements, |status| {
+		quote! {
+			return respond(#status, &None::<()>);
+		}
+	});
+	let public = crate::auth::is_public(&requirements);
+	let caller = if public {
+		quote! {}
+	} else {
+		quote! {
+			let caller = AuthUser::from_tide(&request)?;
+		}
+	};
+	let call = crate::server::call_function(ident, inputs, public);
+	let respond = match crate::rpc::result_types(output) {
+		Some(_) => quote! {
+			match result {
+				Ok(ok) => respond(200, &#result_ident(ok)),
+				Err(error) => respond(database_table::ApiError::status(&error), &Some(error)),
+			}
+		},
+		None => quote! {
+			respond(200, &#result_ident(result))
+		},
+	};
+	quote! {
+		pub async fn #ident<State>(mut request: tide::Request<State>) -> tide::Result
+			where State: Clone + Send + Sync + 'static
+		{
+			#caller
+			let parameters: #params_ident = request.body_json().await?;
+			#checks
+			let result = #call;
+			#respond
+		}
+	}
+}
@@ -0,0 +1,107 @@
+use proc_macro::TokenStream;
+use quote::{
+	format_ident,
+	quote,
+};
+use syn::{
+	*,
+};
+/// Define `<Type>Update` and implement `database_table::Updatable`
+///
+/// Every field of the update is `Option<field type>`, `None` leaves the field
+/// unchanged. Fields of type `Option<T>` become `Option<Option<T>>`, where an
+/// explicit `null` deserializes to `Some(None)` and clears the field.
+pub fn derive_updatable(input: TokenStream) -> TokenStream {
+	let DeriveInput {
+		ident,
+		vis,
+		data,
+		generics,
+		..
+	} = parse_macro_input!(input as DeriveInput);
+	let fields = match data {
+		Data::Struct(DataStruct {
+			fields: Fields::Named(fields),
+			..
+		}) => fields.named,
+		_ => panic!("Updatable can only be derived for structs with named fields"),
+	};
+	let update = format_ident!("{}Update", ident);
+	let names: Vec<Ident> = fields.iter().map(|f| f.ident.clone().unwrap()).collect();
+	let types: Vec<Type> = fields.iter().map(|f| f.ty.clone()).collect();
+	let vises: Vec<Visibility> = fields.iter().map(|f| f.vis.clone()).collect();
+	let params: Vec<Ident> = generics.type_params().map(|param| param.ident.clone()).collect();
+	// serde does not infer bounds for fields with `deserialize_with`
+	let de_bound = params
+		.iter()
+		.map(|param| format!("{}: serde::Deserialize<'de>", param))
+		.collect::<Vec<_>>()
+		.join(", ");
+	let mut bounded = generics.clone();
+	{
+		let where_clause = bounded.make_where_clause();
+		for param in &params {
+			where_clause.predicates.push(parse_quote! {
+				#param: Clone
+					+ std::fmt::Debug
+					+ PartialEq
+					+ serde::Serialize
+					+ serde::de::DeserializeOwned
+			});
+		}
+	}
+	let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+	let (_, _, bounded_where) = bounded.split_for_impl();
+	TokenStream::from(quote! {
+		#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+		#[serde(bound(deserialize = #de_bound))]
+		#vis struct #update #impl_generics #where_clause {
+			#(
+				#[serde(
+					default,
+					deserialize_with = "database_table::deserialize_present",
+					skip_serializing_if = "Option::is_none"
+				)]
+				#vises #names: Option<#types>,
+			)*
+		}
+		impl #impl_generics Default for #update #ty_generics #where_clause {
+			fn default() -> Self {
+				Self {
+					#(
+						#names: None,
+					)*
+				}
+			}
+		}
+		impl #impl_generics #update #ty_generics #where_clause {
+			#(
+				pub fn #names(mut self, #names: #types) -> Self {
+					self.#names = Some(#names);
+					self
+				}
+			)*
+		}
+		impl #impl_generics database_table::Updatable for #ident #ty_generics #bounded_where {
+			type Update = #update #ty_generics;
+			fn apply(&mut self, update: Self::Update) {
+				#(
+					if let Some(#names) = update.#names {
+						self.#names = #names;
+					}
+				)*
+			}
+			fn diff(&self, new: &Self) -> Self::Update {
+				#update {
+					#(
+						#names: if self.#names != new.#names {
+							Some(new.#names.clone())
+						} else {
+							None
+						},
+					)*
+				}
+			}
+		}
+	})
+}
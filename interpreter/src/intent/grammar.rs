@@ -0,0 +1,135 @@
+use crate::{
+	intent::*,
+	parse::*,
+};
+
+// <Units>(€ | Euro | Euros) | €<Units>
+named!(
+	amount<&str, Units>,
+	alt!(
+		complete!(preceded!(tag!("€"), Units::parse))
+			| terminated!(
+				Units::parse,
+				opt!(complete!(alt!(
+					tag!("€")
+						| tag_no_case!(" euros")
+						| tag_no_case!(" euro")
+				)))
+			)
+	)
+);
+// (for <Purpose>)
+named!(
+	purpose<&str, Option<&str>>,
+	opt!(complete!(preceded!(
+		delimited!(space1, tag_no_case!("for"), space1),
+		alpha1
+	)))
+);
+// (<Date>) <Subject> <Action> <Amount> (to|from <Subject>) (for <Purpose>)
+named!(
+	pub record_transaction<&str, Intent>,
+	do_parse!(
+		date: opt!(complete!(terminated!(DateTime::<Utc>::parse, space1))) >>
+		subject: alpha1 >>
+		space1 >>
+		action: complete!(Action::parse) >>
+		space1 >>
+		amount: amount >>
+		counterpart: opt!(complete!(preceded!(
+			delimited!(
+				space1,
+				alt!(tag_no_case!("to") | tag_no_case!("from")),
+				space1
+			),
+			alpha1
+		))) >>
+		purpose: purpose >>
+		(Intent::RecordTransaction {
+			date,
+			subject: subject.to_string(),
+			action,
+			amount,
+			counterpart: counterpart.map(ToString::to_string),
+			purpose: purpose.map(ToString::to_string),
+		})
+	)
+);
+/// split a trailing "due <DateTime>" off a task title
+fn split_due(title: &str) -> (String, Option<DateTime<Utc>>) {
+	let lower = title.to_ascii_lowercase();
+	lower
+		.rfind(" due ")
+		.and_then(|i| {
+			match DateTime::<Utc>::parse(&title[i + 5..]) {
+				Ok((rest, due)) if rest.trim().is_empty() => Some((title[..i].to_string(), Some(due))),
+				_ => None,
+			}
+		})
+		.unwrap_or_else(|| (title.to_string(), None))
+}
+// (create | add | new) (a) task(:) <Title> (due <DateTime>)
+named!(
+	pub create_task<&str, Intent>,
+	do_parse!(
+		complete!(alt!(
+			tag_no_case!("create") | tag_no_case!("add") | tag_no_case!("new")
+		)) >>
+		opt!(complete!(preceded!(space1, terminated!(tag_no_case!("a"), space1)))) >>
+		opt!(space1) >>
+		complete!(tag_no_case!("task")) >>
+		opt!(complete!(tag!(":"))) >>
+		space1 >>
+		title: map!(rest, split_due) >>
+		(Intent::CreateTask {
+			title: title.0,
+			due: title.1,
+		})
+	)
+);
+// (what is the | get (the) | show (the)) price (of) <Symbol>
+named!(
+	pub get_price<&str, Intent>,
+	do_parse!(
+		opt!(complete!(alt!(
+			tag_no_case!("what is the ")
+				| tag_no_case!("what's the ")
+				| tag_no_case!("get the ")
+				| tag_no_case!("get ")
+				| tag_no_case!("show the ")
+				| tag_no_case!("show ")
+		))) >>
+		complete!(tag_no_case!("price")) >>
+		opt!(complete!(preceded!(space1, terminated!(tag_no_case!("of"), space1)))) >>
+		opt!(space1) >>
+		symbol: alphanumeric1 >>
+		(Intent::GetPrice {
+			symbol: symbol.to_uppercase(),
+		})
+	)
+);
+// <Question> (for <Purpose>) (since <DateTime>)
+named!(
+	pub query_budget<&str, Intent>,
+	do_parse!(
+		flow: complete!(alt!(
+			tag_no_case!("how much did i spend") => { |_| Flow::Expenses }
+				| tag_no_case!("how much did i get") => { |_| Flow::Earnings }
+				| tag_no_case!("show expenses") => { |_| Flow::Expenses }
+				| tag_no_case!("show earnings") => { |_| Flow::Earnings }
+				| tag_no_case!("show transactions") => { |_| Flow::All }
+				| tag_no_case!("what is my balance") => { |_| Flow::All }
+				| tag_no_case!("balance") => { |_| Flow::All }
+		)) >>
+		purpose: purpose >>
+		since: opt!(complete!(preceded!(
+			delimited!(space1, tag_no_case!("since"), space1),
+			DateTime::<Utc>::parse
+		))) >>
+		(Intent::QueryBudget {
+			flow,
+			purpose: purpose.map(ToString::to_string),
+			since,
+		})
+	)
+);
@@ -0,0 +1,315 @@
+mod grammar;
+mod suggest;
+pub use grammar::*;
+pub use suggest::*;
+
+use crate::parse::*;
+use std::fmt::{
+	self,
+	Display,
+};
+
+/// Direction of money flow a budget query asks about
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Flow {
+	Expenses,
+	Earnings,
+	All,
+}
+
+/// A classified sentence with its extracted slots
+#[derive(Clone, Debug, PartialEq)]
+pub enum Intent {
+	RecordTransaction {
+		date: Option<DateTime<Utc>>,
+		subject: String,
+		action: Action,
+		amount: Units,
+		counterpart: Option<String>,
+		purpose: Option<String>,
+	},
+	CreateTask {
+		title: String,
+		due: Option<DateTime<Utc>>,
+	},
+	GetPrice {
+		symbol: String,
+	},
+	QueryBudget {
+		flow: Flow,
+		purpose: Option<String>,
+		since: Option<DateTime<Utc>>,
+	},
+}
+impl Display for Intent {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::RecordTransaction {
+				subject,
+				action,
+				amount,
+				counterpart,
+				purpose,
+				..
+			} => {
+				write!(f, "record transaction: {} {:?} {}", subject, action, amount)?;
+				if let Some(counterpart) = counterpart {
+					write!(f, " with {}", counterpart)?;
+				}
+				if let Some(purpose) = purpose {
+					write!(f, " for {}", purpose)?;
+				}
+				Ok(())
+			}
+			Self::CreateTask { title, due } => {
+				write!(f, "create task: {}", title)?;
+				if let Some(due) = due {
+					write!(f, " due {}", due)?;
+				}
+				Ok(())
+			}
+			Self::GetPrice { symbol } => write!(f, "get price: {}", symbol),
+			Self::QueryBudget {
+				flow,
+				purpose,
+				since,
+			} => {
+				write!(f, "query budget: {:?}", flow)?;
+				if let Some(purpose) = purpose {
+					write!(f, " for {}", purpose)?;
+				}
+				if let Some(since) = since {
+					write!(f, " since {}", since)?;
+				}
+				Ok(())
+			}
+		}
+	}
+}
+
+pub type IntentParser = for<'a> fn(&'a str) -> IResult<&'a str, Intent>;
+
+/// A registered intent
+#[derive(Clone)]
+pub struct IntentDef {
+	pub name: &'static str,
+	/// words that identify the intent, used for suggestions
+	pub keywords: &'static [&'static str],
+	/// example sentence shown when the intent is suggested
+	pub example: &'static str,
+	pub parser: IntentParser,
+}
+impl fmt::Debug for IntentDef {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("IntentDef")
+			.field("name", &self.name)
+			.field("keywords", &self.keywords)
+			.field("example", &self.example)
+			.finish()
+	}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Suggestion {
+	pub intent: &'static str,
+	pub sentence: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Recognition {
+	Intent(Intent),
+	DidYouMean(Vec<Suggestion>),
+	Unknown,
+}
+impl Recognition {
+	pub fn intent(self) -> Option<Intent> {
+		match self {
+			Self::Intent(intent) => Some(intent),
+			_ => None,
+		}
+	}
+}
+impl Display for Recognition {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::Intent(intent) => write!(f, "{}", intent),
+			Self::DidYouMean(suggestions) => {
+				write!(f, "Did you mean ")?;
+				for (i, suggestion) in suggestions.iter().enumerate() {
+					if i > 0 {
+						write!(f, " or ")?;
+					}
+					write!(f, "\"{}\"", suggestion.sentence)?;
+				}
+				write!(f, "?")
+			}
+			Self::Unknown => write!(f, "Sorry, I did not understand that."),
+		}
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct IntentRegistry {
+	intents: Vec<IntentDef>,
+}
+impl Default for IntentRegistry {
+	fn default() -> Self {
+		let mut registry = Self::new();
+		registry
+			.register(IntentDef {
+				name: "record transaction",
+				keywords: &["got", "gave", "get", "give", "given", "gotten"],
+				example: "Today I gave 5€ to Bob for food",
+				parser: record_transaction,
+			})
+			.register(IntentDef {
+				name: "create task",
+				keywords: &["create", "add", "new", "task"],
+				example: "create task write report due tomorrow",
+				parser: create_task,
+			})
+			.register(IntentDef {
+				name: "get price",
+				keywords: &["price"],
+				example: "price of BTCUSDT",
+				parser: get_price,
+			})
+			.register(IntentDef {
+				name: "query budget",
+				keywords: &["spend", "expenses", "earnings", "transactions", "balance"],
+				example: "how much did I spend for food since 7 days ago",
+				parser: query_budget,
+			});
+		registry
+	}
+}
+impl IntentRegistry {
+	/// registry without any intents
+	pub fn new() -> Self {
+		Self {
+			intents: Vec::new(),
+		}
+	}
+	/// intents are tried in registration order
+	pub fn register(&mut self, intent: IntentDef) -> &mut Self {
+		self.intents.push(intent);
+		self
+	}
+	pub fn intents(&self) -> &[IntentDef] {
+		&self.intents
+	}
+	fn parse_complete(&self, text: &str) -> Option<Intent> {
+		self.intents.iter().find_map(|def| {
+			match (def.parser)(text) {
+				Ok((rest, intent)) if rest.trim().is_empty() => Some(intent),
+				_ => None,
+			}
+		})
+	}
+	pub fn recognize(&self, text: &str) -> Recognition {
+		let text = normalize(text);
+		if let Some(intent) = self.parse_complete(text) {
+			return Recognition::Intent(intent);
+		}
+		let suggestions = suggestions(&self.intents, text, |sentence| {
+			self.parse_complete(sentence).is_some()
+		});
+		if suggestions.is_empty() {
+			Recognition::Unknown
+		} else {
+			Recognition::DidYouMean(suggestions)
+		}
+	}
+}
+/// strip surrounding whitespace and closing punctuation
+fn normalize(text: &str) -> &str {
+	text.trim()
+		.trim_end_matches(|c| c == '.' || c == '!' || c == '?')
+		.trim_end()
+}
+
+mod tests {
+	#[allow(unused)]
+	use super::*;
+
+	#[test]
+	fn record_transaction() {
+		let intent = IntentRegistry::default()
+			.recognize("Today I gave 5€ to Bob for food.")
+			.intent()
+			.unwrap();
+		match intent {
+			Intent::RecordTransaction {
+				date,
+				subject,
+				action,
+				amount,
+				counterpart,
+				purpose,
+			} => {
+				assert_eq!(date.map(|d| d.date()), Some(Utc::today()));
+				assert_eq!(subject, "I");
+				assert_eq!(action, Action::Give);
+				assert_eq!(amount, 5);
+				assert_eq!(counterpart, Some("Bob".to_string()));
+				assert_eq!(purpose, Some("food".to_string()));
+			}
+			_ => panic!("wrong intent {:?}", intent),
+		}
+	}
+	#[test]
+	fn create_task() {
+		let registry = IntentRegistry::default();
+		assert_eq!(
+			registry.recognize("add a task: water the plants").intent(),
+			Some(Intent::CreateTask {
+				title: "water the plants".into(),
+				due: None,
+			})
+		);
+		assert_eq!(
+			registry.recognize("create task write report due tomorrow").intent(),
+			Some(Intent::CreateTask {
+				title: "write report".into(),
+				due: Some((Utc::today() + Duration::days(1)).and_hms(0, 0, 0)),
+			})
+		);
+	}
+	#[test]
+	fn get_price() {
+		let registry = IntentRegistry::default();
+		for text in &["price of btcusdt", "What is the price of BTCUSDT?", "price BTCUSDT"] {
+			assert_eq!(
+				registry.recognize(text).intent(),
+				Some(Intent::GetPrice {
+					symbol: "BTCUSDT".into(),
+				})
+			);
+		}
+	}
+	#[test]
+	fn query_budget() {
+		assert_eq!(
+			IntentRegistry::default()
+				.recognize("how much did I spend for food since yesterday")
+				.intent(),
+			Some(Intent::QueryBudget {
+				flow: Flow::Expenses,
+				purpose: Some("food".into()),
+				since: Some((Utc::today() - Duration::days(1)).and_hms(0, 0, 0)),
+			})
+		);
+	}
+	#[test]
+	fn did_you_mean() {
+		let registry = IntentRegistry::default();
+		assert_eq!(
+			registry.recognize("prise of ETHBTC"),
+			Recognition::DidYouMean(vec![Suggestion {
+				intent: "get price",
+				sentence: "price of ETHBTC".into(),
+			}])
+		);
+		assert_eq!(registry.recognize("hello there"), Recognition::Unknown);
+	}
+}
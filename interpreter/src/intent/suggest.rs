@@ -0,0 +1,80 @@
+use crate::intent::*;
+
+/// Levenshtein distance between two words, ignoring case
+pub fn edit_distance(a: &str, b: &str) -> usize {
+	let a: Vec<char> = a.to_lowercase().chars().collect();
+	let b: Vec<char> = b.to_lowercase().chars().collect();
+	let mut prev: Vec<usize> = (0..=b.len()).collect();
+	for (i, ca) in a.iter().enumerate() {
+		let mut curr = vec![i + 1; b.len() + 1];
+		for (j, cb) in b.iter().enumerate() {
+			let substitution = prev[j] + if ca == cb { 0 } else { 1 };
+			curr[j + 1] = substitution.min(prev[j + 1] + 1).min(curr[j] + 1);
+		}
+		prev = curr;
+	}
+	prev[b.len()]
+}
+/// maximum edit distance at which a word counts as a misspelled keyword
+fn tolerance(word: &str) -> usize {
+	match word.chars().count() {
+		0..=2 => 0,
+		3..=4 => 1,
+		_ => 2,
+	}
+}
+/// Suggest sentences for intents whose keywords are close to the words in `text`.
+///
+/// Misspelled keywords are replaced and the corrected sentence is kept if `parses`
+/// accepts it. Intents whose keywords appear without a parsable sentence are
+/// suggested with their example sentence.
+pub fn suggestions<F>(intents: &[IntentDef], text: &str, parses: F) -> Vec<Suggestion>
+	where F: Fn(&str) -> bool
+{
+	let words: Vec<&str> = text.split_whitespace().collect();
+	let mut suggestions: Vec<Suggestion> = Vec::new();
+	for def in intents {
+		let mut corrected = None;
+		let mut mentioned = false;
+		for (i, word) in words.iter().enumerate() {
+			for keyword in def.keywords {
+				let distance = edit_distance(word, keyword);
+				if distance == 0 {
+					mentioned = true;
+				} else if distance <= tolerance(word) && corrected.is_none() {
+					let mut fixed = words.clone();
+					fixed[i] = keyword;
+					let sentence = fixed.join(" ");
+					if parses(&sentence) {
+						corrected = Some(sentence);
+					}
+				}
+			}
+		}
+		let sentence = match corrected {
+			Some(sentence) => sentence,
+			None if mentioned => def.example.to_string(),
+			None => continue,
+		};
+		if !suggestions.iter().any(|s| s.sentence == sentence) {
+			suggestions.push(Suggestion {
+				intent: def.name,
+				sentence,
+			});
+		}
+	}
+	suggestions
+}
+
+mod tests {
+	#[allow(unused)]
+	use super::*;
+	#[test]
+	fn distance() {
+		assert_eq!(edit_distance("price", "price"), 0);
+		assert_eq!(edit_distance("Prise", "price"), 1);
+		assert_eq!(edit_distance("gave", "give"), 1);
+		assert_eq!(edit_distance("", "task"), 4);
+		assert_eq!(edit_distance("kitten", "sitting"), 3);
+	}
+}
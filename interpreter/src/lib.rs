@@ -8,6 +8,7 @@ extern crate termion;
 pub mod shell;
 
 pub mod parse;
+pub mod intent;
 //pub mod text;
 //pub mod sentence;
 //pub mod graph;
@@ -18,6 +18,7 @@ pub trait Parse<'a>: Sized {
 	fn parse(input: &'a str) -> IResult<&'a str, Self>;
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Action {
 	Give,
 	Get,
@@ -0,0 +1,72 @@
+//! Protection against cross-site request forgery
+//!
+//! Responses set a random token in the `csrf` cookie, which scripts of the
+//! same origin read and repeat in the `X-CSRF-Token` header. Other sites can
+//! make a browser send the cookie, but cannot read it, so requests changing
+//! state are rejected unless the header matches the token. Requests with an
+//! `Authorization` header are not checked, as browsers only send them to other
+//! origins after a CORS preflight.
+use app_model::auth::server::password::constant_time_eq;
+pub use database_table::{
+    CSRF_COOKIE,
+    CSRF_HEADER,
+};
+
+#[cfg(feature = "tide")]
+pub mod tide;
+#[cfg(feature = "tide")]
+pub use self::tide::*;
+#[cfg(feature = "rocket")]
+pub mod rocket;
+#[cfg(feature = "rocket")]
+pub use self::rocket::*;
+
+/// key of the synchronizer token in a tide session
+pub const CSRF_SESSION_KEY: &str = "csrf";
+
+pub fn generate_token() -> String {
+    crate::generate_secret()
+}
+/// Whether requests with `method` must not change state
+pub fn is_safe_method(method: &str) -> bool {
+    matches!(
+        method.to_ascii_uppercase().as_str(),
+        "GET" | "HEAD" | "OPTIONS" | "TRACE"
+    )
+}
+/// Whether a request with `method` needs a valid token to be accepted
+pub fn needs_token(method: &str, authorized: bool) -> bool {
+    !is_safe_method(method) && !authorized
+}
+/// Whether the token `sent` in the header matches the `expected` token
+pub fn verify_token(expected: Option<&str>, sent: Option<&str>) -> bool {
+    match (expected, sent) {
+        (Some(expected), Some(sent)) if !expected.is_empty() =>
+            constant_time_eq(expected.as_bytes(), sent.as_bytes()),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn tokens() {
+        let token = generate_token();
+        assert_eq!(token.len(), 64);
+        assert_ne!(token, generate_token());
+        assert!(verify_token(Some(&token), Some(&token.clone())));
+        assert!(!verify_token(Some(&token), Some(&generate_token())));
+        assert!(!verify_token(Some(&token), None));
+        assert!(!verify_token(None, Some(&token)));
+        assert!(!verify_token(Some(""), Some("")));
+    }
+    #[test]
+    fn methods() {
+        assert!(!needs_token("GET", false));
+        assert!(!needs_token("options", false));
+        assert!(needs_token("POST", false));
+        assert!(needs_token("DELETE", false));
+        assert!(!needs_token("PATCH", true));
+    }
+}
@@ -0,0 +1,103 @@
+use super::{
+    generate_token,
+    needs_token,
+    verify_token,
+    CSRF_COOKIE,
+    CSRF_HEADER,
+};
+use ::rocket::{
+    fairing::{
+        Fairing,
+        Info,
+        Kind,
+    },
+    handler,
+    http::{
+        uri::Origin,
+        Cookie,
+        Method,
+        SameSite,
+        Status,
+    },
+    Data,
+    Request,
+    Response,
+    Rocket,
+    Route,
+};
+#[allow(unused)]
+use tracing::{
+    debug,
+    error,
+    warn,
+};
+
+/// path of the route answering rejected requests
+const FORBIDDEN_PATH: &str = "/csrf/forbidden";
+
+fn forbidden<'r>(_: &'r Request, _: Data) -> handler::Outcome<'r> {
+    handler::Outcome::failure(Status::Forbidden)
+}
+/// Sets the token in a cookie and rejects requests changing state unless
+/// they repeat it in the header (double-submit cookie)
+///
+/// Fairings can not answer requests, so rejected requests are rerouted to a
+/// route responding with `403 Forbidden`.
+#[derive(Clone, Debug)]
+pub struct CsrfFairing {
+    secure: bool,
+}
+impl Default for CsrfFairing {
+    fn default() -> Self {
+        Self {
+            secure: true,
+        }
+    }
+}
+impl CsrfFairing {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Whether the cookie is only sent over https
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+    fn is_allowed(request: &Request) -> bool {
+        let authorized = request.headers().contains("Authorization");
+        if !needs_token(request.method().as_str(), authorized) {
+            return true;
+        }
+        let expected = request.cookies().get(CSRF_COOKIE).map(|cookie| cookie.value().to_string());
+        verify_token(expected.as_deref(), request.headers().get_one(CSRF_HEADER))
+    }
+}
+impl Fairing for CsrfFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "CSRF token",
+            kind: Kind::Attach | Kind::Request | Kind::Response,
+        }
+    }
+    fn on_attach(&self, rocket: Rocket) -> Result<Rocket, Rocket> {
+        Ok(rocket.mount(FORBIDDEN_PATH, vec![Route::new(Method::Get, "/", forbidden)]))
+    }
+    fn on_request(&self, request: &mut Request, _: &Data) {
+        if !Self::is_allowed(request) {
+            warn!("Rejecting {} {} without CSRF token", request.method(), request.uri());
+            request.set_method(Method::Get);
+            request.set_uri(Origin::parse(FORBIDDEN_PATH).expect("invalid forbidden path"));
+        }
+    }
+    fn on_response(&self, request: &Request, response: &mut Response) {
+        if request.cookies().get(CSRF_COOKIE).is_none() {
+            let cookie = Cookie::build(CSRF_COOKIE, generate_token())
+                .path("/")
+                .secure(self.secure)
+                .http_only(false)
+                .same_site(SameSite::Strict)
+                .finish();
+            response.adjoin_header(cookie);
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use super::{
+    generate_token,
+    needs_token,
+    verify_token,
+    CSRF_COOKIE,
+    CSRF_HEADER,
+    CSRF_SESSION_KEY,
+};
+use ::tide::{
+    http::cookies::{
+        Cookie,
+        SameSite,
+    },
+    Middleware,
+    Next,
+    Request,
+    Response,
+    StatusCode,
+};
+#[allow(unused)]
+use tracing::{
+    debug,
+    error,
+    warn,
+};
+
+/// Keeps a synchronizer token in the tide session and rejects requests
+/// changing state without it
+///
+/// Requests without a session are checked against the token of their cookie
+/// instead, so the token does not create a session for every visitor. It is
+/// stored in the session once a handler changes it, e.g. when logging in.
+/// Needs to be added after the `SessionMiddleware`.
+#[derive(Clone, Debug)]
+pub struct CsrfMiddleware {
+    secure: bool,
+}
+impl Default for CsrfMiddleware {
+    fn default() -> Self {
+        Self {
+            secure: true,
+        }
+    }
+}
+impl CsrfMiddleware {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Whether the cookie is only sent over https
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+    fn cookie(&self, token: String) -> Cookie<'static> {
+        Cookie::build(CSRF_COOKIE, token)
+            .path("/")
+            .secure(self.secure)
+            .http_only(false)
+            .same_site(SameSite::Strict)
+            .finish()
+    }
+}
+#[::tide::utils::async_trait]
+impl<State> Middleware<State> for CsrfMiddleware
+    where State: Clone + Send + Sync + 'static,
+{
+    async fn handle(&self, request: Request<State>, next: Next<'_, State>) -> ::tide::Result {
+        let mut session = request.session().clone();
+        let stored = session.get::<String>(CSRF_SESSION_KEY);
+        let cookie = request.cookie(CSRF_COOKIE).map(|cookie| cookie.value().to_string());
+        // without a session the token is only kept in the cookie
+        let expected = stored.clone().or_else(|| cookie.clone());
+        let authorized = request.header("authorization").is_some();
+        if needs_token(request.method().as_ref(), authorized) {
+            let sent = request.header(CSRF_HEADER).map(|values| values.last().as_str());
+            if !verify_token(expected.as_deref(), sent) {
+                warn!("Rejecting {} {} without CSRF token", request.method(), request.url().path());
+                return Ok(Response::new(StatusCode::Forbidden));
+            }
+        }
+        let mut response = next.run(request).await;
+        let token = expected.unwrap_or_else(generate_token);
+        // the session shares its data with the request's, so sessions the
+        // handler started, e.g. by logging in, keep the token from now on
+        if stored.is_none() && session.data_changed() && !session.is_destroyed() {
+            session.insert(CSRF_SESSION_KEY, &token)?;
+        }
+        if cookie.as_deref() != Some(token.as_str()) {
+            response.insert_cookie(self.cookie(token));
+        }
+        Ok(response)
+    }
+}
@@ -1,27 +1,25 @@
-use async_std::sync::{
-    Arc,
-    RwLock,
-    RwLockReadGuard,
-    RwLockWriteGuard,
-};
+//! Sessions of users in a pluggable `SessionStore`
+//!
+//! ```rust,ignore
+//! set_session_service(
+//!     SessionService::new(FileStore::open("sessions.jsonl")?)
+//!         .with_config(SessionConfig::default().ttl(Duration::hours(1)))
+//! );
+//! // on the runtime of the server
+//! tokio::spawn(run_cleaner());
+//! let session = session_service().create(Some(user));
+//! // log out on all devices
+//! session_service().end_user_sessions(user);
+//! ```
+use app_model::auth::server::random_hex;
 use chrono::{
-    DateTime,
     Duration,
     Utc,
 };
-use sha2::{
-    Digest,
-    Sha256,
-};
-use std::collections::HashMap;
-// TODO: Replace with more general Account type
-use app_model::User;
 use lazy_static::lazy_static;
-use rql::*;
-use std::collections::VecDeque;
-use std::ops::{
-    Deref,
-    DerefMut,
+use std::sync::{
+    Arc,
+    RwLock,
 };
 #[allow(unused)]
 use tracing::{
@@ -30,124 +28,60 @@ use tracing::{
     warn,
 };
 
+pub mod csrf;
+pub use csrf::*;
+pub mod session;
+pub use session::*;
+pub mod service;
+pub use service::*;
+pub mod store;
+pub use store::*;
+
 lazy_static! {
-    static ref SESSIONS: Arc<RwLock<SessionMap>> = Arc::new(RwLock::new(SessionMap::default()));
+    static ref SESSIONS: RwLock<Arc<SessionService>> = RwLock::new(Arc::new(SessionService::new(MemoryStore::new())));
 }
-async fn sessions() -> RwLockReadGuard<'static, SessionMap> {
-    SESSIONS.read().await
+/// Replace the service, which keeps sessions in memory by default
+pub fn set_session_service(service: SessionService) {
+    *SESSIONS.write().expect("SessionService poisoned") = Arc::new(service);
 }
-async fn sessions_mut() -> RwLockWriteGuard<'static, SessionMap> {
-    SESSIONS.write().await
+pub fn session_service() -> Arc<SessionService> {
+    SESSIONS.read().expect("SessionService poisoned").clone()
 }
-const SECRET: &'static str = "AKJDASHDKJAHFAJDFKJHFLASHFLJAKJFHA";
-/// time until session expires
-pub const EXPIRATION_SECS: u32 = 2;
-/// time session remains in memory after expiration
+/// default time until an unused session expires
+pub const EXPIRATION_SECS: u32 = 30 * 60;
+/// default time an expired session can still be renewed
 pub const STALE_SECS: u32 = 60;
+/// longest time the cleaner sleeps, as sessions may change in the meantime
+const CLEANER_INTERVAL_SECS: i64 = 60;
 
-pub type SessionID = String;
-type InternalSessionMap = HashMap<SessionID, Session>;
+/// bytes of entropy in secrets, session ids and CSRF tokens
+const SECRET_BYTES: usize = 32;
 
-#[derive(Debug, Default)]
-pub struct SessionMap {
-    sessions: InternalSessionMap,
-    invalidations: VecDeque<(SessionID, DateTime<Utc>)>,
-}
-impl Deref for SessionMap {
-    type Target = InternalSessionMap;
-    fn deref(&self) -> &Self::Target {
-        &self.sessions
-    }
-}
-impl DerefMut for SessionMap {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.sessions
-    }
-}
+/// Random hex string from the random number generator of the operating system
 pub fn generate_secret() -> String {
-    let mut h = Sha256::new();
-    let timestamp = Utc::now().timestamp_nanos();
-    h.update(format!("{}{}", timestamp, SECRET));
-    let out = h.finalize();
-    hex::encode(out)
+    random_hex(SECRET_BYTES)
 }
-impl SessionMap {
-    fn new_id() -> SessionID {
-        generate_secret()
-    }
-    fn get_session(&self, id: &SessionID) -> Option<&Session> {
-        self.sessions.get(id)
-    }
-    fn remove_session(&mut self, id: &SessionID) {
-        debug!("Removing session {}", id);
-        self.sessions.remove(id);
-    }
-    fn create_session(&mut self) -> Session {
-        let id = Self::new_id();
-        let session = Session::new(id.clone());
-        self.sessions.insert(id.clone(), session.clone());
-        self.invalidations
-            .push_back((id, session.invalidation_time().clone()));
-        session
-    }
-}
-pub async fn create_session() -> Session {
-    sessions_mut().await.create_session()
+pub fn create_session() -> Session {
+    session_service().create(None)
 }
-pub async fn get_session(id: &SessionID) -> Option<Session> {
-    sessions().await.get_session(id).map(Clone::clone)
+pub fn get_session(id: &str) -> Option<Session> {
+    session_service().get(id)
 }
+/// Remove invalid sessions, waking up when the next session becomes invalid
 pub async fn run_cleaner() {
     loop {
-        let invalidation = sessions_mut().await.invalidations.pop_front().clone();
-        if let Some((next, time)) = invalidation {
-            let duration = time - Utc::now();
-            let duration = tokio::time::Duration::from_millis(duration.num_milliseconds() as u64);
-            tokio::time::sleep(duration).await;
-            sessions_mut().await.remove_session(&next);
-        }
-    }
-}
-#[derive(Debug, Clone)]
-pub struct Session {
-    pub id: SessionID,
-    user: Option<Id<User>>,
-    created: DateTime<Utc>,
-}
-
-impl Session {
-    pub fn expiration_time(&self) -> DateTime<Utc> {
-        self.created + Duration::seconds(EXPIRATION_SECS.into())
-    }
-    pub fn invalidation_time(&self) -> DateTime<Utc> {
-        self.expiration_time() + Duration::seconds(STALE_SECS.into())
-    }
-    pub fn new(id: SessionID) -> Self {
-        Self {
-            id,
-            user: None,
-            created: Utc::now(),
-        }
-    }
-    pub fn with_user(id: SessionID, user: Id<User>) -> Self {
-        Self {
-            user: Some(user),
-            ..Self::new(id)
+        let service = session_service();
+        let now = Utc::now();
+        let removed = service.clean(now);
+        if removed > 0 {
+            debug!("Removed {} invalid sessions", removed);
         }
-    }
-    /// session is stale in [expiration, invalidation)
-    pub fn is_stale(&self) -> bool {
-        self.is_valid() && self.expiration_time() <= Utc::now()
-    }
-    /// session is valid in [creation, invalidation)
-    pub fn is_valid(&self) -> bool {
-        Utc::now() < self.invalidation_time()
-    }
-    pub fn cookie_string(&self) -> String {
-        format!(
-            "session={};Max-Age={};Secure;HttpOnly",
-            self.id,
-            (self.invalidation_time() - Utc::now()).num_seconds(),
-        )
+        // sessions created or extended while sleeping are checked on the next wakeup
+        let interval = Duration::seconds(CLEANER_INTERVAL_SECS);
+        let sleep = service.next_invalidation()
+            .map(|next| (next - now).min(interval))
+            .unwrap_or(interval)
+            .max(Duration::zero());
+        futures_timer::Delay::new(sleep.to_std().unwrap_or_default()).await;
     }
 }
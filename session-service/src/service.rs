@@ -0,0 +1,241 @@
+use crate::{
+    session::{
+        Session,
+        SessionConfig,
+    },
+    store::SessionStore,
+};
+use app_model::User;
+use chrono::{
+    DateTime,
+    Utc,
+};
+use database_table::Id;
+#[allow(unused)]
+use tracing::{
+    debug,
+    error,
+    warn,
+};
+
+/// Creates, extends and ends sessions in a `SessionStore`
+pub struct SessionService {
+    store: Box<dyn SessionStore>,
+    config: SessionConfig,
+}
+impl std::fmt::Debug for SessionService {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("SessionService")
+            .field("config", &self.config)
+            .finish()
+    }
+}
+impl SessionService {
+    pub fn new<S: SessionStore + 'static>(store: S) -> Self {
+        Self {
+            store: Box::new(store),
+            config: SessionConfig::default(),
+        }
+    }
+    pub fn with_config(mut self, config: SessionConfig) -> Self {
+        self.config = config;
+        self
+    }
+    pub fn config(&self) -> &SessionConfig {
+        &self.config
+    }
+    pub fn store(&self) -> &dyn SessionStore {
+        &*self.store
+    }
+    pub fn create(&self, user: Option<Id<User>>) -> Session {
+        self.create_at(user, Utc::now())
+    }
+    fn create_at(&self, user: Option<Id<User>>, now: DateTime<Utc>) -> Session {
+        let session = Session::new_at(crate::generate_secret(), user, &self.config, now);
+        self.store.store(&session);
+        session
+    }
+    /// A valid session, extended if sessions are sliding
+    pub fn get(&self, id: &str) -> Option<Session> {
+        self.get_at(id, Utc::now())
+    }
+    fn get_at(&self, id: &str, now: DateTime<Utc>) -> Option<Session> {
+        let mut session = self.store.load(id)?;
+        if !session.is_valid_at(now) {
+            self.store.remove(id);
+            return None;
+        }
+        if session.touch(&self.config, now) {
+            self.store.store(&session);
+        }
+        Some(session)
+    }
+    /// Replace a valid or stale session with a new session of the same user
+    pub fn renew(&self, id: &str) -> Option<Session> {
+        self.renew_at(id, Utc::now())
+    }
+    fn renew_at(&self, id: &str, now: DateTime<Utc>) -> Option<Session> {
+        let session = self.store.remove(id)?;
+        if session.is_valid_at(now) {
+            Some(self.create_at(session.user(), now))
+        } else {
+            None
+        }
+    }
+    /// Keep `data` of `user` in the valid session `id`, which is created if it does not exist
+    pub fn store_data(&self, id: &str, user: Option<Id<User>>, data: String) -> Session {
+        self.store_data_at(id, user, data, Utc::now())
+    }
+    fn store_data_at(&self, id: &str, user: Option<Id<User>>, data: String, now: DateTime<Utc>) -> Session {
+        let mut session = match self.store.load(id) {
+            Some(session) if session.is_valid_at(now) => session,
+            _ => Session::new_at(id.to_string(), None, &self.config, now),
+        };
+        session.set_user(user);
+        session.set_data(data);
+        self.store.store(&session);
+        session
+    }
+    pub fn end(&self, id: &str) -> Option<Session> {
+        debug!("Removing session {}", id);
+        self.store.remove(id)
+    }
+    /// Valid sessions of `user`, e.g. to list the devices they are logged in on
+    pub fn user_sessions(&self, user: Id<User>) -> Vec<Session> {
+        let now = Utc::now();
+        let mut sessions: Vec<Session> = self.store
+            .user_sessions(user)
+            .into_iter()
+            .filter(|session| session.is_valid_at(now))
+            .collect();
+        sessions.sort_by_key(Session::created);
+        sessions
+    }
+    /// Log `user` out everywhere, returning the number of ended sessions
+    pub fn end_user_sessions(&self, user: Id<User>) -> usize {
+        self.store.remove_user(user).len()
+    }
+    /// Remove the sessions invalid at `now`
+    pub fn clean(&self, now: DateTime<Utc>) -> usize {
+        self.store.remove_invalid(now)
+    }
+    pub fn next_invalidation(&self) -> Option<DateTime<Utc>> {
+        self.store.next_invalidation()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::*;
+    use chrono::Duration;
+    fn config() -> SessionConfig {
+        SessionConfig::default()
+            .ttl(Duration::minutes(10))
+            .stale(Duration::minutes(1))
+            .max_age(Duration::minutes(30))
+    }
+    #[test]
+    fn sliding_expiration() {
+        let service = SessionService::new(MemoryStore::new()).with_config(config());
+        let now = Utc::now();
+        let session = service.create_at(None, now);
+        assert_eq!(session.expiration_time(), now + Duration::minutes(10));
+        // not extended while more than half of the ttl is left
+        let used = service.get_at(&session.id, now + Duration::minutes(4)).unwrap();
+        assert_eq!(used.expiration_time(), session.expiration_time());
+        let used = service.get_at(&session.id, now + Duration::minutes(6)).unwrap();
+        assert_eq!(used.expiration_time(), now + Duration::minutes(16));
+        // but not beyond max_age
+        service.get_at(&session.id, now + Duration::minutes(12)).unwrap();
+        service.get_at(&session.id, now + Duration::minutes(18)).unwrap();
+        let used = service.get_at(&session.id, now + Duration::minutes(26)).unwrap();
+        assert_eq!(used.expiration_time(), now + Duration::minutes(30));
+        // stale sessions can be renewed, but are not extended
+        let stale = service.get_at(&session.id, now + Duration::seconds(30 * 60 + 30)).unwrap();
+        assert!(stale.is_stale_at(now + Duration::seconds(30 * 60 + 30)));
+        let renewed = service.renew_at(&session.id, now + Duration::seconds(30 * 60 + 30)).unwrap();
+        assert_ne!(renewed.id, session.id);
+        assert!(service.get_at(&session.id, now).is_none());
+        assert!(service.get_at(&renewed.id, now + Duration::minutes(32)).is_some());
+    }
+    #[test]
+    fn clean_out_of_order() {
+        let service = SessionService::new(MemoryStore::new()).with_config(config());
+        let now = Utc::now();
+        let first = service.create_at(None, now);
+        let second = service.create_at(None, now + Duration::minutes(1));
+        // the first session is extended past the second
+        service.get_at(&first.id, now + Duration::minutes(6)).unwrap();
+        assert_eq!(service.next_invalidation(), Some(second.invalidation_time()));
+        assert_eq!(service.clean(second.invalidation_time()), 1);
+        assert!(service.store().load(&first.id).is_some());
+        assert!(service.store().load(&second.id).is_none());
+    }
+    #[test]
+    fn end_user_sessions() {
+        let service = SessionService::new(MemoryStore::new());
+        let user = Id::new();
+        let first = service.create(Some(user));
+        let second = service.create(Some(user));
+        let other = service.create(Some(Id::new()));
+        let ids: Vec<_> = service.user_sessions(user).into_iter().map(|session| session.id).collect();
+        assert_eq!(ids.len(), 2);
+        assert!(ids.contains(&first.id) && ids.contains(&second.id));
+        // sessions of web frameworks get the user stored with their data
+        let framework = service.store_data("framework", None, "{}".into());
+        assert_eq!(framework.user(), None);
+        service.store_data("framework", Some(user), "{}".into());
+        assert_eq!(service.user_sessions(user).len(), 3);
+        assert_eq!(service.end_user_sessions(user), 3);
+        assert!(service.user_sessions(user).is_empty());
+        assert!(service.get(&other.id).is_some());
+    }
+    #[test]
+    fn file_store() {
+        let path = std::env::temp_dir().join(format!("sessions_{}.jsonl", std::process::id()));
+        let user = Id::new();
+        let session = {
+            let service = SessionService::new(FileStore::open(&path).unwrap());
+            service.create(Some(user))
+        };
+        // sessions survive restarts
+        let service = SessionService::new(FileStore::open(&path).unwrap());
+        assert_eq!(service.get(&session.id), Some(session.clone()));
+        service.end(&session.id);
+        assert!(FileStore::open(&path).unwrap().load(&session.id).is_none());
+        std::fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn file_store_compaction() {
+        let path = std::env::temp_dir().join(format!("sessions_compaction_{}.jsonl", std::process::id()));
+        let service = SessionService::new(FileStore::open(&path).unwrap());
+        let session = service.create(None);
+        for i in 0..200 {
+            service.store_data(&session.id, None, i.to_string());
+        }
+        // the file is rewritten instead of growing with every change
+        let lines = std::fs::read_to_string(&path).unwrap().lines().count();
+        assert!(lines < 100, "{} lines", lines);
+        let store = FileStore::open(&path).unwrap();
+        assert_eq!(store.load(&session.id).and_then(|session| session.data().cloned()), Some("199".to_string()));
+        std::fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn file_store_concurrent_writes() {
+        let path = std::env::temp_dir().join(format!("sessions_concurrent_{}.jsonl", std::process::id()));
+        let service = std::sync::Arc::new(SessionService::new(FileStore::open(&path).unwrap()));
+        let threads: Vec<_> = (0..8)
+            .map(|i| {
+                let service = service.clone();
+                std::thread::spawn(move || service.store_data(&format!("session{}", i), None, i.to_string()))
+            })
+            .collect();
+        let sessions: Vec<_> = threads.into_iter().map(|thread| thread.join().unwrap()).collect();
+        let store = FileStore::open(&path).unwrap();
+        for session in sessions {
+            assert_eq!(store.load(&session.id), Some(session));
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
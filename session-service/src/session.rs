@@ -0,0 +1,152 @@
+use app_model::User;
+use chrono::{
+    DateTime,
+    Duration,
+    Utc,
+};
+use database_table::{
+    Id,
+    Updatable,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+pub type SessionID = String;
+
+/// Lifetimes of sessions
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionConfig {
+    /// time until an unused session expires
+    pub ttl: Duration,
+    /// time an expired session can still be renewed before it is removed
+    pub stale: Duration,
+    /// time after creation when a session expires even if it is used
+    pub max_age: Option<Duration>,
+    /// extend sessions by `ttl` when they are used
+    pub sliding: bool,
+}
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            ttl: Duration::seconds(crate::EXPIRATION_SECS.into()),
+            stale: Duration::seconds(crate::STALE_SECS.into()),
+            max_age: None,
+            sliding: true,
+        }
+    }
+}
+impl SessionConfig {
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+    pub fn stale(mut self, stale: Duration) -> Self {
+        self.stale = stale;
+        self
+    }
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+    pub fn sliding(mut self, sliding: bool) -> Self {
+        self.sliding = sliding;
+        self
+    }
+    /// Expiration of a session created at `created` and used at `now`
+    fn expiration(&self, created: DateTime<Utc>, now: DateTime<Utc>) -> DateTime<Utc> {
+        let expires = now + self.ttl;
+        match self.max_age {
+            Some(max_age) => expires.min(created + max_age),
+            None => expires,
+        }
+    }
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Updatable)]
+pub struct Session {
+    pub id: SessionID,
+    user: Option<Id<User>>,
+    created: DateTime<Utc>,
+    expires: DateTime<Utc>,
+    invalidates: DateTime<Utc>,
+    /// serialized session of a web framework, see `TideSessionStore`
+    #[serde(default)]
+    data: Option<String>,
+}
+impl Session {
+    pub fn new(id: SessionID, config: &SessionConfig) -> Self {
+        Self::new_at(id, None, config, Utc::now())
+    }
+    pub fn with_user(id: SessionID, user: Id<User>, config: &SessionConfig) -> Self {
+        Self::new_at(id, Some(user), config, Utc::now())
+    }
+    pub(crate) fn new_at(id: SessionID, user: Option<Id<User>>, config: &SessionConfig, now: DateTime<Utc>) -> Self {
+        let expires = config.expiration(now, now);
+        Self {
+            id,
+            user,
+            created: now,
+            expires,
+            invalidates: expires + config.stale,
+            data: None,
+        }
+    }
+    pub fn user(&self) -> Option<Id<User>> {
+        self.user
+    }
+    pub fn created(&self) -> DateTime<Utc> {
+        self.created
+    }
+    pub fn expiration_time(&self) -> DateTime<Utc> {
+        self.expires
+    }
+    pub fn invalidation_time(&self) -> DateTime<Utc> {
+        self.invalidates
+    }
+    pub fn data(&self) -> Option<&String> {
+        self.data.as_ref()
+    }
+    pub(crate) fn set_data(&mut self, data: String) {
+        self.data = Some(data);
+    }
+    pub(crate) fn set_user(&mut self, user: Option<Id<User>>) {
+        self.user = user;
+    }
+    /// Extend the session used at `now`, returning whether it changed.
+    /// Sessions are only extended when less than half of `ttl` is left,
+    /// so stores are not written on every request.
+    pub(crate) fn touch(&mut self, config: &SessionConfig, now: DateTime<Utc>) -> bool {
+        if !config.sliding || self.expires <= now || self.expires - now > config.ttl / 2 {
+            return false;
+        }
+        let expires = config.expiration(self.created, now);
+        if expires <= self.expires {
+            return false;
+        }
+        self.expires = expires;
+        self.invalidates = expires + config.stale;
+        true
+    }
+    /// session is stale in [expiration, invalidation)
+    pub fn is_stale(&self) -> bool {
+        self.is_stale_at(Utc::now())
+    }
+    pub fn is_stale_at(&self, time: DateTime<Utc>) -> bool {
+        self.is_valid_at(time) && self.expires <= time
+    }
+    /// session is valid in [creation, invalidation)
+    pub fn is_valid(&self) -> bool {
+        self.is_valid_at(Utc::now())
+    }
+    pub fn is_valid_at(&self, time: DateTime<Utc>) -> bool {
+        time < self.invalidates
+    }
+    pub fn cookie_string(&self) -> String {
+        format!(
+            "session={};Max-Age={};Secure;HttpOnly",
+            self.id,
+            (self.invalidates - Utc::now()).num_seconds(),
+        )
+    }
+}
@@ -0,0 +1,70 @@
+use super::SessionStore;
+use crate::session::Session;
+use app_model::{
+    auth::server::password::constant_time_eq,
+    User,
+};
+use database_table::{
+    Database,
+    DatabaseTable,
+    Id,
+    Updatable,
+};
+use std::marker::PhantomData;
+
+/// Sessions stored in the table of `Session` in the schema `D`
+///
+/// Sessions are looked up by comparing the ids of all rows in constant time.
+#[derive(Debug)]
+pub struct DatabaseStore<D> {
+    _db: PhantomData<fn() -> D>,
+}
+impl<D> Default for DatabaseStore<D> {
+    fn default() -> Self {
+        Self {
+            _db: PhantomData,
+        }
+    }
+}
+impl<D> DatabaseStore<D>
+    where D: Database<'static, Session>,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+impl<D> SessionStore for DatabaseStore<D>
+    where D: Database<'static, Session>,
+{
+    fn load(&self, id: &str) -> Option<Session> {
+        <Session as DatabaseTable<'static, D>>::find(|session| constant_time_eq(session.id.as_bytes(), id.as_bytes()))
+            .map(|entry| entry.into_inner())
+    }
+    fn store(&self, session: &Session) {
+        match <Session as DatabaseTable<'static, D>>::find(|stored| constant_time_eq(stored.id.as_bytes(), session.id.as_bytes())) {
+            Some(entry) => {
+                let update = entry.data().diff(session);
+                <Session as DatabaseTable<'static, D>>::update(entry.id, update);
+            },
+            None => {
+                <Session as DatabaseTable<'static, D>>::insert(session.clone());
+            },
+        }
+    }
+    fn remove(&self, id: &str) -> Option<Session> {
+        <Session as DatabaseTable<'static, D>>::find(|session| constant_time_eq(session.id.as_bytes(), id.as_bytes()))
+            .and_then(|entry| <Session as DatabaseTable<'static, D>>::delete(entry.id))
+    }
+    fn all(&self) -> Vec<Session> {
+        <Session as DatabaseTable<'static, D>>::get_all()
+            .into_iter()
+            .map(|entry| entry.into_inner())
+            .collect()
+    }
+    fn user_sessions(&self, user: Id<User>) -> Vec<Session> {
+        <Session as DatabaseTable<'static, D>>::filter(|session| session.user() == Some(user))
+            .into_iter()
+            .map(|entry| entry.into_inner())
+            .collect()
+    }
+}
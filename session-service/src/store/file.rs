@@ -0,0 +1,183 @@
+use super::{
+    MemoryStore,
+    SessionStore,
+};
+use crate::session::Session;
+use chrono::{
+    DateTime,
+    Utc,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use std::{
+    collections::HashMap,
+    io::{
+        self,
+        BufRead,
+        BufReader,
+        Write,
+    },
+    fs::{
+        File,
+        OpenOptions,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+    sync::{
+        Mutex,
+        MutexGuard,
+    },
+};
+#[allow(unused)]
+use tracing::{
+    debug,
+    error,
+    warn,
+};
+
+/// Line of the file, a stored session or the id of a removed one
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum Line {
+    Stored(Session),
+    Removed { removed: String },
+}
+/// lines the file may have beyond twice the number of sessions before it is compacted
+const COMPACT_SLACK: usize = 64;
+
+#[derive(Debug, Default)]
+struct Log {
+    /// file opened for appending, opened on the first write
+    file: Option<File>,
+    lines: usize,
+}
+/// Sessions kept in memory and logged to a file with one JSON line per change
+///
+/// Stored and removed sessions are appended to the file. It is rewritten
+/// with only the current sessions when invalid sessions are removed, or when
+/// it has grown to more than twice the lines needed.
+#[derive(Debug)]
+pub struct FileStore {
+    path: PathBuf,
+    sessions: MemoryStore,
+    /// held while a change is applied and logged, so the file has the order of the changes
+    log: Mutex<Log>,
+}
+impl FileStore {
+    /// Sessions in the file at `path`, which is created when a session is stored
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut sessions = HashMap::new();
+        let mut lines = 0;
+        match File::open(&path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    let line = line?;
+                    lines += 1;
+                    match serde_json::from_str::<Line>(&line) {
+                        Ok(Line::Stored(session)) => {
+                            sessions.insert(session.id.clone(), session);
+                        },
+                        Ok(Line::Removed { removed }) => {
+                            sessions.remove(&removed);
+                        },
+                        Err(e) => warn!("Skipping invalid session in {}: {}", path.display(), e),
+                    }
+                }
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {},
+            Err(e) => return Err(e),
+        }
+        let now = Utc::now();
+        let store = Self {
+            path,
+            sessions: MemoryStore::from_sessions(sessions.into_values().filter(|session| session.is_valid_at(now))),
+            log: Mutex::new(Log {
+                file: None,
+                lines,
+            }),
+        };
+        Ok(store)
+    }
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    fn lock(&self) -> MutexGuard<'_, Log> {
+        self.log.lock().unwrap_or_else(|e| e.into_inner())
+    }
+    /// Append `line` to the file, compacting it once it grew too long
+    fn append(&self, log: &mut Log, line: &Line) {
+        if log.lines > 2 * self.sessions.len() + COMPACT_SLACK {
+            return self.compact(log);
+        }
+        if let Err(e) = self.write_line(log, line) {
+            error!("Failed to write session to {}: {}", self.path.display(), e);
+        }
+    }
+    fn write_line(&self, log: &mut Log, line: &Line) -> io::Result<()> {
+        let file = match &mut log.file {
+            Some(file) => file,
+            file => file.get_or_insert(OpenOptions::new().create(true).append(true).open(&self.path)?),
+        };
+        let mut bytes = serde_json::to_vec(line)?;
+        bytes.push(b'\n');
+        file.write_all(&bytes)?;
+        log.lines += 1;
+        Ok(())
+    }
+    /// Replace the file with the current sessions
+    fn compact(&self, log: &mut Log) {
+        log.file = None;
+        match self.rewrite() {
+            Ok(lines) => log.lines = lines,
+            Err(e) => error!("Failed to write sessions to {}: {}", self.path.display(), e),
+        }
+    }
+    fn rewrite(&self) -> io::Result<usize> {
+        // write a new file first, so a crash does not leave a partial file
+        let tmp = self.path.with_extension("tmp");
+        let mut file = io::BufWriter::new(File::create(&tmp)?);
+        let sessions = self.sessions.all();
+        for session in &sessions {
+            serde_json::to_writer(&mut file, session)?;
+            file.write_all(b"\n")?;
+        }
+        file.into_inner()?.sync_all()?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(sessions.len())
+    }
+}
+impl SessionStore for FileStore {
+    fn load(&self, id: &str) -> Option<Session> {
+        self.sessions.load(id)
+    }
+    fn store(&self, session: &Session) {
+        let mut log = self.lock();
+        self.sessions.store(session);
+        self.append(&mut log, &Line::Stored(session.clone()));
+    }
+    fn remove(&self, id: &str) -> Option<Session> {
+        let mut log = self.lock();
+        let session = self.sessions.remove(id);
+        if session.is_some() {
+            self.append(&mut log, &Line::Removed { removed: id.to_string() });
+        }
+        session
+    }
+    fn all(&self) -> Vec<Session> {
+        self.sessions.all()
+    }
+    /// Compacts the file, as the cleaner calls this periodically
+    fn remove_invalid(&self, now: DateTime<Utc>) -> usize {
+        let mut log = self.lock();
+        let count = self.sessions.remove_invalid(now);
+        if count > 0 {
+            self.compact(&mut log);
+        }
+        count
+    }
+}
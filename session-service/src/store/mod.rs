@@ -0,0 +1,116 @@
+//! Backends storing sessions
+//!
+//! `MemoryStore` loses its sessions when the server stops, `FileStore` keeps
+//! them in a JSON lines file and `DatabaseStore` in a table of a `Database`.
+//! With the `tide` feature, `TideSessionStore` keeps the sessions of tide's
+//! `SessionMiddleware` in the store of the `SessionService`.
+use crate::session::Session;
+use app_model::User;
+use chrono::{
+    DateTime,
+    Utc,
+};
+use database_table::Id;
+use sha2::{
+    Digest,
+    Sha256,
+};
+use std::{
+    collections::HashMap,
+    sync::RwLock,
+};
+
+pub mod database;
+pub use database::*;
+pub mod file;
+pub use file::*;
+#[cfg(feature = "tide")]
+mod tide;
+#[cfg(feature = "tide")]
+pub use self::tide::*;
+
+pub trait SessionStore: Send + Sync {
+    fn load(&self, id: &str) -> Option<Session>;
+    /// Insert or replace the session with the id of `session`
+    fn store(&self, session: &Session);
+    fn remove(&self, id: &str) -> Option<Session>;
+    /// All stored sessions, including invalid ones which were not removed yet
+    fn all(&self) -> Vec<Session>;
+    fn user_sessions(&self, user: Id<User>) -> Vec<Session> {
+        self.all()
+            .into_iter()
+            .filter(|session| session.user() == Some(user))
+            .collect()
+    }
+    fn remove_user(&self, user: Id<User>) -> Vec<Session> {
+        self.user_sessions(user)
+            .into_iter()
+            .filter_map(|session| self.remove(&session.id))
+            .collect()
+    }
+    /// Remove the sessions invalid at `now`, returning how many were removed
+    fn remove_invalid(&self, now: DateTime<Utc>) -> usize {
+        self.all()
+            .into_iter()
+            .filter(|session| !session.is_valid_at(now))
+            .filter_map(|session| self.remove(&session.id))
+            .count()
+    }
+    /// Earliest time a stored session becomes invalid
+    fn next_invalidation(&self) -> Option<DateTime<Utc>> {
+        self.all()
+            .iter()
+            .map(Session::invalidation_time)
+            .min()
+    }
+}
+/// Sessions in a map from the SHA-256 digests of their ids, so looking up an
+/// id does not take longer the more of it matches the id of a session
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    sessions: RwLock<HashMap<[u8; 32], Session>>,
+}
+fn digest(id: &str) -> [u8; 32] {
+    Sha256::digest(id.as_bytes()).into()
+}
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub(crate) fn from_sessions(sessions: impl IntoIterator<Item = Session>) -> Self {
+        Self {
+            sessions: RwLock::new(
+                sessions
+                    .into_iter()
+                    .map(|session| (digest(&session.id), session))
+                    .collect(),
+            ),
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.sessions.read().unwrap().len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+impl SessionStore for MemoryStore {
+    fn load(&self, id: &str) -> Option<Session> {
+        self.sessions.read().unwrap().get(&digest(id)).cloned()
+    }
+    fn store(&self, session: &Session) {
+        self.sessions.write().unwrap().insert(digest(&session.id), session.clone());
+    }
+    fn remove(&self, id: &str) -> Option<Session> {
+        self.sessions.write().unwrap().remove(&digest(id))
+    }
+    fn all(&self) -> Vec<Session> {
+        self.sessions.read().unwrap().values().cloned().collect()
+    }
+    fn remove_invalid(&self, now: DateTime<Utc>) -> usize {
+        let mut sessions = self.sessions.write().unwrap();
+        let count = sessions.len();
+        sessions.retain(|_, session| session.is_valid_at(now));
+        count - sessions.len()
+    }
+}
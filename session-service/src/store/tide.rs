@@ -0,0 +1,52 @@
+use crate::session_service;
+use app_model::auth::UserSession;
+use ::tide::{
+    sessions::{
+        Session as TideSession,
+        SessionStore as TideStore,
+    },
+    utils::async_trait,
+};
+
+/// Store of tide's `SessionMiddleware` keeping the serialized tide sessions
+/// in the sessions of the `SessionService`, so they expire, are cleaned and
+/// are persisted like these. The user of the `UserSession` under the key
+/// `session` owns the session, so `end_user_sessions` logs them out of it.
+/// ```rust,ignore
+/// set_session_service(SessionService::new(FileStore::open("sessions.jsonl")?));
+/// server.with(SessionMiddleware::new(TideSessionStore::new(), secret.as_bytes()));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct TideSessionStore;
+impl TideSessionStore {
+    pub fn new() -> Self {
+        Self
+    }
+}
+#[async_trait]
+impl TideStore for TideSessionStore {
+    async fn load_session(&self, cookie_value: String) -> anyhow::Result<Option<TideSession>> {
+        let id = TideSession::id_from_cookie_value(&cookie_value)?;
+        Ok(session_service()
+            .get(&id)
+            .and_then(|session| session.data().and_then(|data| serde_json::from_str(data).ok()))
+            .and_then(TideSession::validate))
+    }
+    async fn store_session(&self, session: TideSession) -> anyhow::Result<Option<String>> {
+        let user = session.get::<UserSession>("session").map(|user_session| user_session.user_id);
+        session_service().store_data(session.id(), user, serde_json::to_string(&session)?);
+        session.reset_data_changed();
+        Ok(session.into_cookie_value())
+    }
+    async fn destroy_session(&self, session: TideSession) -> anyhow::Result<()> {
+        session_service().end(session.id());
+        Ok(())
+    }
+    async fn clear_store(&self) -> anyhow::Result<()> {
+        let service = session_service();
+        for session in service.store().all() {
+            service.end(&session.id);
+        }
+        Ok(())
+    }
+}